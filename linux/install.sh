@@ -79,12 +79,6 @@ if ! command -v genisoimage &>/dev/null && ! command -v mkisofs &>/dev/null; the
     echo "       Install: sudo apt install genisoimage"
 fi
 
-# websockify (optional)
-if ! command -v websockify &>/dev/null; then
-    warn "websockify not found (optional, needed for VNC proxy)"
-    echo "       Install: pip3 install websockify"
-fi
-
 # Open vSwitch (required for virtual switches)
 if ! command -v ovs-vsctl &>/dev/null; then
     warn "Open vSwitch not found (required for virtual switches)"
@@ -196,7 +190,6 @@ disk_path: /opt/ctl/data/disks
 iso_path: /opt/ctl/data/iso
 live_path: /opt/ctl/data/backups
 gzip_path: /usr/bin/gzip
-websockify_path: websockify
 vs_up_script: vs-up.sh
 vs_down_script: vs-down.sh
 pctl_script: pctl.sh
@@ -230,6 +223,8 @@ ExecStart=${CTL_BIN}/vm_ctl server 0.0.0.0:8080
 Environment=VMCONTROL_API_KEY=${API_KEY}
 Restart=on-failure
 RestartSec=5
+# vm_ctl creates per-VM cgroups below its own
+Delegate=yes
 StandardOutput=append:${LOG_DIR}/vm_ctl.stdout.log
 StandardError=append:${LOG_DIR}/vm_ctl.stderr.log
 
@@ -1,10 +1,19 @@
 pub mod api_helpers;
+pub mod autoinstall;
+pub mod cgroups;
 pub mod config;
+pub mod console;
 pub mod db;
 pub mod disk_edit;
+pub mod efivars;
 pub mod guest_agent;
+pub mod keys;
 pub mod mds;
 pub mod models;
 pub mod operations;
+pub mod qmp;
+pub mod recording;
 pub mod server;
 pub mod ssh;
+pub mod transfer;
+pub mod unattend;
@@ -35,6 +35,19 @@ pub struct MdsConfig {
     pub power_state: String,          // "" | "reboot" | "poweroff"
     pub extra_runcmd: String,         // newline-separated commands
     pub write_files: String,          // JSON array: [{"path":"/etc/foo","content":"bar"}]
+    // Windows provisioning through the guest agent (cloud-init is not used)
+    pub windows_provision: bool,
+    pub windows_admin_user: String,   // "" = Administrator
+    pub windows_admin_password: String, // "" = root_password
+    pub windows_computer_name: String, // "" = hostname from the VM name
+    pub domain_name: String,          // "" = no domain join
+    pub domain_user: String,          // DOMAIN\user or user@domain
+    pub domain_password: String,
+    pub domain_ou: String,            // optional OU distinguished name
+    /// virtiofs shared folders of the VM — filled from the VM config when
+    /// the seed ISO is built, never stored with the MDS config
+    #[serde(skip)]
+    pub shared_folders: Vec<crate::models::SharedFolder>,
 }
 
 impl Default for MdsConfig {
@@ -63,6 +76,15 @@ impl Default for MdsConfig {
             power_state: "".into(),
             extra_runcmd: "".into(),
             write_files: "".into(),
+            windows_provision: false,
+            windows_admin_user: "".into(),
+            windows_admin_password: "".into(),
+            windows_computer_name: "".into(),
+            domain_name: "".into(),
+            domain_user: "".into(),
+            domain_password: "".into(),
+            domain_ou: "".into(),
+            shared_folders: Vec::new(),
         }
     }
 }
@@ -260,6 +282,23 @@ fn generate_userdata_base(config: &MdsConfig, vmctl_password: &str) -> String {
         }
     }
 
+    // ── mounts (virtiofs shared folders) ──
+    if !config.shared_folders.is_empty() {
+        ud.push_str("mounts:\n");
+        for f in &config.shared_folders {
+            let guest_path = if f.guest_path.is_empty() {
+                format!("/mnt/{}", f.tag)
+            } else {
+                f.guest_path.clone()
+            };
+            let opts = if f.readonly == "1" { "ro,nofail" } else { "rw,nofail" };
+            ud.push_str(&format!(
+                "  - [ \"{}\", \"{}\", virtiofs, \"{}\", \"0\", \"0\" ]\n",
+                f.tag, guest_path, opts
+            ));
+        }
+    }
+
     // ── phone_home ──
     if !config.phone_home_url.is_empty() {
         ud.push_str("phone_home:\n");
@@ -491,12 +530,41 @@ async fn mac_local_ipv4s(req: HttpRequest, path: web::Path<String>) -> HttpRespo
     }
 }
 
+// ──────────────────────────────────────────
+// Unattended Linux install answers
+// ──────────────────────────────────────────
+
+/// Answer files for a VM booted into its installer (see autoinstall.rs).
+/// Only served while the VM's install is pending, and only with the token
+/// from the installer's kernel command line.
+async fn autoinstall_handler(path: web::Path<(String, String, String)>) -> HttpResponse {
+    let (smac, token, file) = path.into_inner();
+    match web::block(move || crate::operations::linux_install_file(&smac, &token, &file)).await {
+        Ok(Ok(body)) => HttpResponse::Ok().content_type("text/plain; charset=UTF-8").body(body),
+        Ok(Err(e)) => HttpResponse::NotFound().content_type("text/plain").body(e),
+        Err(e) => HttpResponse::InternalServerError().content_type("text/plain").body(format!("Internal error: {}", e)),
+    }
+}
+
 // ──────────────────────────────────────────
 // Admin API Handlers
 // ──────────────────────────────────────────
 
+/// MDS credentials that are never sent back to API clients; saving an empty
+/// value keeps the stored one
+pub const MDS_SECRET_KEYS: [&str; 2] = ["windows_admin_password", "domain_password"];
+
+pub fn redact_mds_secrets(mds: &mut serde_json::Value) {
+    for key in MDS_SECRET_KEYS {
+        if let Some(v) = mds.get_mut(key) {
+            *v = serde_json::json!("");
+        }
+    }
+}
+
 async fn get_mds_config_handler() -> HttpResponse {
-    let config = load_mds_config();
+    let mut config = serde_json::to_value(load_mds_config()).unwrap_or_default();
+    redact_mds_secrets(&mut config);
     HttpResponse::Ok().json(ApiResponse {
         success: true,
         message: "MDS config loaded".into(),
@@ -505,7 +573,16 @@ async fn get_mds_config_handler() -> HttpResponse {
 }
 
 async fn save_mds_config_handler(body: web::Json<MdsConfig>) -> HttpResponse {
-    match save_mds_config(&body.into_inner()) {
+    let mut config = body.into_inner();
+    // Empty secrets keep the stored ones (they are redacted on GET)
+    let stored = load_mds_config();
+    if config.windows_admin_password.is_empty() {
+        config.windows_admin_password = stored.windows_admin_password;
+    }
+    if config.domain_password.is_empty() {
+        config.domain_password = stored.domain_password;
+    }
+    match save_mds_config(&config) {
         Ok(()) => HttpResponse::Ok().json(ApiResponse {
             success: true,
             message: "MDS config saved".into(),
@@ -523,12 +600,17 @@ async fn save_mds_config_handler(body: web::Json<MdsConfig>) -> HttpResponse {
 // Route Configuration
 // ──────────────────────────────────────────
 
+/// Admin API, main listener only
+pub fn configure_mds_admin_routes(cfg: &mut web::ServiceConfig) {
+    cfg.route("/api/mds/config", web::get().to(get_mds_config_handler))
+        .route("/api/mds/config", web::post().to(save_mds_config_handler));
+}
+
+/// Guest-facing metadata, also served on the link-local listener
 pub fn configure_mds_routes(cfg: &mut web::ServiceConfig) {
     cfg
-        // Admin API
-        .route("/api/mds/config", web::get().to(get_mds_config_handler))
-        .route("/api/mds/config", web::post().to(save_mds_config_handler))
         // EC2-compatible metadata endpoints
+        .route("/autoinstall/{smac}/{token}/{file}", web::get().to(autoinstall_handler))
         .route("/2009-04-04/user-data", web::get().to(userdata_handler))
         .route("/2009-04-04/meta-data/", web::get().to(metadata_index))
         .route("/2009-04-04/meta-data/instance-id", web::get().to(instance_id_handler))
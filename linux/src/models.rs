@@ -11,8 +11,273 @@ pub struct VmStartConfig {
     pub disks: Vec<DiskInfo>,
     #[serde(default)]
     pub pci_devices: Vec<PciDevice>,
+    #[serde(default)]
+    pub usb_devices: Vec<UsbDevice>,
     #[serde(default = "default_vnc_port")]
     pub vnc_port: u16,
+    #[serde(default)]
+    pub limits: ResourceLimits,
+    #[serde(default)]
+    pub shared_folders: Vec<SharedFolder>,
+    #[serde(default)]
+    pub recording: RecordingSettings,
+    #[serde(default)]
+    pub display: DisplaySettings,
+    #[serde(default)]
+    pub unattend: UnattendSettings,
+    #[serde(default)]
+    pub linux_install: LinuxInstallSettings,
+    #[serde(default)]
+    pub boot: BootSettings,
+}
+
+/// Firmware and boot path. Kernel and initrd are file names in `boot_path`.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct BootSettings {
+    /// "auto" (UEFI for Windows and aarch64, BIOS otherwise), "bios" or "uefi"
+    #[serde(default = "default_auto")]
+    pub firmware: String,
+    /// Device classes in boot order, e.g. "cdrom,disk" or "disk,net"
+    #[serde(default = "default_boot_order")]
+    pub order: String,
+    /// Boot menu timeout in milliseconds ("0" = no menu)
+    #[serde(default = "default_zero")]
+    pub menu_timeout: String,
+    /// Direct kernel boot ("" = boot from the devices in `order`)
+    #[serde(default)]
+    pub kernel: String,
+    #[serde(default)]
+    pub initrd: String,
+    /// Kernel command line for direct kernel boot
+    #[serde(default)]
+    pub cmdline: String,
+    /// UEFI Secure Boot: "auto" (Windows only), "1" or "0"
+    #[serde(default = "default_auto")]
+    pub secure_boot: String,
+}
+
+impl Default for BootSettings {
+    fn default() -> Self {
+        BootSettings {
+            firmware: default_auto(),
+            order: default_boot_order(),
+            menu_timeout: default_zero(),
+            kernel: String::new(),
+            initrd: String::new(),
+            cmdline: String::new(),
+            secure_boot: default_auto(),
+        }
+    }
+}
+
+fn default_boot_order() -> String { "cdrom,disk".into() }
+
+/// Display protocol and video device. The noVNC console stays available in
+/// every mode; SPICE is added next to it for remote-viewer clients.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct DisplaySettings {
+    /// "vnc" or "spice"
+    #[serde(default = "default_vnc")]
+    pub protocol: String,
+    /// "auto" (std on x86_64, virtio on aarch64; qxl with SPICE), "std", "qxl" or "virtio"
+    #[serde(default = "default_auto")]
+    pub video: String,
+    /// Number of SPICE USB redirection channels (0-4)
+    #[serde(default = "default_usb_redirect")]
+    pub usb_redirect: String,
+}
+
+impl Default for DisplaySettings {
+    fn default() -> Self {
+        DisplaySettings {
+            protocol: default_vnc(),
+            video: default_auto(),
+            usb_redirect: default_usb_redirect(),
+        }
+    }
+}
+
+/// Hands-off Windows Setup: an autounattend.xml ISO is attached at start
+/// until the installed guest's agent first answers
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct UnattendSettings {
+    /// "1" while an unattended install is pending
+    #[serde(default = "default_zero")]
+    pub enabled: String,
+    /// Setup, system and user locale, e.g. "en-US"
+    #[serde(default = "default_unattend_locale")]
+    pub locale: String,
+    /// Input locale ("" = same as `locale`), e.g. "0409:00000409" or "de-DE"
+    #[serde(default)]
+    pub keyboard: String,
+    /// Windows time zone name, e.g. "UTC" or "W. Europe Standard Time"
+    #[serde(default = "default_unattend_timezone")]
+    pub timezone: String,
+    /// "" = no key (evaluation and volume media)
+    #[serde(default)]
+    pub product_key: String,
+    /// Image in install.wim by name, e.g. "Windows 11 Pro" ("" = first image)
+    #[serde(default)]
+    pub image_name: String,
+    /// Administrator password ("" = MDS windows_admin_password / root_password)
+    #[serde(default)]
+    pub admin_password: String,
+    /// virtio-win driver folder, e.g. "w11", "w10", "2k22" ("" = w11)
+    #[serde(default)]
+    pub driver_os: String,
+    /// "1" sets the LabConfig TPM / Secure Boot / RAM check bypass keys
+    #[serde(default = "default_one")]
+    pub bypass_checks: String,
+}
+
+impl Default for UnattendSettings {
+    fn default() -> Self {
+        UnattendSettings {
+            enabled: default_zero(),
+            locale: default_unattend_locale(),
+            keyboard: String::new(),
+            timezone: default_unattend_timezone(),
+            product_key: String::new(),
+            image_name: String::new(),
+            admin_password: String::new(),
+            driver_os: String::new(),
+            bypass_checks: default_one(),
+        }
+    }
+}
+
+fn default_unattend_locale() -> String { "en-US".into() }
+fn default_unattend_timezone() -> String { "UTC".into() }
+
+/// Hands-off Linux install from a vanilla installer ISO: the ISO's kernel is
+/// booted directly and fetches its answers from the MDS
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct LinuxInstallSettings {
+    /// "1" while an unattended install is pending
+    #[serde(default = "default_zero")]
+    pub enabled: String,
+    /// "ubuntu" (autoinstall), "debian" (preseed) or "rhel" (kickstart)
+    #[serde(default = "default_linux_install_flavor")]
+    pub flavor: String,
+    /// Installer ISO in iso_path, e.g. "ubuntu-24.04-live-server-amd64.iso"
+    #[serde(default)]
+    pub iso: String,
+    /// e.g. "en_US.UTF-8"
+    #[serde(default = "default_linux_install_locale")]
+    pub locale: String,
+    /// Console keymap / X layout, e.g. "us", "de"
+    #[serde(default = "default_linux_install_keyboard")]
+    pub keyboard: String,
+    /// Olson name, e.g. "UTC" or "Europe/Berlin"
+    #[serde(default = "default_unattend_timezone")]
+    pub timezone: String,
+    /// Sudo user created next to root
+    #[serde(default = "default_linux_install_username")]
+    pub username: String,
+    /// Root and user password ("" = MDS root_password)
+    #[serde(default)]
+    pub password: String,
+    /// Appended to the installer kernel command line
+    #[serde(default)]
+    pub kernel_args: String,
+}
+
+impl Default for LinuxInstallSettings {
+    fn default() -> Self {
+        LinuxInstallSettings {
+            enabled: default_zero(),
+            flavor: default_linux_install_flavor(),
+            iso: String::new(),
+            locale: default_linux_install_locale(),
+            keyboard: default_linux_install_keyboard(),
+            timezone: default_unattend_timezone(),
+            username: default_linux_install_username(),
+            password: String::new(),
+            kernel_args: String::new(),
+        }
+    }
+}
+
+fn default_linux_install_flavor() -> String { "ubuntu".into() }
+fn default_linux_install_locale() -> String { "en_US.UTF-8".into() }
+fn default_linux_install_keyboard() -> String { "us".into() }
+fn default_linux_install_username() -> String { "admin".into() }
+
+fn default_vnc() -> String { "vnc".into() }
+fn default_usb_redirect() -> String { "2".into() }
+
+/// Console session recording (periodic screendumps under live_path/recordings)
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct RecordingSettings {
+    /// "1" = record automatically whenever a VNC client is connected
+    #[serde(default = "default_zero")]
+    pub enabled: String,
+    /// Seconds between captures (fractions allowed, min 0.25)
+    #[serde(default = "default_one")]
+    pub interval: String,
+}
+
+impl Default for RecordingSettings {
+    fn default() -> Self {
+        RecordingSettings { enabled: default_zero(), interval: default_one() }
+    }
+}
+
+/// Host directory exported to the guest through virtiofsd (Linux hosts)
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct SharedFolder {
+    /// virtio-fs mount tag (max 36 chars), e.g. "share"
+    pub tag: String,
+    /// Absolute host directory
+    pub host_path: String,
+    /// Guest mount point written to cloud-init `mounts` ("" = /mnt/<tag>)
+    #[serde(default)]
+    pub guest_path: String,
+    /// "1" = read-only export
+    #[serde(default = "default_zero")]
+    pub readonly: String,
+}
+
+fn default_auto() -> String { "auto".into() }
+
+/// Per-VM cgroup v2 limits (Linux). "0" = no limit.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ResourceLimits {
+    /// cpu.weight (1-10000, kernel default 100)
+    #[serde(default = "default_zero")]
+    pub cpu_weight: String,
+    /// CPU quota in percent of one host CPU ("200" = two CPUs)
+    #[serde(default = "default_zero")]
+    pub cpu_quota: String,
+    /// memory.max in MB, or "auto" = guest RAM + QEMU overhead
+    #[serde(default = "default_zero")]
+    pub memory_max: String,
+    /// Overhead added by "auto" in MB ("0" = max(256, RAM/16))
+    #[serde(default = "default_zero")]
+    pub memory_overhead: String,
+    #[serde(default = "default_zero")]
+    pub io_read_bps: String,
+    #[serde(default = "default_zero")]
+    pub io_write_bps: String,
+    #[serde(default = "default_zero")]
+    pub io_read_iops: String,
+    #[serde(default = "default_zero")]
+    pub io_write_iops: String,
+}
+
+impl Default for ResourceLimits {
+    fn default() -> Self {
+        ResourceLimits {
+            cpu_weight: default_zero(),
+            cpu_quota: default_zero(),
+            memory_max: default_zero(),
+            memory_overhead: default_zero(),
+            io_read_bps: default_zero(),
+            io_write_bps: default_zero(),
+            io_read_iops: default_zero(),
+            io_write_iops: default_zero(),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -21,8 +286,28 @@ pub struct PciDevice {
     pub host: String,
 }
 
+/// Host USB device handed to the guest as `usb-host` on the xHCI controller.
+/// Matched by vendor/product ID, or by physical bus/port when set.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct UsbDevice {
+    pub usbid: String,
+    /// Vendor ID in hex, e.g. "0529"
+    #[serde(default)]
+    pub vendor: String,
+    /// Product ID in hex, e.g. "0001"
+    #[serde(default)]
+    pub product: String,
+    /// Host bus number, e.g. "1"
+    #[serde(default)]
+    pub hostbus: String,
+    /// Host port path, e.g. "2.1"
+    #[serde(default)]
+    pub hostport: String,
+}
+
 fn default_one() -> String { "1".into() }
 fn default_zero() -> String { "0".into() }
+fn default_true() -> bool { true }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct CpuInfo {
@@ -35,17 +320,39 @@ pub struct CpuInfo {
     pub cores: String,
     #[serde(default = "default_one")]
     pub threads: String,
+    /// Upper bound for vCPU hot-add — "0" means no hot-pluggable CPUs
+    #[serde(default = "default_zero")]
+    pub max_vcpus: String,
+    /// Host CPU list per vCPU (entry i pins vCPU i), e.g. ["2", "3"] — Linux only
+    #[serde(default)]
+    pub pinning: Vec<String>,
+    /// Host CPU list for the QEMU emulator/IO threads, e.g. "0-1" — Linux only
+    #[serde(default)]
+    pub emulator_pin: String,
+    /// Guest NUMA nodes — vCPUs and memory are split evenly ("0" = none)
+    #[serde(default = "default_zero")]
+    pub numa_nodes: String,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct MemoryInfo {
     pub size: String,
+    /// Upper bound (MB) for memory hot-add — "0" means no DIMM slots
+    #[serde(default = "default_zero")]
+    pub max_size: String,
+    /// Attach a virtio-balloon device ("1" / "0")
+    #[serde(default = "default_zero")]
+    pub balloon: String,
+    /// Back guest RAM with hugetlbfs pages ("1" / "0") — Linux only
+    #[serde(default = "default_zero")]
+    pub hugepages: String,
 }
 
 fn default_net_mode() -> String { "nat".into() }
 fn default_switch_name() -> String { String::new() }
 fn default_bridge_iface() -> String { String::new() }
 fn default_nic_model() -> String { "virtio".into() }
+fn default_link() -> String { "up".into() }
 fn default_arch() -> String { "x86_64".into() }
 fn default_cloudinit() -> String { "1".into() }
 
@@ -71,6 +378,9 @@ pub struct NetworkAdapter {
     pub bridge_iface: String,
     #[serde(default = "default_nic_model")]
     pub nic_model: String,
+    /// Link state ("up" / "down") — re-applied on every start
+    #[serde(default = "default_link")]
+    pub link: String,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -90,6 +400,18 @@ pub struct SimpleCmd {
     pub smac: String,
 }
 
+/// Graceful shutdown request: guest agent, then ACPI, then (optionally) quit
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ShutdownCmd {
+    pub smac: String,
+    /// Seconds to wait for the guest before escalating (default `shutdown_timeout`)
+    #[serde(default)]
+    pub timeout: Option<u64>,
+    /// Force quit when the guest has not powered off after `timeout`
+    #[serde(default = "default_true")]
+    pub force: bool,
+}
+
 fn default_cd0() -> String { "cd0".into() }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -100,6 +422,21 @@ pub struct MountIsoCmd {
     pub drive: String,
 }
 
+/// Keys to enroll in a VM's UEFI NVRAM, as PEM certificates. A new PK puts
+/// the firmware in user mode; KEK and db entries are appended.
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct SecureBootEnrollCmd {
+    #[serde(default)]
+    pub pk: String,
+    #[serde(default)]
+    pub kek: Vec<String>,
+    #[serde(default)]
+    pub db: Vec<String>,
+    /// Owner GUID recorded with each key ("" = secure_boot_owner_guid)
+    #[serde(default)]
+    pub owner: String,
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct UnmountIsoCmd {
     pub smac: String,
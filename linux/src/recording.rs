@@ -0,0 +1,338 @@
+use crate::config::{get_conf, get_conf_or};
+use crate::ssh::sanitize_name;
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// Stop flags of recordings in progress, by VM
+static ACTIVE: OnceLock<Mutex<HashMap<String, Arc<AtomicBool>>>> = OnceLock::new();
+/// VMs whose automatic recording was stopped by hand; not restarted until
+/// the current VNC session ends
+static PAUSED: OnceLock<Mutex<HashSet<String>>> = OnceLock::new();
+/// Who last opened a console session per VM (from the VNC token/start request)
+static OPENERS: OnceLock<Mutex<HashMap<String, (String, Instant)>>> = OnceLock::new();
+
+fn active() -> &'static Mutex<HashMap<String, Arc<AtomicBool>>> {
+    ACTIVE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn paused() -> &'static Mutex<HashSet<String>> {
+    PAUSED.get_or_init(|| Mutex::new(HashSet::new()))
+}
+
+fn openers() -> &'static Mutex<HashMap<String, (String, Instant)>> {
+    OPENERS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Recordings live under `live_path/recordings`: `{id}.zip` holds the frames,
+/// `{id}.json` is the index entry (kept current while recording)
+pub fn recordings_dir() -> PathBuf {
+    PathBuf::from(get_conf("live_path")).join("recordings")
+}
+
+fn zip_path(id: &str) -> PathBuf {
+    recordings_dir().join(format!("{}.zip", id))
+}
+
+fn meta_path(id: &str) -> PathBuf {
+    recordings_dir().join(format!("{}.json", id))
+}
+
+/// Remember who opened a console session so an automatic recording can be
+/// attributed to them
+pub fn note_session_opener(smac: &str, user: &str) {
+    openers()
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(smac.to_string(), (user.to_string(), Instant::now()));
+}
+
+fn session_opener(smac: &str) -> String {
+    openers()
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .get(smac)
+        .filter(|(_, at)| at.elapsed() < Duration::from_secs(600))
+        .map(|(u, _)| u.clone())
+        .unwrap_or_else(|| "unknown".into())
+}
+
+pub fn is_recording(smac: &str) -> bool {
+    active().lock().unwrap_or_else(|e| e.into_inner()).contains_key(smac)
+}
+
+/// Number of VNC clients attached to the VM's display
+fn vnc_client_count(smac: &str) -> Result<usize, String> {
+    let info = crate::qmp::qmp_command(smac, "query-vnc", None)?;
+    Ok(info.get("clients").and_then(|c| c.as_array()).map(|c| c.len()).unwrap_or(0))
+}
+
+/// Per-VM recording settings from the stored config: (enabled, interval)
+fn recording_settings(config: &serde_json::Value) -> (bool, Duration) {
+    let enabled = config.pointer("/recording/enabled").and_then(|v| v.as_str()) == Some("1");
+    let secs: f64 = config
+        .pointer("/recording/interval")
+        .and_then(|v| v.as_str())
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(1.0);
+    (enabled, Duration::from_millis((secs.max(0.25) * 1000.0) as u64))
+}
+
+/// Start recordings for VMs with recording enabled as soon as a VNC client
+/// connects. Called periodically from the server.
+pub fn supervise() {
+    let vms = crate::db::list_vms().unwrap_or_default();
+    for vm in vms.iter().filter(|vm| vm.status == "running") {
+        let config: serde_json::Value = serde_json::from_str(&vm.config).unwrap_or_default();
+        if !recording_settings(&config).0 || is_recording(&vm.smac) {
+            continue;
+        }
+        let clients = vnc_client_count(&vm.smac).unwrap_or(0);
+        if clients == 0 {
+            paused().lock().unwrap_or_else(|e| e.into_inner()).remove(&vm.smac);
+        } else if !paused().lock().unwrap_or_else(|e| e.into_inner()).contains(&vm.smac) {
+            let user = session_opener(&vm.smac);
+            if let Err(e) = start(&vm.smac, &user, false) {
+                eprintln!("recording {}: {}", vm.smac, e);
+            }
+        }
+    }
+}
+
+/// Begin recording a VM's display. Automatic recordings end when the last
+/// VNC client leaves; manual ones run until `stop` (or the VM stops).
+pub fn start(smac: &str, user: &str, manual: bool) -> Result<String, String> {
+    sanitize_name(smac)?;
+    let vm = crate::db::get_vm(smac).map_err(|_| format!("VM '{}' not found", smac))?;
+    if vm.status != "running" {
+        return Err(format!("VM '{}' is not running", smac));
+    }
+    let config: serde_json::Value = serde_json::from_str(&vm.config).unwrap_or_default();
+    let (_, interval) = recording_settings(&config);
+
+    std::fs::create_dir_all(recordings_dir())
+        .map_err(|e| format!("Create {}: {}", recordings_dir().display(), e))?;
+
+    let stop_flag = Arc::new(AtomicBool::new(false));
+    {
+        let mut map = active().lock().unwrap_or_else(|e| e.into_inner());
+        if map.contains_key(smac) {
+            return Err(format!("VM '{}' is already being recorded", smac));
+        }
+        map.insert(smac.to_string(), stop_flag.clone());
+    }
+
+    let id = format!("{}-{}", smac, chrono::Local::now().format("%Y%m%d-%H%M%S"));
+    let smac = smac.to_string();
+    let user = user.to_string();
+    let thread_id = id.clone();
+    std::thread::spawn(move || {
+        if let Err(e) = record(&smac, &thread_id, &user, interval, &stop_flag, manual) {
+            eprintln!("recording {}: {}", thread_id, e);
+        }
+        active().lock().unwrap_or_else(|e| e.into_inner()).remove(&smac);
+    });
+    Ok(format!("Recording {} started\n", id))
+}
+
+/// Ask a VM's recording to finish; the file is closed on the next tick
+pub fn stop(smac: &str) -> Result<String, String> {
+    sanitize_name(smac)?;
+    match active().lock().unwrap_or_else(|e| e.into_inner()).get(smac) {
+        Some(flag) => {
+            flag.store(true, Ordering::Relaxed);
+            paused().lock().unwrap_or_else(|e| e.into_inner()).insert(smac.to_string());
+            Ok(format!("Recording of {} stopping\n", smac))
+        }
+        None => Err(format!("VM '{}' is not being recorded", smac)),
+    }
+}
+
+fn write_meta(meta: &serde_json::Value, id: &str) {
+    if let Ok(json) = serde_json::to_string_pretty(meta) {
+        let _ = std::fs::write(meta_path(id), json);
+    }
+}
+
+/// Capture loop: a frame is stored only when the screen changed, with its
+/// offset from the start, so idle consoles cost almost nothing
+fn record(
+    smac: &str,
+    id: &str,
+    user: &str,
+    interval: Duration,
+    stop_flag: &AtomicBool,
+    manual: bool,
+) -> Result<(), String> {
+    use zip::write::SimpleFileOptions;
+    let file = std::fs::File::create(zip_path(id))
+        .map_err(|e| format!("Create {}: {}", zip_path(id).display(), e))?;
+    let mut zip = zip::ZipWriter::new(file);
+    // QEMU's screendump PNGs are only lightly compressed
+    let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+
+    let max_duration = Duration::from_secs(
+        get_conf_or("recording_max_minutes", "240").parse::<u64>().unwrap_or(240) * 60,
+    );
+    let started = Instant::now();
+    let mut meta = serde_json::json!({
+        "id": id,
+        "smac": smac,
+        "user": user,
+        "mode": if manual { "manual" } else { "auto" },
+        "started": chrono::Local::now().to_rfc3339(),
+        "ended": null,
+        "recording": true,
+        "interval_ms": interval.as_millis() as u64,
+        "duration_ms": 0,
+        "frames": [],
+    });
+    write_meta(&meta, id);
+
+    let mut frames: Vec<u64> = Vec::new();
+    let mut last: Vec<u8> = Vec::new();
+    let mut idle = Duration::ZERO;
+    let mut tick: u64 = 0;
+    loop {
+        if stop_flag.load(Ordering::Relaxed) || started.elapsed() > max_duration {
+            break;
+        }
+        match crate::operations::capture_frame(smac) {
+            Ok(png) => {
+                if png != last {
+                    zip.start_file(format!("frames/{:06}.png", frames.len()), options)
+                        .and_then(|_| zip.write_all(&png).map_err(Into::into))
+                        .map_err(|e| format!("Write frame: {}", e))?;
+                    frames.push(started.elapsed().as_millis() as u64);
+                    last = png;
+                }
+            }
+            Err(_) => {
+                let running = crate::db::get_vm(smac).map(|vm| vm.status == "running").unwrap_or(false);
+                if !running {
+                    break;
+                }
+            }
+        }
+        // Automatic recordings follow the session: end after 10 s without clients
+        if !manual {
+            if vnc_client_count(smac).unwrap_or(0) == 0 {
+                idle += interval;
+                if idle >= Duration::from_secs(10) {
+                    break;
+                }
+            } else {
+                idle = Duration::ZERO;
+            }
+        }
+        tick += 1;
+        if tick.is_multiple_of(30) {
+            meta["duration_ms"] = serde_json::json!(started.elapsed().as_millis() as u64);
+            meta["frame_count"] = serde_json::json!(frames.len());
+            write_meta(&meta, id);
+        }
+        std::thread::sleep(interval);
+    }
+
+    meta["ended"] = serde_json::json!(chrono::Local::now().to_rfc3339());
+    meta["recording"] = serde_json::json!(false);
+    meta["duration_ms"] = serde_json::json!(started.elapsed().as_millis() as u64);
+    meta["frame_count"] = serde_json::json!(frames.len());
+    meta["frames"] = serde_json::json!(frames);
+    zip.start_file("meta.json", options)
+        .and_then(|_| zip.write_all(meta.to_string().as_bytes()).map_err(Into::into))
+        .and_then(|_| zip.finish().map(|_| ()))
+        .map_err(|e| format!("Finish {}: {}", zip_path(id).display(), e))?;
+    meta["size"] = serde_json::json!(std::fs::metadata(zip_path(id)).map(|m| m.len()).unwrap_or(0));
+    write_meta(&meta, id);
+    Ok(())
+}
+
+/// Recording index, newest first (frame lists omitted)
+pub fn list(smac: Option<&str>) -> Vec<serde_json::Value> {
+    let mut out: Vec<serde_json::Value> = std::fs::read_dir(recordings_dir())
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter(|e| e.path().extension().and_then(|x| x.to_str()) == Some("json"))
+                .filter_map(|e| std::fs::read_to_string(e.path()).ok())
+                .filter_map(|s| serde_json::from_str::<serde_json::Value>(&s).ok())
+                .filter(|m| smac.is_none() || m.get("smac").and_then(|v| v.as_str()) == smac)
+                .map(|mut m| {
+                    if let Some(obj) = m.as_object_mut() {
+                        obj.remove("frames");
+                    }
+                    m
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    out.sort_by(|a, b| {
+        let key = |m: &serde_json::Value| m.get("started").and_then(|v| v.as_str()).unwrap_or("").to_string();
+        key(b).cmp(&key(a))
+    });
+    out
+}
+
+/// Full index entry of one recording, including frame timestamps
+pub fn get(id: &str) -> Result<serde_json::Value, String> {
+    sanitize_name(id)?;
+    let s = std::fs::read_to_string(meta_path(id)).map_err(|_| format!("Recording '{}' not found", id))?;
+    serde_json::from_str(&s).map_err(|e| format!("Recording '{}' index is corrupt: {}", id, e))
+}
+
+/// One frame of a finished recording as PNG
+pub fn frame(id: &str, index: usize) -> Result<Vec<u8>, String> {
+    sanitize_name(id)?;
+    if get(id)?.get("recording").and_then(|v| v.as_bool()) == Some(true) {
+        return Err(format!("Recording '{}' is still in progress", id));
+    }
+    let file = std::fs::File::open(zip_path(id)).map_err(|e| format!("Open recording: {}", e))?;
+    let mut archive = zip::ZipArchive::new(file).map_err(|e| format!("Read recording: {}", e))?;
+    let mut entry = archive
+        .by_name(&format!("frames/{:06}.png", index))
+        .map_err(|_| format!("Frame {} not found in '{}'", index, id))?;
+    let mut data = Vec::new();
+    entry.read_to_end(&mut data).map_err(|e| format!("Read frame: {}", e))?;
+    Ok(data)
+}
+
+/// Path of a recording archive for download
+pub fn archive_path(id: &str) -> Result<PathBuf, String> {
+    sanitize_name(id)?;
+    let path = zip_path(id);
+    if !path.exists() {
+        return Err(format!("Recording '{}' not found", id));
+    }
+    Ok(path)
+}
+
+pub fn delete(id: &str) -> Result<String, String> {
+    sanitize_name(id)?;
+    let meta = get(id)?;
+    if meta.get("recording").and_then(|v| v.as_bool()) == Some(true) {
+        return Err(format!("Recording '{}' is still in progress", id));
+    }
+    let _ = std::fs::remove_file(zip_path(id));
+    let _ = std::fs::remove_file(meta_path(id));
+    Ok(format!("Recording '{}' deleted\n", id))
+}
+
+/// Recordings left "in progress" by a crash or restart are unfinished archives;
+/// mark them so they can be listed and deleted
+pub fn mark_interrupted() {
+    for mut meta in list(None) {
+        if meta.get("recording").and_then(|v| v.as_bool()) == Some(true) {
+            let id = meta.get("id").and_then(|v| v.as_str()).unwrap_or("").to_string();
+            if let Ok(full) = get(&id) {
+                meta = full;
+                meta["recording"] = serde_json::json!(false);
+                meta["interrupted"] = serde_json::json!(true);
+                write_meta(&meta, &id);
+            }
+        }
+    }
+}
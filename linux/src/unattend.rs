@@ -0,0 +1,243 @@
+use crate::models::UnattendSettings;
+
+/// virtio-win driver folders offered to Windows PE, so Setup sees the
+/// virtio-blk disk and virtio NIC without a manual "Load driver"
+const WINPE_DRIVERS: &[&str] = &["viostor", "vioscsi", "NetKVM", "vioserial", "Balloon"];
+
+/// Drive letters the virtio-win ISO may get in Windows PE (it is on cd3,
+/// after the install media and the answer-file ISO)
+const DRIVE_LETTERS: &[char] = &['D', 'E', 'F', 'G', 'H', 'I', 'J'];
+
+/// LabConfig values Setup checks before refusing unsupported hardware
+/// (as in `static/bypass-tpm.reg`, plus the RAM check)
+const BYPASS_KEYS: &[&str] = &["BypassTPMCheck", "BypassSecureBootCheck", "BypassRAMCheck"];
+
+/// Everything needed to render one answer file
+pub struct UnattendParams<'a> {
+    pub settings: &'a UnattendSettings,
+    pub admin_password: &'a str,
+    pub computer_name: &'a str,
+    /// GPT layout with an EFI system partition; MBR for SeaBIOS
+    pub uefi: bool,
+    pub is_aarch64: bool,
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn component(name: &str, arch: &str, body: &str) -> String {
+    format!(
+        "    <component name=\"{}\" processorArchitecture=\"{}\" publicKeyToken=\"31bf3856ad364e35\" language=\"neutral\" versionScope=\"nonSxS\">\n{}    </component>\n",
+        name, arch, body
+    )
+}
+
+/// `<RunSynchronousCommand>` list for a RunSynchronous block
+fn run_synchronous(commands: &[String]) -> String {
+    let mut out = String::from("      <RunSynchronous>\n");
+    for (i, cmd) in commands.iter().enumerate() {
+        out.push_str(&format!(
+            "        <RunSynchronousCommand wcm:action=\"add\">\n          <Order>{}</Order>\n          <Path>{}</Path>\n        </RunSynchronousCommand>\n",
+            i + 1,
+            xml_escape(cmd)
+        ));
+    }
+    out.push_str("      </RunSynchronous>\n");
+    out
+}
+
+fn international(s: &UnattendSettings, winpe: bool) -> String {
+    let keyboard = if s.keyboard.is_empty() { &s.locale } else { &s.keyboard };
+    let mut out = String::new();
+    if winpe {
+        out.push_str(&format!(
+            "      <SetupUILanguage>\n        <UILanguage>{}</UILanguage>\n      </SetupUILanguage>\n",
+            xml_escape(&s.locale)
+        ));
+    }
+    out.push_str(&format!("      <InputLocale>{}</InputLocale>\n", xml_escape(keyboard)));
+    out.push_str(&format!("      <SystemLocale>{}</SystemLocale>\n", xml_escape(&s.locale)));
+    out.push_str(&format!("      <UILanguage>{}</UILanguage>\n", xml_escape(&s.locale)));
+    out.push_str(&format!("      <UserLocale>{}</UserLocale>\n", xml_escape(&s.locale)));
+    out
+}
+
+/// Wipe disk 0 and lay it out for Windows; returns the markup and the
+/// partition Windows goes to
+fn disk_configuration(uefi: bool) -> (String, u32) {
+    let mut out = String::from(
+        "      <DiskConfiguration>\n        <Disk wcm:action=\"add\">\n          <DiskID>0</DiskID>\n          <WillWipeDisk>true</WillWipeDisk>\n          <CreatePartitions>\n",
+    );
+    let create = |order: u32, kind: &str, size: Option<u32>| {
+        let size = match size {
+            Some(mb) => format!("<Size>{}</Size>", mb),
+            None => "<Extend>true</Extend>".into(),
+        };
+        format!(
+            "            <CreatePartition wcm:action=\"add\"><Order>{}</Order><Type>{}</Type>{}</CreatePartition>\n",
+            order, kind, size
+        )
+    };
+    let modify = |order: u32, id: u32, extra: &str| {
+        format!(
+            "            <ModifyPartition wcm:action=\"add\"><Order>{}</Order><PartitionID>{}</PartitionID>{}</ModifyPartition>\n",
+            order, id, extra
+        )
+    };
+    let windows_partition = if uefi {
+        out.push_str(&create(1, "EFI", Some(260)));
+        out.push_str(&create(2, "MSR", Some(16)));
+        out.push_str(&create(3, "Primary", None));
+        out.push_str("          </CreatePartitions>\n          <ModifyPartitions>\n");
+        out.push_str(&modify(1, 1, "<Format>FAT32</Format><Label>System</Label>"));
+        out.push_str(&modify(2, 3, "<Format>NTFS</Format><Label>Windows</Label><Letter>C</Letter>"));
+        3
+    } else {
+        out.push_str(&create(1, "Primary", Some(500)));
+        out.push_str(&create(2, "Primary", None));
+        out.push_str("          </CreatePartitions>\n          <ModifyPartitions>\n");
+        out.push_str(&modify(1, 1, "<Active>true</Active><Format>NTFS</Format><Label>System Reserved</Label>"));
+        out.push_str(&modify(2, 2, "<Format>NTFS</Format><Label>Windows</Label><Letter>C</Letter>"));
+        2
+    };
+    out.push_str("          </ModifyPartitions>\n        </Disk>\n      </DiskConfiguration>\n");
+    (out, windows_partition)
+}
+
+/// Render autounattend.xml: Windows PE picks up the virtio drivers, wipes
+/// disk 0 and installs the chosen image; specialize names the computer;
+/// oobeSystem skips every OOBE page, logs the administrator on once and
+/// installs the virtio-win guest tools (drivers + guest agent).
+pub fn generate(p: &UnattendParams) -> String {
+    let s = p.settings;
+    let arch = if p.is_aarch64 { "arm64" } else { "amd64" };
+    let driver_arch = if p.is_aarch64 { "ARM64" } else { "amd64" };
+    let driver_os = if s.driver_os.is_empty() { "w11" } else { s.driver_os.as_str() };
+    let bypass = s.bypass_checks == "1";
+
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<unattend xmlns=\"urn:schemas-microsoft-com:unattend\" xmlns:wcm=\"http://schemas.microsoft.com/WMIConfig/2002/State\">\n",
+    );
+
+    // ── windowsPE ──
+    xml.push_str("  <settings pass=\"windowsPE\">\n");
+    xml.push_str(&component("Microsoft-Windows-International-Core-WinPE", arch, &international(s, true)));
+
+    let mut paths = String::from("      <DriverPaths>\n");
+    let mut key = 0;
+    for letter in DRIVE_LETTERS {
+        for driver in WINPE_DRIVERS {
+            key += 1;
+            paths.push_str(&format!(
+                "        <PathAndCredentials wcm:action=\"add\" wcm:keyValue=\"{}\"><Path>{}:\\{}\\{}\\{}</Path></PathAndCredentials>\n",
+                key, letter, driver, xml_escape(driver_os), driver_arch
+            ));
+        }
+    }
+    paths.push_str("      </DriverPaths>\n");
+    xml.push_str(&component("Microsoft-Windows-PnpCustomizationsWinPE", arch, &paths));
+
+    let mut setup = String::new();
+    if bypass {
+        let cmds: Vec<String> = BYPASS_KEYS
+            .iter()
+            .map(|k| format!("reg add HKLM\\SYSTEM\\Setup\\LabConfig /v {} /t REG_DWORD /d 1 /f", k))
+            .collect();
+        setup.push_str(&run_synchronous(&cmds));
+    }
+    let (disks, windows_partition) = disk_configuration(p.uefi);
+    setup.push_str(&disks);
+    let (image_key, image_value) = if s.image_name.is_empty() {
+        ("/IMAGE/INDEX", "1".to_string())
+    } else {
+        ("/IMAGE/NAME", xml_escape(&s.image_name))
+    };
+    setup.push_str(&format!(
+        "      <ImageInstall>\n        <OSImage>\n          <InstallTo><DiskID>0</DiskID><PartitionID>{}</PartitionID></InstallTo>\n          <InstallFrom><MetaData wcm:action=\"add\"><Key>{}</Key><Value>{}</Value></MetaData></InstallFrom>\n        </OSImage>\n      </ImageInstall>\n",
+        windows_partition, image_key, image_value
+    ));
+    setup.push_str(&format!(
+        "      <UserData>\n        <AcceptEula>true</AcceptEula>\n        <ProductKey><Key>{}</Key><WillShowUI>Never</WillShowUI></ProductKey>\n      </UserData>\n",
+        xml_escape(&s.product_key)
+    ));
+    xml.push_str(&component("Microsoft-Windows-Setup", arch, &setup));
+    xml.push_str("  </settings>\n");
+
+    // ── specialize ──
+    xml.push_str("  <settings pass=\"specialize\">\n");
+    let mut shell = String::new();
+    if !p.computer_name.is_empty() {
+        shell.push_str(&format!("      <ComputerName>{}</ComputerName>\n", xml_escape(p.computer_name)));
+    }
+    shell.push_str(&format!("      <TimeZone>{}</TimeZone>\n", xml_escape(&s.timezone)));
+    xml.push_str(&component("Microsoft-Windows-Shell-Setup", arch, &shell));
+    if bypass {
+        // Local account setup without a network (Windows 11 OOBE)
+        let nro = vec![
+            "reg add HKLM\\SOFTWARE\\Microsoft\\Windows\\CurrentVersion\\OOBE /v BypassNRO /t REG_DWORD /d 1 /f".to_string(),
+        ];
+        xml.push_str(&component("Microsoft-Windows-Deployment", arch, &run_synchronous(&nro)));
+    }
+    xml.push_str("  </settings>\n");
+
+    // ── oobeSystem ──
+    xml.push_str("  <settings pass=\"oobeSystem\">\n");
+    xml.push_str(&component("Microsoft-Windows-International-Core", arch, &international(s, false)));
+    let password = xml_escape(p.admin_password);
+    // virtio-win-guest-tools.exe is an x64 installer; ARM64 guests only get
+    // the agent MSI, when the virtio-win ISO ships one
+    let agent_msi = if p.is_aarch64 { "qemu-ga-aarch64.msi" } else { "qemu-ga-x86_64.msi" };
+    let install_agent = format!(
+        "$m = $r | ForEach-Object {{ Join-Path $_ 'guest-agent\\{}' }} | Where-Object {{ Test-Path $_ }} | Select-Object -First 1; \
+        if ($m) {{ Start-Process msiexec.exe -ArgumentList '/i',$m,'/qn' -Wait }}",
+        agent_msi
+    );
+    let install = if p.is_aarch64 {
+        install_agent
+    } else {
+        format!(
+            "$t = $r | ForEach-Object {{ Join-Path $_ 'virtio-win-guest-tools.exe' }} | Where-Object {{ Test-Path $_ }} | Select-Object -First 1; \
+            if ($t) {{ Start-Process $t -ArgumentList '/install','/quiet','/norestart' -Wait }} else {{ {} }}",
+            install_agent
+        )
+    };
+    let tools = format!(
+        "powershell -NoProfile -ExecutionPolicy Bypass -Command \"\
+        $r = Get-PSDrive -PSProvider FileSystem | ForEach-Object {{ $_.Root }}; {}\"",
+        install
+    );
+    let first_logon = [
+        ("net user Administrator /active:yes", "Enable the administrator account"),
+        (tools.as_str(), "Install virtio-win guest tools and guest agent"),
+    ];
+    let mut oobe = String::from(
+        "      <OOBE>\n        <HideEULAPage>true</HideEULAPage>\n        <HideLocalAccountScreen>true</HideLocalAccountScreen>\n        <HideOEMRegistrationScreen>true</HideOEMRegistrationScreen>\n        <HideOnlineAccountScreens>true</HideOnlineAccountScreens>\n        <HideWirelessSetupInOOBE>true</HideWirelessSetupInOOBE>\n        <ProtectYourPC>3</ProtectYourPC>\n      </OOBE>\n",
+    );
+    oobe.push_str(&format!(
+        "      <UserAccounts>\n        <AdministratorPassword><Value>{}</Value><PlainText>true</PlainText></AdministratorPassword>\n      </UserAccounts>\n",
+        password
+    ));
+    oobe.push_str(&format!(
+        "      <AutoLogon>\n        <Enabled>true</Enabled>\n        <Username>Administrator</Username>\n        <Password><Value>{}</Value><PlainText>true</PlainText></Password>\n        <LogonCount>1</LogonCount>\n      </AutoLogon>\n",
+        password
+    ));
+    oobe.push_str("      <FirstLogonCommands>\n");
+    for (i, (cmd, desc)) in first_logon.iter().enumerate() {
+        oobe.push_str(&format!(
+            "        <SynchronousCommand wcm:action=\"add\">\n          <Order>{}</Order>\n          <CommandLine>{}</CommandLine>\n          <Description>{}</Description>\n        </SynchronousCommand>\n",
+            i + 1,
+            xml_escape(cmd),
+            desc
+        ));
+    }
+    oobe.push_str("      </FirstLogonCommands>\n");
+    oobe.push_str(&format!("      <TimeZone>{}</TimeZone>\n", xml_escape(&s.timezone)));
+    xml.push_str(&component("Microsoft-Windows-Shell-Setup", arch, &oobe));
+    xml.push_str("  </settings>\n</unattend>\n");
+    xml
+}
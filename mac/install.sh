@@ -69,12 +69,6 @@ if [[ ! -x "$QEMU_IMG_PATH" ]]; then
     warn "qemu-img not found at $QEMU_IMG_PATH (installed with QEMU)"
 fi
 
-# websockify (optional)
-if ! command -v websockify &>/dev/null; then
-    warn "websockify not found (optional, needed for VNC proxy)"
-    echo "       Install: pip3 install websockify"
-fi
-
 echo ""
 
 # --- Step 1b: Stop old processes before build ---
@@ -178,7 +172,6 @@ disk_path: /opt/ctl/data/disks
 iso_path: /opt/ctl/data/iso
 live_path: /opt/ctl/data/backups
 gzip_path: /usr/bin/gzip
-websockify_path: websockify
 vs_up_script: vs-up.sh
 vs_down_script: vs-down.sh
 pctl_script: pctl.sh
@@ -0,0 +1,287 @@
+use crate::config::{get_conf, get_conf_or};
+use crate::models::VmStartConfig;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Controllers vm_ctl tries to enable for VM cgroups
+const CONTROLLERS: [&str; 4] = ["cpu", "memory", "io", "pids"];
+
+static BASE_DIR: OnceLock<Result<PathBuf, String>> = OnceLock::new();
+
+/// cgroup v2 placement is on for Linux hosts with a unified hierarchy,
+/// unless `cgroups: false` is set in config.yaml
+pub fn available() -> bool {
+    cfg!(target_os = "linux")
+        && get_conf_or("cgroups", "true") != "false"
+        && Path::new("/sys/fs/cgroup/cgroup.controllers").exists()
+}
+
+/// Parent cgroup for all VM cgroups.
+/// `cgroup_root` from config.yaml wins; otherwise vm_ctl uses its own
+/// (delegated) cgroup and moves itself into a `manager` leaf, since cgroup v2
+/// only lets a cgroup without member processes enable controllers for children.
+fn base_dir() -> Result<PathBuf, String> {
+    BASE_DIR
+        .get_or_init(|| {
+            let configured = get_conf_or("cgroup_root", "");
+            let base = if !configured.is_empty() {
+                let base = PathBuf::from(configured);
+                std::fs::create_dir_all(&base)
+                    .map_err(|e| format!("Create {}: {}", base.display(), e))?;
+                base
+            } else {
+                let own = std::fs::read_to_string("/proc/self/cgroup")
+                    .map_err(|e| format!("Read /proc/self/cgroup: {}", e))?;
+                let rel = own
+                    .lines()
+                    .find_map(|l| l.strip_prefix("0::"))
+                    .ok_or("No cgroup v2 entry in /proc/self/cgroup")?
+                    .trim()
+                    .to_string();
+                let own_dir = PathBuf::from(format!("/sys/fs/cgroup{}", rel));
+                if own_dir.file_name().and_then(|n| n.to_str()) == Some("manager") {
+                    own_dir.parent().map(|p| p.to_path_buf()).unwrap_or(own_dir)
+                } else {
+                    let manager = own_dir.join("manager");
+                    std::fs::create_dir_all(&manager)
+                        .map_err(|e| format!("Create {}: {}", manager.display(), e))?;
+                    std::fs::write(manager.join("cgroup.procs"), std::process::id().to_string())
+                        .map_err(|e| format!("Move vm_ctl into {}: {}", manager.display(), e))?;
+                    own_dir
+                }
+            };
+            // Enable what the parent offers; a missing controller only
+            // disables the matching limits, it is not fatal
+            let offered = std::fs::read_to_string(base.join("cgroup.controllers")).unwrap_or_default();
+            for c in CONTROLLERS {
+                if offered.split_whitespace().any(|o| o == c) {
+                    let _ = std::fs::write(base.join("cgroup.subtree_control"), format!("+{}", c));
+                }
+            }
+            Ok(base)
+        })
+        .clone()
+}
+
+/// cgroup directory of one VM
+pub fn vm_cgroup_path(smac: &str) -> Result<PathBuf, String> {
+    Ok(base_dir()?.join(format!("vm-{}", smac)))
+}
+
+/// Block device (MAJ:MIN) holding `path`, resolved to the whole disk since
+/// io.max does not accept partitions
+#[cfg(unix)]
+fn block_device_of(path: &str) -> Option<String> {
+    use std::os::unix::fs::MetadataExt;
+    let dev = std::fs::metadata(path).ok()?.dev();
+    let major = ((dev >> 8) & 0xfff) | ((dev >> 32) & !0xfff);
+    let minor = (dev & 0xff) | ((dev >> 12) & !0xff);
+    let sys = PathBuf::from(format!("/sys/dev/block/{}:{}", major, minor));
+    if !sys.exists() {
+        return None;
+    }
+    if sys.join("partition").exists() {
+        let parent = std::fs::read_to_string(sys.join("../dev")).ok()?;
+        return Some(parent.trim().to_string());
+    }
+    Some(format!("{}:{}", major, minor))
+}
+
+#[cfg(not(unix))]
+fn block_device_of(_path: &str) -> Option<String> {
+    None
+}
+
+fn write_limit(dir: &Path, file: &str, value: &str, output_log: &mut String) {
+    match std::fs::write(dir.join(file), value) {
+        Ok(_) => output_log.push_str(&format!("cgroup: {} = {}\n", file, value)),
+        Err(e) => output_log.push_str(&format!("WARNING: cgroup {} = {} failed: {}\n", file, value, e)),
+    }
+}
+
+/// Create (or recreate) the VM's cgroup and write its limits.
+/// QEMU joins it at exec time (see `wrap_command`) so all of its memory is charged here.
+pub fn prepare(smac: &str, cfg: &VmStartConfig, output_log: &mut String) -> Result<PathBuf, String> {
+    let dir = vm_cgroup_path(smac)?;
+    if dir.exists() {
+        // Leftover from a crashed run — only removable once empty
+        std::fs::remove_dir(&dir)
+            .map_err(|e| format!("Stale cgroup {} still in use: {}", dir.display(), e))?;
+    }
+    std::fs::create_dir(&dir).map_err(|e| format!("Create {}: {}", dir.display(), e))?;
+    output_log.push_str(&format!("cgroup: {}\n", dir.display()));
+
+    let limits = &cfg.limits;
+    let weight: u32 = limits.cpu_weight.parse().unwrap_or(0);
+    if weight > 0 {
+        write_limit(&dir, "cpu.weight", &weight.min(10000).to_string(), output_log);
+    }
+    // Quota in percent of one host CPU over a 100 ms period
+    let quota: u64 = limits.cpu_quota.parse().unwrap_or(0);
+    if quota > 0 {
+        write_limit(&dir, "cpu.max", &format!("{} 100000", quota * 1000), output_log);
+    }
+
+    let mem_max = match limits.memory_max.as_str() {
+        "0" | "" => None,
+        "auto" => {
+            let size: u64 = cfg.memory.size.parse().unwrap_or(0);
+            let max_size: u64 = cfg.memory.max_size.parse().unwrap_or(0);
+            let guest = size.max(max_size);
+            let overhead = match limits.memory_overhead.parse::<u64>().unwrap_or(0) {
+                0 => (guest / 16).max(256),
+                n => n,
+            };
+            // hugetlbfs pages are charged to the hugetlb controller, not memory
+            if cfg.memory.hugepages == "1" {
+                Some(overhead)
+            } else {
+                Some(guest + overhead)
+            }
+        }
+        mb => Some(mb.parse::<u64>().map_err(|_| format!("Invalid limits.memory_max '{}'", mb))?),
+    };
+    if let Some(mb) = mem_max {
+        write_limit(&dir, "memory.max", &(mb * 1024 * 1024).to_string(), output_log);
+    }
+
+    let io: Vec<String> = [
+        ("rbps", &limits.io_read_bps),
+        ("wbps", &limits.io_write_bps),
+        ("riops", &limits.io_read_iops),
+        ("wiops", &limits.io_write_iops),
+    ]
+    .iter()
+    .filter_map(|(k, v)| v.parse::<u64>().ok().filter(|n| *n > 0).map(|n| format!("{}={}", k, n)))
+    .collect();
+    if !io.is_empty() {
+        let disk_path = get_conf("disk_path");
+        match block_device_of(&disk_path) {
+            Some(dev) => write_limit(&dir, "io.max", &format!("{} {}", dev, io.join(" ")), output_log),
+            None => output_log.push_str(&format!(
+                "WARNING: io limits skipped — no block device found for {}\n",
+                disk_path
+            )),
+        }
+    }
+    Ok(dir)
+}
+
+/// Wrap a command so the process joins `dir` before exec'ing `program`.
+/// The shell's PID becomes the program's PID, so nothing runs outside the cgroup.
+pub fn wrap_command(dir: &Path, program: &str, args: &[String]) -> (String, Vec<String>) {
+    let mut wrapped = vec![
+        "-c".to_string(),
+        "echo $$ > \"$0/cgroup.procs\" && exec \"$@\"".to_string(),
+        dir.display().to_string(),
+        program.to_string(),
+    ];
+    wrapped.extend(args.iter().cloned());
+    ("/bin/sh".to_string(), wrapped)
+}
+
+/// Remove a VM's cgroup once QEMU has exited. Best effort — a populated
+/// cgroup is left for `reap_empty`.
+pub fn remove(smac: &str) {
+    if !available() {
+        return;
+    }
+    if let Ok(dir) = vm_cgroup_path(smac) {
+        if dir.exists() && std::fs::remove_dir(&dir).is_ok() {
+            eprintln!("cgroup: removed {}", dir.display());
+        }
+    }
+}
+
+/// Remove every VM cgroup whose processes have all exited
+pub fn reap_empty() {
+    if !available() {
+        return;
+    }
+    let base = match base_dir() {
+        Ok(b) => b,
+        Err(_) => return,
+    };
+    let entries = match std::fs::read_dir(&base) {
+        Ok(e) => e,
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if !name.starts_with("vm-") {
+            continue;
+        }
+        let events = std::fs::read_to_string(entry.path().join("cgroup.events")).unwrap_or_default();
+        if events.lines().any(|l| l == "populated 0") && std::fs::remove_dir(entry.path()).is_ok() {
+            eprintln!("cgroup: reaped {}", name);
+        }
+    }
+}
+
+/// Parse a flat-keyed cgroup file ("key value" per line) into JSON numbers
+fn read_keyed(dir: &Path, file: &str, keys: &[&str]) -> serde_json::Value {
+    let content = std::fs::read_to_string(dir.join(file)).unwrap_or_default();
+    let mut map = serde_json::Map::new();
+    for line in content.lines() {
+        let mut parts = line.split_whitespace();
+        if let (Some(k), Some(v)) = (parts.next(), parts.next()) {
+            if keys.contains(&k) {
+                map.insert(k.to_string(), serde_json::json!(v.parse::<u64>().unwrap_or(0)));
+            }
+        }
+    }
+    serde_json::Value::Object(map)
+}
+
+fn read_value(dir: &Path, file: &str) -> serde_json::Value {
+    match std::fs::read_to_string(dir.join(file)) {
+        Ok(v) => {
+            let v = v.trim();
+            v.parse::<u64>().map(|n| serde_json::json!(n)).unwrap_or_else(|_| serde_json::json!(v))
+        }
+        Err(_) => serde_json::Value::Null,
+    }
+}
+
+/// CPU / memory / IO / pids counters of a VM's cgroup
+pub fn stats(smac: &str) -> Result<serde_json::Value, String> {
+    if !available() {
+        return Err("cgroup v2 is not available on this host".into());
+    }
+    let dir = vm_cgroup_path(smac)?;
+    if !dir.exists() {
+        return Err(format!("VM '{}' has no cgroup (not running, or cgroups disabled)", smac));
+    }
+    let io: Vec<serde_json::Value> = std::fs::read_to_string(dir.join("io.stat"))
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            let device = parts.next()?;
+            let mut entry = serde_json::json!({ "device": device });
+            for kv in parts {
+                if let Some((k, v)) = kv.split_once('=') {
+                    entry[k] = serde_json::json!(v.parse::<u64>().unwrap_or(0));
+                }
+            }
+            Some(entry)
+        })
+        .collect();
+
+    Ok(serde_json::json!({
+        "cgroup": dir.display().to_string(),
+        "cpu": read_keyed(&dir, "cpu.stat", &["usage_usec", "user_usec", "system_usec", "nr_throttled", "throttled_usec"]),
+        "cpu_max": read_value(&dir, "cpu.max"),
+        "cpu_weight": read_value(&dir, "cpu.weight"),
+        "memory": {
+            "current": read_value(&dir, "memory.current"),
+            "peak": read_value(&dir, "memory.peak"),
+            "max": read_value(&dir, "memory.max"),
+            "stat": read_keyed(&dir, "memory.stat", &["anon", "file", "kernel", "shmem", "pgmajfault"]),
+            "events": read_keyed(&dir, "memory.events", &["high", "max", "oom", "oom_kill"]),
+        },
+        "io": io,
+        "io_max": read_value(&dir, "io.max"),
+        "pids": read_value(&dir, "pids.current"),
+    }))
+}
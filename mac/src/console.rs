@@ -0,0 +1,206 @@
+use crate::config::{get_conf, get_conf_or};
+use std::collections::{HashMap, VecDeque};
+use std::io::{Read, Write};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use tokio::sync::mpsc::{channel, Receiver, Sender};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+#[cfg(windows)]
+use uds_windows::UnixStream;
+
+/// One live connection to a VM's serial chardev, shared by all console clients.
+/// QEMU's socket chardev accepts a single client, so vm_ctl holds it and fans
+/// the output out; input from any client goes straight to the guest.
+pub struct ConsoleHub {
+    writer: Mutex<UnixStream>,
+    state: Mutex<HubState>,
+}
+
+struct HubState {
+    scrollback: VecDeque<u8>,
+    subscribers: Vec<Sender<Vec<u8>>>,
+    log: Option<std::fs::File>,
+    log_bytes: u64,
+}
+
+/// Output chunks (up to 4 KB each) queued per client. A client that falls
+/// this far behind is dropped and can reconnect for the scrollback.
+const SUBSCRIBER_QUEUE: usize = 256;
+
+static HUBS: OnceLock<Mutex<HashMap<String, Arc<ConsoleHub>>>> = OnceLock::new();
+
+fn hubs() -> &'static Mutex<HashMap<String, Arc<ConsoleHub>>> {
+    HUBS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Path of the per-VM serial socket (created by `-chardev socket,id=serial0` at start)
+pub fn serial_socket_path(smac: &str) -> String {
+    format!("{}/{}_serial", get_conf("pctl_path"), smac)
+}
+
+/// Scrollback log kept next to the VM's other runtime files
+pub fn log_path(smac: &str) -> String {
+    format!("{}/{}_console.log", get_conf("pctl_path"), smac)
+}
+
+/// Scrollback size in bytes (`console_scrollback_kb`, default 256 KB)
+fn scrollback_limit() -> usize {
+    get_conf_or("console_scrollback_kb", "256")
+        .parse::<usize>()
+        .unwrap_or(256)
+        .max(16)
+        * 1024
+}
+
+/// Last `limit` bytes of the on-disk log, used to seed a new hub so history
+/// survives a vm_ctl restart
+fn read_log_tail(path: &str, limit: usize) -> Vec<u8> {
+    let data = std::fs::read(path).unwrap_or_default();
+    data[data.len().saturating_sub(limit)..].to_vec()
+}
+
+impl ConsoleHub {
+    /// Current scrollback plus a receiver for everything after it.
+    /// Taken under one lock so no output falls between the two.
+    pub fn subscribe(&self) -> (Vec<u8>, Receiver<Vec<u8>>) {
+        let (tx, rx) = channel(SUBSCRIBER_QUEUE);
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let scrollback: Vec<u8> = state.scrollback.iter().copied().collect();
+        state.subscribers.push(tx);
+        (scrollback, rx)
+    }
+
+    /// Send keyboard input to the guest's serial port. Blocks while the
+    /// socket is full; call it off the async runtime.
+    pub fn write(&self, data: &[u8]) -> Result<(), String> {
+        let mut w = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        w.write_all(data).map_err(|e| format!("Serial write failed: {}", e))
+    }
+
+    fn push_output(&self, smac: &str, data: &[u8]) {
+        let limit = scrollback_limit();
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.scrollback.extend(data);
+        let excess = state.scrollback.len().saturating_sub(limit);
+        state.scrollback.drain(..excess);
+
+        // The file is append-only until it reaches twice the ring size, then
+        // rewritten from the ring — bounded on disk without a write per trim
+        state.log_bytes += data.len() as u64;
+        if state.log_bytes > (limit * 2) as u64 {
+            let snapshot: Vec<u8> = state.scrollback.iter().copied().collect();
+            let path = log_path(smac);
+            let _ = std::fs::write(&path, &snapshot);
+            state.log = std::fs::OpenOptions::new().append(true).open(&path).ok();
+            state.log_bytes = snapshot.len() as u64;
+        } else if let Some(f) = state.log.as_mut() {
+            let _ = f.write_all(data);
+        }
+
+        state.subscribers.retain(|tx| tx.try_send(data.to_vec()).is_ok());
+    }
+}
+
+/// Connect to the VM's serial socket, retrying while QEMU starts up
+fn connect(smac: &str, attempts: u32) -> Result<UnixStream, String> {
+    let path = serial_socket_path(smac);
+    let mut last_err = String::new();
+    for i in 0..attempts {
+        if i > 0 {
+            std::thread::sleep(Duration::from_millis(250));
+        }
+        match UnixStream::connect(&path) {
+            Ok(s) => return Ok(s),
+            Err(e) => last_err = e.to_string(),
+        }
+    }
+    Err(format!("Serial console not available ({}): {}", path, last_err))
+}
+
+/// Get the VM's console hub, connecting to QEMU if nobody holds the socket yet
+pub fn attach(smac: &str) -> Result<Arc<ConsoleHub>, String> {
+    if let Some(hub) = hubs().lock().unwrap_or_else(|e| e.into_inner()).get(smac) {
+        return Ok(hub.clone());
+    }
+    let stream = connect(smac, 1)?;
+    register(smac, stream)
+}
+
+fn register(smac: &str, stream: UnixStream) -> Result<Arc<ConsoleHub>, String> {
+    let mut map = hubs().lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(hub) = map.get(smac) {
+        return Ok(hub.clone());
+    }
+    let mut reader = stream
+        .try_clone()
+        .map_err(|e| format!("Serial socket clone failed: {}", e))?;
+    let path = log_path(smac);
+    let scrollback: VecDeque<u8> = read_log_tail(&path, scrollback_limit()).into();
+    let log_bytes = std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+    let hub = Arc::new(ConsoleHub {
+        writer: Mutex::new(stream),
+        state: Mutex::new(HubState {
+            scrollback,
+            subscribers: Vec::new(),
+            log: std::fs::OpenOptions::new().create(true).append(true).open(&path).ok(),
+            log_bytes,
+        }),
+    });
+    map.insert(smac.to_string(), hub.clone());
+
+    let smac_owned = smac.to_string();
+    let hub_for_thread = hub.clone();
+    std::thread::spawn(move || {
+        let mut buf = [0u8; 4096];
+        loop {
+            match reader.read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => hub_for_thread.push_output(&smac_owned, &buf[..n]),
+            }
+        }
+        // QEMU exited or detach() shut the socket: drop the hub (only if it is
+        // still ours) so subscribers see end-of-stream
+        let mut map = hubs().lock().unwrap_or_else(|e| e.into_inner());
+        if map.get(&smac_owned).is_some_and(|h| Arc::ptr_eq(h, &hub_for_thread)) {
+            map.remove(&smac_owned);
+        }
+        drop(map);
+        hub_for_thread
+            .state
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .subscribers
+            .clear();
+    });
+    Ok(hub)
+}
+
+/// Called right after QEMU is spawned: start a fresh log for this boot and
+/// capture output from the first byte (firmware, bootloader, kernel)
+pub fn start_capture(smac: &str) {
+    let _ = std::fs::write(log_path(smac), b"");
+    let smac = smac.to_string();
+    std::thread::spawn(move || {
+        if let Err(e) = connect(&smac, 40).and_then(|stream| register(&smac, stream)) {
+            eprintln!("console {}: {}", smac, e);
+        }
+    });
+}
+
+/// Disconnect from the serial socket (VM stop); the reader thread cleans up
+pub fn detach(smac: &str) {
+    if let Some(hub) = hubs().lock().unwrap_or_else(|e| e.into_inner()).remove(smac) {
+        let w = hub.writer.lock().unwrap_or_else(|e| e.into_inner());
+        let _ = w.shutdown(std::net::Shutdown::Both);
+    }
+}
+
+/// Saved console output (last boot), for reading without a live session
+pub fn read_log(smac: &str) -> Result<String, String> {
+    let path = log_path(smac);
+    if !std::path::Path::new(&path).exists() {
+        return Err(format!("No console log for VM '{}'", smac));
+    }
+    Ok(String::from_utf8_lossy(&read_log_tail(&path, scrollback_limit())).to_string())
+}
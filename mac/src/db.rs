@@ -178,6 +178,15 @@ fn run_migrations(conn: &Connection) -> Result<(), String> {
     )
     .map_err(|e| format!("DB os_templates table init error: {}", e))?;
 
+    // Migration: add unattend column to os_templates if not exists
+    let has_unattend: bool = conn
+        .prepare("SELECT unattend FROM os_templates LIMIT 0")
+        .is_ok();
+    if !has_unattend {
+        conn.execute_batch("ALTER TABLE os_templates ADD COLUMN unattend TEXT NOT NULL DEFAULT '0';")
+            .map_err(|e| format!("DB migrate unattend error: {}", e))?;
+    }
+
     conn.execute_batch(
         "CREATE TABLE IF NOT EXISTS backups (
             id INTEGER PRIMARY KEY AUTOINCREMENT,
@@ -213,6 +222,28 @@ fn run_migrations(conn: &Connection) -> Result<(), String> {
     )
     .map_err(|e| format!("DB settings table init error: {}", e))?;
 
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS guest_info (
+            smac TEXT PRIMARY KEY,
+            guest_ips TEXT NOT NULL DEFAULT '[]',
+            os_name TEXT NOT NULL DEFAULT '',
+            kernel TEXT NOT NULL DEFAULT '',
+            agent_version TEXT NOT NULL DEFAULT '',
+            updated_at TEXT NOT NULL DEFAULT (datetime('now'))
+        );",
+    )
+    .map_err(|e| format!("DB guest_info table init error: {}", e))?;
+
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS guest_provision (
+            smac TEXT PRIMARY KEY,
+            state TEXT NOT NULL DEFAULT '',
+            message TEXT NOT NULL DEFAULT '',
+            updated_at TEXT NOT NULL DEFAULT (datetime('now'))
+        );",
+    )
+    .map_err(|e| format!("DB guest_provision table init error: {}", e))?;
+
     Ok(())
 }
 
@@ -304,6 +335,10 @@ pub fn delete_vm(smac: &str) -> Result<(), String> {
     let conn = open_db()?;
     conn.execute("DELETE FROM vms WHERE smac = ?1", params![smac])
         .map_err(|e| format!("DB delete error: {}", e))?;
+    conn.execute("DELETE FROM guest_info WHERE smac = ?1", params![smac])
+        .map_err(|e| format!("DB delete guest info error: {}", e))?;
+    conn.execute("DELETE FROM guest_provision WHERE smac = ?1", params![smac])
+        .map_err(|e| format!("DB delete guest provision error: {}", e))?;
     Ok(())
 }
 
@@ -384,6 +419,14 @@ pub fn rename_vm(old_smac: &str, new_smac: &str) -> Result<(), String> {
             "UPDATE disks SET owner = ?2 WHERE owner = ?1",
             params![old_smac, new_smac],
         ).map_err(|e| format!("DB rename disk owner error: {}", e))?;
+        conn.execute(
+            "UPDATE guest_info SET smac = ?2 WHERE smac = ?1",
+            params![old_smac, new_smac],
+        ).map_err(|e| format!("DB rename guest info error: {}", e))?;
+        conn.execute(
+            "UPDATE guest_provision SET smac = ?2 WHERE smac = ?1",
+            params![old_smac, new_smac],
+        ).map_err(|e| format!("DB rename guest provision error: {}", e))?;
         // Delete old row
         conn.execute(
             "DELETE FROM vms WHERE smac = ?1",
@@ -640,6 +683,113 @@ pub fn rename_switch(id: i64, new_name: &str) -> Result<(), String> {
     Ok(())
 }
 
+// ======== Guest info (reported by the guest agent) ========
+
+#[derive(Debug, Serialize, Clone)]
+pub struct GuestInfoRecord {
+    pub smac: String,
+    pub guest_ips: Vec<String>,
+    pub os_name: String,
+    pub kernel: String,
+    pub agent_version: String,
+    pub updated_at: String,
+}
+
+/// Store the latest guest-agent report for a VM
+pub fn upsert_guest_info(
+    smac: &str,
+    guest_ips: &[String],
+    os_name: &str,
+    kernel: &str,
+    agent_version: &str,
+) -> Result<(), String> {
+    let conn = open_db()?;
+    let ips = serde_json::to_string(guest_ips).unwrap_or_else(|_| "[]".into());
+    conn.execute(
+        "INSERT INTO guest_info (smac, guest_ips, os_name, kernel, agent_version, updated_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, datetime('now'))
+         ON CONFLICT(smac) DO UPDATE SET guest_ips = ?2, os_name = ?3, kernel = ?4,
+             agent_version = ?5, updated_at = datetime('now')",
+        params![smac, ips, os_name, kernel, agent_version],
+    )
+    .map_err(|e| format!("DB upsert guest info error: {}", e))?;
+    Ok(())
+}
+
+/// Forget a VM's guest-agent report (the VM stopped)
+pub fn delete_guest_info(smac: &str) -> Result<(), String> {
+    let conn = open_db()?;
+    conn.execute("DELETE FROM guest_info WHERE smac = ?1", params![smac])
+        .map_err(|e| format!("DB delete guest info error: {}", e))?;
+    Ok(())
+}
+
+/// All cached guest-agent reports
+pub fn list_guest_info() -> Result<Vec<GuestInfoRecord>, String> {
+    let conn = open_db()?;
+    let mut stmt = conn
+        .prepare("SELECT smac, guest_ips, os_name, kernel, agent_version, updated_at FROM guest_info")
+        .map_err(|e| format!("DB query error: {}", e))?;
+    let rows = stmt
+        .query_map([], |row| {
+            let ips: String = row.get(1)?;
+            Ok(GuestInfoRecord {
+                smac: row.get(0)?,
+                guest_ips: serde_json::from_str(&ips).unwrap_or_default(),
+                os_name: row.get(2)?,
+                kernel: row.get(3)?,
+                agent_version: row.get(4)?,
+                updated_at: row.get(5)?,
+            })
+        })
+        .map_err(|e| format!("DB query error: {}", e))?;
+    let mut list = Vec::new();
+    for row in rows {
+        list.push(row.map_err(|e| format!("DB row error: {}", e))?);
+    }
+    Ok(list)
+}
+
+/// Outcome of first-boot provisioning through the guest agent
+#[derive(Debug, Serialize, Clone)]
+pub struct GuestProvisionRecord {
+    pub smac: String,
+    /// "done" or "failed"
+    pub state: String,
+    pub message: String,
+    pub updated_at: String,
+}
+
+pub fn get_guest_provision(smac: &str) -> Result<Option<GuestProvisionRecord>, String> {
+    let conn = open_db()?;
+    let mut stmt = conn
+        .prepare("SELECT smac, state, message, updated_at FROM guest_provision WHERE smac = ?1")
+        .map_err(|e| format!("DB query error: {}", e))?;
+    let result = stmt
+        .query_row(params![smac], |row| {
+            Ok(GuestProvisionRecord {
+                smac: row.get(0)?,
+                state: row.get(1)?,
+                message: row.get(2)?,
+                updated_at: row.get(3)?,
+            })
+        })
+        .ok();
+    Ok(result)
+}
+
+pub fn set_guest_provision(smac: &str, state: &str, message: &str) -> Result<(), String> {
+    let conn = open_db()?;
+    conn.execute(
+        "INSERT INTO guest_provision (smac, state, message, updated_at)
+         VALUES (?1, ?2, ?3, datetime('now'))
+         ON CONFLICT(smac) DO UPDATE SET state = ?2, message = ?3, updated_at = datetime('now')",
+        params![smac, state, message],
+    )
+    .map_err(|e| format!("DB upsert guest provision error: {}", e))?;
+    Ok(())
+}
+
 // ======== DHCP Lease operations ========
 
 #[derive(Debug, Serialize, Clone)]
@@ -789,12 +939,16 @@ pub struct OsTemplate {
     pub is_windows: String,
     pub arch: String,
     pub image: String,
+    /// "1" preselects an unattended Windows install (autounattend.xml ISO);
+    /// "ubuntu", "debian" or "rhel" an unattended Linux install of that flavor
+    #[serde(default)]
+    pub unattend: String,
 }
 
 pub fn list_os_templates() -> Result<Vec<OsTemplate>, String> {
     let conn = open_db()?;
     let mut stmt = conn
-        .prepare("SELECT id, key, name, vcpus, memory, is_windows, arch, image FROM os_templates ORDER BY id ASC")
+        .prepare("SELECT id, key, name, vcpus, memory, is_windows, arch, image, unattend FROM os_templates ORDER BY id ASC")
         .map_err(|e| format!("DB query error: {}", e))?;
     let rows = stmt
         .query_map([], |row| {
@@ -807,6 +961,7 @@ pub fn list_os_templates() -> Result<Vec<OsTemplate>, String> {
                 is_windows: row.get(5)?,
                 arch: row.get(6)?,
                 image: row.get(7)?,
+                unattend: row.get(8)?,
             })
         })
         .map_err(|e| format!("DB query error: {}", e))?;
@@ -817,25 +972,26 @@ pub fn list_os_templates() -> Result<Vec<OsTemplate>, String> {
     Ok(result)
 }
 
-pub fn create_os_template(key: &str, name: &str, vcpus: &str, memory: &str, is_windows: &str, arch: &str, image: &str) -> Result<i64, String> {
+/// Insert a template (`t.id` is ignored) and return its id
+pub fn create_os_template(t: &OsTemplate) -> Result<i64, String> {
     let conn = open_db()?;
     conn.execute(
-        "INSERT INTO os_templates (key, name, vcpus, memory, is_windows, arch, image) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
-        params![key, name, vcpus, memory, is_windows, arch, image],
+        "INSERT INTO os_templates (key, name, vcpus, memory, is_windows, arch, image, unattend) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        params![t.key, t.name, t.vcpus, t.memory, t.is_windows, t.arch, t.image, t.unattend],
     )
     .map_err(|e| format!("DB insert os_template error: {}", e))?;
     Ok(conn.last_insert_rowid())
 }
 
-pub fn update_os_template(id: i64, key: &str, name: &str, vcpus: &str, memory: &str, is_windows: &str, arch: &str, image: &str) -> Result<(), String> {
+pub fn update_os_template(t: &OsTemplate) -> Result<(), String> {
     let conn = open_db()?;
     let changed = conn.execute(
-        "UPDATE os_templates SET key=?2, name=?3, vcpus=?4, memory=?5, is_windows=?6, arch=?7, image=?8 WHERE id=?1",
-        params![id, key, name, vcpus, memory, is_windows, arch, image],
+        "UPDATE os_templates SET key=?2, name=?3, vcpus=?4, memory=?5, is_windows=?6, arch=?7, image=?8, unattend=?9 WHERE id=?1",
+        params![t.id, t.key, t.name, t.vcpus, t.memory, t.is_windows, t.arch, t.image, t.unattend],
     )
     .map_err(|e| format!("DB update os_template error: {}", e))?;
     if changed == 0 {
-        return Err(format!("OS template id {} not found", id));
+        return Err(format!("OS template id {} not found", t.id));
     }
     Ok(())
 }
@@ -1,17 +1,78 @@
 use crate::config::get_conf;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::io::{Read, Write};
+use std::sync::{Condvar, Mutex, OnceLock};
 use std::time::Duration;
 #[cfg(unix)]
 use std::os::unix::net::UnixStream;
 #[cfg(windows)]
 use uds_windows::UnixStream;
 
+/// VMs whose guest agent channel is in use, and a wakeup for waiters
+static QGA_BUSY: OnceLock<(Mutex<HashSet<String>>, Condvar)> = OnceLock::new();
+
+/// How long a caller waits for another user of the same agent channel
+const QGA_LOCK_WAIT: Duration = Duration::from_secs(60);
+
+/// Exclusive use of one VM's agent channel. The virtio-serial port carries a
+/// single byte stream, so interleaved commands would get each other's replies.
+struct QgaLock(String);
+
+impl QgaLock {
+    fn acquire(smac: &str) -> Result<QgaLock, String> {
+        let (busy, cvar) = QGA_BUSY.get_or_init(|| (Mutex::new(HashSet::new()), Condvar::new()));
+        let mut set = busy.lock().unwrap_or_else(|e| e.into_inner());
+        let start = std::time::Instant::now();
+        while set.contains(smac) {
+            let left = QGA_LOCK_WAIT.saturating_sub(start.elapsed());
+            if left.is_zero() {
+                return Err(format!("Guest agent of '{}' is busy with another request", smac));
+            }
+            set = cvar.wait_timeout(set, left).unwrap_or_else(|e| e.into_inner()).0;
+        }
+        set.insert(smac.to_string());
+        Ok(QgaLock(smac.to_string()))
+    }
+}
+
+impl Drop for QgaLock {
+    fn drop(&mut self) {
+        if let Some((busy, cvar)) = QGA_BUSY.get() {
+            busy.lock().unwrap_or_else(|e| e.into_inner()).remove(&self.0);
+            cvar.notify_all();
+        }
+    }
+}
+
 /// Send a QGA command and get the JSON response
 pub fn qga_command(
     smac: &str,
     command: &str,
     args: Option<serde_json::Value>,
 ) -> Result<serde_json::Value, String> {
+    qga_exchange(smac, command, args, Duration::from_secs(10))
+}
+
+/// Send a QGA command that gives no reply on success (`guest-shutdown`).
+/// Only an error the agent sends back right away counts as failure.
+pub fn qga_command_no_reply(smac: &str, command: &str, args: Option<serde_json::Value>) -> Result<(), String> {
+    match qga_exchange(smac, command, args, Duration::from_secs(1)) {
+        Err(e) if e.starts_with("QGA error") => Err(e),
+        // No reply in time, or the guest already closed the channel
+        Err(e) if e.starts_with("QGA response timeout") || e.starts_with("No valid JSON") => Ok(()),
+        Err(e) => Err(e),
+        Ok(_) => Ok(()),
+    }
+}
+
+fn qga_exchange(
+    smac: &str,
+    command: &str,
+    args: Option<serde_json::Value>,
+    max_wait: Duration,
+) -> Result<serde_json::Value, String> {
+    let _lock = QgaLock::acquire(smac)?;
     let pctl_path = get_conf("pctl_path");
     let sock_path = format!("{}/{}_qga", pctl_path, smac);
 
@@ -86,7 +147,6 @@ pub fn qga_command(
     std::thread::sleep(Duration::from_millis(200));
     let mut response = String::new();
     let start = std::time::Instant::now();
-    let max_wait = Duration::from_secs(10);
     loop {
         if start.elapsed() > max_wait {
             return Err(format!(
@@ -100,7 +160,9 @@ pub fn qga_command(
             Ok(n) => {
                 let s = String::from_utf8_lossy(&chunk[..n]);
                 response.push_str(&s);
-                if response.contains("\"return\"") || response.contains("\"error\"") {
+                // Large replies (exec output, file reads) arrive in several
+                // chunks; QGA ends each reply with a newline
+                if response_complete(&response) {
                     break;
                 }
             }
@@ -108,7 +170,7 @@ pub fn qga_command(
                 if e.kind() == std::io::ErrorKind::WouldBlock
                     || e.kind() == std::io::ErrorKind::TimedOut =>
             {
-                if response.contains("\"return\"") || response.contains("\"error\"") {
+                if response_complete(&response) {
                     break;
                 }
                 // No data yet, sleep briefly and retry
@@ -149,89 +211,483 @@ pub fn qga_command(
     Err(format!("No valid JSON in QGA response: {}", clean))
 }
 
+fn response_complete(response: &str) -> bool {
+    (response.contains("\"return\"") || response.contains("\"error\"")) && response.ends_with('\n')
+}
+
+/// One guest-agent connection kept open for a sequence of commands, so
+/// bulk transfers pay for the socket connect and `guest-sync-delimited`
+/// handshake once instead of per chunk. Other agent commands for the VM
+/// wait until the session is dropped.
+pub struct QgaSession {
+    writer: UnixStream,
+    reader: std::io::BufReader<UnixStream>,
+    _lock: QgaLock,
+}
+
+impl QgaSession {
+    pub fn connect(smac: &str) -> Result<QgaSession, String> {
+        crate::ssh::sanitize_name(smac)?;
+        let lock = QgaLock::acquire(smac)?;
+        let sock_path = format!("{}/{}_qga", get_conf("pctl_path"), smac);
+        let stream = UnixStream::connect(&sock_path)
+            .map_err(|e| format!("Guest agent socket connect failed ({}): {}", sock_path, e))?;
+        stream
+            .set_read_timeout(Some(Duration::from_secs(30)))
+            .map_err(|e| format!("Set timeout error: {}", e))?;
+        stream
+            .set_write_timeout(Some(Duration::from_secs(30)))
+            .map_err(|e| format!("Set write timeout error: {}", e))?;
+        let reader = std::io::BufReader::new(
+            stream.try_clone().map_err(|e| format!("Socket clone failed: {}", e))?,
+        );
+        let mut session = QgaSession { writer: stream, reader, _lock: lock };
+
+        // 0xFF resets the agent's parser; replies to anything a previous
+        // client left behind are skipped until our sync id comes back
+        let sync_id: u64 = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u64
+            & 0x7fff_ffff_ffff;
+        session
+            .writer
+            .write_all(&[0xFF])
+            .map_err(|e| format!("Write error: {}", e))?;
+        session.send("guest-sync-delimited", Some(serde_json::json!({ "id": sync_id })))?;
+        loop {
+            let reply = session.read_reply("guest-sync-delimited")?;
+            if reply.get("return").and_then(|r| r.as_u64()) == Some(sync_id) {
+                return Ok(session);
+            }
+        }
+    }
+
+    fn send(&mut self, command: &str, args: Option<serde_json::Value>) -> Result<(), String> {
+        let cmd = match args {
+            Some(a) => serde_json::json!({ "execute": command, "arguments": a }),
+            None => serde_json::json!({ "execute": command }),
+        };
+        self.writer
+            .write_all(format!("{}\n", cmd).as_bytes())
+            .map_err(|e| format!("Write error: {}", e))
+    }
+
+    /// Next JSON reply line, ignoring 0xFF delimiters and blank lines
+    fn read_reply(&mut self, command: &str) -> Result<serde_json::Value, String> {
+        use std::io::BufRead;
+        loop {
+            let mut line = Vec::new();
+            let n = self
+                .reader
+                .read_until(b'\n', &mut line)
+                .map_err(|e| format!("QGA read error during '{}': {}", command, e))?;
+            if n == 0 {
+                return Err(format!("Guest agent closed the connection during '{}'", command));
+            }
+            line.retain(|b| *b != 0xFF);
+            let text = String::from_utf8_lossy(&line);
+            let text = text.trim();
+            if text.is_empty() {
+                continue;
+            }
+            if let Ok(v) = serde_json::from_str::<serde_json::Value>(text) {
+                return Ok(v);
+            }
+        }
+    }
+
+    /// Run a command and return its `return` value
+    pub fn execute(
+        &mut self,
+        command: &str,
+        args: Option<serde_json::Value>,
+    ) -> Result<serde_json::Value, String> {
+        self.send(command, args)?;
+        let reply = self.read_reply(command)?;
+        if let Some(err) = reply.get("error") {
+            let class = err.get("class").and_then(|v| v.as_str()).unwrap_or("Unknown");
+            let desc = err.get("desc").and_then(|v| v.as_str()).unwrap_or("no description");
+            return Err(format!("QGA error ({}): {}", class, desc));
+        }
+        reply
+            .get("return")
+            .cloned()
+            .ok_or_else(|| format!("{}: reply without 'return': {}", command, reply))
+    }
+
+    /// Open a guest file; `mode` as for fopen ("rb", "wb", "r+b", "ab")
+    pub fn file_open(&mut self, path: &str, mode: &str) -> Result<i64, String> {
+        self.execute("guest-file-open", Some(serde_json::json!({ "path": path, "mode": mode })))?
+            .as_i64()
+            .ok_or_else(|| "guest-file-open returned no handle".to_string())
+    }
+
+    pub fn file_close(&mut self, handle: i64) -> Result<(), String> {
+        self.execute("guest-file-close", Some(serde_json::json!({ "handle": handle })))
+            .map(|_| ())
+    }
+
+    /// Seek and return the new position; `whence` is "set", "cur" or "end"
+    pub fn file_seek(&mut self, handle: i64, offset: i64, whence: &str) -> Result<u64, String> {
+        let ret = self.execute(
+            "guest-file-seek",
+            Some(serde_json::json!({ "handle": handle, "offset": offset, "whence": whence })),
+        )?;
+        ret.get("position")
+            .and_then(|v| v.as_u64())
+            .ok_or_else(|| format!("guest-file-seek: unexpected reply {}", ret))
+    }
+
+    /// Write all of `data` at the current position
+    pub fn file_write(&mut self, handle: i64, data: &[u8]) -> Result<(), String> {
+        use base64::Engine;
+        let ret = self.execute(
+            "guest-file-write",
+            Some(serde_json::json!({
+                "handle": handle,
+                "buf-b64": base64::engine::general_purpose::STANDARD.encode(data),
+            })),
+        )?;
+        let written = ret.get("count").and_then(|c| c.as_u64()).unwrap_or(0);
+        if written != data.len() as u64 {
+            return Err(format!("Partial write: expected {} bytes, wrote {}", data.len(), written));
+        }
+        Ok(())
+    }
+
+    /// Read up to `count` bytes; the flag is true at end of file
+    pub fn file_read(&mut self, handle: i64, count: u64) -> Result<(Vec<u8>, bool), String> {
+        use base64::Engine;
+        let ret = self.execute(
+            "guest-file-read",
+            Some(serde_json::json!({ "handle": handle, "count": count })),
+        )?;
+        let data = match ret.get("buf-b64").and_then(|v| v.as_str()) {
+            Some(b) => base64::engine::general_purpose::STANDARD
+                .decode(b)
+                .map_err(|e| format!("Invalid data from guest-file-read: {}", e))?,
+            None => Vec::new(),
+        };
+        let eof = ret.get("eof").and_then(|v| v.as_bool()).unwrap_or(data.is_empty());
+        Ok((data, eof))
+    }
+
+    /// Size of a guest file (open, seek to end, close)
+    pub fn file_size(&mut self, path: &str) -> Result<u64, String> {
+        let handle = self.file_open(path, "rb")?;
+        let size = self.file_seek(handle, 0, "end");
+        let _ = self.file_close(handle);
+        size
+    }
+}
+
 /// Check if guest agent is available (guest-ping)
 pub fn guest_ping(smac: &str) -> bool {
     qga_command(smac, "guest-ping", None).is_ok()
 }
 
 /// Write a file to the guest filesystem via QGA
-/// Sends data in 1MB base64 chunks
+/// Sends data in 1MB base64 chunks over one agent connection
 pub fn guest_file_write(smac: &str, guest_path: &str, data: &[u8]) -> Result<(), String> {
-    use base64::Engine;
-    let b64 = base64::engine::general_purpose::STANDARD;
-
-    // Open file in guest
-    let open_result = qga_command(
-        smac,
-        "guest-file-open",
-        Some(serde_json::json!({
-            "path": guest_path,
-            "mode": "wb"
-        })),
-    )?;
-
-    let handle = open_result
-        .get("return")
-        .and_then(|v| v.as_i64())
-        .ok_or_else(|| format!("guest-file-open failed: {:?}", open_result))?;
+    let mut session = QgaSession::connect(smac)?;
+    let handle = session.file_open(guest_path, "wb")?;
 
     // Write in chunks (1MB raw = ~1.33MB base64)
     const CHUNK_SIZE: usize = 1024 * 1024;
-    let mut offset = 0;
     let mut write_err: Option<String> = None;
+    for (i, chunk) in data.chunks(CHUNK_SIZE).enumerate() {
+        if let Err(e) = session.file_write(handle, chunk) {
+            write_err = Some(format!(
+                "guest-file-write failed at offset {}: {}",
+                i * CHUNK_SIZE,
+                e
+            ));
+            break;
+        }
+    }
 
-    while offset < data.len() {
-        let end = std::cmp::min(offset + CHUNK_SIZE, data.len());
-        let chunk_b64 = b64.encode(&data[offset..end]);
+    // Always close the file handle
+    let _ = session.file_close(handle);
 
-        let write_result = qga_command(
-            smac,
-            "guest-file-write",
-            Some(serde_json::json!({
-                "handle": handle,
-                "buf-b64": chunk_b64
-            })),
-        );
+    if let Some(e) = write_err {
+        return Err(e);
+    }
+
+    Ok(())
+}
+
+/// The `return` member of a QGA reply
+fn qga_return(
+    smac: &str,
+    command: &str,
+    args: Option<serde_json::Value>,
+) -> Result<serde_json::Value, String> {
+    crate::ssh::sanitize_name(smac)?;
+    let resp = qga_command(smac, command, args)?;
+    resp.get("return")
+        .cloned()
+        .ok_or_else(|| format!("{}: reply without 'return': {}", command, resp))
+}
+
+fn qga_typed<T: serde::de::DeserializeOwned>(
+    smac: &str,
+    command: &str,
+    args: Option<serde_json::Value>,
+) -> Result<T, String> {
+    let ret = qga_return(smac, command, args)?;
+    serde_json::from_value(ret).map_err(|e| format!("{}: unexpected reply: {}", command, e))
+}
+
+/// Finished (or still running) `guest-exec` process with decoded output
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct GuestExecResult {
+    pub pid: i64,
+    pub exited: bool,
+    /// Exit code; None while running or when killed by a signal
+    pub exitcode: Option<i64>,
+    pub signal: Option<i64>,
+    pub stdout: String,
+    pub stderr: String,
+    /// QGA caps captured output (16 MB per stream)
+    pub stdout_truncated: bool,
+    pub stderr_truncated: bool,
+    /// true when `timeout` ran out before the process exited
+    pub timed_out: bool,
+}
+
+/// Run a program in the guest (`guest-exec`) with stdout/stderr captured and
+/// poll `guest-exec-status` until it exits or `timeout` runs out. A process
+/// that times out keeps running in the guest; its pid is returned so
+/// `guest_exec_status` can be asked again later.
+pub fn guest_exec(
+    smac: &str,
+    path: &str,
+    args: &[String],
+    env: &[String],
+    input: Option<&[u8]>,
+    timeout: Duration,
+) -> Result<GuestExecResult, String> {
+    use base64::Engine;
+    if path.is_empty() {
+        return Err("Missing program path".into());
+    }
+    let mut exec_args = serde_json::json!({
+        "path": path,
+        "arg": args,
+        "capture-output": true,
+    });
+    if !env.is_empty() {
+        exec_args["env"] = serde_json::json!(env);
+    }
+    if let Some(data) = input {
+        exec_args["input-data"] =
+            serde_json::json!(base64::engine::general_purpose::STANDARD.encode(data));
+    }
+    let pid = qga_return(smac, "guest-exec", Some(exec_args))?
+        .get("pid")
+        .and_then(|v| v.as_i64())
+        .ok_or("guest-exec returned no pid")?;
+
+    let start = std::time::Instant::now();
+    loop {
+        let status = guest_exec_status(smac, pid)?;
+        if status.exited {
+            return Ok(status);
+        }
+        if start.elapsed() >= timeout {
+            return Ok(GuestExecResult { timed_out: true, ..status });
+        }
+        std::thread::sleep(Duration::from_millis(250));
+    }
+}
+
+/// One `guest-exec-status` poll for a process started by `guest_exec`
+pub fn guest_exec_status(smac: &str, pid: i64) -> Result<GuestExecResult, String> {
+    use base64::Engine;
+    let b64 = base64::engine::general_purpose::STANDARD;
+    let ret = qga_return(smac, "guest-exec-status", Some(serde_json::json!({ "pid": pid })))?;
+    let decode = |key: &str| {
+        ret.get(key)
+            .and_then(|v| v.as_str())
+            .and_then(|s| b64.decode(s).ok())
+            .map(|d| String::from_utf8_lossy(&d).to_string())
+            .unwrap_or_default()
+    };
+    let flag = |key: &str| ret.get(key).and_then(|v| v.as_bool()).unwrap_or(false);
+    Ok(GuestExecResult {
+        pid,
+        exited: flag("exited"),
+        exitcode: ret.get("exitcode").and_then(|v| v.as_i64()),
+        signal: ret.get("signal").and_then(|v| v.as_i64()),
+        stdout: decode("out-data"),
+        stderr: decode("err-data"),
+        stdout_truncated: flag("out-truncated"),
+        stderr_truncated: flag("err-truncated"),
+        timed_out: false,
+    })
+}
+
+/// Read a whole file out of the guest (`guest-file-open`/`guest-file-read`),
+/// refusing files larger than `max_bytes`
+pub fn guest_file_read(smac: &str, guest_path: &str, max_bytes: u64) -> Result<Vec<u8>, String> {
+    let mut session = QgaSession::connect(smac)?;
+    let handle = session.file_open(guest_path, "rb")?;
 
-        match write_result {
-            Ok(resp) => {
-                // Verify bytes written match
-                let written = resp
-                    .get("return")
-                    .and_then(|r| r.get("count"))
-                    .and_then(|c| c.as_u64())
-                    .unwrap_or(0);
-                let expected = (end - offset) as u64;
-                if written != expected {
-                    write_err = Some(format!(
-                        "Partial write: expected {} bytes, wrote {} at offset {}",
-                        expected, written, offset
-                    ));
+    const CHUNK_SIZE: u64 = 1024 * 1024;
+    let mut data: Vec<u8> = Vec::new();
+    let mut read_err: Option<String> = None;
+    loop {
+        match session.file_read(handle, CHUNK_SIZE) {
+            Ok((chunk, eof)) => {
+                data.extend_from_slice(&chunk);
+                if data.len() as u64 > max_bytes {
+                    read_err = Some(format!("File is larger than {} bytes", max_bytes));
+                    break;
+                }
+                if eof {
                     break;
                 }
             }
             Err(e) => {
-                write_err = Some(format!(
-                    "guest-file-write failed at offset {}: {}",
-                    offset, e
-                ));
+                read_err = Some(format!("guest-file-read failed at offset {}: {}", data.len(), e));
                 break;
             }
         }
-
-        offset = end;
     }
 
     // Always close the file handle
-    let _ = qga_command(
-        smac,
-        "guest-file-close",
-        Some(serde_json::json!({"handle": handle})),
-    );
+    let _ = session.file_close(handle);
 
-    if let Some(e) = write_err {
-        return Err(e);
+    match read_err {
+        Some(e) => Err(e),
+        None => Ok(data),
     }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct GuestIpAddress {
+    /// "ipv4" or "ipv6"
+    pub ip_address_type: String,
+    pub ip_address: String,
+    pub prefix: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct GuestNetworkInterface {
+    pub name: String,
+    #[serde(default)]
+    pub hardware_address: Option<String>,
+    #[serde(default)]
+    pub ip_addresses: Vec<GuestIpAddress>,
+    #[serde(default)]
+    pub statistics: Option<serde_json::Value>,
+}
+
+/// Guest network interfaces with their addresses (`guest-network-get-interfaces`)
+pub fn guest_network_interfaces(smac: &str) -> Result<Vec<GuestNetworkInterface>, String> {
+    qga_typed(smac, "guest-network-get-interfaces", None)
+}
+
+/// Guest OS identification (`guest-get-osinfo`); fields the guest does not
+/// report are left out
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct GuestOsInfo {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pretty_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variant: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kernel_release: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kernel_version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub machine: Option<String>,
+}
+
+pub fn guest_osinfo(smac: &str) -> Result<GuestOsInfo, String> {
+    qga_typed(smac, "guest-get-osinfo", None)
+}
 
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct GuestUser {
+    pub user: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub domain: Option<String>,
+    /// Seconds since the epoch
+    pub login_time: f64,
+}
+
+/// Users logged in to the guest (`guest-get-users`)
+pub fn guest_users(smac: &str) -> Result<Vec<GuestUser>, String> {
+    qga_typed(smac, "guest-get-users", None)
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct GuestFilesystem {
+    pub name: String,
+    pub mountpoint: String,
+    #[serde(rename = "type")]
+    pub fs_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total_bytes: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub used_bytes: Option<u64>,
+    /// Backing disks (bus, serial, PCI address) as reported by the agent
+    #[serde(default)]
+    pub disk: Vec<serde_json::Value>,
+}
+
+/// Mounted guest filesystems with usage (`guest-get-fsinfo`)
+pub fn guest_fsinfo(smac: &str) -> Result<Vec<GuestFilesystem>, String> {
+    qga_typed(smac, "guest-get-fsinfo", None)
+}
+
+/// Set a guest account's password (`guest-set-user-password`). With `crypted`
+/// the password is an already hashed value (Linux guests only).
+pub fn guest_set_user_password(
+    smac: &str,
+    username: &str,
+    password: &str,
+    crypted: bool,
+) -> Result<(), String> {
+    use base64::Engine;
+    if username.is_empty() || password.is_empty() {
+        return Err("Username and password are required".into());
+    }
+    qga_return(
+        smac,
+        "guest-set-user-password",
+        Some(serde_json::json!({
+            "username": username,
+            "password": base64::engine::general_purpose::STANDARD.encode(password),
+            "crypted": crypted,
+        })),
+    )?;
     Ok(())
 }
+
+/// Version of qemu-guest-agent running in the guest (`guest-info`)
+pub fn guest_agent_version(smac: &str) -> Result<String, String> {
+    let ret = qga_return(smac, "guest-info", None)?;
+    Ok(ret.get("version").and_then(|v| v.as_str()).unwrap_or("").to_string())
+}
+
+/// Guest's current host (computer) name (`guest-get-host-name`)
+pub fn guest_host_name(smac: &str) -> Result<String, String> {
+    let ret = qga_return(smac, "guest-get-host-name", None)?;
+    Ok(ret.get("host-name").and_then(|v| v.as_str()).unwrap_or("").to_string())
+}
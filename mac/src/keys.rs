@@ -0,0 +1,302 @@
+use crate::config::get_conf_or;
+use crate::ssh::sanitize_name;
+use std::time::Duration;
+
+/// Longest text accepted by one `type_text` call
+const MAX_TEXT_CHARS: usize = 4096;
+
+/// How a character is produced on the guest keyboard: base key plus modifiers.
+/// `dead` keys only print their symbol when followed by a space.
+#[derive(Clone, Copy)]
+struct Stroke {
+    key: &'static str,
+    shift: bool,
+    altgr: bool,
+    dead: bool,
+}
+
+const fn plain(key: &'static str) -> Stroke {
+    Stroke { key, shift: false, altgr: false, dead: false }
+}
+const fn shift(key: &'static str) -> Stroke {
+    Stroke { key, shift: true, altgr: false, dead: false }
+}
+const fn altgr(key: &'static str) -> Stroke {
+    Stroke { key, shift: false, altgr: true, dead: false }
+}
+const fn dead(s: Stroke) -> Stroke {
+    Stroke { dead: true, ..s }
+}
+
+/// Layouts `type_text` can translate for (the guest's keyboard layout)
+pub const LAYOUTS: &[&str] = &["us", "gb", "de", "fr"];
+
+/// Symbols outside letters and digits, US layout
+const US: &[(char, Stroke)] = &[
+    ('!', shift("1")), ('@', shift("2")), ('#', shift("3")), ('$', shift("4")),
+    ('%', shift("5")), ('^', shift("6")), ('&', shift("7")), ('*', shift("8")),
+    ('(', shift("9")), (')', shift("0")),
+    ('-', plain("minus")), ('_', shift("minus")), ('=', plain("equal")), ('+', shift("equal")),
+    ('[', plain("bracket_left")), ('{', shift("bracket_left")),
+    (']', plain("bracket_right")), ('}', shift("bracket_right")),
+    ('\\', plain("backslash")), ('|', shift("backslash")),
+    (';', plain("semicolon")), (':', shift("semicolon")),
+    ('\'', plain("apostrophe")), ('"', shift("apostrophe")),
+    ('`', plain("grave_accent")), ('~', shift("grave_accent")),
+    (',', plain("comma")), ('<', shift("comma")), ('.', plain("dot")), ('>', shift("dot")),
+    ('/', plain("slash")), ('?', shift("slash")),
+];
+
+/// UK layout: differences from US
+const GB: &[(char, Stroke)] = &[
+    ('"', shift("2")), ('£', shift("3")), ('@', shift("apostrophe")),
+    ('#', plain("backslash")), ('~', shift("backslash")),
+    ('\\', plain("less")), ('|', shift("less")), ('¬', shift("grave_accent")),
+    ('€', altgr("4")),
+];
+
+/// German (QWERTZ) layout: differences from US
+const DE: &[(char, Stroke)] = &[
+    ('z', plain("y")), ('y', plain("z")),
+    ('"', shift("2")), ('§', shift("3")), ('&', shift("6")), ('/', shift("7")),
+    ('(', shift("8")), (')', shift("9")), ('=', shift("0")),
+    ('ß', plain("minus")), ('?', shift("minus")),
+    ('´', dead(plain("equal"))), ('`', dead(shift("equal"))),
+    ('ü', plain("bracket_left")), ('Ü', shift("bracket_left")),
+    ('+', plain("bracket_right")), ('*', shift("bracket_right")),
+    ('ö', plain("semicolon")), ('Ö', shift("semicolon")),
+    ('ä', plain("apostrophe")), ('Ä', shift("apostrophe")),
+    ('#', plain("backslash")), ('\'', shift("backslash")),
+    ('^', dead(plain("grave_accent"))), ('°', shift("grave_accent")),
+    (';', shift("comma")), (':', shift("dot")), ('-', plain("slash")), ('_', shift("slash")),
+    ('<', plain("less")), ('>', shift("less")), ('|', altgr("less")),
+    ('@', altgr("q")), ('€', altgr("e")), ('²', altgr("2")), ('³', altgr("3")),
+    ('{', altgr("7")), ('[', altgr("8")), (']', altgr("9")), ('}', altgr("0")),
+    ('\\', altgr("minus")), ('~', altgr("bracket_right")), ('µ', altgr("m")),
+];
+
+/// French (AZERTY) layout: differences from US
+const FR: &[(char, Stroke)] = &[
+    ('a', plain("q")), ('q', plain("a")), ('z', plain("w")), ('w', plain("z")),
+    ('m', plain("semicolon")),
+    ('1', shift("1")), ('2', shift("2")), ('3', shift("3")), ('4', shift("4")), ('5', shift("5")),
+    ('6', shift("6")), ('7', shift("7")), ('8', shift("8")), ('9', shift("9")), ('0', shift("0")),
+    ('&', plain("1")), ('é', plain("2")), ('"', plain("3")), ('\'', plain("4")),
+    ('(', plain("5")), ('-', plain("6")), ('è', plain("7")), ('_', plain("8")),
+    ('ç', plain("9")), ('à', plain("0")), (')', plain("minus")), ('=', plain("equal")),
+    ('°', shift("minus")), ('+', shift("equal")),
+    ('~', dead(altgr("2"))), ('#', altgr("3")), ('{', altgr("4")), ('[', altgr("5")),
+    ('|', altgr("6")), ('`', dead(altgr("7"))), ('\\', altgr("8")), ('^', altgr("9")),
+    ('@', altgr("0")), (']', altgr("minus")), ('}', altgr("equal")), ('€', altgr("e")),
+    ('$', plain("bracket_right")), ('£', shift("bracket_right")),
+    ('ù', plain("apostrophe")), ('%', shift("apostrophe")),
+    ('*', plain("backslash")), ('µ', shift("backslash")),
+    (',', plain("m")), ('?', shift("m")), (';', plain("comma")), ('.', shift("comma")),
+    (':', plain("dot")), ('/', shift("dot")), ('!', plain("slash")), ('§', shift("slash")),
+    ('<', plain("less")), ('>', shift("less")), ('²', plain("grave_accent")),
+];
+
+/// Layout-independent keys
+fn common_stroke(c: char) -> Option<Stroke> {
+    match c {
+        ' ' => Some(plain("spc")),
+        '\n' => Some(plain("ret")),
+        '\t' => Some(plain("tab")),
+        _ => None,
+    }
+}
+
+fn layout_table(layout: &str) -> Result<&'static [(char, Stroke)], String> {
+    match layout {
+        "us" => Ok(&[]),
+        "gb" | "uk" => Ok(GB),
+        "de" => Ok(DE),
+        "fr" => Ok(FR),
+        other => Err(format!(
+            "Unknown keyboard layout '{}' (supported: {})",
+            other,
+            LAYOUTS.join(", ")
+        )),
+    }
+}
+
+/// Keys for one character in the given layout: the layout's own entry, else
+/// the US key, unless the layout puts a different character on that key
+fn char_stroke(table: &[(char, Stroke)], c: char) -> Option<Stroke> {
+    if let Some(s) = common_stroke(c) {
+        return Some(s);
+    }
+    if let Some((_, s)) = table.iter().find(|(k, _)| *k == c) {
+        return Some(*s);
+    }
+    // Capitals are Shift plus wherever the layout puts the lowercase letter
+    if c.is_ascii_uppercase() {
+        let base = char_stroke(table, c.to_ascii_lowercase())?;
+        let plain_key = !base.shift && !base.altgr && !base.dead;
+        return plain_key.then_some(Stroke { shift: true, ..base });
+    }
+    let us = if c.is_ascii_alphanumeric() {
+        let key = QCODE_LETTERS_DIGITS.iter().find(|k| k.starts_with(c))?;
+        plain(key)
+    } else {
+        US.iter().find(|(k, _)| *k == c).map(|(_, s)| *s)?
+    };
+    let clash = table
+        .iter()
+        .any(|(_, t)| t.key == us.key && t.shift == us.shift && t.altgr == us.altgr);
+    (!clash).then_some(us)
+}
+
+const QCODE_LETTERS_DIGITS: &[&str] = &[
+    "a", "b", "c", "d", "e", "f", "g", "h", "i", "j", "k", "l", "m", "n", "o", "p", "q", "r",
+    "s", "t", "u", "v", "w", "x", "y", "z", "0", "1", "2", "3", "4", "5", "6", "7", "8", "9",
+];
+
+/// Key name (as used in combos) to QEMU qcode
+fn key_qcode(name: &str) -> Option<String> {
+    let n = name.to_ascii_lowercase();
+    let q = match n.as_str() {
+        "ctrl" | "control" | "ctrl_l" | "lctrl" => "ctrl",
+        "ctrl_r" | "rctrl" => "ctrl_r",
+        "alt" | "alt_l" | "lalt" => "alt",
+        "altgr" | "alt_r" | "ralt" => "alt_r",
+        "shift" | "shift_l" | "lshift" => "shift",
+        "shift_r" | "rshift" => "shift_r",
+        "meta" | "win" | "super" | "cmd" | "meta_l" => "meta_l",
+        "meta_r" => "meta_r",
+        "menu" | "compose" => "compose",
+        "del" | "delete" => "delete",
+        "ins" | "insert" => "insert",
+        "ret" | "enter" | "return" => "ret",
+        "esc" | "escape" => "esc",
+        "tab" => "tab",
+        "spc" | "space" => "spc",
+        "bs" | "backspace" => "backspace",
+        "up" | "down" | "left" | "right" | "home" | "end" => n.as_str(),
+        "pgup" | "pageup" | "page_up" => "pgup",
+        "pgdn" | "pagedown" | "page_down" => "pgdn",
+        "print" | "sysrq" | "prtsc" => "sysrq",
+        "pause" => "pause",
+        "caps_lock" | "capslock" => "caps_lock",
+        "num_lock" | "numlock" => "num_lock",
+        "scroll_lock" | "scrolllock" => "scroll_lock",
+        "minus" | "equal" | "bracket_left" | "bracket_right" | "backslash" | "semicolon"
+        | "apostrophe" | "grave_accent" | "comma" | "dot" | "slash" | "less" => n.as_str(),
+        _ if n.len() == 1 && QCODE_LETTERS_DIGITS.contains(&n.as_str()) => n.as_str(),
+        _ if n.starts_with('f')
+            && n[1..].parse::<u8>().map(|f| (1..=12).contains(&f)).unwrap_or(false) =>
+        {
+            n.as_str()
+        }
+        _ if n.starts_with("kp_") => n.as_str(),
+        _ => return None,
+    };
+    Some(q.to_string())
+}
+
+/// Parse "ctrl-alt-del" / "ctrl+alt+f2" into qcodes
+fn parse_combo(combo: &str) -> Result<Vec<String>, String> {
+    let keys: Vec<String> = combo
+        .split(['-', '+'])
+        .filter(|k| !k.is_empty())
+        .map(|k| key_qcode(k).ok_or_else(|| format!("Unknown key '{}' in '{}'", k, combo)))
+        .collect::<Result<_, _>>()?;
+    if keys.is_empty() {
+        return Err("Empty key combination".into());
+    }
+    if keys.len() > 8 {
+        return Err(format!("Too many keys in '{}'", combo));
+    }
+    Ok(keys)
+}
+
+fn qcode(key: &str) -> serde_json::Value {
+    serde_json::json!({ "type": "qcode", "data": key })
+}
+
+fn ensure_running(smac: &str) -> Result<(), String> {
+    sanitize_name(smac)?;
+    let vm = crate::db::get_vm(smac)?;
+    if vm.status != "running" {
+        return Err(format!("VM '{}' is not running", smac));
+    }
+    Ok(())
+}
+
+/// Press whitespace-separated combos one after another with QMP `send-key`,
+/// e.g. "ctrl-alt-del" or "ctrl-alt-f2 ret". All keys of a combo go down
+/// together and are released after `hold_ms`.
+pub fn send_combos(smac: &str, combos: &str, hold_ms: u64) -> Result<usize, String> {
+    ensure_running(smac)?;
+    let parsed: Vec<Vec<String>> = combos
+        .split_whitespace()
+        .map(parse_combo)
+        .collect::<Result<_, _>>()?;
+    if parsed.is_empty() {
+        return Err("No keys given".into());
+    }
+    for keys in &parsed {
+        let keys: Vec<serde_json::Value> = keys.iter().map(|k| qcode(k)).collect();
+        crate::qmp::qmp_command(
+            smac,
+            "send-key",
+            Some(serde_json::json!({ "keys": keys, "hold-time": hold_ms.clamp(10, 5000) })),
+        )?;
+        std::thread::sleep(Duration::from_millis(hold_ms.clamp(10, 5000)));
+    }
+    Ok(parsed.len())
+}
+
+/// Default guest layout for typing (`keyboard_layout`, default "us")
+pub fn default_layout() -> String {
+    get_conf_or("keyboard_layout", "us")
+}
+
+/// Type text into the guest as key presses (`input-send-event`), translated
+/// for the guest's keyboard layout. Checks every character before sending
+/// anything, so an unsupported character never leaves half a password typed.
+pub fn type_text(smac: &str, text: &str, layout: &str, delay_ms: u64) -> Result<usize, String> {
+    ensure_running(smac)?;
+    let table = layout_table(layout)?;
+    let text = text.replace("\r\n", "\n").replace('\r', "\n");
+    if text.is_empty() {
+        return Err("No text given".into());
+    }
+    if text.chars().count() > MAX_TEXT_CHARS {
+        return Err(format!("Text longer than {} characters", MAX_TEXT_CHARS));
+    }
+    let strokes: Vec<Stroke> = text
+        .chars()
+        .map(|c| {
+            char_stroke(table, c).ok_or_else(|| {
+                format!("Character {:?} cannot be typed with the '{}' layout", c, layout)
+            })
+        })
+        .collect::<Result<_, _>>()?;
+
+    let key_event = |key: &str, down: bool| {
+        serde_json::json!({ "type": "key", "data": { "down": down, "key": qcode(key) } })
+    };
+    for s in &strokes {
+        let mut mods: Vec<&str> = Vec::new();
+        if s.shift {
+            mods.push("shift");
+        }
+        if s.altgr {
+            mods.push("alt_r");
+        }
+        let mut events: Vec<serde_json::Value> = mods.iter().map(|m| key_event(m, true)).collect();
+        events.push(key_event(s.key, true));
+        events.push(key_event(s.key, false));
+        events.extend(mods.iter().rev().map(|m| key_event(m, false)));
+        if s.dead {
+            events.push(key_event("spc", true));
+            events.push(key_event("spc", false));
+        }
+        crate::qmp::qmp_command(smac, "input-send-event", Some(serde_json::json!({ "events": events })))?;
+        if delay_ms > 0 {
+            std::thread::sleep(Duration::from_millis(delay_ms));
+        }
+    }
+    Ok(strokes.len())
+}
@@ -2,7 +2,7 @@ use std::env;
 
 fn print_usage(prog: &str) {
     println!(
-        "Usage : {} {{server,stop,start,startlive,powerdown,reset,restart,create,delete,mountiso,livemigrate,backup,vnc-start,vnc-stop}}",
+        "Usage : {} {{server,stop,start,startlive,powerdown,shutdown,reset,restart,create,delete,mountiso,livemigrate,backup,vnc-start,vnc-stop}}",
         prog
     );
 }
@@ -37,6 +37,7 @@ async fn main() {
             "start" => println!("Usage : {} start '{{\"cpu\": {{\"vcpus\": \"4\"}},\"memory\": {{\"size\": \"2048\"}},\"features\": {{\"is_windows\": \"0\"}},\"network_adapters\": [{{\"netid\": \"0\",\"mac\": \"52:54:c4:ca:42:38\",\"vlan\": \"0\"}}],\"disks\": [{{\"diskid\": \"0\",\"diskname\": \"52-54-c4-ca-42-38\",\"iops-total\": \"9600\",\"iops-total-max\": \"11520\",\"iops-total-max-length\": \"60\"}}]}}'", prog),
             "startlive" => println!("Usage : {} startlive", prog),
             "powerdown" => println!("Usage : {} powerdown '{{\"smac\": \"52-54-c4-ca-42-38\"}}'", prog),
+            "shutdown" => println!("Usage : {} shutdown '{{\"smac\": \"52-54-c4-ca-42-38\",\"timeout\": 120,\"force\": true}}'", prog),
             "reset" => println!("Usage : {} reset '{{\"smac\": \"52-54-c4-ca-42-38\"}}'", prog),
             "create" => println!("Usage : {} create '{{\"smac\": \"52-54-c4-ca-42-38\",\"size\": \"40G\"}}'", prog),
             "copyimage" => println!("Usage : {} copyimage '{{\"itemplate\": \"CentOS-7-x86_64-GenericCloud-1907\",\"smac\": \"52-54-c4-ca-42-38\",\"size\": \"40G\"}}'", prog),
@@ -59,6 +60,7 @@ async fn main() {
             "stop" => vm_ctl::operations::stop(json_str),
             "start" | "startlive" => vm_ctl::operations::start(json_str),
             "powerdown" => vm_ctl::operations::powerdown(json_str),
+            "shutdown" => vm_ctl::operations::shutdown(json_str),
             "reset" => vm_ctl::operations::reset(json_str),
             "create" => vm_ctl::operations::create_config(json_str),
             "update" => vm_ctl::operations::update_config(json_str),
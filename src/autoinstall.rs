@@ -0,0 +1,324 @@
+use crate::models::LinuxInstallSettings;
+use sha2::{Digest, Sha512};
+
+/// Answer-file formats, by `linux_install.flavor`
+pub const FLAVORS: &[&str] = &["ubuntu", "debian", "rhel"];
+
+/// Everything needed to render one answer file
+pub struct InstallParams<'a> {
+    pub settings: &'a LinuxInstallSettings,
+    pub hostname: &'a str,
+    /// SHA-512 crypt hash (see `hash_password`); answer files never carry
+    /// the plaintext password
+    pub password_hash: &'a str,
+    /// "" = no key
+    pub ssh_pubkey: &'a str,
+    /// Installed on top of the base system (qemu-guest-agent is always added)
+    pub packages: &'a [String],
+    /// Fetched by the installer as its last step (MDS `/autoinstall/{smac}/{token}/done`)
+    pub done_url: &'a str,
+}
+
+/// Kernel and initrd paths inside the installer ISO, tried in order
+pub fn boot_files(flavor: &str, is_aarch64: bool) -> (&'static [&'static str], &'static [&'static str]) {
+    match (flavor, is_aarch64) {
+        ("ubuntu", _) => (
+            &["casper/vmlinuz", "casper/hwe-vmlinuz"],
+            &["casper/initrd", "casper/initrd.gz", "casper/initrd.lz", "casper/hwe-initrd"],
+        ),
+        ("debian", false) => (
+            &["install.amd/vmlinuz", "install/vmlinuz"],
+            &["install.amd/initrd.gz", "install/initrd.gz"],
+        ),
+        ("debian", true) => (&["install.a64/vmlinuz"], &["install.a64/initrd.gz"]),
+        _ => (&["images/pxeboot/vmlinuz"], &["images/pxeboot/initrd.img"]),
+    }
+}
+
+/// Installer kernel command line. `base_url` is the VM's answer directory
+/// on the MDS, without a trailing slash. The serial console gets kernel
+/// messages; the installer itself runs on the display (last console=).
+pub fn kernel_cmdline(settings: &LinuxInstallSettings, base_url: &str, hostname: &str) -> String {
+    let mut args = match settings.flavor.as_str() {
+        "ubuntu" => format!("autoinstall ds=nocloud-net;s={}/", base_url),
+        "debian" => format!(
+            "auto=true priority=critical url={}/preseed.cfg interface=auto hostname={} domain= locale={} keymap={}",
+            base_url, hostname, settings.locale, settings.keyboard
+        ),
+        _ => format!("inst.ks={}/ks.cfg inst.repo=cdrom", base_url),
+    };
+    args.push_str(" console=ttyS0,115200 console=tty0");
+    if !settings.kernel_args.trim().is_empty() {
+        args.push(' ');
+        args.push_str(settings.kernel_args.trim());
+    }
+    if settings.flavor == "ubuntu" {
+        args.push_str(" ---");
+    }
+    args
+}
+
+const CRYPT_ALPHABET: &[u8] = b"./0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+/// `$6$` (SHA-512 crypt, 5000 rounds) hash with a random salt, as accepted
+/// by cloud-init, debian-installer and Anaconda
+pub fn hash_password(password: &str) -> String {
+    let salt: String = crate::operations::read_urandom_bytes(16)
+        .iter()
+        .map(|b| CRYPT_ALPHABET[(*b & 0x3f) as usize] as char)
+        .collect();
+    sha512_crypt(password.as_bytes(), salt.as_bytes())
+}
+
+/// Drepper's SHA-crypt with the default round count
+fn sha512_crypt(pw: &[u8], salt: &[u8]) -> String {
+    const ROUNDS: usize = 5000;
+    let salt = &salt[..salt.len().min(16)];
+
+    let alt = Sha512::new().chain_update(pw).chain_update(salt).chain_update(pw).finalize();
+    let mut ctx = Sha512::new().chain_update(pw).chain_update(salt);
+    let mut n = pw.len();
+    while n > 64 {
+        ctx.update(alt);
+        n -= 64;
+    }
+    ctx.update(&alt[..n]);
+    let mut n = pw.len();
+    while n > 0 {
+        if n & 1 == 1 {
+            ctx.update(alt);
+        } else {
+            ctx.update(pw);
+        }
+        n >>= 1;
+    }
+    let a = ctx.finalize();
+
+    let mut dp = Sha512::new();
+    for _ in 0..pw.len() {
+        dp.update(pw);
+    }
+    let dp = dp.finalize();
+    let p: Vec<u8> = dp.iter().cycle().take(pw.len()).copied().collect();
+    let mut ds = Sha512::new();
+    for _ in 0..16 + a[0] as usize {
+        ds.update(salt);
+    }
+    let ds = ds.finalize();
+    let s: Vec<u8> = ds.iter().cycle().take(salt.len()).copied().collect();
+
+    let mut c = a;
+    for i in 0..ROUNDS {
+        let mut ctx = Sha512::new();
+        if i & 1 == 1 {
+            ctx.update(&p);
+        } else {
+            ctx.update(c);
+        }
+        if i % 3 != 0 {
+            ctx.update(&s);
+        }
+        if i % 7 != 0 {
+            ctx.update(&p);
+        }
+        if i & 1 == 1 {
+            ctx.update(c);
+        } else {
+            ctx.update(&p);
+        }
+        c = ctx.finalize();
+    }
+
+    let mut out = format!("$6${}$", String::from_utf8_lossy(salt));
+    let mut push = |b2: u8, b1: u8, b0: u8, chars: usize| {
+        let mut w = ((b2 as u32) << 16) | ((b1 as u32) << 8) | b0 as u32;
+        for _ in 0..chars {
+            out.push(CRYPT_ALPHABET[(w & 0x3f) as usize] as char);
+            w >>= 6;
+        }
+    };
+    for i in 0..21 {
+        let (x, y, z) = [(0, 21, 42), (22, 43, 1), (44, 2, 23)][i % 3];
+        let k = i / 3 * 3;
+        push(c[x + k], c[y + k], c[z + k], 4);
+    }
+    push(0, 0, c[63], 2);
+    out
+}
+
+/// Double-quoted YAML scalar
+fn yaml_quote(s: &str) -> String {
+    format!(
+        "\"{}\"",
+        s.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n").replace('\r', "")
+    )
+}
+
+/// Single-quoted shell word
+fn sh_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+/// Kickstart arguments are split shell-style
+fn ks_quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', ""))
+}
+
+fn single_line(s: &str) -> String {
+    s.replace(['\n', '\r'], "")
+}
+
+/// Ubuntu Subiquity `autoinstall` user-data. Accounts are set through the
+/// embedded cloud-init `user-data` of the target.
+pub fn ubuntu_user_data(p: &InstallParams) -> String {
+    let s = p.settings;
+    let mut ud = String::from("#cloud-config\nautoinstall:\n  version: 1\n");
+    ud.push_str(&format!("  locale: {}\n", yaml_quote(&s.locale)));
+    ud.push_str(&format!("  keyboard:\n    layout: {}\n", yaml_quote(&s.keyboard)));
+    ud.push_str(&format!("  timezone: {}\n", yaml_quote(&s.timezone)));
+    ud.push_str("  refresh-installer:\n    update: false\n");
+    ud.push_str("  storage:\n    layout:\n      name: direct\n");
+    ud.push_str("  ssh:\n    install-server: true\n    allow-pw: true\n");
+    ud.push_str("  packages:\n    - qemu-guest-agent\n");
+    for pkg in p.packages {
+        ud.push_str(&format!("    - {}\n", yaml_quote(pkg)));
+    }
+    ud.push_str("  user-data:\n");
+    ud.push_str(&format!("    hostname: {}\n", yaml_quote(p.hostname)));
+    ud.push_str("    disable_root: false\n    ssh_pwauth: true\n    users:\n");
+    ud.push_str(&format!("      - name: {}\n", yaml_quote(&s.username)));
+    ud.push_str("        groups: [sudo]\n        shell: /bin/bash\n        lock_passwd: false\n");
+    ud.push_str(&format!("        passwd: {}\n", yaml_quote(p.password_hash)));
+    if !p.ssh_pubkey.is_empty() {
+        ud.push_str(&format!("        ssh_authorized_keys:\n          - {}\n", yaml_quote(p.ssh_pubkey)));
+    }
+    ud.push_str("    chpasswd:\n      expire: false\n      list: |\n");
+    ud.push_str(&format!("        root:{}\n", single_line(p.password_hash)));
+    ud.push_str("  late-commands:\n");
+    ud.push_str("    - curtin in-target -- systemctl enable qemu-guest-agent\n");
+    ud.push_str(&format!(
+        "    - {}\n",
+        yaml_quote(&format!("curl -fsS -o /dev/null {} || true", sh_quote(p.done_url)))
+    ));
+    ud.push_str("  shutdown: reboot\n");
+    ud
+}
+
+/// NoCloud meta-data served next to the autoinstall user-data
+pub fn ubuntu_meta_data(hostname: &str) -> String {
+    format!("instance-id: autoinstall-{}\nlocal-hostname: {}\n", hostname, hostname)
+}
+
+/// Debian installer preseed
+pub fn debian_preseed(p: &InstallParams) -> String {
+    let s = p.settings;
+    let pw = single_line(p.password_hash);
+    let mut lines = vec![
+        format!("d-i debian-installer/locale string {}", s.locale),
+        format!("d-i keyboard-configuration/xkb-keymap select {}", s.keyboard),
+        "d-i netcfg/choose_interface select auto".to_string(),
+        format!("d-i netcfg/get_hostname string {}", p.hostname),
+        "d-i netcfg/get_domain string".to_string(),
+        format!("d-i netcfg/hostname string {}", p.hostname),
+        "d-i mirror/country string manual".to_string(),
+        "d-i mirror/http/hostname string deb.debian.org".to_string(),
+        "d-i mirror/http/directory string /debian".to_string(),
+        "d-i mirror/http/proxy string".to_string(),
+        "d-i passwd/root-login boolean true".to_string(),
+        format!("d-i passwd/root-password-crypted password {}", pw),
+        format!("d-i passwd/user-fullname string {}", s.username),
+        format!("d-i passwd/username string {}", s.username),
+        format!("d-i passwd/user-password-crypted password {}", pw),
+        "d-i clock-setup/utc boolean true".to_string(),
+        format!("d-i time/zone string {}", s.timezone),
+        "d-i clock-setup/ntp boolean true".to_string(),
+        "d-i partman-auto/disk string /dev/vda".to_string(),
+        "d-i partman-auto/method string regular".to_string(),
+        "d-i partman-auto/choose_recipe select atomic".to_string(),
+        "d-i partman-partitioning/confirm_write_new_label boolean true".to_string(),
+        "d-i partman/choose_partition select finish".to_string(),
+        "d-i partman/confirm boolean true".to_string(),
+        "d-i partman/confirm_nooverwrite boolean true".to_string(),
+        "d-i partman-efi/non_efi_system boolean true".to_string(),
+        "d-i apt-setup/cdrom/set-first boolean false".to_string(),
+        "tasksel tasksel/first multiselect standard, ssh-server".to_string(),
+        format!("d-i pkgsel/include string {}", {
+            let mut pkgs = vec!["qemu-guest-agent".to_string(), "sudo".to_string()];
+            pkgs.extend(p.packages.iter().cloned());
+            pkgs.join(" ")
+        }),
+        "d-i pkgsel/upgrade select none".to_string(),
+        "popularity-contest popularity-contest/participate boolean false".to_string(),
+        "d-i grub-installer/only_debian boolean true".to_string(),
+        "d-i grub-installer/bootdev string /dev/vda".to_string(),
+    ];
+    let mut late = Vec::new();
+    if !p.ssh_pubkey.is_empty() {
+        late.push("mkdir -p /target/root/.ssh".to_string());
+        late.push(format!("echo {} >> /target/root/.ssh/authorized_keys", sh_quote(p.ssh_pubkey)));
+        late.push("chmod 600 /target/root/.ssh/authorized_keys".to_string());
+    }
+    late.push(format!("(wget -q -O /dev/null {} || true)", sh_quote(p.done_url)));
+    lines.push(format!("d-i preseed/late_command string {}", late.join("; ")));
+    lines.push("d-i finish-install/reboot_in_progress note".to_string());
+    let mut out = lines.join("\n");
+    out.push('\n');
+    out
+}
+
+/// Anaconda kickstart (RHEL, Rocky, Alma, CentOS Stream, Fedora Server)
+pub fn kickstart(p: &InstallParams) -> String {
+    let s = p.settings;
+    let mut ks = String::from("text\ncdrom\n");
+    ks.push_str(&format!("lang {}\n", s.locale));
+    ks.push_str(&format!("keyboard --vckeymap={} --xlayouts={}\n", s.keyboard, ks_quote(&s.keyboard)));
+    ks.push_str(&format!("timezone {} --utc\n", s.timezone));
+    ks.push_str(&format!("network --bootproto=dhcp --device=link --activate --hostname={}\n", p.hostname));
+    ks.push_str(&format!("rootpw --iscrypted {}\n", ks_quote(p.password_hash)));
+    ks.push_str(&format!(
+        "user --name={} --groups=wheel --iscrypted --password={}\n",
+        s.username,
+        ks_quote(p.password_hash)
+    ));
+    if !p.ssh_pubkey.is_empty() {
+        ks.push_str(&format!("sshkey --username=root {}\n", ks_quote(p.ssh_pubkey)));
+    }
+    ks.push_str("ignoredisk --only-use=vda\nclearpart --all --initlabel --drives=vda\nautopart\n");
+    ks.push_str("bootloader --boot-drive=vda\nfirstboot --disable\nreboot\n\n");
+    ks.push_str("%packages\n@^minimal-environment\nqemu-guest-agent\n");
+    for pkg in p.packages {
+        ks.push_str(&format!("{}\n", pkg));
+    }
+    ks.push_str("%end\n\n%post\nsystemctl enable qemu-guest-agent\n%end\n\n");
+    ks.push_str(&format!(
+        "%post --nochroot\ncurl -fsS -o /dev/null {} || true\n%end\n",
+        sh_quote(p.done_url)
+    ));
+    ks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::sha512_crypt;
+
+    #[test]
+    fn sha512_crypt_known_answers() {
+        // glibc crypt-sha512 test vectors, default rounds
+        assert_eq!(
+            sha512_crypt(b"Hello world!", b"saltstring"),
+            "$6$saltstring$svn8UoSVapNtMuq1ukKS4tPQd8iKwSMHWjl/O817G3uBnIFNjnQJuesI68u4OTLiBFdcbYEdFCoEOfaS35inz1"
+        );
+        // Salt cut to 16 characters, password longer than one digest
+        assert_eq!(
+            sha512_crypt(
+                b"a very much longer text to encrypt.  This one even stretches over morethan one line.",
+                b"toolongsaltstringXXXX"
+            ),
+            "$6$toolongsaltstrin$d83lI1f8Dmg5G54BIEUCk.d1wzcvMvHiDIygj5z1mZBp8sK1sb1wb5GVvQ7hgyXszzhH1BdryFoyDxA5CIFdb1"
+        );
+        assert_eq!(
+            sha512_crypt(b"we have a short salt string but not a short password", b"short"),
+            "$6$short$qmfj2meTBr5G2EAGIJ4vjX7RpefsD4JzpEyTAeEUJdzdxlBS6pe8gdMHm5zFftaFSj/2p2bjBwyVS9ZhWpLZt."
+        );
+    }
+}
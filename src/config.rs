@@ -28,7 +28,6 @@ fn defaults() -> HashMap<String, String> {
         m.insert("pctl_script".into(), "pctl.bat".into());
         m.insert("swtpm_path".into(), r"C:\msys64\mingw64\bin\swtpm.exe".into());
         m.insert("mkisofs_path".into(), r"C:\msys64\mingw64\bin\mkisofs.exe".into());
-        m.insert("websockify_path".into(), r"C:\msys64\mingw64\bin\websockify.exe".into());
         m.insert("qemu_cpu_x86".into(), "qemu64".into());
         m.insert("qemu_accel".into(), "tcg,thread=multi".into());
         m.insert("vnc_bind_host".into(), "0.0.0.0".into());
@@ -0,0 +1,214 @@
+use serde_json::{json, Value};
+
+/// EFI_GLOBAL_VARIABLE (PK, KEK)
+const GLOBAL_GUID: &str = "8be4df61-93ca-11d2-aa0d-00e098032b8c";
+/// EFI_IMAGE_SECURITY_DATABASE_GUID (db, dbx)
+const IMAGE_SECURITY_GUID: &str = "d719b2cb-3d3a-4596-a3bc-dad00e67656f";
+/// edk2 gEfiSecureBootEnableDisableGuid (SecureBootEnable)
+const SECURE_BOOT_ENABLE_GUID: &str = "f0a30bc7-af08-4556-99c4-001009c93a44";
+
+const CERT_X509_GUID: &str = "a5c059a1-94e4-4aa7-87b5-ab155c2bf072";
+const CERT_SHA256_GUID: &str = "c1c41626-504c-4092-aca9-41f936934328";
+
+/// Key databases reported by `secure_boot_state`, with their vendor GUID
+pub const KEY_DATABASES: &[(&str, &str)] = &[
+    ("PK", GLOBAL_GUID),
+    ("KEK", GLOBAL_GUID),
+    ("db", IMAGE_SECURITY_GUID),
+    ("dbx", IMAGE_SECURITY_GUID),
+];
+
+/// One live variable of an edk2 variable store
+pub struct Variable {
+    pub name: String,
+    pub guid: String,
+    pub data: Vec<u8>,
+}
+
+fn u16_at(b: &[u8], off: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(b.get(off..off + 2)?.try_into().ok()?))
+}
+
+fn u32_at(b: &[u8], off: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(b.get(off..off + 4)?.try_into().ok()?))
+}
+
+/// EFI_GUID in its usual text form (first three fields little-endian)
+fn guid_at(b: &[u8], off: usize) -> Option<String> {
+    let g = b.get(off..off + 16)?;
+    Some(format!(
+        "{:08x}-{:04x}-{:04x}-{}-{}",
+        u32::from_le_bytes(g[0..4].try_into().ok()?),
+        u16::from_le_bytes(g[4..6].try_into().ok()?),
+        u16::from_le_bytes(g[6..8].try_into().ok()?),
+        hex(&g[8..10]),
+        hex(&g[10..16])
+    ))
+}
+
+fn hex(b: &[u8]) -> String {
+    b.iter().map(|x| format!("{:02x}", x)).collect()
+}
+
+/// Live variables of a raw edk2 NVRAM image (the firmware volume that
+/// OVMF/AAVMF keep in pflash1). Deleted and half-written entries are skipped.
+pub fn parse_variable_store(img: &[u8]) -> Result<Vec<Variable>, String> {
+    if img.get(40..44) != Some(b"_FVH".as_slice()) {
+        return Err("Not an edk2 variable store (no firmware volume header)".into());
+    }
+    let fv_header_len = u16_at(img, 48).ok_or("Truncated firmware volume header")? as usize;
+    // VARIABLE_STORE_HEADER: Signature GUID, Size, Format, State, 6 reserved bytes
+    let store_guid = guid_at(img, fv_header_len).ok_or("Truncated variable store header")?;
+    let authenticated = match store_guid.as_str() {
+        "aaf32c78-947b-439a-a180-2e144ec37792" | "515fa686-b06e-4550-9112-382bf1067bfb" => true,
+        "ddcf3616-3275-4164-98b6-fe85707ffe7d" => false,
+        other => return Err(format!("Unknown variable store format {}", other)),
+    };
+    let store_size = u32_at(img, fv_header_len + 16).ok_or("Truncated variable store header")? as usize;
+    let end = (fv_header_len + store_size).min(img.len());
+    let header_len = if authenticated { 60 } else { 32 };
+    let (name_size_off, guid_off) = if authenticated { (36, 44) } else { (8, 16) };
+
+    let mut vars = Vec::new();
+    let mut off = fv_header_len + 28;
+    while off + header_len <= end && u16_at(img, off) == Some(0x55aa) {
+        let state = img[off + 2];
+        let name_size = u32_at(img, off + name_size_off).unwrap_or(0) as usize;
+        let data_size = u32_at(img, off + name_size_off + 4).unwrap_or(0) as usize;
+        let guid = guid_at(img, off + guid_off).unwrap_or_default();
+        let name_off = off + header_len;
+        let data_off = name_off + name_size;
+        if data_off + data_size > end {
+            break;
+        }
+        // VAR_ADDED, optionally with VAR_IN_DELETED_TRANSITION cleared
+        if state == 0x3f || state == 0x3e {
+            let name: Vec<u16> = img[name_off..data_off]
+                .chunks_exact(2)
+                .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                .take_while(|c| *c != 0)
+                .collect();
+            vars.push(Variable {
+                name: String::from_utf16_lossy(&name),
+                guid,
+                data: img[data_off..data_off + data_size].to_vec(),
+            });
+        }
+        off = (data_off + data_size + 3) & !3;
+    }
+    Ok(vars)
+}
+
+/// Tag, content range and end of the DER element at `pos`
+fn der_element(b: &[u8], pos: usize) -> Option<(u8, usize, usize)> {
+    let tag = *b.get(pos)?;
+    let first = *b.get(pos + 1)? as usize;
+    let (len, start) = if first < 0x80 {
+        (first, pos + 2)
+    } else {
+        let n = first & 0x7f;
+        if n == 0 || n > 4 {
+            return None;
+        }
+        let len = b.get(pos + 2..pos + 2 + n)?.iter().fold(0usize, |acc, x| (acc << 8) | *x as usize);
+        (len, pos + 2 + n)
+    };
+    let end = start.checked_add(len)?;
+    (end <= b.len()).then_some((tag, start, end))
+}
+
+/// commonName of a DER Name, falling back to the first printable attribute
+fn der_name_cn(b: &[u8], start: usize, end: usize) -> Option<String> {
+    const CN_OID: &[u8] = &[0x06, 0x03, 0x55, 0x04, 0x03];
+    let mut fallback = None;
+    let mut pos = start;
+    // Name ::= SEQUENCE OF SET OF SEQUENCE { type OID, value ANY }
+    while pos < end {
+        let (_, set_start, set_end) = der_element(b, pos)?;
+        let (_, atv_start, _) = der_element(b, set_start)?;
+        let (_, _, oid_end) = der_element(b, atv_start)?;
+        let (_, val_start, val_end) = der_element(b, oid_end)?;
+        let value = String::from_utf8_lossy(&b[val_start..val_end]).to_string();
+        if b.get(atv_start..oid_end) == Some(CN_OID) {
+            return Some(value);
+        }
+        fallback.get_or_insert(value);
+        pos = set_end;
+    }
+    fallback
+}
+
+/// Subject and issuer common names of a DER X.509 certificate
+pub fn x509_names(cert: &[u8]) -> Option<(String, String)> {
+    let (_, cert_start, _) = der_element(cert, 0)?;
+    let (_, tbs_start, _) = der_element(cert, cert_start)?;
+    let mut pos = tbs_start;
+    // Optional [0] version
+    if cert.get(pos) == Some(&0xa0) {
+        pos = der_element(cert, pos)?.2;
+    }
+    // serialNumber, signature
+    pos = der_element(cert, pos)?.2;
+    pos = der_element(cert, pos)?.2;
+    let (_, issuer_start, issuer_end) = der_element(cert, pos)?;
+    // validity
+    pos = der_element(cert, issuer_end)?.2;
+    let (_, subject_start, subject_end) = der_element(cert, pos)?;
+    Some((
+        der_name_cn(cert, subject_start, subject_end).unwrap_or_default(),
+        der_name_cn(cert, issuer_start, issuer_end).unwrap_or_default(),
+    ))
+}
+
+/// Entries of an EFI_SIGNATURE_LIST sequence (the data of PK, KEK, db, dbx)
+pub fn parse_signature_lists(data: &[u8]) -> Vec<Value> {
+    let mut entries = Vec::new();
+    let mut off = 0;
+    while off + 28 <= data.len() {
+        let sig_type = guid_at(data, off).unwrap_or_default();
+        let list_size = u32_at(data, off + 16).unwrap_or(0) as usize;
+        let header_size = u32_at(data, off + 20).unwrap_or(0) as usize;
+        let sig_size = u32_at(data, off + 24).unwrap_or(0) as usize;
+        if list_size < 28 || off + list_size > data.len() || sig_size <= 16 {
+            break;
+        }
+        let mut pos = off + 28 + header_size;
+        while pos + sig_size <= off + list_size {
+            let owner = guid_at(data, pos).unwrap_or_default();
+            let sig = &data[pos + 16..pos + sig_size];
+            let entry = match sig_type.as_str() {
+                CERT_X509_GUID => {
+                    let (subject, issuer) = x509_names(sig).unwrap_or_default();
+                    json!({ "type": "x509", "owner": owner, "subject": subject, "issuer": issuer })
+                }
+                CERT_SHA256_GUID => json!({ "type": "sha256", "owner": owner, "hash": hex(sig) }),
+                other => json!({ "type": other, "owner": owner, "size": sig.len() }),
+            };
+            entries.push(entry);
+            pos += sig_size;
+        }
+        off += list_size;
+    }
+    entries
+}
+
+/// Enrolled keys of a raw NVRAM image. Without a PK the firmware is in
+/// setup mode and does not verify signatures, whatever is in db.
+pub fn secure_boot_state(img: &[u8]) -> Result<Value, String> {
+    let vars = parse_variable_store(img)?;
+    let find = |name: &str, guid: &str| vars.iter().find(|v| v.name == name && v.guid == guid);
+    let mut out = serde_json::Map::new();
+    for (name, guid) in KEY_DATABASES {
+        let entries = find(name, guid).map(|v| parse_signature_lists(&v.data)).unwrap_or_default();
+        out.insert(name.to_lowercase(), json!(entries));
+    }
+    let setup_mode = find("PK", GLOBAL_GUID).is_none_or(|v| v.data.is_empty());
+    // edk2's SecureBootEnable (absent = enabled once a PK is enrolled)
+    let enabled = find("SecureBootEnable", SECURE_BOOT_ENABLE_GUID)
+        .and_then(|v| v.data.first().copied())
+        .is_none_or(|b| b != 0);
+    out.insert("setup_mode".into(), json!(setup_mode));
+    out.insert("enforcing".into(), json!(!setup_mode && enabled));
+    out.insert("variables".into(), json!(vars.len()));
+    Ok(Value::Object(out))
+}
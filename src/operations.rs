@@ -77,6 +77,93 @@ pub fn host_total_cpus() -> u32 {
     0
 }
 
+/// Hugepage size and free hugepage memory on the host, both in MB.
+/// (0, 0) when hugepages are not available (non-Linux or none reserved).
+pub fn host_hugepages_mb() -> (u64, u64) {
+    #[cfg(target_os = "linux")]
+    {
+        if let Ok(content) = std::fs::read_to_string("/proc/meminfo") {
+            let field = |name: &str| -> u64 {
+                content
+                    .lines()
+                    .find(|l| l.starts_with(name))
+                    .and_then(|l| l.split_whitespace().nth(1))
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(0)
+            };
+            let page_kb = field("Hugepagesize:");
+            let free_pages = field("HugePages_Free:");
+            return (page_kb / 1024, free_pages * page_kb / 1024);
+        }
+    }
+    (0, 0)
+}
+
+/// Validate a host CPU list as accepted by taskset ("2", "0-3", "1,5-7")
+fn validate_cpulist(list: &str, host_cpus: u32) -> Result<(), String> {
+    if list.is_empty() {
+        return Err("Empty CPU list".into());
+    }
+    for part in list.split(',') {
+        let (lo, hi) = match part.split_once('-') {
+            Some((a, b)) => (a, b),
+            None => (part, part),
+        };
+        let lo: u32 = lo.trim().parse().map_err(|_| format!("Invalid CPU list '{}'", list))?;
+        let hi: u32 = hi.trim().parse().map_err(|_| format!("Invalid CPU list '{}'", list))?;
+        if lo > hi || (host_cpus > 0 && hi >= host_cpus) {
+            return Err(format!("CPU list '{}' out of range (host has {} CPUs)", list, host_cpus));
+        }
+    }
+    Ok(())
+}
+
+/// Last QMP-reported memory per VM: smac -> (queried at, MB)
+static ACTUAL_RAM: std::sync::OnceLock<
+    std::sync::Mutex<std::collections::HashMap<String, (std::time::Instant, u64)>>,
+> = std::sync::OnceLock::new();
+
+/// How long an `ACTUAL_RAM` entry is reused before QMP is asked again
+const ACTUAL_RAM_TTL: std::time::Duration = std::time::Duration::from_secs(10);
+
+fn actual_ram_cache() -> &'static std::sync::Mutex<std::collections::HashMap<String, (std::time::Instant, u64)>> {
+    ACTUAL_RAM.get_or_init(|| std::sync::Mutex::new(std::collections::HashMap::new()))
+}
+
+/// Drop the cached size after the VM's memory was changed
+fn forget_actual_ram(smac: &str) {
+    actual_ram_cache().lock().unwrap().remove(smac);
+}
+
+/// Memory a running VM currently holds (MB): the balloon-adjusted size,
+/// else boot + hot-added memory. None when QMP is unreachable.
+/// Cached briefly so admission checks do not query every VM each time.
+fn vm_actual_ram_mb(smac: &str) -> Option<u64> {
+    if let Some((at, mb)) = actual_ram_cache().lock().unwrap().get(smac) {
+        if at.elapsed() < ACTUAL_RAM_TTL {
+            return Some(*mb);
+        }
+    }
+    let mb = query_actual_ram_mb(smac)?;
+    actual_ram_cache()
+        .lock()
+        .unwrap()
+        .insert(smac.to_string(), (std::time::Instant::now(), mb));
+    Some(mb)
+}
+
+fn query_actual_ram_mb(smac: &str) -> Option<u64> {
+    if let Ok(b) = crate::qmp::qmp_command(smac, "query-balloon", None) {
+        if let Some(actual) = b.get("actual").and_then(|v| v.as_u64()) {
+            return Some(actual / 1024 / 1024);
+        }
+    }
+    let summary = crate::qmp::qmp_command(smac, "query-memory-size-summary", None).ok()?;
+    let base = summary.get("base-memory").and_then(|v| v.as_u64())?;
+    let plugged = summary.get("plugged-memory").and_then(|v| v.as_u64()).unwrap_or(0);
+    Some((base + plugged) / 1024 / 1024)
+}
+
 /// Get total RAM allocated by running VMs (in MB)
 pub fn running_vms_ram_mb(exclude_smac: Option<&str>) -> u64 {
     let mut total: u64 = 0;
@@ -90,6 +177,11 @@ pub fn running_vms_ram_mb(exclude_smac: Option<&str>) -> u64 {
                     continue;
                 }
             }
+            // Prefer what the VM really holds (balloon / hot-added DIMMs)
+            if let Some(actual) = vm_actual_ram_mb(&vm.smac) {
+                total += actual;
+                continue;
+            }
             if let Ok(cfg) = serde_json::from_str::<serde_json::Value>(&vm.config) {
                 if let Some(size) = cfg
                     .get("memory")
@@ -225,7 +317,6 @@ fn setup_switch_tap_windows(tap_name: &str) -> Result<(), String> {
 /// Disable a TAP-Windows adapter so it can be re-used on next boot.
 /// Leaving the adapter in place avoids churn from install/uninstall cycles.
 #[cfg(target_os = "windows")]
-#[allow(dead_code)]
 fn cleanup_switch_tap_windows(tap_name: &str) {
     use std::process::Command;
     let _ = Command::new("powershell")
@@ -258,29 +349,9 @@ fn cleanup_switch_taps_windows(smac: &str) {
     }
 }
 
-/// Kill the websockify proxy for a VM if one was spawned at start.
-fn cleanup_websockify(smac: &str) {
-    let pctl_path = get_conf("pctl_path");
-    let pid_file = format!("{}/{}_wsp.pid", pctl_path, smac);
-    if let Ok(pid_str) = std::fs::read_to_string(&pid_file) {
-        if let Ok(pid) = pid_str.trim().parse::<u32>() {
-            #[cfg(target_os = "windows")]
-            let _ = std::process::Command::new("taskkill")
-                .args(["/F", "/PID", &pid.to_string()])
-                .output();
-            #[cfg(unix)]
-            let _ = std::process::Command::new("kill")
-                .arg(pid.to_string())
-                .output();
-        }
-        let _ = std::fs::remove_file(&pid_file);
-    }
-}
-
 /// Clean up TAP interfaces for a VM's switch adapters on Linux
 #[cfg(target_os = "linux")]
 fn cleanup_switch_taps(smac: &str) {
-    use std::process::Command;
     // Load VM config to find switch adapters
     let vm = match db::get_vm(smac) {
         Ok(v) => v,
@@ -290,32 +361,472 @@ fn cleanup_switch_taps(smac: &str) {
         Ok(c) => c,
         Err(_) => return,
     };
-    let use_sudo = get_conf_or("bridge_sudo", "true") == "true";
-    let sudo_path = get_conf_or("bridge_sudo_path", "/usr/bin/sudo");
-
     for adapter in &cfg.network_adapters {
         if adapter.mode == "switch" && !adapter.switch_name.is_empty() {
             let mac_clean = smac.replace(":", "");
             let mac_suffix = &mac_clean[mac_clean.len().saturating_sub(4)..];
             let tap_name = format!("vt{}n{}", mac_suffix, adapter.netid);
-            let result = if use_sudo {
-                Command::new(&sudo_path)
-                    .args(["ip", "link", "del", &tap_name])
-                    .output()
+            delete_switch_tap(&tap_name);
+        }
+    }
+}
+
+/// Delete a single switch TAP interface on Linux
+#[cfg(target_os = "linux")]
+fn delete_switch_tap(tap_name: &str) {
+    use std::process::Command;
+    let use_sudo = get_conf_or("bridge_sudo", "true") == "true";
+    let sudo_path = get_conf_or("bridge_sudo_path", "/usr/bin/sudo");
+    let result = if use_sudo {
+        Command::new(&sudo_path)
+            .args(["ip", "link", "del", tap_name])
+            .output()
+    } else {
+        Command::new("ip")
+            .args(["link", "del", tap_name])
+            .output()
+    };
+    if let Ok(o) = result {
+        if o.status.success() {
+            eprintln!("switch-tap: cleaned up {}", tap_name);
+        }
+    }
+}
+
+/// Release the host-side TAP of one switch adapter (NIC hot-unplug)
+#[cfg(any(target_os = "linux", target_os = "windows"))]
+fn release_switch_tap(smac: &str, adapter: &NetworkAdapter) {
+    if adapter.mode != "switch" || adapter.switch_name.is_empty() {
+        return;
+    }
+    let mac_clean = smac.replace(":", "");
+    let mac_suffix = &mac_clean[mac_clean.len().saturating_sub(4)..];
+    #[cfg(target_os = "linux")]
+    delete_switch_tap(&format!("vt{}n{}", mac_suffix, adapter.netid));
+    #[cfg(target_os = "windows")]
+    cleanup_switch_tap_windows(&format!("vmt{}n{}", mac_suffix, adapter.netid));
+}
+
+/// Switch adapters have no host-side TAP on macOS
+#[cfg(not(any(target_os = "linux", target_os = "windows")))]
+fn release_switch_tap(_smac: &str, _adapter: &NetworkAdapter) {}
+
+/// SLIRP subnet options so the guest lands on the /24 of its MDS local_ipv4
+fn slirp_net_opts(local_ipv4: &str) -> String {
+    let parts: Vec<&str> = local_ipv4.split('.').collect();
+    if local_ipv4.is_empty() || parts.len() != 4 {
+        return String::new();
+    }
+    let net = format!("{}.{}.{}.0/24", parts[0], parts[1], parts[2]);
+    let host = format!("{}.{}.{}.1", parts[0], parts[1], parts[2]);
+    let dns = format!("{}.{}.{}.2", parts[0], parts[1], parts[2]);
+    let last: u8 = parts[3].parse().unwrap_or(10);
+    let dhcp_start = if last <= 2 {
+        format!("{}.{}.{}.10", parts[0], parts[1], parts[2])
+    } else {
+        local_ipv4.to_string()
+    };
+    format!(",net={},host={},dns={},dhcpstart={}",
+        net, host, dns, dhcp_start)
+}
+
+/// hostfwd options built from the VM's port_forwards config
+fn hostfwd_net_opts(vm_cfg: &serde_json::Value, output_log: &mut String) -> String {
+    let mut fwd = String::new();
+    if let Some(forwards) = vm_cfg.get("port_forwards").and_then(|v| v.as_array()) {
+        for rule in forwards {
+            let proto = rule.get("protocol").and_then(|v| v.as_str()).unwrap_or("tcp");
+            let host_port = rule.get("host_port").and_then(|v| v.as_u64()).unwrap_or(0);
+            let guest_port = rule.get("guest_port").and_then(|v| v.as_u64()).unwrap_or(0);
+            if host_port > 0 && guest_port > 0 {
+                fwd.push_str(&format!(",hostfwd={}::{}-:{}", proto, host_port, guest_port));
+                output_log.push_str(&format!("portfwd: {}:{} -> guest:{}\n",
+                    proto, host_port, guest_port));
+            }
+        }
+    }
+    fwd
+}
+
+/// Build the `-netdev` value for one adapter (also used by NIC hot-plug).
+/// Switch mode sets up the host-side TAP as a side effect.
+fn build_netdev_spec(
+    smac: &str,
+    adapter: &NetworkAdapter,
+    slirp_opts: &str,
+    hostfwd_opts: &str,
+    output_log: &mut String,
+) -> Result<String, String> {
+    let spec: Option<String>;
+    if adapter.mode == "switch" && !adapter.switch_name.is_empty() {
+        // Virtual switch mode
+        let vlan_id: u16 = adapter.vlan.parse().unwrap_or(0);
+        if vlan_id > 4094 {
+            return Err(format!(
+                "VLAN {} out of range (0-4094) for adapter {}",
+                vlan_id, adapter.netid
+            ));
+        }
+        match db::get_switch_by_name(&adapter.switch_name) {
+            Ok(sw) => {
+                if sw.mcast_port <= 0 || sw.mcast_port > u16::MAX as i64 {
+                    return Err(format!(
+                        "Switch '{}' has invalid port {} (must be 1..={})",
+                        adapter.switch_name, sw.mcast_port, u16::MAX
+                    ));
+                }
+                let switch_port = sw.mcast_port as u16;
+                let mcast_hi = vlan_id / 256;
+                let mcast_lo = vlan_id % 256;
+                #[cfg(target_os = "linux")]
+                {
+                    // Linux: TAP + bridge per (switch, vlan) pair for L2 isolation.
+                    // Bridge name fits 15-char ifname limit: vb<sw_id>v<vlan> (max ~11 chars).
+                    let bridge_name = format!("vb{}v{}", sw.id, vlan_id);
+                    let mac_clean = smac.replace(":", "");
+                    let mac_suffix = &mac_clean[mac_clean.len().saturating_sub(4)..];
+                    let tap_name = format!("vt{}n{}", mac_suffix, adapter.netid);
+                    output_log.push_str(&format!(
+                        "switch : {} vlan {} → bridge {} tap {}\n",
+                        adapter.switch_name, vlan_id, bridge_name, tap_name
+                    ));
+                    setup_switch_tap(&bridge_name, &tap_name, output_log)?;
+                    spec = Some(format!(
+                        "tap,id=net{},ifname={},script=no,downscript=no",
+                        adapter.netid, tap_name
+                    ));
+                }
+                #[cfg(target_os = "macos")]
+                {
+                    // UDP multicast for many-to-many L2 switching.
+                    // VLAN encoded in mcast address → separate broadcast domain per VLAN.
+                    output_log.push_str(&format!(
+                        "switch : {} (mcast port {})\n",
+                        adapter.switch_name, switch_port
+                    ));
+                    output_log.push_str(&format!(
+                        "vlan   : {} (mcast 230.{}.{}.1:{})\n",
+                        vlan_id, mcast_hi, mcast_lo, switch_port
+                    ));
+                    spec = Some(format!(
+                        "socket,id=net{},mcast=230.{}.{}.1:{}",
+                        adapter.netid, mcast_hi, mcast_lo, switch_port
+                    ));
+                }
+                #[cfg(target_os = "windows")]
+                {
+                    // Windows: use TAP-Windows adapter per VM. install.bat
+                    // auto-installs the driver; we create/rename an adapter
+                    // on demand so each VM gets a dedicated NIC.
+                    let mac_clean = smac.replace(":", "");
+                    let mac_suffix = &mac_clean[mac_clean.len().saturating_sub(4)..];
+                    let tap_name = format!("vmt{}n{}", mac_suffix, adapter.netid);
+                    match setup_switch_tap_windows(&tap_name) {
+                        Ok(_) => {
+                            output_log.push_str(&format!(
+                                "switch : {} → TAP adapter {}\n",
+                                adapter.switch_name, tap_name
+                            ));
+                            spec = Some(format!(
+                                "tap,id=net{},ifname={}",
+                                adapter.netid, tap_name
+                            ));
+                        }
+                        Err(e) => {
+                            output_log.push_str(&format!(
+                                "switch : {} (TAP setup failed: {} — falling back to NAT)\n",
+                                adapter.switch_name, e
+                            ));
+                            spec = Some(format!("user,id=net{}", adapter.netid));
+                        }
+                    }
+                }
+                #[cfg(not(any(target_os = "macos", target_os = "linux", target_os = "windows")))]
+                {
+                    spec = None;
+                }
+                let _ = &sw;
+                let _ = switch_port;
+                let _ = mcast_hi;
+                let _ = mcast_lo;
+            }
+            Err(e) => {
+                return Err(format!(
+                    "Switch '{}' not found for adapter {}: {}",
+                    adapter.switch_name, adapter.netid, e
+                ));
+            }
+        }
+    } else if adapter.mode == "bridge" {
+        // Bridge/tap mode — host↔VM bidirectional connectivity (requires sudo/admin)
+        // Validate bridge_iface to prevent command injection
+        if !adapter.bridge_iface.is_empty() {
+            for c in adapter.bridge_iface.chars() {
+                if !c.is_alphanumeric() && c != '-' && c != '_' && c != ' ' {
+                    return Err(format!(
+                        "Invalid bridge interface name '{}' for adapter {}",
+                        adapter.bridge_iface, adapter.netid
+                    ));
+                }
+            }
+        }
+
+        #[cfg(target_os = "macos")]
+        {
+            if adapter.bridge_iface.is_empty() {
+                // vmnet-shared: macOS built-in DHCP (192.168.64.x), host↔VM bidirectional
+                spec = Some(format!("vmnet-shared,id=net{}", adapter.netid));
+                output_log.push_str("bridge : vmnet-shared (macOS)\n");
+            } else {
+                // vmnet-bridged: bridge to physical interface (e.g. en0)
+                spec = Some(format!(
+                    "vmnet-bridged,id=net{},ifname={}",
+                    adapter.netid, adapter.bridge_iface
+                ));
+                output_log.push_str(&format!(
+                    "bridge : vmnet-bridged ifname={} (macOS)\n",
+                    adapter.bridge_iface
+                ));
+            }
+        }
+        #[cfg(target_os = "linux")]
+        {
+            if adapter.bridge_iface.is_empty() {
+                let tap_name = format!("tap{}", adapter.netid);
+                spec = Some(format!(
+                    "tap,id=net{},ifname={},script=no,downscript=no",
+                    adapter.netid, tap_name
+                ));
+                output_log.push_str(&format!("bridge : tap ifname={} (Linux)\n", tap_name));
+            } else {
+                spec = Some(format!(
+                    "bridge,id=net{},br={}",
+                    adapter.netid, adapter.bridge_iface
+                ));
+                output_log.push_str(&format!(
+                    "bridge : bridge br={} (Linux)\n",
+                    adapter.bridge_iface
+                ));
+            }
+        }
+        #[cfg(target_os = "windows")]
+        {
+            // Windows: TAP-Windows adapter (requires OpenVPN TAP driver installed)
+            let tap_name = if adapter.bridge_iface.is_empty() {
+                "TAP-Windows Adapter V9".to_string()
             } else {
-                Command::new("ip")
-                    .args(["link", "del", &tap_name])
-                    .output()
+                adapter.bridge_iface.clone()
             };
-            if let Ok(o) = result {
-                if o.status.success() {
-                    eprintln!("switch-tap: cleaned up {}", tap_name);
-                }
+            spec = Some(format!(
+                "tap,id=net{},ifname={}",
+                adapter.netid, tap_name
+            ));
+            output_log.push_str(&format!("bridge : tap ifname={} (Windows)\n", tap_name));
+        }
+        #[cfg(not(any(target_os = "macos", target_os = "linux", target_os = "windows")))]
+        {
+            return Err(format!(
+                "Bridge networking not supported on this platform for adapter {}",
+                adapter.netid
+            ));
+        }
+    } else {
+        // Default: NAT (user-mode/SLIRP) networking with port forwarding
+        spec = Some(format!("user,id=net{}{}{}", adapter.netid, slirp_opts, hostfwd_opts));
+    }
+    spec.ok_or_else(|| {
+        format!(
+            "Network mode '{}' not supported on this platform for adapter {}",
+            adapter.mode, adapter.netid
+        )
+    })
+}
+
+/// NIC model: virtio (fastest, needs driver) or e1000 (compatible, no driver needed)
+fn nic_device_model(model: &str) -> &'static str {
+    match model {
+        "e1000" => "e1000",
+        "e1000e" => "e1000e",
+        "rtl8139" => "rtl8139",
+        _ => "virtio-net-pci", // default: virtio
+    }
+}
+
+/// Check shared folder definitions before any sidecar is started and
+/// resolve each host_path below the configured `shared_folders_root`.
+/// Returns the canonical host paths, in order, so symlinks cannot point
+/// virtiofsd outside the root.
+#[cfg(target_os = "linux")]
+fn validate_shared_folders(folders: &[SharedFolder]) -> Result<Vec<String>, String> {
+    let root = get_conf_or("shared_folders_root", "");
+    if root.is_empty() {
+        return Err("Shared folders are disabled: set shared_folders_root in config.yaml".into());
+    }
+    let root = std::fs::canonicalize(&root)
+        .map_err(|e| format!("shared_folders_root {}: {}", root, e))?;
+    let mut host_paths = Vec::new();
+    let mut tags = std::collections::HashSet::new();
+    for f in folders {
+        if f.tag.is_empty()
+            || f.tag.len() > 36
+            || !f.tag.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(format!("Invalid shared folder tag '{}' (1-36 chars: a-z, 0-9, -, _)", f.tag));
+        }
+        if !tags.insert(f.tag.as_str()) {
+            return Err(format!("Duplicate shared folder tag '{}'", f.tag));
+        }
+        if !f.host_path.starts_with('/') || f.host_path.contains(',') {
+            return Err(format!("Shared folder '{}': host_path must be an absolute path without commas", f.tag));
+        }
+        let real = std::fs::canonicalize(&f.host_path)
+            .map_err(|e| format!("Shared folder '{}': {}: {}", f.tag, f.host_path, e))?;
+        if !real.starts_with(&root) {
+            return Err(format!(
+                "Shared folder '{}': {} is outside shared_folders_root {}",
+                f.tag, f.host_path, root.display()
+            ));
+        }
+        if !real.is_dir() {
+            return Err(format!("Shared folder '{}': {} is not a directory", f.tag, f.host_path));
+        }
+        let real = real.to_string_lossy().to_string();
+        if real.contains(',') {
+            return Err(format!("Shared folder '{}': host_path must not resolve to a path with commas", f.tag));
+        }
+        if !f.guest_path.is_empty()
+            && (!f.guest_path.starts_with('/')
+                || f.guest_path.chars().any(|c| c.is_whitespace() || c == '"' || c == '\''))
+        {
+            return Err(format!("Shared folder '{}': invalid guest_path '{}'", f.tag, f.guest_path));
+        }
+        host_paths.push(real);
+    }
+    Ok(host_paths)
+}
+
+/// vhost-user socket of one shared folder's virtiofsd
+#[cfg(target_os = "linux")]
+fn virtiofsd_socket(smac: &str, tag: &str) -> String {
+    format!("{}/{}_fs_{}.sock", get_conf("pctl_path"), smac, tag)
+}
+
+/// Start virtiofsd for one shared folder (`host_path` already resolved by
+/// `validate_shared_folders`) and wait for its socket.
+#[cfg(target_os = "linux")]
+fn start_virtiofsd(smac: &str, folder: &SharedFolder, host_path: &str) -> Result<(), String> {
+    let pctl_path = get_conf("pctl_path");
+    let sock = virtiofsd_socket(smac, &folder.tag);
+    let pid_file = format!("{}/{}_fs_{}.pid", pctl_path, smac, folder.tag);
+    stop_virtiofsd_pid(&pid_file);
+    let _ = std::fs::remove_file(&sock);
+
+    let virtiofsd = get_conf_or("virtiofsd_path", "/usr/libexec/virtiofsd");
+    let mut args = vec![
+        format!("--socket-path={}", sock),
+        format!("--shared-dir={}", host_path),
+        "--cache=auto".to_string(),
+    ];
+    if folder.readonly == "1" {
+        args.push("--readonly".into());
+    }
+    let sandbox = get_conf_or("virtiofsd_sandbox", "");
+    if !sandbox.is_empty() {
+        args.push(format!("--sandbox={}", sandbox));
+    }
+    let args_ref: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+    let (pid, _) = spawn_background(&virtiofsd, &args_ref)
+        .map_err(|e| format!("virtiofsd for '{}' failed: {}", folder.tag, e))?;
+    let _ = std::fs::write(&pid_file, pid.to_string());
+
+    for _ in 0..50 {
+        if std::path::Path::new(&sock).exists() {
+            return Ok(());
+        }
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+    stop_virtiofsd_pid(&pid_file);
+    Err(format!("virtiofsd for '{}' did not create {}", folder.tag, sock))
+}
+
+/// Kill a virtiofsd left over from a previous run (by pid file)
+#[cfg(target_os = "linux")]
+fn stop_virtiofsd_pid(pid_file: &str) {
+    if let Ok(pid_str) = std::fs::read_to_string(pid_file) {
+        if let Ok(pid) = pid_str.trim().parse::<u32>() {
+            let _ = std::process::Command::new("kill")
+                .arg(pid.to_string())
+                .output();
+        }
+        let _ = std::fs::remove_file(pid_file);
+    }
+}
+
+/// Stop all virtiofsd sidecars of a VM
+#[cfg(target_os = "linux")]
+fn cleanup_virtiofsd(smac: &str) {
+    let pctl_path = get_conf("pctl_path");
+    let prefix = format!("{}_fs_", smac);
+    if let Ok(entries) = std::fs::read_dir(&pctl_path) {
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.starts_with(&prefix) && name.ends_with(".pid") {
+                stop_virtiofsd_pid(&entry.path().to_string_lossy());
             }
         }
     }
 }
 
+/// Parse a 4-digit hex USB ID ("0529" or "0x0529")
+fn parse_usb_id(value: &str) -> Result<u32, String> {
+    let hex = value.trim().trim_start_matches("0x").trim_start_matches("0X");
+    if hex.is_empty() || hex.len() > 4 {
+        return Err(format!("Invalid USB ID '{}'", value));
+    }
+    u32::from_str_radix(hex, 16).map_err(|_| format!("Invalid USB ID '{}'", value))
+}
+
+/// Validate a USB passthrough entry and return its device properties as JSON
+/// (also used for QMP device_add)
+fn usb_host_props(dev: &UsbDevice) -> Result<serde_json::Value, String> {
+    let mut props = serde_json::json!({
+        "driver": "usb-host",
+        "id": format!("usb{}", dev.usbid),
+        "bus": "xhci.0",
+    });
+    if !dev.hostbus.is_empty() || !dev.hostport.is_empty() {
+        let bus: u32 = dev.hostbus.trim().parse()
+            .map_err(|_| format!("Invalid USB hostbus '{}'", dev.hostbus))?;
+        if dev.hostport.is_empty() || !dev.hostport.chars().all(|c| c.is_ascii_digit() || c == '.') {
+            return Err(format!("Invalid USB hostport '{}'", dev.hostport));
+        }
+        props["hostbus"] = serde_json::json!(bus);
+        props["hostport"] = serde_json::json!(dev.hostport);
+    } else if !dev.vendor.is_empty() && !dev.product.is_empty() {
+        props["vendorid"] = serde_json::json!(parse_usb_id(&dev.vendor)?);
+        props["productid"] = serde_json::json!(parse_usb_id(&dev.product)?);
+    } else {
+        return Err(format!("USB device {}: set vendor+product or hostbus+hostport", dev.usbid));
+    }
+    Ok(props)
+}
+
+/// `-device` value for a USB passthrough entry
+fn usb_host_device_spec(dev: &UsbDevice) -> Result<String, String> {
+    let props = usb_host_props(dev)?;
+    let mut spec = format!("usb-host,bus=xhci.0,id=usb{}", dev.usbid);
+    if let Some(bus) = props.get("hostbus") {
+        spec.push_str(&format!(",hostbus={},hostport={}", bus, dev.hostport));
+    } else {
+        spec.push_str(&format!(
+            ",vendorid=0x{:04x},productid=0x{:04x}",
+            parse_usb_id(&dev.vendor)?,
+            parse_usb_id(&dev.product)?
+        ));
+    }
+    Ok(spec)
+}
+
 /// Ensure a writable pflash NVRAM file is in qcow2 format (converting in-place from raw).
 /// savevm refuses to run if any writable device backed by raw is attached, so NVRAM must be qcow2.
 /// Idempotent: returns Ok if already qcow2 or if file is missing (caller creates it later).
@@ -358,6 +869,12 @@ pub const VNC_PORT_MAX: u16 = 13000;
 pub const VNC_PORT_STEP: u16 = 2;
 pub const VNC_PORT_BASE: u16 = 12000;
 
+/// Number of spare pcie-root-ports (hp0..) added on q35/virt for hot-plug
+pub const HOTPLUG_PORTS: u8 = 4;
+
+/// DIMM slots reserved for memory hot-add when memory.max_size is set
+pub const MEMORY_HOTPLUG_SLOTS: u8 = 8;
+
 /// Check if an ISO is mounted by any running VM — returns Err with VM name if so
 pub fn check_iso_not_mounted(iso_name: &str) -> Result<(), String> {
     use crate::api_helpers::qemu_monitor_cmd;
@@ -439,6 +956,21 @@ pub fn check_disk_not_in_use(disk_name: &str) -> Result<(), String> {
     Ok(())
 }
 
+/// Guest hostname: the VM name, with the MDS hostname prefix unless that
+/// would duplicate it (like "GW-GW" or "vm-vm3")
+fn vm_hostname(config: &mds::MdsConfig, vm_name: &str) -> String {
+    if config.hostname_prefix.is_empty()
+        || config.hostname_prefix == vm_name
+        || config.hostname_prefix == "nocloud"
+        || vm_name.starts_with(&format!("{}-", config.hostname_prefix))
+        || vm_name.starts_with(&config.hostname_prefix)
+    {
+        vm_name.to_string()
+    } else {
+        format!("{}-{}", config.hostname_prefix, vm_name)
+    }
+}
+
 /// Generate a cloud-init NoCloud seed ISO from per-VM MDS config.
 /// Returns (iso_path, vmctl_password).
 fn generate_seed_iso(vm_name: &str) -> Result<(String, String), String> {
@@ -450,8 +982,12 @@ fn generate_seed_iso(vm_name: &str) -> Result<(String, String), String> {
     let _ = std::fs::create_dir_all(&seed_dir);
 
     // Load per-VM MDS config from DB, fall back to global
-    let config = if let Ok(vm) = db::get_vm(vm_name) {
+    let mut shared_folders = Vec::new();
+    let mut config = if let Ok(vm) = db::get_vm(vm_name) {
         let vm_config: serde_json::Value = serde_json::from_str(&vm.config).unwrap_or_default();
+        if let Some(folders) = vm_config.get("shared_folders") {
+            shared_folders = serde_json::from_value(folders.clone()).unwrap_or_default();
+        }
         if let Some(mds_val) = vm_config.get("mds") {
             serde_json::from_value::<mds::MdsConfig>(mds_val.clone())
                 .unwrap_or_else(|_| mds::load_mds_config())
@@ -461,20 +997,13 @@ fn generate_seed_iso(vm_name: &str) -> Result<(String, String), String> {
     } else {
         mds::load_mds_config()
     };
+    // Only shares that actually get a virtiofsd (Linux hosts) are mounted
+    if cfg!(target_os = "linux") {
+        config.shared_folders = shared_folders;
+    }
 
     // Generate meta-data (NoCloud format with full MDS fields)
-    // Avoid duplicated hostnames like "GW-GW" or "vm-vm3" when VM name already
-    // starts with the prefix (or equals it)
-    let hostname = if config.hostname_prefix.is_empty()
-        || config.hostname_prefix == vm_name
-        || config.hostname_prefix == "nocloud"
-        || vm_name.starts_with(&format!("{}-", config.hostname_prefix))
-        || vm_name.starts_with(&config.hostname_prefix)
-    {
-        vm_name.to_string()
-    } else {
-        format!("{}-{}", config.hostname_prefix, vm_name)
-    };
+    let hostname = vm_hostname(&config, vm_name);
     let mut meta_data = String::new();
     // Use unique instance-id per boot — cloud-init only re-applies hostname/config
     // when instance-id changes. Append timestamp so every VM start is a "new instance".
@@ -552,28 +1081,38 @@ fn generate_seed_iso(vm_name: &str) -> Result<(String, String), String> {
             .map_err(|e| format!("Failed to write network-config: {}", e))?;
     }
 
-    // Create ISO — platform-specific tool
-    let _ = std::fs::remove_file(&iso_path); // remove old ISO if exists
+    // Create ISO, then clean up the seed directory either way
+    let built = build_iso(&seed_dir, &iso_path, "CIDATA");
+    let _ = std::fs::remove_dir_all(&seed_dir);
+    built.map_err(|e| format!("Seed ISO: {}", e))?;
+
+    Ok((iso_path, vmctl_password))
+}
+
+/// Build a small Joliet/Rock Ridge ISO from a directory with the platform's
+/// tool (hdiutil, genisoimage/mkisofs, or MSYS2 mkisofs on Windows)
+fn build_iso(dir: &str, iso_path: &str, volid: &str) -> Result<(), String> {
+    let _ = std::fs::remove_file(iso_path); // remove old ISO if exists
     #[cfg(target_os = "macos")]
     {
         run_cmd("hdiutil", &[
             "makehybrid", "-iso", "-joliet",
-            "-default-volume-name", "CIDATA",
-            "-o", &iso_path, &seed_dir,
-        ]).map_err(|e| format!("Failed to create seed ISO: {}", e))?;
+            "-default-volume-name", volid,
+            "-o", iso_path, dir,
+        ]).map_err(|e| format!("Failed to create ISO: {}", e))?;
     }
     #[cfg(target_os = "linux")]
     {
         // Try genisoimage first, fall back to mkisofs
         let result = run_cmd("genisoimage", &[
-            "-output", &iso_path, "-volid", "CIDATA",
-            "-joliet", "-rock", &seed_dir,
+            "-output", iso_path, "-volid", volid,
+            "-joliet", "-rock", dir,
         ]);
         if result.is_err() {
             run_cmd("mkisofs", &[
-                "-output", &iso_path, "-volid", "CIDATA",
-                "-joliet", "-rock", &seed_dir,
-            ]).map_err(|e| format!("Failed to create seed ISO (install genisoimage or mkisofs): {}", e))?;
+                "-output", iso_path, "-volid", volid,
+                "-joliet", "-rock", dir,
+            ]).map_err(|e| format!("Failed to create ISO (install genisoimage or mkisofs): {}", e))?;
         }
     }
     #[cfg(target_os = "windows")]
@@ -588,44 +1127,353 @@ fn generate_seed_iso(vm_name: &str) -> Result<(String, String), String> {
             ));
         }
         run_cmd(&mkisofs_path, &[
-            "-output", &iso_path, "-volid", "CIDATA",
-            "-joliet", "-rock", &seed_dir,
-        ]).map_err(|e| format!("Failed to create seed ISO: {}", e))?;
+            "-output", iso_path, "-volid", volid,
+            "-joliet", "-rock", dir,
+        ]).map_err(|e| format!("Failed to create ISO: {}", e))?;
     }
 
-    // Cleanup seed directory
-    let _ = std::fs::remove_dir_all(&seed_dir);
-
     // Verify ISO was actually created
-    if !std::path::Path::new(&iso_path).exists() {
+    if !std::path::Path::new(iso_path).exists() {
         // hdiutil sometimes appends .iso automatically
         let alt = format!("{}.iso", iso_path);
         if std::path::Path::new(&alt).exists() {
-            let _ = std::fs::rename(&alt, &iso_path);
+            let _ = std::fs::rename(&alt, iso_path);
         } else {
-            return Err(format!("Seed ISO not found at {} after creation", iso_path));
+            return Err(format!("ISO not found at {} after creation", iso_path));
         }
     }
-
-    Ok((iso_path, vmctl_password))
+    Ok(())
 }
 
-/// Create an ISO from files in a directory and auto-mount on a free CD drive
-pub fn create_and_mount_sendfiles_iso(
-    smac: &str,
-    temp_dir: &str,
-) -> Result<(String, String), String> {
-    let iso_dir = get_conf("iso_path");
-    let timestamp = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_secs();
-    let safe_name = sanitize_name(smac).unwrap_or(smac);
-    let iso_name = format!("sendfiles_{}_{}.iso", safe_name, timestamp);
-    let iso_path = format!("{}/{}", iso_dir, iso_name);
+/// Answer-file ISO for an unattended Windows install (`{pctl_path}/unattend_{smac}.iso`,
+/// volume UNATTEND). Setup finds `autounattend.xml` at the root of any
+/// removable drive.
+fn generate_unattend_iso(smac: &str, cfg: &VmStartConfig, uefi: bool, is_aarch64: bool) -> Result<String, String> {
+    let vm_config: serde_json::Value = db::get_vm(smac)
+        .ok()
+        .and_then(|vm| serde_json::from_str(&vm.config).ok())
+        .unwrap_or_default();
+    let mds_config = vm_mds_config(&vm_config);
+    let password = [
+        &cfg.unattend.admin_password,
+        &mds_config.windows_admin_password,
+        &mds_config.root_password,
+    ]
+    .into_iter()
+    .find(|p| !p.is_empty())
+    .ok_or("No administrator password: set unattend admin_password, or windows_admin_password / root_password in the MDS config")?;
+    let computer_name = windows_computer_name(&mds_config, smac);
+    let xml = crate::unattend::generate(&crate::unattend::UnattendParams {
+        settings: &cfg.unattend,
+        admin_password: password,
+        computer_name: &computer_name,
+        uefi,
+        is_aarch64,
+    });
 
-    // Create ISO — same platform-specific approach as generate_seed_iso
-    let _ = std::fs::remove_file(&iso_path);
+    let pctl_path = get_conf("pctl_path");
+    let dir = format!("{}/unattend_{}", pctl_path, smac);
+    let iso_path = format!("{}/unattend_{}.iso", pctl_path, smac);
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).map_err(|e| format!("Create {}: {}", dir, e))?;
+    let written = std::fs::write(format!("{}/autounattend.xml", dir), xml)
+        .map_err(|e| format!("Write autounattend.xml: {}", e))
+        .and_then(|_| build_iso(&dir, &iso_path, "UNATTEND"));
+    let _ = std::fs::remove_dir_all(&dir);
+    written?;
+    Ok(iso_path)
+}
+
+/// The answer file is consumed by the first boot from the install media:
+/// Setup caches it on the target disk, and booting the ISO again would
+/// start over and wipe disk 0. Stop attaching it from the next start on.
+fn finish_unattended_install(smac: &str) {
+    let Ok(vm) = db::get_vm(smac) else { return };
+    let Ok(mut vm_config) = serde_json::from_str::<serde_json::Value>(&vm.config) else { return };
+    if vm_config.pointer("/unattend/enabled").and_then(|v| v.as_str()) != Some("1") {
+        return;
+    }
+    vm_config["unattend"]["enabled"] = serde_json::json!("0");
+    match db::update_vm(smac, &serde_json::to_string(&vm_config).unwrap_or_default()) {
+        Ok(()) => {
+            log::info!("unattended install of {} started from the install media", smac);
+            let _ = std::fs::remove_file(format!("{}/unattend_{}.iso", get_conf("pctl_path"), smac));
+        }
+        Err(e) => log::warn!("unattended install {}: {}", smac, e),
+    }
+}
+
+/// Windows install media show "Press any key to boot from CD or DVD" and
+/// fall through to the empty disk after a few seconds; answer it for the
+/// first seconds after start (`unattend_boot_keys`), then mark the answer
+/// file as used so later boots run from disk
+fn press_boot_keys(smac: &str) {
+    let press = get_conf_or("unattend_boot_keys", "true") == "true";
+    let smac = smac.to_string();
+    std::thread::spawn(move || {
+        for _ in 0..20 {
+            std::thread::sleep(std::time::Duration::from_secs(1));
+            if press {
+                let _ = crate::keys::send_combos(&smac, "ret", 100);
+            }
+        }
+        finish_unattended_install(&smac);
+    });
+}
+
+/// Direct kernel boot of a Linux installer ISO (see autoinstall.rs)
+struct LinuxInstallBoot {
+    iso: String,
+    kernel: String,
+    initrd: String,
+    cmdline: String,
+}
+
+/// Values that end up on the kernel command line or in answer files
+fn validate_linux_install(s: &LinuxInstallSettings) -> Result<(), String> {
+    if !crate::autoinstall::FLAVORS.contains(&s.flavor.as_str()) {
+        return Err(format!("Unknown install flavor '{}' (ubuntu, debian, rhel)", s.flavor));
+    }
+    if s.iso.is_empty() {
+        return Err("No installer ISO selected".into());
+    }
+    sanitize_name(&s.iso)?;
+    let mut user = s.username.chars();
+    if !user.next().is_some_and(|c| c.is_ascii_lowercase() || c == '_')
+        || !user.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-')
+    {
+        return Err(format!("Invalid install username '{}'", s.username));
+    }
+    for (name, v) in [("locale", &s.locale), ("keyboard", &s.keyboard), ("timezone", &s.timezone)] {
+        if v.is_empty() || !v.chars().all(|c| c.is_ascii_alphanumeric() || "._-/+:@".contains(c)) {
+            return Err(format!("Invalid install {} '{}'", name, v));
+        }
+    }
+    if s.kernel_args.contains(['\n', '\r']) {
+        return Err("Kernel arguments must be a single line".into());
+    }
+    Ok(())
+}
+
+/// Copy one file out of an ISO (the first of `candidates` that exists) to
+/// `dest`. bsdtar ships with macOS and Windows; 7z and xorriso are fallbacks.
+fn extract_iso_file(iso: &str, candidates: &[&str], dest: &str) -> Result<(), String> {
+    let tmp = format!("{}.extract", dest);
+    let mut last_err = String::from("no extractor ran");
+    for inner in candidates {
+        let base = inner.rsplit('/').next().unwrap_or(inner);
+        let _ = std::fs::remove_dir_all(&tmp);
+        std::fs::create_dir_all(&tmp).map_err(|e| format!("Create {}: {}", tmp, e))?;
+        let attempts: [(&str, Vec<String>, String); 3] = [
+            ("bsdtar", vec!["-x".into(), "-f".into(), iso.into(), "-C".into(), tmp.clone(), inner.to_string()],
+                format!("{}/{}", tmp, inner)),
+            ("7z", vec!["e".into(), "-y".into(), format!("-o{}", tmp), iso.into(), inner.to_string()],
+                format!("{}/{}", tmp, base)),
+            ("xorriso", vec!["-osirrox".into(), "on".into(), "-indev".into(), iso.into(),
+                "-extract".into(), format!("/{}", inner), format!("{}/{}", tmp, base)],
+                format!("{}/{}", tmp, base)),
+        ];
+        for (program, args, out) in &attempts {
+            let args: Vec<&str> = args.iter().map(|a| a.as_str()).collect();
+            match run_cmd(program, &args) {
+                Ok(_) if std::path::Path::new(out).is_file() => {
+                    let moved = std::fs::rename(out, dest).map_err(|e| format!("Move {}: {}", out, e));
+                    let _ = std::fs::remove_dir_all(&tmp);
+                    return moved;
+                }
+                Ok(_) => last_err = format!("{}: {} not in ISO", program, inner),
+                Err(e) => last_err = e,
+            }
+        }
+    }
+    let _ = std::fs::remove_dir_all(&tmp);
+    Err(format!("Cannot extract {} from {}: {}", candidates.join(" / "), iso, last_err))
+}
+
+/// Answers are served from `{autoinstall_url}/autoinstall/{smac}/{token}`
+fn linux_install_base_url(smac: &str, token: &str) -> String {
+    format!(
+        "{}/autoinstall/{}/{}",
+        get_conf_or("autoinstall_url", "http://169.254.169.254").trim_end_matches('/'),
+        smac,
+        token
+    )
+}
+
+/// Random per-install token, kept next to the extracted kernel. Only the
+/// installer (through its kernel command line) knows it.
+fn linux_install_token_file(smac: &str) -> String {
+    format!("{}/install_{}/token", get_conf("pctl_path"), smac)
+}
+
+/// VMs stopped through the API, so `watch_linux_install` can tell them from
+/// an installer that exited on its own
+static STOP_REQUESTED: std::sync::OnceLock<std::sync::Mutex<std::collections::HashSet<String>>> =
+    std::sync::OnceLock::new();
+
+fn stop_requested() -> &'static std::sync::Mutex<std::collections::HashSet<String>> {
+    STOP_REQUESTED.get_or_init(|| std::sync::Mutex::new(std::collections::HashSet::new()))
+}
+
+fn request_stop(smac: &str) {
+    stop_requested().lock().unwrap_or_else(|e| e.into_inner()).insert(smac.to_string());
+}
+
+/// Clears the flag; true if a stop had been requested
+fn take_stop_request(smac: &str) -> bool {
+    stop_requested().lock().unwrap_or_else(|e| e.into_inner()).remove(smac)
+}
+
+/// Extract the installer kernel and initrd to `{pctl_path}/install_{smac}/`
+fn prepare_linux_install(smac: &str, cfg: &VmStartConfig, is_aarch64: bool) -> Result<LinuxInstallBoot, String> {
+    let s = &cfg.linux_install;
+    validate_linux_install(s)?;
+    let iso = format!("{}/{}", get_conf("iso_path"), s.iso);
+    if !std::path::Path::new(&iso).is_file() {
+        return Err(format!("Installer ISO '{}' not found", s.iso));
+    }
+    let dir = format!("{}/install_{}", get_conf("pctl_path"), smac);
+    std::fs::create_dir_all(&dir).map_err(|e| format!("Create {}: {}", dir, e))?;
+    let (kernels, initrds) = crate::autoinstall::boot_files(&s.flavor, is_aarch64);
+    let kernel = format!("{}/vmlinuz", dir);
+    let initrd = format!("{}/initrd", dir);
+    extract_iso_file(&iso, kernels, &kernel)?;
+    extract_iso_file(&iso, initrds, &initrd)?;
+
+    let vm_config: serde_json::Value = db::get_vm(smac)
+        .ok()
+        .and_then(|vm| serde_json::from_str(&vm.config).ok())
+        .unwrap_or_default();
+    let hostname = vm_hostname(&vm_mds_config(&vm_config), smac);
+    let token = random_password(32);
+    let token_file = linux_install_token_file(smac);
+    std::fs::write(&token_file, &token).map_err(|e| format!("Write {}: {}", token_file, e))?;
+    let cmdline = crate::autoinstall::kernel_cmdline(s, &linux_install_base_url(smac, &token), &hostname);
+    Ok(LinuxInstallBoot { iso, kernel, initrd, cmdline })
+}
+
+/// Answer file for a pending install, requested by the installer through
+/// the MDS (`user-data`, `meta-data`, `vendor-data`, `preseed.cfg`, `ks.cfg`).
+/// `done` marks the install finished. `token` must match the one the
+/// installer was booted with.
+pub fn linux_install_file(smac: &str, token: &str, file: &str) -> Result<String, String> {
+    sanitize_name(smac)?;
+    let expected = std::fs::read_to_string(linux_install_token_file(smac)).unwrap_or_default();
+    if expected.is_empty() || expected.trim() != token {
+        return Err(format!("No unattended install pending for '{}'", smac));
+    }
+    let vm = db::get_vm(smac)?;
+    let vm_config: serde_json::Value =
+        serde_json::from_str(&vm.config).map_err(|e| format!("Config parse error: {}", e))?;
+    let settings: LinuxInstallSettings = vm_config
+        .get("linux_install")
+        .and_then(|v| serde_json::from_value(v.clone()).ok())
+        .unwrap_or_default();
+    if settings.enabled != "1" {
+        return Err(format!("No unattended install pending for '{}'", smac));
+    }
+    validate_linux_install(&settings)?;
+    if file == "done" {
+        finish_linux_install(smac);
+        return Ok("ok\n".into());
+    }
+
+    let mds_config = vm_mds_config(&vm_config);
+    let hostname = vm_hostname(&mds_config, smac);
+    let password = if settings.password.is_empty() { &mds_config.root_password } else { &settings.password };
+    if password.is_empty() {
+        return Err("No install password: set linux_install password or the MDS root_password".into());
+    }
+    let password_hash = crate::autoinstall::hash_password(password);
+    let packages: Vec<String> = mds_config
+        .extra_packages
+        .split(',')
+        .map(|p| p.trim())
+        .filter(|p| !p.is_empty() && p.chars().all(|c| c.is_ascii_alphanumeric() || ".+-_:@".contains(c)))
+        .map(String::from)
+        .collect();
+    let ssh_pubkey = mds_config.ssh_pubkey.lines().next().unwrap_or("").trim().to_string();
+    let done_url = format!("{}/done", linux_install_base_url(smac, token));
+    let params = crate::autoinstall::InstallParams {
+        settings: &settings,
+        hostname: &hostname,
+        password_hash: &password_hash,
+        ssh_pubkey: &ssh_pubkey,
+        packages: &packages,
+        done_url: &done_url,
+    };
+    match (settings.flavor.as_str(), file) {
+        ("ubuntu", "user-data") => Ok(crate::autoinstall::ubuntu_user_data(&params)),
+        ("ubuntu", "meta-data") => Ok(crate::autoinstall::ubuntu_meta_data(&hostname)),
+        ("ubuntu", "vendor-data") => Ok(String::new()),
+        ("debian", "preseed.cfg") => Ok(crate::autoinstall::debian_preseed(&params)),
+        ("rhel", "ks.cfg") => Ok(crate::autoinstall::kickstart(&params)),
+        _ => Err(format!("No '{}' for a {} install", file, settings.flavor)),
+    }
+}
+
+/// The installer reported success: boot from disk from now on
+fn finish_linux_install(smac: &str) {
+    let Ok(vm) = db::get_vm(smac) else { return };
+    let Ok(mut vm_config) = serde_json::from_str::<serde_json::Value>(&vm.config) else { return };
+    if vm_config.pointer("/linux_install/enabled").and_then(|v| v.as_str()) != Some("1") {
+        return;
+    }
+    vm_config["linux_install"]["enabled"] = serde_json::json!("0");
+    match db::update_vm(smac, &serde_json::to_string(&vm_config).unwrap_or_default()) {
+        Ok(()) => {
+            log::info!("unattended install of {} finished", smac);
+            let _ = std::fs::remove_dir_all(format!("{}/install_{}", get_conf("pctl_path"), smac));
+        }
+        Err(e) => log::warn!("unattended install {}: {}", smac, e),
+    }
+}
+
+/// The installer runs with -no-reboot, so QEMU exits when it reboots at
+/// the end. If it reported `done` by then, start the VM again from disk.
+fn watch_linux_install(smac: &str) {
+    let smac = smac.to_string();
+    std::thread::spawn(move || {
+        while crate::qmp::qemu_alive(&smac) {
+            std::thread::sleep(std::time::Duration::from_secs(5));
+        }
+        // Stopped through the API: nothing to do
+        if take_stop_request(&smac) {
+            return;
+        }
+        let Ok(vm) = db::get_vm(&smac) else { return };
+        cleanup_after_exit(&smac);
+        let _ = db::set_vm_status(&smac, "stopped");
+        let pending = serde_json::from_str::<serde_json::Value>(&vm.config)
+            .ok()
+            .and_then(|c| c.pointer("/linux_install/enabled").and_then(|v| v.as_str()).map(String::from));
+        if pending.as_deref() == Some("1") {
+            log::warn!("installer of {} exited before reporting done; VM left stopped", smac);
+            return;
+        }
+        match start(&serde_json::json!({ "smac": smac }).to_string()) {
+            Ok(_) => log::info!("{} restarted from disk after unattended install", smac),
+            Err(e) => log::warn!("{}: restart after unattended install failed: {}", smac, e),
+        }
+    });
+}
+
+/// Create an ISO from files in a directory and auto-mount on a free CD drive
+pub fn create_and_mount_sendfiles_iso(
+    smac: &str,
+    temp_dir: &str,
+) -> Result<(String, String), String> {
+    let iso_dir = get_conf("iso_path");
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let safe_name = sanitize_name(smac).unwrap_or(smac);
+    let iso_name = format!("sendfiles_{}_{}.iso", safe_name, timestamp);
+    let iso_path = format!("{}/{}", iso_dir, iso_name);
+
+    // Create ISO — same platform-specific approach as generate_seed_iso
+    let _ = std::fs::remove_file(&iso_path);
     #[cfg(target_os = "macos")]
     {
         run_cmd(
@@ -809,21 +1657,31 @@ fn start_vm_with_config(smac: &str, cfg: &VmStartConfig) -> Result<String, Strin
     // Build QEMU arguments safely (no shell involved)
     let mut qemu_args: Vec<String> = Vec::new();
 
+    let video = display_video(cfg, is_aarch64)?;
+    output_log.push_str(&format!("display: {} ({})\n", cfg.display.protocol, video));
+
+    // Boot settings: firmware, device order, menu, direct kernel boot
+    validate_boot(&cfg.boot, is_aarch64, is_windows)?;
+    let boot_order = boot_order(&cfg.boot)?;
+    let x86_firmware = if is_aarch64 { None } else { x86_uefi_firmware(&cfg.boot, is_windows)? };
+    let boot_kernel = boot_kernel_files(&cfg.boot)?;
+    // -kernel registers its own boot entry with bootindex 0
+    let kernel_boot = boot_kernel.is_some() || (!is_windows && cfg.linux_install.enabled == "1");
+    let boot_arg = match boot_menu_timeout(&cfg.boot)? {
+        0 => "d".to_string(),
+        ms => format!("order=d,menu=on,splash-time={}", ms),
+    };
+    output_log.push_str(&format!("boot order: {} (menu {}ms)\n", boot_order.join(","), cfg.boot.menu_timeout));
+
     // Boot options — aarch64 virt machine uses virtio-gpu instead of -vga std
     // NOTE: no -daemonize — QEMU's daemonize + websocket VNC crashes on macOS.
     // We use spawn_background() instead to run QEMU in the background.
     if is_aarch64 {
-        qemu_args.extend([
-            "-nodefaults", "-boot", "d",
-        ].map(String::from));
-
-        // aarch64 Windows: use Secure Boot firmware (Debian AAVMF with Microsoft keys)
-        // aarch64 Linux: use standard edk2 firmware
-        let use_aarch64_secboot = is_windows && {
-            let sc = get_conf_or("edk2_aarch64_secure_code",
-                "/opt/homebrew/share/qemu/AAVMF_CODE.secboot.fd");
-            std::path::Path::new(&sc).exists()
-        };
+        qemu_args.extend(["-nodefaults".into(), "-boot".into(), boot_arg.clone()]);
+
+        // boot.secure_boot (default: Windows only): Secure Boot firmware
+        // (Debian AAVMF with Microsoft keys), else standard edk2 firmware
+        let use_aarch64_secboot = aarch64_secure_boot(&cfg.boot, is_windows)?;
 
         let bios;
         let nvram_file = format!("{}/{}_efivars.fd", pctl_path, ismac);
@@ -869,7 +1727,7 @@ fn start_vm_with_config(smac: &str, cfg: &VmStartConfig) -> Result<String, Strin
                     }
                 }
             }
-            if is_windows {
+            if is_windows && cfg.boot.secure_boot != "0" {
                 output_log.push_str(&format!(
                     "Secure Boot: DISABLED (aarch64 secure firmware not found)\n"));
                 output_log.push_str("  Install AAVMF_CODE.secboot.fd + AAVMF_VARS.ms.fd for Secure Boot\n");
@@ -900,7 +1758,7 @@ fn start_vm_with_config(smac: &str, cfg: &VmStartConfig) -> Result<String, Strin
         qemu_args.push("virtio-gpu-pci".into());
         // USB controller + keyboard + tablet (virt machine has no PS/2)
         qemu_args.push("-device".into());
-        qemu_args.push("qemu-xhci".into());
+        qemu_args.push("qemu-xhci,id=xhci".into());
         qemu_args.push("-device".into());
         qemu_args.push("usb-kbd".into());
         qemu_args.push("-device".into());
@@ -921,48 +1779,52 @@ fn start_vm_with_config(smac: &str, cfg: &VmStartConfig) -> Result<String, Strin
         let cpu_model = get_conf_or("qemu_cpu_x86", cpu_default);
         qemu_args.push("-cpu".into());
         qemu_args.push(cpu_model);
-        qemu_args.extend([
-            "-nodefaults", "-vga", "std", "-boot", "d",
-        ].map(String::from));
-
-        // x86_64 Windows: enable UEFI Secure Boot via pflash if firmware available
-        if is_windows {
-            let secure_code = get_conf_or("edk2_x86_secure_code",
-                "/opt/homebrew/share/qemu/edk2-x86_64-secure-code.fd");
-            let vars_template = get_conf_or("edk2_x86_vars",
-                "/opt/homebrew/share/qemu/edk2-i386-vars.fd");
-
-            if std::path::Path::new(&secure_code).exists() {
-                // Per-VM NVRAM file: prefer template NVRAM, fallback to generic
-                let nvram_file = format!("{}/{}_efivars.fd", pctl_path, ismac);
-                if !std::path::Path::new(&nvram_file).exists() {
-                    if let Some(tpl_nvram) = find_template_nvram(cfg) {
-                        log::info!("Using template NVRAM: {}", tpl_nvram);
-                        let _ = std::fs::copy(&tpl_nvram, &nvram_file);
-                    } else if std::path::Path::new(&vars_template).exists() {
-                        let _ = std::fs::copy(&vars_template, &nvram_file);
-                    } else {
-                        let _ = std::fs::write(&nvram_file, vec![0u8; 256 * 1024]);
-                    }
+        qemu_args.extend(["-nodefaults".into(), "-boot".into(), boot_arg.clone()]);
+        match video {
+            "qxl" => qemu_args.extend(["-device", "qxl-vga"].map(String::from)),
+            "virtio" => qemu_args.extend(["-device", "virtio-vga"].map(String::from)),
+            _ => qemu_args.extend(["-vga", "std"].map(String::from)),
+        }
+
+        // x86_64 UEFI via pflash (boot.firmware; Secure Boot edk2 per boot.secure_boot)
+        if let Some(fw) = &x86_firmware {
+            // Per-VM NVRAM file: prefer template NVRAM, fallback to generic
+            let nvram_file = format!("{}/{}_efivars.fd", pctl_path, ismac);
+            if !std::path::Path::new(&nvram_file).exists() {
+                if let Some(tpl_nvram) = find_template_nvram(cfg) {
+                    log::info!("Using template NVRAM: {}", tpl_nvram);
+                    let _ = std::fs::copy(&tpl_nvram, &nvram_file);
+                } else if std::path::Path::new(&fw.vars).exists() {
+                    let _ = std::fs::copy(&fw.vars, &nvram_file);
+                } else {
+                    let _ = std::fs::write(&nvram_file, vec![0u8; 256 * 1024]);
                 }
-                // pflash0 = secure firmware code (read-only)
-                // pflash1 = NVRAM with Secure Boot keys (read-write, qcow2 for savevm).
-                ensure_nvram_qcow2(&nvram_file)?;
-                qemu_args.push("-drive".into());
-                qemu_args.push(format!(
-                    "if=pflash,format=raw,readonly=on,file={}", secure_code));
-                qemu_args.push("-drive".into());
-                qemu_args.push(format!(
-                    "if=pflash,format=qcow2,file={}", nvram_file));
+            }
+            // pflash0 = firmware code (read-only)
+            // pflash1 = NVRAM (read-write, qcow2 for savevm).
+            ensure_nvram_qcow2(&nvram_file)?;
+            qemu_args.push("-drive".into());
+            qemu_args.push(format!(
+                "if=pflash,format=raw,readonly=on,file={}", fw.code));
+            qemu_args.push("-drive".into());
+            qemu_args.push(format!(
+                "if=pflash,format=qcow2,file={}", nvram_file));
+            if fw.secure {
                 // SMM required for Secure Boot on x86_64
                 qemu_args.push("-global".into());
                 qemu_args.push("driver=cfi.pflash01,property=secure,value=on".into());
                 output_log.push_str("Secure Boot: ENABLED (edk2 secure pflash)\n");
             } else {
-                output_log.push_str(&format!(
-                    "Secure Boot: DISABLED (firmware not found: {})\n", secure_code));
-                output_log.push_str("  Use Win11 Bypass button to skip Secure Boot check\n");
+                output_log.push_str(&format!("firmware: UEFI ({})\n", fw.code));
+                if is_windows && cfg.boot.secure_boot != "0" {
+                    output_log.push_str(&format!(
+                        "Secure Boot: DISABLED (firmware not found: {})\n",
+                        get_conf_or("edk2_x86_secure_code", "/opt/homebrew/share/qemu/edk2-x86_64-secure-code.fd")));
+                    output_log.push_str("  Use Win11 Bypass button to skip Secure Boot check\n");
+                }
             }
+        } else {
+            output_log.push_str("firmware: BIOS (SeaBIOS)\n");
         }
     }
 
@@ -979,7 +1841,7 @@ fn start_vm_with_config(smac: &str, cfg: &VmStartConfig) -> Result<String, Strin
             .output()
             .is_ok()
         {
-            let tpm_dir = format!("{}/{}_tpm", pctl_path, ismac);
+            let tpm_dir = tpm_dir(&ismac);
             let _ = std::fs::create_dir_all(&tpm_dir);
             let tpm_sock = format!("{}/{}_tpm.sock", pctl_path, ismac);
 
@@ -1021,7 +1883,40 @@ fn start_vm_with_config(smac: &str, cfg: &VmStartConfig) -> Result<String, Strin
         }
     }
 
-    // Display VNC with built-in WebSocket (no websockify needed)
+    // Shared folders — one virtiofsd sidecar per folder; like swtpm it
+    // exits on its own once QEMU closes the vhost-user socket. The sidecars
+    // are spawned right before QEMU, once every other check has passed.
+    #[cfg(target_os = "linux")]
+    let shared_host_paths = if cfg.shared_folders.is_empty() {
+        Vec::new()
+    } else {
+        validate_shared_folders(&cfg.shared_folders)?
+    };
+    if !cfg.shared_folders.is_empty() {
+        #[cfg(target_os = "linux")]
+        {
+            for (i, folder) in cfg.shared_folders.iter().enumerate() {
+                let sock = virtiofsd_socket(&ismac, &folder.tag);
+                qemu_args.push("-chardev".into());
+                qemu_args.push(format!("socket,id=fs{},path={}", i, sock));
+                qemu_args.push("-device".into());
+                qemu_args.push(format!(
+                    "vhost-user-fs-pci,queue-size=1024,chardev=fs{},tag={}",
+                    i, folder.tag
+                ));
+                output_log.push_str(&format!(
+                    "virtiofs : {} → tag {} ({})\n",
+                    shared_host_paths[i],
+                    folder.tag,
+                    if folder.readonly == "1" { "ro" } else { "rw" }
+                ));
+            }
+        }
+        #[cfg(not(target_os = "linux"))]
+        output_log.push_str("virtiofs : shared folders need virtiofsd (Linux hosts only) — skipped\n");
+    }
+
+    // Display VNC with built-in WebSocket
     // Prevent VNC port collision: if another running VM already uses this port, auto-reassign
     let mut vnc_port = cfg.vnc_port;
     let running_ports = running_vnc_ports(smac);
@@ -1047,56 +1942,40 @@ fn start_vm_with_config(smac: &str, cfg: &VmStartConfig) -> Result<String, Strin
     // Bind address defaults to loopback; set vnc_bind_host=0.0.0.0 in config.yaml
     // to expose the VNC WebSocket for browsers on other machines.
     let vnc_bind_host = get_conf_or("vnc_bind_host", "127.0.0.1");
+    // Per-session passwords: QEMU refuses every client until vm_ctl issues a
+    // password with the VNC token (see issue_vnc_password)
+    let mut vnc_opts = String::new();
+    if vnc_password_enabled() {
+        vnc_opts.push_str(",password=on");
+    }
+    let vnc_tls_dir = get_conf_or("vnc_tls_dir", "");
     qemu_args.push("-display".into());
 
-    // External websockify proxy: if websockify_path is set and the binary exists,
-    // skip QEMU's built-in WebSocket and spawn websockify to proxy ws_port -> 5900+N.
-    // Useful on Windows where QEMU's WebSocket doesn't dual-stack IPv4/IPv6.
-    let websockify_path = get_conf("websockify_path");
-    let use_websockify = !websockify_path.is_empty()
-        && std::path::Path::new(&websockify_path).exists();
-
-    if use_websockify {
-        // QEMU binds raw VNC on loopback; websockify handles WebSocket upgrade.
-        qemu_args.push(format!("vnc=127.0.0.1:{}", vnc_display));
-        let raw_vnc_port = 5900u16 + vnc_display;
-        let ws_listen = format!("{}:{}", vnc_bind_host, vnc_port);
-        let ws_target = format!("127.0.0.1:{}", raw_vnc_port);
-        let pid_file = format!("{}/{}_wsp.pid", pctl_path, ismac);
-
-        // Kill any stale websockify for this VM
-        if let Ok(prev_pid_str) = std::fs::read_to_string(&pid_file) {
-            if let Ok(prev_pid) = prev_pid_str.trim().parse::<u32>() {
-                #[cfg(target_os = "windows")]
-                let _ = std::process::Command::new("taskkill")
-                    .args(["/F", "/PID", &prev_pid.to_string()])
-                    .output();
-                #[cfg(unix)]
-                let _ = std::process::Command::new("kill")
-                    .arg(prev_pid.to_string())
-                    .output();
-            }
-        }
-
-        match std::process::Command::new(&websockify_path)
-            .args([&ws_listen, &ws_target])
-            .stdout(std::process::Stdio::null())
-            .stderr(std::process::Stdio::null())
-            .spawn()
-        {
-            Ok(child) => {
-                let _ = std::fs::write(&pid_file, child.id().to_string());
-                output_log.push_str(&format!(
-                    "VNC: websockify {} -> {} (PID {})\n",
-                    ws_listen, ws_target, child.id()
-                ));
-            }
-            Err(e) => {
-                return Err(format!("Failed to spawn websockify: {}", e));
-            }
-        }
+    if vnc_proxy_enabled() {
+        // vm_ctl proxies /vnc/ws/{token} to the raw VNC port, which never
+        // leaves loopback — no per-VM port to open
+        qemu_args.push(format!("vnc=127.0.0.1:{}{}", vnc_display, vnc_opts));
+        output_log.push_str(&format!(
+            "VNC: 127.0.0.1:{} via built-in WebSocket proxy\n",
+            5900 + vnc_display
+        ));
     } else {
-        qemu_args.push(format!("vnc={}:{},websocket={}", vnc_bind_host, vnc_display, vnc_port));
+        // With tls-creds QEMU serves the WebSocket as wss://
+        if !vnc_tls_dir.is_empty() {
+            qemu_args.push("-object".into());
+            qemu_args.push(format!(
+                "tls-creds-x509,id=vnctls0,dir={},endpoint=server,verify-peer=off",
+                vnc_tls_dir
+            ));
+            vnc_opts.push_str(",tls-creds=vnctls0");
+        }
+        qemu_args.push(format!(
+            "vnc={}:{},websocket={}{}",
+            vnc_bind_host, vnc_display, vnc_port, vnc_opts
+        ));
+    }
+    if vnc_password_enabled() {
+        output_log.push_str("VNC: password auth on (per-session passwords)\n");
     }
 
     // Memory — validate against host RAM
@@ -1126,7 +2005,21 @@ fn start_vm_with_config(smac: &str, cfg: &VmStartConfig) -> Result<String, Strin
         ));
     }
     qemu_args.push("-m".into());
-    qemu_args.push(format!("{}M", cfg.memory.size));
+    let max_ram: u64 = cfg.memory.max_size.parse().unwrap_or(0);
+    if max_ram > vm_ram && vm_ram > 0 {
+        // Reserve DIMM slots so memory can be hot-added up to max_size
+        qemu_args.push(format!(
+            "{}M,slots={},maxmem={}M",
+            cfg.memory.size, MEMORY_HOTPLUG_SLOTS, max_ram
+        ));
+        output_log.push_str(&format!("ram: hot-add up to {} MB\n", max_ram));
+    } else {
+        qemu_args.push(format!("{}M", cfg.memory.size));
+    }
+    if cfg.memory.balloon == "1" {
+        qemu_args.push("-device".into());
+        qemu_args.push("virtio-balloon-pci,id=balloon0".into());
+    }
 
     // Disks
     for disk in &cfg.disks {
@@ -1164,16 +2057,19 @@ fn start_vm_with_config(smac: &str, cfg: &VmStartConfig) -> Result<String, Strin
             disk_file, drive_id
         ));
         qemu_args.push("-device".into());
-        // bootindex=1+ so disk boots after CD-ROM (bootindex=0)
-        let bootidx = disk.diskid.parse::<u32>().unwrap_or(0) + 1;
+        // bootindex from boot.order (disks after the CD-ROM by default)
+        let bootidx = boot_index(&boot_order, "disk", disk.diskid.parse::<u32>().unwrap_or(0), kernel_boot)
+            .map(|i| format!(",bootindex={}", i))
+            .unwrap_or_default();
         // Use virtio-blk-pci for all VMs (including Windows):
         // - Best I/O performance
         // - Works on both x86_64 and aarch64 (nvme has issues on aarch64 virt)
         // - Windows gets viostor driver from virtio-win ISO (auto-mounted on cd3)
         //   → During install: Load Driver → Browse → cd3:\viostor\w10\amd64
+        // id=vdN lets the disk be hot-unplugged later (see detach_disk)
         qemu_args.push(format!(
-            "virtio-blk-pci,drive={},bootindex={}",
-            drive_id, bootidx
+            "virtio-blk-pci,id=vd{},drive={}{}",
+            disk.diskid, drive_id, bootidx
         ));
     }
 
@@ -1202,26 +2098,7 @@ fn start_vm_with_config(smac: &str, cfg: &VmStartConfig) -> Result<String, Strin
         mds::load_mds_config()
     };
     output_log.push_str(&format!("slirp_ipv4: {}\n", mds_config.local_ipv4));
-    let slirp_opts = if !mds_config.local_ipv4.is_empty() {
-        let parts: Vec<&str> = mds_config.local_ipv4.split('.').collect();
-        if parts.len() == 4 {
-            let net = format!("{}.{}.{}.0/24", parts[0], parts[1], parts[2]);
-            let host = format!("{}.{}.{}.1", parts[0], parts[1], parts[2]);
-            let dns = format!("{}.{}.{}.2", parts[0], parts[1], parts[2]);
-            let last: u8 = parts[3].parse().unwrap_or(10);
-            let dhcp_start = if last <= 2 {
-                format!("{}.{}.{}.10", parts[0], parts[1], parts[2])
-            } else {
-                mds_config.local_ipv4.clone()
-            };
-            format!(",net={},host={},dns={},dhcpstart={}",
-                net, host, dns, dhcp_start)
-        } else {
-            String::new()
-        }
-    } else {
-        String::new()
-    };
+    let slirp_opts = slirp_net_opts(&mds_config.local_ipv4);
 
     // Build hostfwd options from port_forwards config
     let hostfwd_opts = {
@@ -1230,20 +2107,7 @@ fn start_vm_with_config(smac: &str, cfg: &VmStartConfig) -> Result<String, Strin
         } else {
             serde_json::Value::default()
         };
-        let mut fwd = String::new();
-        if let Some(forwards) = vm_cfg.get("port_forwards").and_then(|v| v.as_array()) {
-            for rule in forwards {
-                let proto = rule.get("protocol").and_then(|v| v.as_str()).unwrap_or("tcp");
-                let host_port = rule.get("host_port").and_then(|v| v.as_u64()).unwrap_or(0);
-                let guest_port = rule.get("guest_port").and_then(|v| v.as_u64()).unwrap_or(0);
-                if host_port > 0 && guest_port > 0 {
-                    fwd.push_str(&format!(",hostfwd={}::{}-:{}", proto, host_port, guest_port));
-                    output_log.push_str(&format!("portfwd: {}:{} -> guest:{}\n",
-                        proto, host_port, guest_port));
-                }
-            }
-        }
-        fwd
+        hostfwd_net_opts(&vm_cfg, &mut output_log)
     };
 
     for adapter in &cfg.network_adapters {
@@ -1254,188 +2118,16 @@ fn start_vm_with_config(smac: &str, cfg: &VmStartConfig) -> Result<String, Strin
 
         qemu_args.push("-netdev".into());
 
-        if adapter.mode == "switch" && !adapter.switch_name.is_empty() {
-            // Virtual switch mode
-            let vlan_id: u16 = adapter.vlan.parse().unwrap_or(0);
-            if vlan_id > 4094 {
-                return Err(format!(
-                    "VLAN {} out of range (0-4094) for adapter {}",
-                    vlan_id, adapter.netid
-                ));
-            }
-            match db::get_switch_by_name(&adapter.switch_name) {
-                Ok(sw) => {
-                    if sw.mcast_port <= 0 || sw.mcast_port > u16::MAX as i64 {
-                        return Err(format!(
-                            "Switch '{}' has invalid port {} (must be 1..={})",
-                            adapter.switch_name, sw.mcast_port, u16::MAX
-                        ));
-                    }
-                    let switch_port = sw.mcast_port as u16;
-                    let mcast_hi = vlan_id / 256;
-                    let mcast_lo = vlan_id % 256;
-                    #[cfg(target_os = "linux")]
-                    {
-                        // Linux: TAP + bridge per (switch, vlan) pair for L2 isolation.
-                        // Bridge name fits 15-char ifname limit: vb<sw_id>v<vlan> (max ~11 chars).
-                        let bridge_name = format!("vb{}v{}", sw.id, vlan_id);
-                        let mac_clean = smac.replace(":", "");
-                        let mac_suffix = &mac_clean[mac_clean.len().saturating_sub(4)..];
-                        let tap_name = format!("vt{}n{}", mac_suffix, adapter.netid);
-                        output_log.push_str(&format!(
-                            "switch : {} vlan {} → bridge {} tap {}\n",
-                            adapter.switch_name, vlan_id, bridge_name, tap_name
-                        ));
-                        setup_switch_tap(&bridge_name, &tap_name, &mut output_log)?;
-                        qemu_args.push(format!(
-                            "tap,id=net{},ifname={},script=no,downscript=no",
-                            adapter.netid, tap_name
-                        ));
-                    }
-                    #[cfg(target_os = "macos")]
-                    {
-                        // UDP multicast for many-to-many L2 switching.
-                        // VLAN encoded in mcast address → separate broadcast domain per VLAN.
-                        output_log.push_str(&format!(
-                            "switch : {} (mcast port {})\n",
-                            adapter.switch_name, switch_port
-                        ));
-                        output_log.push_str(&format!(
-                            "vlan   : {} (mcast 230.{}.{}.1:{})\n",
-                            vlan_id, mcast_hi, mcast_lo, switch_port
-                        ));
-                        qemu_args.push(format!(
-                            "socket,id=net{},mcast=230.{}.{}.1:{}",
-                            adapter.netid, mcast_hi, mcast_lo, switch_port
-                        ));
-                    }
-                    #[cfg(target_os = "windows")]
-                    {
-                        // Windows: use TAP-Windows adapter per VM. install.bat
-                        // auto-installs the driver; we create/rename an adapter
-                        // on demand so each VM gets a dedicated NIC.
-                        let mac_clean = smac.replace(":", "");
-                        let mac_suffix = &mac_clean[mac_clean.len().saturating_sub(4)..];
-                        let tap_name = format!("vmt{}n{}", mac_suffix, adapter.netid);
-                        match setup_switch_tap_windows(&tap_name) {
-                            Ok(_) => {
-                                output_log.push_str(&format!(
-                                    "switch : {} → TAP adapter {}\n",
-                                    adapter.switch_name, tap_name
-                                ));
-                                qemu_args.push(format!(
-                                    "tap,id=net{},ifname={}",
-                                    adapter.netid, tap_name
-                                ));
-                            }
-                            Err(e) => {
-                                output_log.push_str(&format!(
-                                    "switch : {} (TAP setup failed: {} — falling back to NAT)\n",
-                                    adapter.switch_name, e
-                                ));
-                                qemu_args.push(format!("user,id=net{}", adapter.netid));
-                            }
-                        }
-                    }
-                    let _ = &sw;
-                    let _ = switch_port;
-                    let _ = mcast_hi;
-                    let _ = mcast_lo;
-                }
-                Err(e) => {
-                    return Err(format!(
-                        "Switch '{}' not found for adapter {}: {}",
-                        adapter.switch_name, adapter.netid, e
-                    ));
-                }
-            }
-        } else if adapter.mode == "bridge" {
-            // Bridge/tap mode — host↔VM bidirectional connectivity (requires sudo/admin)
-            // Validate bridge_iface to prevent command injection
-            if !adapter.bridge_iface.is_empty() {
-                for c in adapter.bridge_iface.chars() {
-                    if !c.is_alphanumeric() && c != '-' && c != '_' && c != ' ' {
-                        return Err(format!(
-                            "Invalid bridge interface name '{}' for adapter {}",
-                            adapter.bridge_iface, adapter.netid
-                        ));
-                    }
-                }
-            }
-
-            #[cfg(target_os = "macos")]
-            {
-                if adapter.bridge_iface.is_empty() {
-                    // vmnet-shared: macOS built-in DHCP (192.168.64.x), host↔VM bidirectional
-                    qemu_args.push(format!("vmnet-shared,id=net{}", adapter.netid));
-                    output_log.push_str("bridge : vmnet-shared (macOS)\n");
-                } else {
-                    // vmnet-bridged: bridge to physical interface (e.g. en0)
-                    qemu_args.push(format!(
-                        "vmnet-bridged,id=net{},ifname={}",
-                        adapter.netid, adapter.bridge_iface
-                    ));
-                    output_log.push_str(&format!(
-                        "bridge : vmnet-bridged ifname={} (macOS)\n",
-                        adapter.bridge_iface
-                    ));
-                }
-            }
-            #[cfg(target_os = "linux")]
-            {
-                if adapter.bridge_iface.is_empty() {
-                    let tap_name = format!("tap{}", adapter.netid);
-                    qemu_args.push(format!(
-                        "tap,id=net{},ifname={},script=no,downscript=no",
-                        adapter.netid, tap_name
-                    ));
-                    output_log.push_str(&format!("bridge : tap ifname={} (Linux)\n", tap_name));
-                } else {
-                    qemu_args.push(format!(
-                        "bridge,id=net{},br={}",
-                        adapter.netid, adapter.bridge_iface
-                    ));
-                    output_log.push_str(&format!(
-                        "bridge : bridge br={} (Linux)\n",
-                        adapter.bridge_iface
-                    ));
-                }
-            }
-            #[cfg(target_os = "windows")]
-            {
-                // Windows: TAP-Windows adapter (requires OpenVPN TAP driver installed)
-                let tap_name = if adapter.bridge_iface.is_empty() {
-                    "TAP-Windows Adapter V9".to_string()
-                } else {
-                    adapter.bridge_iface.clone()
-                };
-                qemu_args.push(format!(
-                    "tap,id=net{},ifname={}",
-                    adapter.netid, tap_name
-                ));
-                output_log.push_str(&format!("bridge : tap ifname={} (Windows)\n", tap_name));
-            }
-            #[cfg(not(any(target_os = "macos", target_os = "linux", target_os = "windows")))]
-            {
-                return Err(format!(
-                    "Bridge networking not supported on this platform for adapter {}",
-                    adapter.netid
-                ));
-            }
-        } else {
-            // Default: NAT (user-mode/SLIRP) networking with port forwarding
-            qemu_args.push(format!("user,id=net{}{}{}", adapter.netid, slirp_opts, hostfwd_opts));
-        }
+        qemu_args.push(build_netdev_spec(smac, adapter, &slirp_opts, &hostfwd_opts, &mut output_log)?);
 
         qemu_args.push("-device".into());
-        // NIC model: virtio (fastest, needs driver) or e1000 (compatible, no driver needed)
-        let nic_device = match adapter.nic_model.as_str() {
-            "e1000" => "e1000",
-            "e1000e" => "e1000e",
-            "rtl8139" => "rtl8139",
-            _ => "virtio-net-pci", // default: virtio
-        };
-        qemu_args.push(format!("{},netdev=net{},mac={}", nic_device, adapter.netid, adapter.mac));
+        let bootidx = boot_index(&boot_order, "net", adapter.netid.parse::<u32>().unwrap_or(0), kernel_boot)
+            .map(|i| format!(",bootindex={}", i))
+            .unwrap_or_default();
+        qemu_args.push(format!(
+            "{},id=nic{},netdev=net{},mac={}{}",
+            nic_device_model(&adapter.nic_model), adapter.netid, adapter.netid, adapter.mac, bootidx
+        ));
     }
 
     // Internal network — VM-to-VM communication on 192.168.100.0/24
@@ -1485,22 +2177,22 @@ fn start_vm_with_config(smac: &str, cfg: &VmStartConfig) -> Result<String, Strin
     }
 
     // PCI Passthrough — VFIO devices (GPU, NIC, etc.)
-    for (idx, pci) in cfg.pci_devices.iter().enumerate() {
-        let addr = pci.host.trim();
-        if !addr.is_empty() {
-            output_log.push_str(&format!("pci_passthrough[{}]: {}\n", idx, addr));
-            qemu_args.push("-device".into());
-            qemu_args.push(format!("vfio-pci,host={}", addr));
-        }
+    let pci_addrs: Vec<String> = cfg
+        .pci_devices
+        .iter()
+        .filter(|p| !p.host.trim().is_empty())
+        .map(|p| normalize_pci_address(&p.host))
+        .collect::<Result<_, _>>()?;
+    validate_pci_passthrough(smac, &pci_addrs, true)?;
+    for (idx, addr) in pci_addrs.iter().enumerate() {
+        output_log.push_str(&format!("pci_passthrough[{}]: {}\n", idx, addr));
+        qemu_args.push("-device".into());
+        qemu_args.push(format!("vfio-pci,host={}", addr));
     }
 
     // Machine type — configurable accelerator (hvf:tcg for macOS, kvm:tcg for Linux)
     // Windows x86_64 with Secure Boot needs q35 + smm=on
-    let use_secureboot = is_windows && !is_aarch64 && {
-        let sc = get_conf_or("edk2_x86_secure_code",
-            "/opt/homebrew/share/qemu/edk2-x86_64-secure-code.fd");
-        std::path::Path::new(&sc).exists()
-    };
+    let use_secureboot = x86_firmware.as_ref().is_some_and(|fw| fw.secure);
     // Pass accelerator via -accel so sub-options like "tcg,thread=multi" work.
     // Cannot combine -accel with -machine accel=... so leave accel out of -machine.
     qemu_args.push("-machine".into());
@@ -1516,6 +2208,19 @@ fn start_vm_with_config(smac: &str, cfg: &VmStartConfig) -> Result<String, Strin
     qemu_args.push("-accel".into());
     qemu_args.push(qemu_accel.clone());
 
+    // Spare PCIe root ports for hot-plug (disks, NICs). q35 and virt put
+    // devices on pcie.0, which can't hot-plug; i440fx `pc` can, so skip it.
+    let is_pcie_machine = is_aarch64 || use_secureboot || qemu_machine.starts_with("q35");
+    if is_pcie_machine {
+        for n in 0..HOTPLUG_PORTS {
+            qemu_args.push("-device".into());
+            qemu_args.push(format!(
+                "pcie-root-port,id=hp{},chassis={},slot={}",
+                n, n + 1, n + 1
+            ));
+        }
+    }
+
     // SMP — if vcpus is set, auto-compute topology; otherwise use explicit values
     let vcpus: u32 = cfg.cpu.vcpus.parse().unwrap_or(0);
     let (total_cpus, sockets, cores, threads) = if vcpus > 0 {
@@ -1527,17 +2232,124 @@ fn start_vm_with_config(smac: &str, cfg: &VmStartConfig) -> Result<String, Strin
         let t: u32 = cfg.cpu.threads.parse().unwrap_or(1);
         (s * c * t, s, c, t)
     };
+    // maxcpus must equal sockets*cores*threads, so the topology is widened
+    // to make room for hot-pluggable vCPUs
+    let max_vcpus: u32 = cfg.cpu.max_vcpus.parse().unwrap_or(0);
+    let (max_cpus, sockets, cores) = if max_vcpus > total_cpus {
+        if vcpus > 0 {
+            (max_vcpus, 1, max_vcpus)
+        } else if max_vcpus.is_multiple_of(cores * threads) {
+            (max_vcpus, max_vcpus / (cores * threads), cores)
+        } else {
+            return Err(format!(
+                "max_vcpus {} must be a multiple of cores*threads ({})",
+                max_vcpus, cores * threads
+            ));
+        }
+    } else {
+        (total_cpus, sockets, cores)
+    };
     qemu_args.push("-smp".into());
-    qemu_args.push(format!("{},sockets={},cores={},threads={}",
-        total_cpus, sockets, cores, threads));
+    qemu_args.push(format!("{},sockets={},cores={},threads={},maxcpus={}",
+        total_cpus, sockets, cores, threads, max_cpus));
 
-    // NOTE: virtio-scsi-pci removed — CD-ROM now uses usb-storage which needs no
-    // extra drivers. This ensures Windows Setup can access virtio-win ISO on cd3.
+    // vCPU / emulator pinning is applied after spawn; validate it up front
+    if cfg.cpu.pinning.len() > max_cpus as usize {
+        return Err(format!(
+            "cpu.pinning has {} entries but the VM has at most {} vCPUs",
+            cfg.cpu.pinning.len(), max_cpus
+        ));
+    }
+    let host_cpus = host_total_cpus();
+    for list in cfg.cpu.pinning.iter().filter(|l| !l.is_empty()) {
+        validate_cpulist(list, host_cpus)?;
+    }
+    if !cfg.cpu.emulator_pin.is_empty() {
+        validate_cpulist(&cfg.cpu.emulator_pin, host_cpus)?;
+    }
 
-    // Cloud-init seed ISO — attach as virtio-blk so ALL guests can see it
-    // (virtio-scsi requires guest kernel module; virtio-blk always works since
-    //  the main disk already uses it → shows up as /dev/vdb with CIDATA label)
-    let cloudinit_enabled = cfg.features.cloudinit != "0";
+    // Guest NUMA nodes / hugepage-backed RAM — both need explicit memdevs
+    let numa_nodes: u32 = cfg.cpu.numa_nodes.parse().unwrap_or(0);
+    let use_hugepages = cfg.memory.hugepages == "1";
+    if numa_nodes > max_cpus {
+        return Err(format!("numa_nodes {} exceeds vCPU count {}", numa_nodes, max_cpus));
+    }
+    if use_hugepages {
+        let (page_mb, free_mb) = host_hugepages_mb();
+        if page_mb == 0 {
+            return Err("Hugepages requested but none are reserved on this host (vm.nr_hugepages)".into());
+        }
+        if vm_ram > free_mb {
+            return Err(format!(
+                "Not enough free hugepages: VM needs {} MB, host has {} MB free ({} MB pages)",
+                vm_ram, free_mb, page_mb
+            ));
+        }
+        let node_ram = vm_ram / numa_nodes.max(1) as u64;
+        if !node_ram.is_multiple_of(page_mb) {
+            return Err(format!(
+                "Memory per NUMA node ({} MB) must be a multiple of the {} MB hugepage size",
+                node_ram, page_mb
+            ));
+        }
+        output_log.push_str(&format!("hugepages: {} MB ({} MB pages, {} MB free)\n", vm_ram, page_mb, free_mb));
+    }
+    // vhost-user (virtiofsd) needs all guest RAM in shared memory
+    let shared_mem = cfg!(target_os = "linux") && !cfg.shared_folders.is_empty();
+    if numa_nodes > 0 || use_hugepages || shared_mem {
+        let nodes = numa_nodes.max(1);
+        let hugepages_path = get_conf_or("hugepages_path", "/dev/hugepages");
+        for node in 0..nodes {
+            // Last node takes the remainder of both memory and vCPUs
+            let node_ram = if node + 1 == nodes {
+                vm_ram - (vm_ram / nodes as u64) * (nodes as u64 - 1)
+            } else {
+                vm_ram / nodes as u64
+            };
+            let per_node = max_cpus / nodes;
+            let first_cpu = node * per_node;
+            let last_cpu = if node + 1 == nodes { max_cpus - 1 } else { first_cpu + per_node - 1 };
+            qemu_args.push("-object".into());
+            if use_hugepages {
+                qemu_args.push(format!(
+                    "memory-backend-file,id=ram-node{},size={}M,mem-path={},share=on,prealloc=on",
+                    node, node_ram, hugepages_path
+                ));
+            } else if shared_mem {
+                qemu_args.push(format!("memory-backend-memfd,id=ram-node{},size={}M,share=on", node, node_ram));
+            } else {
+                qemu_args.push(format!("memory-backend-ram,id=ram-node{},size={}M", node, node_ram));
+            }
+            qemu_args.push("-numa".into());
+            qemu_args.push(format!(
+                "node,nodeid={},cpus={}-{},memdev=ram-node{}",
+                node, first_cpu, last_cpu, node
+            ));
+        }
+        output_log.push_str(&format!("numa: {} node(s)\n", nodes));
+    }
+
+    // NOTE: virtio-scsi-pci removed — CD-ROM now uses usb-storage which needs no
+    // extra drivers. This ensures Windows Setup can access virtio-win ISO on cd3.
+
+    // Cloud-init seed ISO — attach as virtio-blk so ALL guests can see it
+    // (virtio-scsi requires guest kernel module; virtio-blk always works since
+    //  the main disk already uses it → shows up as /dev/vdb with CIDATA label)
+    // An unattended Windows install leaves the seed ISO out: Windows PE would
+    // list it as a second disk next to the one Setup wipes
+    let unattended = is_windows && cfg.unattend.enabled == "1";
+    // Unattended Linux install: the installer kernel is booted straight from
+    // the ISO and reads its answers from the MDS. The seed ISO would be
+    // picked up by the live system's cloud-init instead.
+    let linux_install = if !is_windows && cfg.linux_install.enabled == "1" {
+        match prepare_linux_install(&ismac, cfg, is_aarch64) {
+            Ok(boot) => Some(boot),
+            Err(e) => return Err(format!("Unattended install: {}", e)),
+        }
+    } else {
+        None
+    };
+    let cloudinit_enabled = cfg.features.cloudinit != "0" && !unattended && linux_install.is_none();
     if cloudinit_enabled {
         match generate_seed_iso(&ismac) {
             Ok((seed_iso_path, vmctl_pw)) => {
@@ -1582,8 +2394,16 @@ fn start_vm_with_config(smac: &str, cfg: &VmStartConfig) -> Result<String, Strin
         qemu_args.push("usb-tablet,bus=xhci.0".into());
     }
 
+    // USB passthrough — usb-host devices on the xHCI controller
+    for dev in &cfg.usb_devices {
+        let spec = usb_host_device_spec(dev)?;
+        output_log.push_str(&format!("usb_passthrough[{}]: {}\n", dev.usbid, spec));
+        qemu_args.push("-device".into());
+        qemu_args.push(spec);
+    }
+
     // CDROM — 4 named drives "cd0"–"cd3" for runtime ISO mount/unmount via monitor
-    // bootindex=0 on cd0 so UEFI/BIOS tries CD first, then falls through to disk
+    // bootindex on cd0 from boot.order (first by default, then the disks)
     // All platforms use usb-storage (no extra drivers needed)
     //
     // For Windows VMs: auto-mount virtio-win ISO on cd3 if available
@@ -1619,6 +2439,8 @@ fn start_vm_with_config(smac: &str, cfg: &VmStartConfig) -> Result<String, Strin
             } else {
                 qemu_args.push(format!("if=none,id={},media=cdrom", drive_id));
             }
+        } else if let (0, Some(boot)) = (i, &linux_install) {
+            qemu_args.push(format!("if=none,id={},media=cdrom,file={},readonly=on", drive_id, boot.iso));
         } else {
             qemu_args.push(format!("if=none,id={},media=cdrom", drive_id));
         }
@@ -1626,8 +2448,9 @@ fn start_vm_with_config(smac: &str, cfg: &VmStartConfig) -> Result<String, Strin
         // Use usb-storage for all platforms — universally recognized by all OS
         // without extra drivers (scsi-cd on virtio-scsi requires vioscsi driver
         // which Windows Setup doesn't have, making virtio-win ISO inaccessible)
-        if i == 0 {
-            qemu_args.push(format!("usb-storage,drive={},removable=true,bootindex=0", drive_id));
+        let bootidx = if i == 0 { boot_index(&boot_order, "cdrom", 0, kernel_boot) } else { None };
+        if let Some(idx) = bootidx {
+            qemu_args.push(format!("usb-storage,drive={},removable=true,bootindex={}", drive_id, idx));
         } else {
             qemu_args.push(format!("usb-storage,drive={},removable=true", drive_id));
         }
@@ -1635,10 +2458,50 @@ fn start_vm_with_config(smac: &str, cfg: &VmStartConfig) -> Result<String, Strin
 
     // (USB xHCI + tablet already added before CD-ROM loop above)
 
+    // Unattended Windows install: autounattend.xml on an extra USB CD-ROM
+    let mut unattend_attached = false;
+    if unattended {
+        match generate_unattend_iso(&ismac, cfg, is_aarch64 || x86_firmware.is_some(), is_aarch64) {
+            Ok(iso) => {
+                output_log.push_str(&format!("unattend ISO: {}\n", iso));
+                qemu_args.push("-drive".into());
+                qemu_args.push(format!("if=none,id=unattend0,media=cdrom,file={},readonly=on", iso));
+                qemu_args.push("-device".into());
+                qemu_args.push("usb-storage,drive=unattend0,removable=true".into());
+                unattend_attached = true;
+            }
+            Err(e) => output_log.push_str(&format!("WARNING: unattend ISO not attached: {}\n", e)),
+        }
+    }
+
+    if let Some(boot) = &linux_install {
+        output_log.push_str(&format!("install  : {} ({})\n", boot.iso, cfg.linux_install.flavor));
+        output_log.push_str(&format!("cmdline  : {}\n", boot.cmdline));
+        qemu_args.extend(["-kernel".into(), boot.kernel.clone(), "-initrd".into(), boot.initrd.clone()]);
+        qemu_args.extend(["-append".into(), boot.cmdline.clone(), "-no-reboot".into()]);
+    } else if let Some((kernel, initrd)) = &boot_kernel {
+        output_log.push_str(&format!("kernel   : {}\n", kernel));
+        qemu_args.extend(["-kernel".into(), kernel.clone()]);
+        if let Some(initrd) = initrd {
+            output_log.push_str(&format!("initrd   : {}\n", initrd));
+            qemu_args.extend(["-initrd".into(), initrd.clone()]);
+        }
+        if !cfg.boot.cmdline.is_empty() {
+            output_log.push_str(&format!("cmdline  : {}\n", cfg.boot.cmdline));
+            qemu_args.extend(["-append".into(), cfg.boot.cmdline.clone()]);
+        }
+    }
+
     // Monitor socket
     qemu_args.push("-monitor".into());
     qemu_args.push(format!("unix:{}/{},server,nowait", pctl_path, ismac));
 
+    // QMP socket — structured control channel for hot-plug (see qmp.rs)
+    let qmp_sock = crate::qmp::qmp_socket_path(&ismac);
+    let _ = std::fs::remove_file(&qmp_sock); // Remove stale socket
+    qemu_args.push("-qmp".into());
+    qemu_args.push(format!("unix:{},server,nowait", qmp_sock));
+
     // Guest Agent socket — for direct file transfer via QEMU Guest Agent (qemu-ga)
     let qga_sock = format!("{}/{}_qga", pctl_path, ismac);
     let _ = std::fs::remove_file(&qga_sock); // Remove stale socket
@@ -1653,6 +2516,58 @@ fn start_vm_with_config(smac: &str, cfg: &VmStartConfig) -> Result<String, Strin
     qemu_args.push("virtserialport,chardev=qga0,name=org.qemu.guest_agent.0".into());
     output_log.push_str(&format!("guest-agent: socket {}\n", qga_sock));
 
+    // SPICE — served next to VNC, with the vdagent channel (clipboard, guest
+    // resolution) and USB redirection channels on the xHCI controller
+    if cfg.display.protocol == "spice" {
+        let port = spice_port(vnc_port);
+        let spice_bind_host = spice_bind_host();
+        check_spice_port(port, &spice_bind_host)?;
+        let mut spice_opts = format!("port={},addr={}", port, spice_bind_host);
+        if spice_password_enabled() {
+            // Unknown startup password; each session gets its own through
+            // spice_connection_file
+            let pw_file = spice_password_file(&ismac);
+            std::fs::write(&pw_file, random_password(24))
+                .map_err(|e| format!("Write SPICE password file: {}", e))?;
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                let _ = std::fs::set_permissions(&pw_file, std::fs::Permissions::from_mode(0o600));
+            }
+            qemu_args.push("-object".into());
+            qemu_args.push(format!("secret,id=spicepw0,file={}", pw_file));
+            spice_opts.push_str(",password-secret=spicepw0");
+        } else {
+            spice_opts.push_str(",disable-ticketing=on");
+        }
+        qemu_args.push("-spice".into());
+        qemu_args.push(spice_opts);
+        qemu_args.push("-chardev".into());
+        qemu_args.push("spicevmc,id=vdagent0,name=vdagent".into());
+        qemu_args.push("-device".into());
+        qemu_args.push("virtserialport,chardev=vdagent0,name=com.redhat.spice.0".into());
+        let usb_redirect = cfg.display.usb_redirect.parse::<u32>().unwrap_or(0).min(4);
+        for i in 0..usb_redirect {
+            qemu_args.push("-chardev".into());
+            qemu_args.push(format!("spicevmc,id=usbredir{},name=usbredir", i));
+            qemu_args.push("-device".into());
+            qemu_args.push(format!("usb-redir,chardev=usbredir{},id=usbredir{},bus=xhci.0", i, i));
+        }
+        output_log.push_str(&format!(
+            "SPICE: {}:{} (vdagent, {} USB redirection channels)\n",
+            spice_bind_host, port, usb_redirect
+        ));
+    }
+
+    // Serial console — held by vm_ctl and bridged to /api/vm/{smac}/console
+    let serial_sock = crate::console::serial_socket_path(&ismac);
+    let _ = std::fs::remove_file(&serial_sock);
+    qemu_args.push("-chardev".into());
+    qemu_args.push(format!("socket,id=serial0,path={},server=on,wait=off", serial_sock));
+    qemu_args.push("-serial".into());
+    qemu_args.push("chardev:serial0".into());
+    output_log.push_str(&format!("serial: socket {}\n", serial_sock));
+
     // Start VM as a background process (no -daemonize, which breaks WebSocket VNC)
     // Bridge/vmnet modes require sudo on macOS; TAP+bridge switches require sudo on Linux
     let needs_bridge = cfg.network_adapters.iter().any(|a| a.mode == "bridge");
@@ -1662,7 +2577,37 @@ fn start_vm_with_config(smac: &str, cfg: &VmStartConfig) -> Result<String, Strin
     let needs_sudo = needs_bridge || needs_vmnet_internal || needs_switch_tap;
     let use_sudo = needs_sudo && get_conf_or("bridge_sudo", "true") == "true";
 
-    let (pid, log_path) = if use_sudo {
+    // Per-VM cgroup v2 (Linux): limits are written first, then QEMU joins
+    // the cgroup at exec so every allocation is charged to it
+    let vm_cgroup = if crate::cgroups::available() {
+        match crate::cgroups::prepare(smac, cfg, &mut output_log) {
+            Ok(dir) => Some(dir),
+            Err(e) => {
+                output_log.push_str(&format!("WARNING: cgroup setup failed, running unconstrained: {}\n", e));
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let spawn = |program: &str, args: Vec<String>| -> Result<(u32, String), String> {
+        let (program, args) = match &vm_cgroup {
+            Some(dir) => crate::cgroups::wrap_command(dir, program, &args),
+            None => (program.to_string(), args),
+        };
+        let args_ref: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+        spawn_background(&program, &args_ref)
+    };
+
+    #[cfg(target_os = "linux")]
+    for (folder, host_path) in cfg.shared_folders.iter().zip(&shared_host_paths) {
+        if let Err(e) = start_virtiofsd(&ismac, folder, host_path) {
+            cleanup_virtiofsd(&ismac);
+            return Err(e);
+        }
+    }
+
+    let spawned = if use_sudo {
         let sudo_path = get_conf_or("bridge_sudo_path", "/usr/bin/sudo");
         output_log.push_str("SUDO: bridge mode requires elevated privileges\n");
         // `-n` = non-interactive. vm_ctl runs under a service / the desktop
@@ -1671,8 +2616,7 @@ fn start_vm_with_config(smac: &str, cfg: &VmStartConfig) -> Result<String, Strin
         let mut sudo_args: Vec<String> = vec!["-n".to_string(), qemu_path.clone()];
         sudo_args.extend(qemu_args.iter().cloned());
         output_log.push_str(&format!("QEMU: {} -n {} {}\n", sudo_path, qemu_path, qemu_args.join(" ")));
-        let sudo_args_ref: Vec<&str> = sudo_args.iter().map(|s| s.as_str()).collect();
-        spawn_background(&sudo_path, &sudo_args_ref).map_err(|e| {
+        spawn(&sudo_path, sudo_args).map_err(|e| {
             let hint = if e.contains("password is required") || e.contains("terminal is required") {
                 format!(
                     "\n\nBridge/vmnet mode needs passwordless sudo for {qp}. Add it:\n\n  \
@@ -1685,24 +2629,159 @@ fn start_vm_with_config(smac: &str, cfg: &VmStartConfig) -> Result<String, Strin
                 String::new()
             };
             format!("QEMU start error (sudo): {}{}", e, hint)
-        })?
+        })
     } else {
         output_log.push_str(&format!("QEMU: {} {}\n", qemu_path, qemu_args.join(" ")));
-        let args_ref: Vec<&str> = qemu_args.iter().map(|s| s.as_str()).collect();
-        spawn_background(&qemu_path, &args_ref)
-            .map_err(|e| format!("QEMU start error: {}", e))?
+        spawn(&qemu_path, qemu_args.clone())
+            .map_err(|e| format!("QEMU start error: {}", e))
+    };
+    let (pid, log_path) = match spawned {
+        Ok(started) => started,
+        Err(e) => {
+            #[cfg(target_os = "linux")]
+            cleanup_virtiofsd(&ismac);
+            return Err(e);
+        }
     };
     output_log.push_str(&format!("QEMU started (PID {})\n", pid));
+    crate::console::start_capture(smac);
     output_log.push_str(&format!("QEMU log: {}\n", log_path));
+    if is_windows {
+        watch_windows_provision(smac);
+    }
+    if unattend_attached {
+        press_boot_keys(smac);
+    }
+    take_stop_request(smac);
+    if linux_install.is_some() {
+        watch_linux_install(smac);
+    }
 
     // Set status to running
     if let Err(e) = db::set_vm_status(smac, "running") {
         output_log.push_str(&format!("WARNING: DB status update failed: {}\n", e));
     }
 
+    #[cfg(target_os = "linux")]
+    if let Err(e) = apply_cpu_pinning(smac, &cfg.cpu, &mut output_log) {
+        output_log.push_str(&format!("WARNING: CPU pinning failed: {}\n", e));
+    }
+    #[cfg(not(target_os = "linux"))]
+    if !cfg.cpu.pinning.is_empty() || !cfg.cpu.emulator_pin.is_empty() {
+        output_log.push_str("WARNING: CPU pinning is only supported on Linux — ignored\n");
+    }
+
+    // QEMU always brings links up — re-apply adapters saved as link down.
+    // The monitor socket appears shortly after spawn, so retry briefly.
+    for adapter in cfg.network_adapters.iter().filter(|a| a.link == "down") {
+        let hmp = format!("set_link net{} off", adapter.netid);
+        let mut applied = false;
+        for _ in 0..20 {
+            if crate::api_helpers::qemu_monitor_cmd(smac, &hmp).is_ok() {
+                applied = true;
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(250));
+        }
+        if applied {
+            output_log.push_str(&format!("link : net{} down\n", adapter.netid));
+        } else {
+            output_log.push_str(&format!("WARNING: could not set net{} link down\n", adapter.netid));
+        }
+    }
+
     Ok(output_log)
 }
 
+/// Pin vCPU threads (found via query-cpus-fast) and the remaining QEMU
+/// threads according to cpu.pinning / cpu.emulator_pin. QMP comes up shortly
+/// after spawn, so the first query is retried.
+#[cfg(target_os = "linux")]
+fn apply_cpu_pinning(smac: &str, cpu: &CpuInfo, output_log: &mut String) -> Result<(), String> {
+    if cpu.pinning.iter().all(|l| l.is_empty()) && cpu.emulator_pin.is_empty() {
+        return Ok(());
+    }
+    let mut reply = None;
+    for _ in 0..20 {
+        if let Ok(v) = crate::qmp::qmp_command(smac, "query-cpus-fast", None) {
+            reply = Some(v);
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(250));
+    }
+    let reply = reply.ok_or("QMP not reachable")?;
+    let vcpu_threads: Vec<(usize, u64)> = reply
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|c| {
+            let idx = c.get("cpu-index")?.as_u64()? as usize;
+            let tid = c.get("thread-id")?.as_u64()?;
+            Some((idx, tid))
+        })
+        .collect();
+
+    if !cpu.emulator_pin.is_empty() {
+        // Tgid of a vCPU thread is the QEMU process (the spawned PID may be sudo's)
+        let first_tid = vcpu_threads.first().map(|t| t.1).ok_or("No vCPU threads reported")?;
+        let status = std::fs::read_to_string(format!("/proc/{}/status", first_tid))
+            .map_err(|e| format!("Read /proc/{}/status: {}", first_tid, e))?;
+        let qemu_pid = status
+            .lines()
+            .find_map(|l| l.strip_prefix("Tgid:"))
+            .map(|v| v.trim().to_string())
+            .ok_or("Tgid not found")?;
+        let tasks = std::fs::read_dir(format!("/proc/{}/task", qemu_pid))
+            .map_err(|e| format!("List QEMU threads: {}", e))?;
+        let mut pinned = 0;
+        for task in tasks.flatten() {
+            let tid = task.file_name().to_string_lossy().to_string();
+            if vcpu_threads.iter().any(|t| t.1.to_string() == tid) {
+                continue;
+            }
+            run_taskset(&["-pc", &cpu.emulator_pin, &tid])?;
+            pinned += 1;
+        }
+        output_log.push_str(&format!(
+            "pin: emulator ({} threads of PID {}) → CPUs {}\n",
+            pinned, qemu_pid, cpu.emulator_pin
+        ));
+    }
+
+    for (idx, tid) in &vcpu_threads {
+        if let Some(list) = cpu.pinning.get(*idx).filter(|l| !l.is_empty()) {
+            run_taskset(&["-pc", list, &tid.to_string()])?;
+            output_log.push_str(&format!("pin: vCPU {} (TID {}) → CPUs {}\n", idx, tid, list));
+        }
+    }
+    Ok(())
+}
+
+/// Run taskset, retrying through sudo — QEMU started via sudo (bridge /
+/// switch TAP) is root-owned.
+#[cfg(target_os = "linux")]
+fn run_taskset(args: &[&str]) -> Result<(), String> {
+    use std::process::Command;
+    let out = Command::new("taskset")
+        .args(args)
+        .output()
+        .map_err(|e| format!("taskset exec error: {}", e))?;
+    if out.status.success() {
+        return Ok(());
+    }
+    let mut stderr = String::from_utf8_lossy(&out.stderr).trim().to_string();
+    if get_conf_or("bridge_sudo", "true") == "true" {
+        let sudo_path = get_conf_or("bridge_sudo_path", "/usr/bin/sudo");
+        if let Ok(o) = Command::new(&sudo_path).arg("-n").arg("taskset").args(args).output() {
+            if o.status.success() {
+                return Ok(());
+            }
+            stderr = String::from_utf8_lossy(&o.stderr).trim().to_string();
+        }
+    }
+    Err(format!("taskset {} failed: {}", args.join(" "), stderr))
+}
+
 /// Start VM by smac — loads config from DB
 pub fn start(json_str: &str) -> Result<String, String> {
     let cmd: SimpleCmd =
@@ -1730,14 +2809,11 @@ pub fn stop(json_str: &str) -> Result<String, String> {
         serde_json::from_str(json_str).map_err(|e| format!("JSON parse error: {}", e))?;
     sanitize_name(&cmd.smac)?;
     let mut output = format!("now stopping compute {}\n", cmd.smac);
-    // Clean up TAP interfaces before stopping QEMU
-    #[cfg(target_os = "linux")]
-    cleanup_switch_taps(&cmd.smac);
-    #[cfg(target_os = "windows")]
-    cleanup_switch_taps_windows(&cmd.smac);
-    cleanup_websockify(&cmd.smac);
+    request_stop(&cmd.smac);
     let pctl_output = send_cmd_pctl("stop", &cmd.smac);
     output.push_str(&pctl_output);
+    // QEMU may need a moment to exit; reap_empty catches a still-populated cgroup
+    cleanup_after_exit(&cmd.smac);
     // Only mark stopped if the QEMU monitor command succeeded (no error reported)
     if !pctl_output.contains("Error:") {
         if let Err(e) = db::set_vm_status(&cmd.smac, "stopped") {
@@ -1761,6 +2837,7 @@ pub fn powerdown(json_str: &str) -> Result<String, String> {
     let cmd: SimpleCmd =
         serde_json::from_str(json_str).map_err(|e| format!("JSON parse error: {}", e))?;
     sanitize_name(&cmd.smac)?;
+    request_stop(&cmd.smac);
     let mut output = send_cmd_pctl("powerdown", &cmd.smac);
     // ACPI powerdown is async — wait briefly then check if QEMU process exited
     std::thread::sleep(std::time::Duration::from_secs(3));
@@ -1769,11 +2846,7 @@ pub fn powerdown(json_str: &str) -> Result<String, String> {
     if !std::path::Path::new(&sock_path).exists() {
         // Monitor socket gone = QEMU exited
         let _ = db::set_vm_status(&cmd.smac, "stopped");
-        // Clean up TAP interfaces
-        #[cfg(target_os = "linux")]
-        cleanup_switch_taps(&cmd.smac);
-        #[cfg(target_os = "windows")]
-        cleanup_switch_taps_windows(&cmd.smac);
+        cleanup_after_exit(&cmd.smac);
         output.push_str("VM stopped.\n");
     } else {
         // QEMU still running — guest is shutting down
@@ -1782,6 +2855,138 @@ pub fn powerdown(json_str: &str) -> Result<String, String> {
     Ok(output)
 }
 
+/// Host-side cleanup once a VM's QEMU process has exited or been stopped
+fn cleanup_after_exit(smac: &str) {
+    #[cfg(target_os = "linux")]
+    cleanup_switch_taps(smac);
+    #[cfg(target_os = "windows")]
+    cleanup_switch_taps_windows(smac);
+    crate::console::detach(smac);
+    let _ = std::fs::remove_file(spice_password_file(smac));
+    // Addresses and OS reported by the agent are stale once QEMU is gone
+    let _ = db::delete_guest_info(smac);
+    #[cfg(target_os = "linux")]
+    cleanup_virtiofsd(smac);
+    crate::cgroups::remove(smac);
+}
+
+/// Poll until QEMU has exited or `limit` runs out
+fn wait_for_exit(smac: &str, limit: std::time::Duration) -> bool {
+    let start = std::time::Instant::now();
+    loop {
+        if !crate::qmp::qemu_alive(smac) {
+            return true;
+        }
+        if start.elapsed() >= limit {
+            return false;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(500));
+    }
+}
+
+/// Graceful shutdown with escalation. The guest agent (`guest-shutdown`) is
+/// asked first, since it works for guests that ignore ACPI. ACPI
+/// `system_powerdown` follows if there is no agent, or if the guest is still
+/// up after a third of the timeout. After the timeout QEMU is told to `quit`
+/// (unless `force` is false). The VM is marked stopped only once the QEMU
+/// process has really exited.
+pub fn shutdown(json_str: &str) -> Result<String, String> {
+    let cmd: ShutdownCmd =
+        serde_json::from_str(json_str).map_err(|e| format!("JSON parse error: {}", e))?;
+    sanitize_name(&cmd.smac)?;
+    let smac = cmd.smac.as_str();
+    let timeout = cmd
+        .timeout
+        .unwrap_or_else(|| get_conf_or("shutdown_timeout", "120").parse().unwrap_or(120))
+        .clamp(5, 3600);
+    let start = std::time::Instant::now();
+    let mut output = format!("shutting down {} (timeout {}s)\n", smac, timeout);
+    request_stop(smac);
+    let stage = |output: &mut String, msg: &str| {
+        output.push_str(&format!("[{:>4}s] {}\n", start.elapsed().as_secs(), msg));
+    };
+
+    if !crate::qmp::qemu_alive(smac) {
+        let _ = db::set_vm_status(smac, "stopped");
+        cleanup_after_exit(smac);
+        stage(&mut output, "QEMU is not running; marked stopped");
+        return Ok(output);
+    }
+
+    // 1. Guest agent
+    let mut acpi_sent = false;
+    let agent_sent = if crate::guest_agent::guest_ping(smac) {
+        // guest-shutdown sends no reply on success
+        match crate::guest_agent::qga_command_no_reply(
+            smac,
+            "guest-shutdown",
+            Some(serde_json::json!({ "mode": "powerdown" })),
+        ) {
+            Err(e) => {
+                stage(&mut output, &format!("guest agent refused shutdown: {}", e));
+                false
+            }
+            Ok(()) => {
+                stage(&mut output, "guest-shutdown sent through the guest agent");
+                true
+            }
+        }
+    } else {
+        stage(&mut output, "guest agent not available");
+        false
+    };
+
+    // 2. ACPI, right away without an agent, else after a third of the timeout
+    let total = std::time::Duration::from_secs(timeout);
+    let mut exited = false;
+    if agent_sent {
+        exited = wait_for_exit(smac, total / 3);
+    }
+    if !exited {
+        let pctl_output = send_cmd_pctl("powerdown", smac);
+        if pctl_output.contains("Error:") {
+            stage(&mut output, &format!("ACPI powerdown failed: {}", pctl_output.trim()));
+        } else {
+            stage(&mut output, "ACPI system_powerdown sent");
+            acpi_sent = true;
+        }
+        exited = wait_for_exit(smac, total.saturating_sub(start.elapsed()));
+    }
+
+    // 3. Force quit
+    if !exited {
+        if !cmd.force {
+            stage(&mut output, "guest still running after timeout; not forcing (force=false)");
+            return Err(output);
+        }
+        stage(&mut output, "guest still running after timeout; forcing quit");
+        crate::console::detach(smac);
+        let pctl_output = send_cmd_pctl("stop", smac);
+        if pctl_output.contains("Error:") {
+            stage(&mut output, &format!("quit failed: {}", pctl_output.trim()));
+        }
+        exited = wait_for_exit(smac, std::time::Duration::from_secs(15));
+        if !exited {
+            stage(&mut output, "QEMU did not exit; status left as running");
+            return Err(output);
+        }
+        stage(&mut output, "QEMU exited after forced quit");
+    } else {
+        let how = match (agent_sent, acpi_sent) {
+            (true, false) => "guest agent",
+            (_, true) => "ACPI",
+            _ => "guest",
+        };
+        stage(&mut output, &format!("guest powered off ({})", how));
+    }
+
+    cleanup_after_exit(smac);
+    if let Err(e) = db::set_vm_status(smac, "stopped") {
+        output.push_str(&format!("WARNING: DB status update failed: {}\n", e));
+    }
+    Ok(output)
+}
+
 pub fn delete_vm(json_str: &str) -> Result<String, String> {
     let cmd: SimpleCmd =
         serde_json::from_str(json_str).map_err(|e| format!("JSON parse error: {}", e))?;
@@ -1799,7 +3004,15 @@ pub fn delete_vm(json_str: &str) -> Result<String, String> {
     cleanup_switch_taps(&cmd.smac);
     #[cfg(target_os = "windows")]
     cleanup_switch_taps_windows(&cmd.smac);
-    cleanup_websockify(&cmd.smac);
+    let _ = std::fs::remove_file(crate::console::log_path(&cmd.smac));
+    let _ = std::fs::remove_file(screenshot_path(&cmd.smac));
+    let _ = std::fs::remove_file(spice_password_file(&cmd.smac));
+    // The UEFI NVRAM (boot entries, enrolled Secure Boot keys) belongs to this VM too
+    let _ = std::fs::remove_file(nvram_file(&cmd.smac));
+    // TPM state is tied to this VM (a new VM with the same name gets a fresh TPM)
+    let tpm = tpm_dir(&cmd.smac);
+    let _ = std::fs::remove_dir_all(&tpm);
+    let _ = std::fs::remove_file(format!("{}.sock", tpm));
 
     let mut output = String::new();
     set_ma_mode("1", &cmd.smac);
@@ -2316,6 +3529,8 @@ pub fn create_config(json_str: &str) -> Result<String, String> {
     }
     // Validate MAC address uniqueness before saving
     validate_mac_uniqueness(&config, None)?;
+    validate_boot_config(&config)?;
+    normalize_pci_devices(&mut config)?;
     // Validate disk names
     if let Some(disks) = config.get("disks").and_then(|d| d.as_array()) {
         for disk in disks {
@@ -2351,6 +3566,10 @@ pub fn create_config(json_str: &str) -> Result<String, String> {
 }
 
 /// Update VM config in DB + reassign disk owners
+/// `(section, key)` of VM config credentials that the API never returns;
+/// an empty value on update keeps the stored one
+pub const VM_CONFIG_SECRETS: &[(&str, &str)] = &[("unattend", "admin_password"), ("linux_install", "password")];
+
 pub fn update_config(json_str: &str) -> Result<String, String> {
     let val: serde_json::Value =
         serde_json::from_str(json_str).map_err(|e| format!("JSON parse error: {}", e))?;
@@ -2366,6 +3585,15 @@ pub fn update_config(json_str: &str) -> Result<String, String> {
 
     // Validate MAC address uniqueness (exclude this VM's own MACs)
     validate_mac_uniqueness(new_config, Some(&smac))?;
+    // Reject passthrough lists that split an IOMMU group
+    let new_pci = if new_config.get("pci_devices").is_some() {
+        let mut pci = serde_json::json!({ "pci_devices": new_config["pci_devices"].clone() });
+        let addrs = normalize_pci_devices(&mut pci)?;
+        validate_pci_passthrough(&smac, &addrs, false)?;
+        Some(pci["pci_devices"].take())
+    } else {
+        None
+    };
     // Validate disk names
     if let Some(disks) = new_config.get("disks").and_then(|d| d.as_array()) {
         for disk in disks {
@@ -2385,10 +3613,32 @@ pub fn update_config(json_str: &str) -> Result<String, String> {
     };
     if let (Some(old_map), Some(new_map)) = (config.as_object_mut(), new_config.as_object()) {
         for (k, v) in new_map {
+            // cpu / memory / recording are merged key-by-key so API-only
+            // settings (pinning, NUMA, hugepages, capture interval) survive
+            // edits from the UI form
+            if k == "cpu" || k == "memory" || k == "recording" || k == "display" || k == "unattend"
+                || k == "linux_install" || k == "boot"
+            {
+                if let (Some(old_sub), Some(new_sub)) =
+                    (old_map.get_mut(k).and_then(|o| o.as_object_mut()), v.as_object())
+                {
+                    for (sk, sv) in new_sub {
+                        if sv.as_str() == Some("") && VM_CONFIG_SECRETS.contains(&(k.as_str(), sk.as_str())) {
+                            continue;
+                        }
+                        old_sub.insert(sk.clone(), sv.clone());
+                    }
+                    continue;
+                }
+            }
             old_map.insert(k.clone(), v.clone());
         }
     }
 
+    if let Some(pci) = new_pci {
+        config["pci_devices"] = pci;
+    }
+    validate_boot_config(&config)?;
     let config_str = serde_json::to_string(&config).unwrap_or_default();
 
     db::update_vm(&smac, &config_str)?;
@@ -2439,8 +3689,40 @@ pub fn rename_vm(old_name: &str, new_name: &str) -> Result<String, String> {
         let _ = std::fs::rename(&old_sock, &new_sock);
     }
 
+    // UEFI NVRAM and TPM state are named after the VM
+    let artifacts = [
+        (nvram_file(old_name), nvram_file(new_name)),
+        (tpm_dir(old_name), tpm_dir(new_name)),
+    ];
+    let undo = |moved: &[&(String, String)]| {
+        for (from, to) in moved.iter().copied() {
+            let _ = std::fs::rename(to, from);
+        }
+    };
+    let mut moved: Vec<&(String, String)> = Vec::new();
+    for pair in &artifacts {
+        let (from, to) = pair;
+        if !std::path::Path::new(from).exists() {
+            continue;
+        }
+        if std::path::Path::new(to).exists() {
+            // Leftover of a deleted VM with the new name
+            let _ = std::fs::remove_dir_all(to);
+            let _ = std::fs::remove_file(to);
+        }
+        if let Err(e) = std::fs::rename(from, to) {
+            undo(&moved);
+            return Err(format!("Failed to move {}: {}", from, e));
+        }
+        moved.push(pair);
+    }
+    let _ = std::fs::remove_file(format!("{}.sock", tpm_dir(old_name)));
+
     // Rename in database (VM + disk owners)
-    db::rename_vm(old_name, new_name)?;
+    if let Err(e) = db::rename_vm(old_name, new_name) {
+        undo(&moved);
+        return Err(e);
+    }
 
     Ok(format!("VM renamed from '{}' to '{}'", old_name, new_name))
 }
@@ -2565,10 +3847,34 @@ pub fn create_full_backup(vm_name: &str, note: &str) -> Result<String, String> {
         return Err("No disk files found to backup".into());
     }
 
+    // UEFI NVRAM (boot entries, Secure Boot keys), with its snapshots
+    let nvram = nvram_file(vm_name);
+    let has_nvram = std::path::Path::new(&nvram).exists();
+    if has_nvram {
+        let dst = format!("{}/efivars.fd", backup_dir);
+        if let Err(e) = std::fs::copy(&nvram, &dst) {
+            let _ = std::fs::remove_dir_all(&backup_dir);
+            return Err(format!("Backup failed for NVRAM: {}", e));
+        }
+        total_size += std::fs::metadata(&dst).map(|m| m.len() as i64).unwrap_or(0);
+    }
+
+    // swtpm state (BitLocker keys)
+    let tpm = tpm_dir(vm_name);
+    let has_tpm = std::path::Path::new(&tpm).is_dir();
+    if has_tpm {
+        if let Err(e) = copy_tpm_state(&tpm, &format!("{}/tpm", backup_dir)) {
+            let _ = std::fs::remove_dir_all(&backup_dir);
+            return Err(format!("Backup failed for TPM state: {}", e));
+        }
+    }
+
     // Write metadata.json
     let meta = serde_json::json!({
         "vm_name": vm_name,
         "disks": backed_up,
+        "nvram": has_nvram,
+        "tpm": has_tpm,
         "backup_id": backup_id,
         "created_at": chrono::Local::now().to_rfc3339(),
         "note": note,
@@ -2582,8 +3888,9 @@ pub fn create_full_backup(vm_name: &str, note: &str) -> Result<String, String> {
     let disk_json = serde_json::to_string(&backed_up).unwrap_or_default();
     db::insert_backup(&backup_id, vm_name, &disk_json, "full", note, total_size)?;
 
-    Ok(format!("Full backup '{}' created ({} disks, {})",
-        backup_id, backed_up.len(), format_bytes(total_size as u64)))
+    Ok(format!("Full backup '{}' created ({} disks{}{}, {})",
+        backup_id, backed_up.len(), if has_nvram { " + NVRAM" } else { "" },
+        if has_tpm { " + TPM" } else { "" }, format_bytes(total_size as u64)))
 }
 
 /// Restore a full backup — copies disk files back to disk_path
@@ -2613,7 +3920,22 @@ pub fn restore_full_backup(backup_id: &str, vm_name: &str) -> Result<String, Str
         let _ = db::set_disk_backing(dname, "");
         restored += 1;
     }
-    Ok(format!("Restored {} disk(s) from backup '{}'", restored, backup_id))
+    // Backups made before NVRAM was included leave it as is
+    let nvram_src = format!("{}/efivars.fd", backup_dir);
+    let nvram_restored = std::path::Path::new(&nvram_src).exists();
+    if nvram_restored {
+        std::fs::copy(&nvram_src, nvram_file(vm_name))
+            .map_err(|e| format!("Restore failed for NVRAM: {}", e))?;
+    }
+    let tpm_src = format!("{}/tpm", backup_dir);
+    let tpm_restored = std::path::Path::new(&tpm_src).is_dir();
+    if tpm_restored {
+        copy_tpm_state(&tpm_src, &tpm_dir(vm_name))
+            .map_err(|e| format!("Restore failed for TPM state: {}", e))?;
+    }
+    Ok(format!("Restored {} disk(s){}{} from backup '{}'",
+        restored, if nvram_restored { " + NVRAM" } else { "" },
+        if tpm_restored { " + TPM" } else { "" }, backup_id))
 }
 
 /// Delete a full backup (files + DB record)
@@ -2662,6 +3984,16 @@ pub fn create_snapshot(vm_name: &str, name: &str, note: &str) -> Result<String,
         format!("snap_{}_{}", ts, rnd)
     };
 
+    // UEFI NVRAM (boot entries, Secure Boot keys) is snapshotted alongside
+    // the disks; it is not a disk, so it has no DB record
+    let nvram = nvram_file(vm_name);
+    let has_nvram = std::path::Path::new(&nvram).exists();
+    if has_nvram {
+        ensure_nvram_qcow2(&nvram)?;
+        crate::ssh::run_cmd(&qemu_img, &["snapshot", "-c", &snapshot_id, &nvram])
+            .map_err(|e| format!("Snapshot failed for NVRAM: {}", e))?;
+    }
+
     let mut created: Vec<String> = Vec::new();
     for dname in &disk_names {
         let disk_file = format!("{}/{}.qcow2", disk_path, dname);
@@ -2675,6 +4007,9 @@ pub fn create_snapshot(vm_name: &str, name: &str, note: &str) -> Result<String,
                 let prev_file = format!("{}/{}.qcow2", disk_path, prev);
                 let _ = crate::ssh::run_cmd(&qemu_img, &["snapshot", "-d", &snapshot_id, &prev_file]);
             }
+            if has_nvram {
+                let _ = crate::ssh::run_cmd(&qemu_img, &["snapshot", "-d", &snapshot_id, &nvram]);
+            }
             return Err(format!("Snapshot failed for disk '{}': {}", dname, e));
         }
         db::insert_snapshot(&snapshot_id, dname, vm_name, note)?;
@@ -2682,9 +4017,20 @@ pub fn create_snapshot(vm_name: &str, name: &str, note: &str) -> Result<String,
     }
 
     if created.is_empty() {
+        if has_nvram {
+            let _ = crate::ssh::run_cmd(&qemu_img, &["snapshot", "-d", &snapshot_id, &nvram]);
+        }
         return Err("No disk files found to snapshot".into());
     }
-    Ok(format!("Snapshot '{}' created ({} disks)", snapshot_id, created.len()))
+    Ok(format!("Snapshot '{}' created ({} disks{})",
+        snapshot_id, created.len(), if has_nvram { " + NVRAM" } else { "" }))
+}
+
+/// Whether a qcow2 image has an internal snapshot with this tag
+fn qcow2_has_snapshot(qemu_img: &str, file: &str, snapshot_id: &str) -> bool {
+    crate::ssh::run_cmd(qemu_img, &["snapshot", "-l", file])
+        .map(|out| out.lines().any(|l| l.split_whitespace().nth(1) == Some(snapshot_id)))
+        .unwrap_or(false)
 }
 
 /// List all snapshots for a VM, grouped by snapshot_id
@@ -2739,7 +4085,16 @@ pub fn revert_snapshot(vm_name: &str, snapshot_id: &str) -> Result<String, Strin
             .map_err(|e| format!("Revert failed for disk '{}': {}", r.disk_name, e))?;
         reverted += 1;
     }
-    Ok(format!("Reverted {} disk(s) to snapshot '{}'", reverted, snapshot_id))
+    // Snapshots taken before NVRAM was included leave it as is
+    let nvram = nvram_file(vm_name);
+    let nvram_reverted = std::path::Path::new(&nvram).exists()
+        && qcow2_has_snapshot(&qemu_img, &nvram, snapshot_id);
+    if nvram_reverted {
+        crate::ssh::run_cmd(&qemu_img, &["snapshot", "-a", snapshot_id, &nvram])
+            .map_err(|e| format!("Revert failed for NVRAM: {}", e))?;
+    }
+    Ok(format!("Reverted {} disk(s){} to snapshot '{}'",
+        reverted, if nvram_reverted { " and NVRAM" } else { "" }, snapshot_id))
 }
 
 /// Create a live snapshot (VM running, RAM + device state included) via QEMU HMP savevm.
@@ -2826,6 +4181,10 @@ pub fn delete_snapshot(vm_name: &str, snapshot_id: &str) -> Result<String, Strin
                 let _ = crate::ssh::run_cmd(&qemu_img, &["snapshot", "-d", snapshot_id, &disk_file]);
             }
         }
+        let nvram = nvram_file(vm_name);
+        if std::path::Path::new(&nvram).exists() && qcow2_has_snapshot(&qemu_img, &nvram, snapshot_id) {
+            let _ = crate::ssh::run_cmd(&qemu_img, &["snapshot", "-d", snapshot_id, &nvram]);
+        }
     }
     for r in &snap_disks {
         let _ = db::delete_snapshot_record(snapshot_id, &r.disk_name);
@@ -2955,72 +4314,1707 @@ pub fn resize_disk(json_str: &str) -> Result<String, String> {
     Ok(output)
 }
 
-// --- Port forwarding operations ---
-
-/// Collect all host ports used by port_forwards across all VMs
-fn used_host_ports(exclude_smac: Option<&str>) -> Vec<(u16, String, String)> {
-    // Returns Vec<(host_port, protocol, vm_name)>
-    let mut ports = Vec::new();
-    if let Ok(vms) = db::list_vms() {
-        for vm in &vms {
-            if let Some(exc) = exclude_smac {
-                if vm.smac == exc { continue; }
-            }
-            if let Ok(cfg) = serde_json::from_str::<serde_json::Value>(&vm.config) {
-                if let Some(forwards) = cfg.get("port_forwards").and_then(|v| v.as_array()) {
-                    for rule in forwards {
-                        let proto = rule.get("protocol").and_then(|v| v.as_str()).unwrap_or("tcp");
-                        let hp = rule.get("host_port").and_then(|v| v.as_u64()).unwrap_or(0) as u16;
-                        if hp > 0 {
-                            ports.push((hp, proto.to_string(), vm.smac.clone()));
-                        }
-                    }
-                }
-            }
-        }
-    }
-    ports
-}
+// --- Disk hot-plug operations ---
 
-/// Check that a host port is not already used by another VM's port forward
-pub fn validate_host_port_unique(host_port: u16, protocol: &str, exclude_smac: Option<&str>) -> Result<(), String> {
-    let used = used_host_ports(exclude_smac);
-    for (port, proto, vm_name) in &used {
-        if *port == host_port && proto == protocol {
-            return Err(format!(
-                "Host port {} ({}) is already used by VM '{}'",
-                host_port, protocol, vm_name
-            ));
-        }
-    }
-    Ok(())
+/// IOPS preset of a disk as (iops-total, burst max, burst seconds).
+/// None when iops-total is 0 or unset (unlimited).
+fn disk_iops_limits(total: &str, max: &str, length: &str) -> Option<(u64, u64, u64)> {
+    let total = total.trim().parse::<u64>().ok().filter(|t| *t > 0)?;
+    let max = max.trim().parse::<u64>().unwrap_or(0);
+    let length = length.trim().parse::<u64>().unwrap_or(0);
+    Some((total, max, length))
 }
 
-/// Add a port forward rule to a VM config. If VM is running, also apply via QEMU monitor.
-pub fn add_port_forward(smac: &str, protocol: &str, host_port: u16, guest_port: u16) -> Result<String, String> {
-    // Validate protocol
-    if protocol != "tcp" && protocol != "udp" {
-        return Err("Protocol must be 'tcp' or 'udp'".into());
-    }
-    // Validate port ranges
-    if host_port < 1024 {
-        return Err(format!("Host port {} is below 1024 (reserved range)", host_port));
-    }
-    if guest_port == 0 {
-        return Err("Guest port must be 1-65535".into());
-    }
-
-    // Check host port uniqueness
-    validate_host_port_unique(host_port, protocol, Some(smac))?;
+/// Attach a disk to a VM. Running VMs get it live via QMP blockdev-add +
+/// device_add; stopped VMs just get the config entry for the next start.
+pub fn attach_disk(smac: &str, disk_name: &str, iops: &serde_json::Value) -> Result<String, String> {
+    sanitize_name(smac)?;
+    validate_disk_name(disk_name)?;
+    let iops_field = |key: &str| iops.get(key).and_then(|v| v.as_str()).unwrap_or("0").to_string();
+    let (iops_total, iops_max, iops_max_length) =
+        (iops_field("iops-total"), iops_field("iops-total-max"), iops_field("iops-total-max-length"));
 
-    // Load current config
     let vm = db::get_vm(smac)?;
     let mut config: serde_json::Value = serde_json::from_str(&vm.config)
         .map_err(|e| format!("Config parse error: {}", e))?;
 
-    // Get or create port_forwards array
-    let forwards = config.get("port_forwards")
-        .and_then(|v| v.as_array())
+    let disk_path = get_conf("disk_path");
+    let disk_file = format!("{}/{}.qcow2", disk_path, disk_name);
+    if !std::path::Path::new(&disk_file).exists() {
+        return Err(format!("Disk '{}' not found", disk_name));
+    }
+
+    let disks = config.get("disks").and_then(|d| d.as_array()).cloned().unwrap_or_default();
+    if disks.iter().any(|d| d.get("diskname").and_then(|v| v.as_str()) == Some(disk_name)) {
+        return Err(format!("Disk '{}' is already attached to VM '{}'", disk_name, smac));
+    }
+
+    // Refuse disks that belong to another VM (running or not)
+    check_disk_not_in_use(disk_name)?;
+    if let Ok(disks) = db::list_disks() {
+        if let Some(d) = disks.iter().find(|d| d.name == disk_name) {
+            if !d.owner.is_empty() && d.owner != smac {
+                return Err(format!(
+                    "Disk '{}' is already attached to VM '{}' — detach it there first",
+                    disk_name, d.owner
+                ));
+            }
+        }
+    }
+
+    // Next free disk id — drive/device ids (hdN / vdN) are derived from it
+    let diskid = disks
+        .iter()
+        .filter_map(|d| d.get("diskid").and_then(|v| v.as_str()))
+        .filter_map(|s| s.parse::<u32>().ok())
+        .max()
+        .map(|m| m + 1)
+        .unwrap_or(0);
+
+    let mut output = String::new();
+    if vm.status == "running" {
+        let node = format!("hd{}", diskid);
+        let dev_id = format!("vd{}", diskid);
+        crate::qmp::qmp_command(
+            smac,
+            "blockdev-add",
+            Some(serde_json::json!({
+                "driver": "qcow2",
+                "node-name": node,
+                "file": { "driver": "file", "filename": disk_file },
+            })),
+        )?;
+        let mut dev_args = serde_json::json!({
+            "driver": "virtio-blk-pci",
+            "id": dev_id,
+            "drive": node,
+        });
+        match crate::qmp::free_hotplug_port(smac) {
+            Ok(Some(port)) => dev_args["bus"] = serde_json::json!(port),
+            Ok(None) => {}
+            Err(e) => {
+                let _ = crate::qmp::qmp_command(smac, "blockdev-del", Some(serde_json::json!({ "node-name": node })));
+                return Err(e);
+            }
+        }
+        if let Err(e) = crate::qmp::qmp_command(smac, "device_add", Some(dev_args)) {
+            // Roll back the block node so a retry doesn't hit "duplicate node name"
+            let _ = crate::qmp::qmp_command(smac, "blockdev-del", Some(serde_json::json!({ "node-name": node })));
+            return Err(format!("device_add failed: {}", e));
+        }
+        // Limits for the live device only; the config keeps them as the disk's preset
+        if let Some((total, max, length)) = disk_iops_limits(&iops_total, &iops_max, &iops_max_length) {
+            let mut limits = serde_json::json!({
+                "id": dev_id,
+                "bps": 0, "bps_rd": 0, "bps_wr": 0,
+                "iops": total, "iops_rd": 0, "iops_wr": 0,
+            });
+            if max > 0 {
+                limits["iops_max"] = serde_json::json!(max);
+                if length > 0 {
+                    limits["iops_max_length"] = serde_json::json!(length);
+                }
+            }
+            if let Err(e) = crate::qmp::qmp_command(smac, "block_set_io_throttle", Some(limits)) {
+                let _ = crate::qmp::device_del_wait(smac, &dev_id, std::time::Duration::from_secs(15));
+                let _ = crate::qmp::qmp_command(smac, "blockdev-del", Some(serde_json::json!({ "node-name": node })));
+                return Err(format!("block_set_io_throttle failed: {}", e));
+            }
+            output.push_str(&format!("iops-total {} (burst {} for {}s)\n", total, max, length));
+        }
+        output.push_str(&format!("Hot-plugged '{}' as {} (live)\n", disk_name, dev_id));
+    } else {
+        output.push_str("VM is stopped — disk will be attached on next start\n");
+    }
+
+    let mut new_disks = disks;
+    new_disks.push(serde_json::json!({
+        "diskid": diskid.to_string(),
+        "diskname": disk_name,
+        "iops-total": iops_total,
+        "iops-total-max": iops_max,
+        "iops-total-max-length": iops_max_length,
+    }));
+    config["disks"] = serde_json::json!(new_disks);
+    let config_str = serde_json::to_string(&config).unwrap_or_default();
+    db::update_vm(smac, &config_str)?;
+    db::set_disk_owner(disk_name, smac)?;
+
+    output.push_str(&format!("Disk '{}' attached to VM '{}' (diskid {})\n", disk_name, smac, diskid));
+    Ok(output)
+}
+
+/// Detach a disk from a VM. Running VMs get a live device_del; the guest
+/// must release the device before the block node can be removed.
+pub fn detach_disk(smac: &str, disk_name: &str) -> Result<String, String> {
+    sanitize_name(smac)?;
+    validate_disk_name(disk_name)?;
+
+    let vm = db::get_vm(smac)?;
+    let mut config: serde_json::Value = serde_json::from_str(&vm.config)
+        .map_err(|e| format!("Config parse error: {}", e))?;
+    let disks = config.get("disks").and_then(|d| d.as_array()).cloned().unwrap_or_default();
+    let diskid = disks
+        .iter()
+        .find(|d| d.get("diskname").and_then(|v| v.as_str()) == Some(disk_name))
+        .and_then(|d| d.get("diskid").and_then(|v| v.as_str()))
+        .map(|s| s.to_string())
+        .ok_or_else(|| format!("Disk '{}' is not attached to VM '{}'", disk_name, smac))?;
+
+    let mut output = String::new();
+    if vm.status == "running" {
+        let dev_id = format!("vd{}", diskid);
+        if crate::qmp::on_pcie_root_bus(smac, &dev_id)? {
+            return Err(format!(
+                "Disk '{}' was attached at VM start on the PCIe root bus, which can't hot-unplug — stop the VM to detach it",
+                disk_name
+            ));
+        }
+        crate::qmp::device_del_wait(smac, &dev_id, std::time::Duration::from_secs(15))?;
+        // Cold-plugged -drive backends are auto-deleted with the device;
+        // only blockdev-add'ed nodes need an explicit blockdev-del.
+        let _ = crate::qmp::qmp_command(
+            smac,
+            "blockdev-del",
+            Some(serde_json::json!({ "node-name": format!("hd{}", diskid) })),
+        );
+        output.push_str(&format!("Unplugged {} from running VM (live)\n", dev_id));
+    }
+
+    let new_disks: Vec<serde_json::Value> = disks
+        .into_iter()
+        .filter(|d| d.get("diskname").and_then(|v| v.as_str()) != Some(disk_name))
+        .collect();
+    config["disks"] = serde_json::json!(new_disks);
+    let config_str = serde_json::to_string(&config).unwrap_or_default();
+    db::update_vm(smac, &config_str)?;
+    db::set_disk_owner(disk_name, "")?;
+
+    output.push_str(&format!("Disk '{}' detached from VM '{}'\n", disk_name, smac));
+    Ok(output)
+}
+
+// --- NIC hot-plug operations ---
+
+/// Build a NetworkAdapter from a request body, falling back to `base` for
+/// fields the caller did not send.
+fn nic_from_request(
+    body: &serde_json::Value,
+    netid: &str,
+    base: Option<&NetworkAdapter>,
+) -> Result<NetworkAdapter, String> {
+    let field = |key: &str, default: &str| -> String {
+        body.get(key)
+            .and_then(|v| v.as_str())
+            .map(|s| s.trim().to_string())
+            .unwrap_or_else(|| default.to_string())
+    };
+    let mode = field("mode", base.map(|b| b.mode.as_str()).unwrap_or("nat"));
+    if !matches!(mode.as_str(), "nat" | "switch" | "bridge") {
+        return Err(format!("Invalid network mode '{}' (nat, switch, bridge)", mode));
+    }
+    let nic_model = field("nic_model", base.map(|b| b.nic_model.as_str()).unwrap_or("virtio"));
+    if !matches!(nic_model.as_str(), "virtio" | "e1000" | "e1000e" | "rtl8139") {
+        return Err(format!("Invalid NIC model '{}' (virtio, e1000, e1000e, rtl8139)", nic_model));
+    }
+    let mut mac = field("mac", base.map(|b| b.mac.as_str()).unwrap_or("")).to_lowercase();
+    if mac.is_empty() {
+        mac = generate_random_mac();
+    }
+    let mac_ok = mac.len() == 17
+        && mac.split(':').count() == 6
+        && mac.split(':').all(|o| o.len() == 2 && o.chars().all(|c| c.is_ascii_hexdigit()));
+    if !mac_ok {
+        return Err(format!("Invalid MAC address '{}'", mac));
+    }
+    let switch_name = field("switch_name", base.map(|b| b.switch_name.as_str()).unwrap_or(""));
+    if mode == "switch" && switch_name.is_empty() {
+        return Err("Switch mode requires 'switch_name'".into());
+    }
+    Ok(NetworkAdapter {
+        netid: netid.to_string(),
+        mac,
+        vlan: field("vlan", base.map(|b| b.vlan.as_str()).unwrap_or("0")),
+        mode,
+        switch_name,
+        bridge_iface: field("bridge_iface", base.map(|b| b.bridge_iface.as_str()).unwrap_or("")),
+        nic_model,
+        link: base.map(|b| b.link.clone()).unwrap_or_else(|| "up".into()),
+    })
+}
+
+/// Load the VM config plus its typed adapter list
+fn load_nic_config(smac: &str) -> Result<(db::VmRecord, serde_json::Value, Vec<NetworkAdapter>), String> {
+    let vm = db::get_vm(smac)?;
+    let config: serde_json::Value = serde_json::from_str(&vm.config)
+        .map_err(|e| format!("Config parse error: {}", e))?;
+    let adapters: Vec<NetworkAdapter> = match config.get("network_adapters") {
+        Some(a) => serde_json::from_value(a.clone())
+            .map_err(|e| format!("network_adapters parse error: {}", e))?,
+        None => Vec::new(),
+    };
+    Ok((vm, config, adapters))
+}
+
+fn save_nic_config(smac: &str, mut config: serde_json::Value, adapters: &[NetworkAdapter]) -> Result<(), String> {
+    config["network_adapters"] = serde_json::to_value(adapters).unwrap_or_default();
+    let config_str = serde_json::to_string(&config).unwrap_or_default();
+    db::update_vm(smac, &config_str)
+}
+
+/// Plug a netdev + NIC into a running VM.
+/// The netdev goes through HMP `netdev_add`, which takes the same option
+/// string as `-netdev` — QMP would need every backend re-modelled as JSON.
+fn hotplug_nic(
+    smac: &str,
+    adapter: &NetworkAdapter,
+    config: &serde_json::Value,
+    others: &[NetworkAdapter],
+    output: &mut String,
+) -> Result<(), String> {
+    // Port forwards and the MDS subnet only apply to the first NAT adapter
+    let first_nat = adapter.mode == "nat" && !others.iter().any(|a| a.mode == "nat");
+    let (slirp_opts, hostfwd_opts) = if first_nat {
+        let local_ipv4 = config
+            .get("mds")
+            .and_then(|m| m.get("local_ipv4"))
+            .and_then(|v| v.as_str())
+            .unwrap_or("");
+        (slirp_net_opts(local_ipv4), hostfwd_net_opts(config, output))
+    } else {
+        (String::new(), String::new())
+    };
+    let spec = build_netdev_spec(smac, adapter, &slirp_opts, &hostfwd_opts, output)?;
+
+    let out = crate::api_helpers::qemu_monitor_cmd(smac, &format!("netdev_add {}", spec));
+    match out {
+        Ok(o) if o.contains("Error") || o.contains("error") => {
+            release_switch_tap(smac, adapter);
+            return Err(format!("netdev_add failed: {}", o.trim()));
+        }
+        Err(e) => {
+            release_switch_tap(smac, adapter);
+            return Err(format!("netdev_add failed: {}", e));
+        }
+        Ok(_) => {}
+    }
+
+    let netdev_id = format!("net{}", adapter.netid);
+    let rollback = |smac: &str| {
+        let _ = crate::qmp::qmp_command(smac, "netdev_del", Some(serde_json::json!({ "id": netdev_id })));
+        release_switch_tap(smac, adapter);
+    };
+    let mut dev_args = serde_json::json!({
+        "driver": nic_device_model(&adapter.nic_model),
+        "id": format!("nic{}", adapter.netid),
+        "netdev": netdev_id,
+        "mac": adapter.mac,
+    });
+    match crate::qmp::free_hotplug_port(smac) {
+        Ok(Some(port)) => dev_args["bus"] = serde_json::json!(port),
+        Ok(None) => {}
+        Err(e) => {
+            rollback(smac);
+            return Err(e);
+        }
+    }
+    if let Err(e) = crate::qmp::qmp_command(smac, "device_add", Some(dev_args)) {
+        rollback(smac);
+        return Err(format!("device_add failed: {}", e));
+    }
+    output.push_str(&format!(
+        "Hot-plugged nic{} ({}, {}, mac {}) (live)\n",
+        adapter.netid, adapter.mode, nic_device_model(&adapter.nic_model), adapter.mac
+    ));
+    Ok(())
+}
+
+/// Unplug a NIC + netdev from a running VM and release its host TAP
+fn hotunplug_nic(smac: &str, adapter: &NetworkAdapter, output: &mut String) -> Result<(), String> {
+    let dev_id = format!("nic{}", adapter.netid);
+    crate::qmp::device_del_wait(smac, &dev_id, std::time::Duration::from_secs(15))?;
+    if let Err(e) = crate::qmp::qmp_command(
+        smac,
+        "netdev_del",
+        Some(serde_json::json!({ "id": format!("net{}", adapter.netid) })),
+    ) {
+        output.push_str(&format!("WARNING: netdev_del net{}: {}\n", adapter.netid, e));
+    }
+    release_switch_tap(smac, adapter);
+    output.push_str(&format!("Unplugged {} from running VM (live)\n", dev_id));
+    Ok(())
+}
+
+/// Add a network adapter. Running VMs get it live; stopped VMs on next start.
+pub fn attach_nic(smac: &str, body: &serde_json::Value) -> Result<String, String> {
+    sanitize_name(smac)?;
+    let (vm, config, mut adapters) = load_nic_config(smac)?;
+
+    let netid = adapters
+        .iter()
+        .filter_map(|a| a.netid.parse::<u32>().ok())
+        .max()
+        .map(|m| m + 1)
+        .unwrap_or(0);
+    let adapter = nic_from_request(body, &netid.to_string(), None)?;
+
+    let mut check = config.clone();
+    let mut all = adapters.clone();
+    all.push(adapter.clone());
+    check["network_adapters"] = serde_json::to_value(&all).unwrap_or_default();
+    validate_mac_uniqueness(&check, Some(smac))?;
+
+    let mut output = String::new();
+    if vm.status == "running" {
+        hotplug_nic(smac, &adapter, &config, &adapters, &mut output)?;
+    } else {
+        output.push_str("VM is stopped — adapter will be added on next start\n");
+    }
+
+    adapters.push(adapter.clone());
+    save_nic_config(smac, config, &adapters)?;
+    output.push_str(&format!(
+        "Adapter net{} ({}, mac {}) added to VM '{}'\n",
+        adapter.netid, adapter.mode, adapter.mac, smac
+    ));
+    Ok(output)
+}
+
+/// Remove a network adapter, unplugging it live if the VM is running
+pub fn detach_nic(smac: &str, netid: &str) -> Result<String, String> {
+    sanitize_name(smac)?;
+    let (vm, config, mut adapters) = load_nic_config(smac)?;
+    let idx = adapters
+        .iter()
+        .position(|a| a.netid == netid)
+        .ok_or_else(|| format!("Adapter {} not found on VM '{}'", netid, smac))?;
+
+    let mut output = String::new();
+    if vm.status == "running" {
+        hotunplug_nic(smac, &adapters[idx], &mut output)?;
+    }
+    let removed = adapters.remove(idx);
+    save_nic_config(smac, config, &adapters)?;
+    output.push_str(&format!("Adapter net{} (mac {}) removed from VM '{}'\n", removed.netid, removed.mac, smac));
+    Ok(output)
+}
+
+/// Bring a NIC link up or down (cable plug/unplug as seen by the guest)
+pub fn set_nic_link(smac: &str, netid: &str, up: bool) -> Result<String, String> {
+    sanitize_name(smac)?;
+    let (vm, config, mut adapters) = load_nic_config(smac)?;
+    let adapter = adapters
+        .iter_mut()
+        .find(|a| a.netid == netid)
+        .ok_or_else(|| format!("Adapter {} not found on VM '{}'", netid, smac))?;
+
+    let state = if up { "up" } else { "down" };
+    let mut output = String::new();
+    if vm.status == "running" {
+        crate::qmp::qmp_command(
+            smac,
+            "set_link",
+            Some(serde_json::json!({ "name": format!("net{}", netid), "up": up })),
+        )?;
+        output.push_str(&format!("net{} link {} (live)\n", netid, state));
+    }
+    adapter.link = state.to_string();
+    save_nic_config(smac, config, &adapters)?;
+    output.push_str(&format!("Adapter net{} link saved as {}\n", netid, state));
+    Ok(output)
+}
+
+/// Change an adapter's mode / switch / VLAN / model / MAC. Running VMs get
+/// the NIC swapped live (unplug + replug under the same netid).
+pub fn update_nic(smac: &str, netid: &str, body: &serde_json::Value) -> Result<String, String> {
+    sanitize_name(smac)?;
+    let (vm, config, mut adapters) = load_nic_config(smac)?;
+    let idx = adapters
+        .iter()
+        .position(|a| a.netid == netid)
+        .ok_or_else(|| format!("Adapter {} not found on VM '{}'", netid, smac))?;
+    let old = adapters[idx].clone();
+    let new = nic_from_request(body, netid, Some(&old))?;
+
+    let mut check = config.clone();
+    let mut all = adapters.clone();
+    all[idx] = new.clone();
+    check["network_adapters"] = serde_json::to_value(&all).unwrap_or_default();
+    validate_mac_uniqueness(&check, Some(smac))?;
+
+    let mut output = String::new();
+    if vm.status == "running" {
+        let others: Vec<NetworkAdapter> = adapters
+            .iter()
+            .filter(|a| a.netid != netid)
+            .cloned()
+            .collect();
+        hotunplug_nic(smac, &old, &mut output)?;
+        if let Err(e) = hotplug_nic(smac, &new, &config, &others, &mut output) {
+            // Put the previous adapter back so the guest is not left without it
+            let restored = hotplug_nic(smac, &old, &config, &others, &mut output);
+            return Err(match restored {
+                Ok(()) => format!("{} — previous adapter restored", e),
+                Err(e2) => format!("{} — restoring previous adapter also failed: {}", e, e2),
+            });
+        }
+        if new.link == "down" {
+            let _ = crate::qmp::qmp_command(
+                smac,
+                "set_link",
+                Some(serde_json::json!({ "name": format!("net{}", netid), "up": false })),
+            );
+        }
+    } else {
+        output.push_str("VM is stopped — changes apply on next start\n");
+    }
+
+    adapters[idx] = new.clone();
+    save_nic_config(smac, config, &adapters)?;
+    output.push_str(&format!(
+        "Adapter net{} updated ({}, {}, mac {})\n",
+        netid, new.mode, new.nic_model, new.mac
+    ));
+    Ok(output)
+}
+
+// --- CPU / memory hot-add operations ---
+
+/// Change the vCPU count. Running VMs get CPUs hot-added (or hot-added ones
+/// removed) via QMP; boot CPUs can only go away with a restart.
+pub fn set_vcpus(smac: &str, count: u32) -> Result<String, String> {
+    sanitize_name(smac)?;
+    if count == 0 {
+        return Err("vCPU count must be at least 1".into());
+    }
+    let vm = db::get_vm(smac)?;
+    let mut config: serde_json::Value = serde_json::from_str(&vm.config)
+        .map_err(|e| format!("Config parse error: {}", e))?;
+    let cfg: VmStartConfig = serde_json::from_value(config.clone())
+        .map_err(|e| format!("Config parse error: {}", e))?;
+    let max_vcpus: u32 = cfg.cpu.max_vcpus.parse().unwrap_or(0);
+    if max_vcpus > 0 && count > max_vcpus {
+        return Err(format!("{} vCPUs exceeds max_vcpus {}", count, max_vcpus));
+    }
+
+    let mut output = String::new();
+    if vm.status == "running" {
+        if cfg.cpu.vcpus.parse::<u32>().unwrap_or(0) == 0 {
+            return Err("Live vCPU changes need the VM sized by 'vcpus' — explicit sockets/cores/threads are fixed until restart".into());
+        }
+        let slots = crate::qmp::qmp_command(smac, "query-hotpluggable-cpus", None)?;
+        let mut slots: Vec<serde_json::Value> = slots.as_array().cloned().unwrap_or_default();
+        // QEMU lists slots highest-first; plug/unplug in topology order
+        let slot_key = |s: &serde_json::Value| -> (u64, u64, u64) {
+            let p = s.get("props");
+            let id = |k: &str| p.and_then(|p| p.get(k)).and_then(|v| v.as_u64()).unwrap_or(0);
+            (id("socket-id"), id("core-id"), id("thread-id"))
+        };
+        slots.sort_by_key(slot_key);
+        if count as usize > slots.len() {
+            return Err(format!(
+                "VM was started with room for {} vCPUs — raise max_vcpus and restart to go higher",
+                slots.len()
+            ));
+        }
+        let plugged = slots.iter().filter(|s| s.get("qom-path").is_some()).count() as u32;
+
+        if count > plugged {
+            let mut added = 0;
+            for (idx, slot) in slots.iter().enumerate() {
+                if plugged + added >= count {
+                    break;
+                }
+                if slot.get("qom-path").is_some() {
+                    continue;
+                }
+                let mut args = slot.get("props").cloned().unwrap_or_else(|| serde_json::json!({}));
+                args["driver"] = slot.get("type").cloned().unwrap_or_default();
+                args["id"] = serde_json::json!(format!("cpu{}", idx));
+                crate::qmp::qmp_command(smac, "device_add", Some(args))
+                    .map_err(|e| format!("CPU hot-add failed after {} added: {}", added, e))?;
+                added += 1;
+            }
+            output.push_str(&format!("Hot-added {} vCPU(s) (live)\n", added));
+            // New vCPU threads start unpinned
+            #[cfg(target_os = "linux")]
+            if let Err(e) = apply_cpu_pinning(smac, &cfg.cpu, &mut output) {
+                output.push_str(&format!("WARNING: CPU pinning failed: {}\n", e));
+            }
+        } else if count < plugged {
+            // Only CPUs we hot-added have a qdev id under /machine/peripheral
+            let removable: Vec<String> = slots
+                .iter()
+                .rev()
+                .filter_map(|s| s.get("qom-path").and_then(|v| v.as_str()))
+                .filter_map(|p| p.strip_prefix("/machine/peripheral/"))
+                .map(|id| id.to_string())
+                .collect();
+            let to_remove = (plugged - count) as usize;
+            if removable.len() < to_remove {
+                return Err(format!(
+                    "Only {} hot-added vCPU(s) can be removed live — boot vCPUs need a restart",
+                    removable.len()
+                ));
+            }
+            for id in removable.iter().take(to_remove) {
+                crate::qmp::device_del_wait(smac, id, std::time::Duration::from_secs(15))?;
+            }
+            output.push_str(&format!("Removed {} vCPU(s) (live)\n", to_remove));
+        }
+    } else {
+        output.push_str("VM is stopped — new vCPU count applies on next start\n");
+    }
+
+    config["cpu"]["vcpus"] = serde_json::json!(count.to_string());
+    let config_str = serde_json::to_string(&config).unwrap_or_default();
+    db::update_vm(smac, &config_str)?;
+    output.push_str(&format!("VM '{}' set to {} vCPUs\n", smac, count));
+    Ok(output)
+}
+
+/// Resize memory. Running VMs grow by hot-adding a pc-dimm and shrink by
+/// removing previously hot-added DIMMs; boot memory is fixed until restart.
+pub fn set_memory(smac: &str, size_mb: u64) -> Result<String, String> {
+    sanitize_name(smac)?;
+    if size_mb < 256 {
+        return Err("Memory must be at least 256 MB".into());
+    }
+    let vm = db::get_vm(smac)?;
+    let mut config: serde_json::Value = serde_json::from_str(&vm.config)
+        .map_err(|e| format!("Config parse error: {}", e))?;
+    let cfg: VmStartConfig = serde_json::from_value(config.clone())
+        .map_err(|e| format!("Config parse error: {}", e))?;
+    let max_mb: u64 = cfg.memory.max_size.parse().unwrap_or(0);
+    if max_mb > 0 && size_mb > max_mb {
+        return Err(format!("{} MB exceeds max_size {} MB", size_mb, max_mb));
+    }
+
+    let mut output = String::new();
+    if vm.status == "running" {
+        let summary = crate::qmp::qmp_command(smac, "query-memory-size-summary", None)?;
+        let base = summary.get("base-memory").and_then(|v| v.as_u64()).unwrap_or(0) / 1024 / 1024;
+        let plugged = summary.get("plugged-memory").and_then(|v| v.as_u64()).unwrap_or(0) / 1024 / 1024;
+        let current = base + plugged;
+        let dimms: Vec<(u32, String, u64, String)> = crate::qmp::qmp_command(smac, "query-memory-devices", None)?
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|d| {
+                let data = d.get("data")?;
+                let id = data.get("id")?.as_str()?.to_string();
+                let n = id.strip_prefix("dimm")?.parse::<u32>().ok()?;
+                let size = data.get("size")?.as_u64()? / 1024 / 1024;
+                let memdev = data.get("memdev").and_then(|v| v.as_str()).unwrap_or("");
+                let backend = memdev.trim_start_matches("/objects/").to_string();
+                Some((n, id, size, backend))
+            })
+            .collect();
+
+        if size_mb > current {
+            if max_mb == 0 {
+                return Err("Memory hot-add needs memory.max_size set before the VM starts".into());
+            }
+            let add = size_mb - current;
+            // Guests online hot-added memory in 128 MB blocks
+            if !add.is_multiple_of(128) {
+                return Err(format!("Memory can only grow in multiples of 128 MB (requested +{} MB)", add));
+            }
+            let host_ram = host_total_ram_mb();
+            if host_ram > 0 {
+                let used = running_vms_ram_mb(Some(smac));
+                let usable = host_ram.saturating_sub(1024);
+                if used + size_mb > usable {
+                    return Err(format!(
+                        "Not enough RAM: {} MB requested (+{} MB), other running VMs use {} MB, usable {} MB",
+                        size_mb, add, used, usable
+                    ));
+                }
+            }
+            let n = dimms.iter().map(|d| d.0 + 1).max().unwrap_or(0);
+            let backend = format!("mem{}", n);
+            // virtiofsd maps guest RAM, so hot-added memory must be shared too
+            let backend_args = if cfg!(target_os = "linux") && !cfg.shared_folders.is_empty() {
+                serde_json::json!({
+                    "qom-type": "memory-backend-memfd",
+                    "id": backend,
+                    "size": add * 1024 * 1024,
+                    "share": true,
+                })
+            } else {
+                serde_json::json!({
+                    "qom-type": "memory-backend-ram",
+                    "id": backend,
+                    "size": add * 1024 * 1024,
+                })
+            };
+            crate::qmp::qmp_command(smac, "object-add", Some(backend_args))?;
+            if let Err(e) = crate::qmp::qmp_command(
+                smac,
+                "device_add",
+                Some(serde_json::json!({ "driver": "pc-dimm", "id": format!("dimm{}", n), "memdev": backend })),
+            ) {
+                let _ = crate::qmp::qmp_command(smac, "object-del", Some(serde_json::json!({ "id": backend })));
+                return Err(format!("DIMM hot-add failed: {}", e));
+            }
+            output.push_str(&format!("Hot-added {} MB as dimm{} (live)\n", add, n));
+        } else if size_mb < current {
+            let reduce = current - size_mb;
+            // Newest DIMMs first; the removed sizes must add up exactly
+            let mut newest_first = dimms.clone();
+            newest_first.sort_by_key(|d| std::cmp::Reverse(d.0));
+            let mut picked = Vec::new();
+            let mut sum = 0;
+            for d in &newest_first {
+                if sum + d.2 <= reduce {
+                    sum += d.2;
+                    picked.push(d.clone());
+                }
+                if sum == reduce {
+                    break;
+                }
+            }
+            if sum != reduce {
+                return Err(format!(
+                    "Cannot remove {} MB live — only whole hot-added DIMMs ({} MB total) can be unplugged; use the balloon instead",
+                    reduce, dimms.iter().map(|d| d.2).sum::<u64>()
+                ));
+            }
+            for (_, id, size, backend) in &picked {
+                // The guest has to offline the memory first, which can take a while
+                crate::qmp::device_del_wait(smac, id, std::time::Duration::from_secs(30))?;
+                let _ = crate::qmp::qmp_command(smac, "object-del", Some(serde_json::json!({ "id": backend })));
+                output.push_str(&format!("Removed {} ({} MB) (live)\n", id, size));
+            }
+        }
+    } else {
+        output.push_str("VM is stopped — new memory size applies on next start\n");
+    }
+
+    forget_actual_ram(smac);
+    config["memory"]["size"] = serde_json::json!(size_mb.to_string());
+    let config_str = serde_json::to_string(&config).unwrap_or_default();
+    db::update_vm(smac, &config_str)?;
+    output.push_str(&format!("VM '{}' memory set to {} MB\n", smac, size_mb));
+    Ok(output)
+}
+
+/// Set the balloon target (MB) — the guest hands memory back to the host
+pub fn set_balloon(smac: &str, target_mb: u64) -> Result<String, String> {
+    sanitize_name(smac)?;
+    let vm = db::get_vm(smac)?;
+    if vm.status != "running" {
+        return Err(format!("VM '{}' is not running", smac));
+    }
+    if target_mb < 128 {
+        return Err("Balloon target must be at least 128 MB".into());
+    }
+    let summary = crate::qmp::qmp_command(smac, "query-memory-size-summary", None)?;
+    let total = (summary.get("base-memory").and_then(|v| v.as_u64()).unwrap_or(0)
+        + summary.get("plugged-memory").and_then(|v| v.as_u64()).unwrap_or(0))
+        / 1024 / 1024;
+    if target_mb > total {
+        return Err(format!("Balloon target {} MB exceeds VM memory {} MB", target_mb, total));
+    }
+    crate::qmp::qmp_command(
+        smac,
+        "balloon",
+        Some(serde_json::json!({ "value": target_mb * 1024 * 1024 })),
+    )?;
+    forget_actual_ram(smac);
+    Ok(format!("Balloon target for '{}' set to {} MB (of {} MB)\n", smac, target_mb, total))
+}
+
+/// Balloon size plus guest-reported memory statistics
+pub fn balloon_info(smac: &str) -> Result<serde_json::Value, String> {
+    sanitize_name(smac)?;
+    let vm = db::get_vm(smac)?;
+    if vm.status != "running" {
+        return Err(format!("VM '{}' is not running", smac));
+    }
+    let balloon = crate::qmp::qmp_command(smac, "query-balloon", None)?;
+    let actual_mb = balloon.get("actual").and_then(|v| v.as_u64()).unwrap_or(0) / 1024 / 1024;
+
+    // Stats are only collected once a polling interval is set
+    let path = "/machine/peripheral/balloon0";
+    let interval = crate::qmp::qmp_command(
+        smac,
+        "qom-get",
+        Some(serde_json::json!({ "path": path, "property": "guest-stats-polling-interval" })),
+    )
+    .ok()
+    .and_then(|v| v.as_u64())
+    .unwrap_or(0);
+    if interval == 0 {
+        let _ = crate::qmp::qmp_command(
+            smac,
+            "qom-set",
+            Some(serde_json::json!({ "path": path, "property": "guest-stats-polling-interval", "value": 5 })),
+        );
+    }
+    let stats = crate::qmp::qmp_command(
+        smac,
+        "qom-get",
+        Some(serde_json::json!({ "path": path, "property": "guest-stats" })),
+    )
+    .unwrap_or_default();
+
+    Ok(serde_json::json!({
+        "actual_mb": actual_mb,
+        "stats": stats.get("stats").cloned().unwrap_or_default(),
+        "last_update": stats.get("last-update").cloned().unwrap_or_default(),
+        "polling_started": interval == 0,
+    }))
+}
+
+// --- USB passthrough operations ---
+
+/// vendor:product of the host device plugged in at bus/port (Linux sysfs)
+fn usb_ids_at(hostbus: &str, hostport: &str) -> Option<(u32, u32)> {
+    if !cfg!(target_os = "linux")
+        || hostbus.is_empty()
+        || !hostbus.chars().all(|c| c.is_ascii_digit())
+        || hostport.is_empty()
+        || !hostport.chars().all(|c| c.is_ascii_digit() || c == '.')
+    {
+        return None;
+    }
+    let dir = format!("/sys/bus/usb/devices/{}-{}", hostbus, hostport);
+    let read = |f: &str| std::fs::read_to_string(format!("{}/{}", dir, f)).ok();
+    Some((parse_usb_id(read("idVendor")?.trim()).ok()?, parse_usb_id(read("idProduct")?.trim()).ok()?))
+}
+
+/// Same physical device? Bus/port entries match exactly, ID entries by
+/// vendor:product. A bus/port entry is compared with an ID entry through
+/// the device currently plugged in at that port.
+fn usb_same_device(a: &UsbDevice, b: &UsbDevice) -> bool {
+    let ids = |d: &UsbDevice| (parse_usb_id(&d.vendor).ok(), parse_usb_id(&d.product).ok());
+    match (a.hostbus.is_empty(), b.hostbus.is_empty()) {
+        (false, false) => a.hostbus == b.hostbus && a.hostport == b.hostport,
+        (true, true) => ids(a) == ids(b),
+        (false, true) => usb_ids_at(&a.hostbus, &a.hostport).map(|(v, p)| (Some(v), Some(p))) == Some(ids(b)),
+        (true, false) => usb_ids_at(&b.hostbus, &b.hostport).map(|(v, p)| (Some(v), Some(p))) == Some(ids(a)),
+    }
+}
+
+/// Pass a host USB device to a VM — hot-plugged via QMP if it is running
+pub fn attach_usb(smac: &str, body: &serde_json::Value) -> Result<String, String> {
+    sanitize_name(smac)?;
+    let vm = db::get_vm(smac)?;
+    let mut config: serde_json::Value = serde_json::from_str(&vm.config)
+        .map_err(|e| format!("Config parse error: {}", e))?;
+    let mut devices: Vec<UsbDevice> = config
+        .get("usb_devices")
+        .and_then(|v| serde_json::from_value(v.clone()).ok())
+        .unwrap_or_default();
+
+    let field = |k: &str| body.get(k).and_then(|v| v.as_str()).unwrap_or("").trim().to_string();
+    let usbid = devices
+        .iter()
+        .filter_map(|d| d.usbid.parse::<u32>().ok())
+        .max()
+        .map(|m| m + 1)
+        .unwrap_or(0);
+    let dev = UsbDevice {
+        usbid: usbid.to_string(),
+        vendor: field("vendor"),
+        product: field("product"),
+        hostbus: field("hostbus"),
+        hostport: field("hostport"),
+    };
+    let props = usb_host_props(&dev)?;
+
+    if devices.iter().any(|d| usb_same_device(d, &dev)) {
+        return Err(format!("USB device is already assigned to VM '{}'", smac));
+    }
+    // A device can only be claimed by one running QEMU
+    if let Ok(vms) = db::list_vms() {
+        for other in vms.iter().filter(|v| v.smac != smac && v.status == "running") {
+            let other_cfg: serde_json::Value = serde_json::from_str(&other.config).unwrap_or_default();
+            let other_devs: Vec<UsbDevice> = other_cfg
+                .get("usb_devices")
+                .and_then(|v| serde_json::from_value(v.clone()).ok())
+                .unwrap_or_default();
+            if other_devs.iter().any(|d| usb_same_device(d, &dev)) {
+                return Err(format!("USB device is in use by running VM '{}'", other.smac));
+            }
+        }
+    }
+
+    let mut output = String::new();
+    if vm.status == "running" {
+        crate::qmp::qmp_command(smac, "device_add", Some(props))
+            .map_err(|e| format!("USB hot-plug failed: {}", e))?;
+        output.push_str(&format!("Hot-plugged usb{} (live)\n", dev.usbid));
+    } else {
+        output.push_str("VM is stopped — device will be attached on next start\n");
+    }
+
+    devices.push(dev.clone());
+    config["usb_devices"] = serde_json::to_value(&devices).unwrap_or_default();
+    let config_str = serde_json::to_string(&config).unwrap_or_default();
+    db::update_vm(smac, &config_str)?;
+    output.push_str(&format!("USB device usb{} assigned to VM '{}'\n", dev.usbid, smac));
+    Ok(output)
+}
+
+/// Take a USB device away from a VM — unplugged live if it is running
+pub fn detach_usb(smac: &str, usbid: &str) -> Result<String, String> {
+    sanitize_name(smac)?;
+    let vm = db::get_vm(smac)?;
+    let mut config: serde_json::Value = serde_json::from_str(&vm.config)
+        .map_err(|e| format!("Config parse error: {}", e))?;
+    let mut devices: Vec<UsbDevice> = config
+        .get("usb_devices")
+        .and_then(|v| serde_json::from_value(v.clone()).ok())
+        .unwrap_or_default();
+    let idx = devices
+        .iter()
+        .position(|d| d.usbid == usbid)
+        .ok_or_else(|| format!("USB device {} not found on VM '{}'", usbid, smac))?;
+
+    let mut output = String::new();
+    if vm.status == "running" {
+        // USB unplug needs no guest cooperation, so this returns quickly
+        crate::qmp::device_del_wait(smac, &format!("usb{}", usbid), std::time::Duration::from_secs(5))?;
+        output.push_str(&format!("Unplugged usb{} (live)\n", usbid));
+    }
+    devices.remove(idx);
+    config["usb_devices"] = serde_json::to_value(&devices).unwrap_or_default();
+    let config_str = serde_json::to_string(&config).unwrap_or_default();
+    db::update_vm(smac, &config_str)?;
+    output.push_str(&format!("USB device usb{} removed from VM '{}'\n", usbid, smac));
+    Ok(output)
+}
+
+/// Host USB devices from sysfs, with the VM each one is assigned to (Linux)
+pub fn list_host_usb_devices() -> Vec<serde_json::Value> {
+    let mut devices = Vec::new();
+    #[cfg(target_os = "linux")]
+    {
+        let assigned: Vec<(String, UsbDevice)> = db::list_vms()
+            .unwrap_or_default()
+            .into_iter()
+            .flat_map(|vm| {
+                let cfg: serde_json::Value = serde_json::from_str(&vm.config).unwrap_or_default();
+                let devs: Vec<UsbDevice> = cfg
+                    .get("usb_devices")
+                    .and_then(|v| serde_json::from_value(v.clone()).ok())
+                    .unwrap_or_default();
+                devs.into_iter().map(move |d| (vm.smac.clone(), d))
+            })
+            .collect();
+
+        if let Ok(entries) = std::fs::read_dir("/sys/bus/usb/devices") {
+            for entry in entries.flatten() {
+                let name = entry.file_name().to_string_lossy().to_string();
+                // Devices are "<bus>-<port>[.<port>...]"; skip root hubs (usbN) and interfaces (x:y.z)
+                let Some((bus, port)) = name.split_once('-') else { continue };
+                if name.contains(':') {
+                    continue;
+                }
+                let read = |f: &str| {
+                    std::fs::read_to_string(entry.path().join(f))
+                        .unwrap_or_default()
+                        .trim()
+                        .to_string()
+                };
+                // Hubs cannot be passed through usefully
+                if read("bDeviceClass") == "09" {
+                    continue;
+                }
+                let vendor = read("idVendor");
+                let product = read("idProduct");
+                let probe = UsbDevice {
+                    usbid: String::new(),
+                    vendor: vendor.clone(),
+                    product: product.clone(),
+                    hostbus: bus.to_string(),
+                    hostport: port.to_string(),
+                };
+                let in_use_by: Vec<&String> = assigned
+                    .iter()
+                    .filter(|(_, d)| usb_same_device(d, &probe))
+                    .map(|(vm, _)| vm)
+                    .collect();
+                devices.push(serde_json::json!({
+                    "vendor": vendor,
+                    "product": product,
+                    "hostbus": bus,
+                    "hostport": port,
+                    "devnum": read("devnum"),
+                    "manufacturer": read("manufacturer"),
+                    "name": read("product"),
+                    "serial": read("serial"),
+                    "speed": read("speed"),
+                    "assigned_to": in_use_by,
+                }));
+            }
+        }
+    }
+    devices
+}
+
+// --- VFIO / PCI passthrough operations ---
+
+/// Canonical form of a PCI address as sysfs names it: lowercase, with the
+/// `0000:` domain added to the short `BB:SS.F` form
+fn normalize_pci_address(addr: &str) -> Result<String, String> {
+    let addr = addr.trim().to_ascii_lowercase();
+    let addr = if addr.len() == 7 { format!("0000:{}", addr) } else { addr };
+    validate_pci_address(&addr)?;
+    Ok(addr)
+}
+
+/// Normalize the `host` of every `pci_devices` entry in a VM config in
+/// place and return the non-empty addresses
+fn normalize_pci_devices(config: &mut serde_json::Value) -> Result<Vec<String>, String> {
+    let mut addrs = Vec::new();
+    if let Some(list) = config.get_mut("pci_devices").and_then(|v| v.as_array_mut()) {
+        for entry in list {
+            let host = entry.get("host").and_then(|h| h.as_str()).unwrap_or("").trim().to_string();
+            if host.is_empty() {
+                continue;
+            }
+            let addr = normalize_pci_address(&host)?;
+            entry["host"] = serde_json::json!(addr);
+            addrs.push(addr);
+        }
+    }
+    Ok(addrs)
+}
+
+/// Validate a full PCI address, e.g. "0000:01:00.0"
+fn validate_pci_address(addr: &str) -> Result<(), String> {
+    let b = addr.as_bytes();
+    let hex = |r: std::ops::Range<usize>| b[r].iter().all(|c| c.is_ascii_hexdigit());
+    let ok = b.len() == 12
+        && hex(0..4)
+        && b[4] == b':'
+        && hex(5..7)
+        && b[7] == b':'
+        && hex(8..10)
+        && b[10] == b'.'
+        && (b'0'..=b'7').contains(&b[11]);
+    if ok {
+        Ok(())
+    } else {
+        Err(format!("Invalid PCI address '{}' (expected DDDD:BB:SS.F)", addr))
+    }
+}
+
+/// Name of the symlink target in sysfs (driver, iommu_group)
+fn sysfs_link_name(path: &str) -> String {
+    std::fs::read_link(path)
+        .ok()
+        .and_then(|p| p.file_name().map(|n| n.to_string_lossy().to_string()))
+        .unwrap_or_default()
+}
+
+/// PCI addresses in the same IOMMU group as `addr` (including itself)
+fn iommu_group_members(addr: &str) -> Result<(String, Vec<String>), String> {
+    let group = sysfs_link_name(&format!("/sys/bus/pci/devices/{}/iommu_group", addr));
+    if group.is_empty() {
+        return Err(format!(
+            "{} has no IOMMU group — enable the IOMMU (intel_iommu=on / amd_iommu=on)",
+            addr
+        ));
+    }
+    let mut members: Vec<String> = std::fs::read_dir(format!("/sys/kernel/iommu_groups/{}/devices", group))
+        .map_err(|e| format!("Read IOMMU group {}: {}", group, e))?
+        .flatten()
+        .map(|e| e.file_name().to_string_lossy().to_string())
+        .collect();
+    members.sort();
+    Ok((group, members))
+}
+
+/// PCI bridges share groups with endpoints but are never passed through
+fn is_pci_bridge(addr: &str) -> bool {
+    std::fs::read_to_string(format!("/sys/bus/pci/devices/{}/class", addr))
+        .map(|c| c.trim().starts_with("0x0604"))
+        .unwrap_or(false)
+}
+
+/// VMs (other than `exclude`) whose config assigns `addr`, with their status
+fn pci_device_owners(addr: &str, exclude: Option<&str>) -> Vec<(String, String)> {
+    db::list_vms()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|vm| Some(vm.smac.as_str()) != exclude)
+        .filter(|vm| {
+            let cfg: serde_json::Value = serde_json::from_str(&vm.config).unwrap_or_default();
+            cfg.get("pci_devices")
+                .and_then(|v| v.as_array())
+                .map(|list| {
+                    list.iter().any(|p| {
+                        p.get("host")
+                            .and_then(|h| h.as_str())
+                            .and_then(|h| normalize_pci_address(h).ok())
+                            .as_deref()
+                            == Some(addr)
+                    })
+                })
+                .unwrap_or(false)
+        })
+        .map(|vm| (vm.smac, vm.status))
+        .collect()
+}
+
+/// Check a VM's PCI passthrough list: whole IOMMU groups must be assigned,
+/// and no device may be given to another running VM. With `require_bound`,
+/// every device must also be bound to vfio-pci (checked at start).
+pub fn validate_pci_passthrough(smac: &str, addrs: &[String], require_bound: bool) -> Result<(), String> {
+    if addrs.is_empty() || !std::path::Path::new("/sys/bus/pci/devices").exists() {
+        return Ok(());
+    }
+    let mut seen = std::collections::HashSet::new();
+    for addr in addrs {
+        validate_pci_address(addr)?;
+        if !seen.insert(addr.as_str()) {
+            return Err(format!("PCI device {} is listed twice", addr));
+        }
+        if !std::path::Path::new(&format!("/sys/bus/pci/devices/{}", addr)).exists() {
+            return Err(format!("PCI device {} not found on this host", addr));
+        }
+        let (group, members) = iommu_group_members(addr)?;
+        let missing: Vec<&String> = members
+            .iter()
+            .filter(|m| !is_pci_bridge(m) && !addrs.contains(m))
+            .collect();
+        if !missing.is_empty() {
+            return Err(format!(
+                "{} is in IOMMU group {} with {} — the whole group must be passed to the same VM",
+                addr,
+                group,
+                missing.iter().map(|m| m.as_str()).collect::<Vec<_>>().join(", ")
+            ));
+        }
+        if let Some((other, _)) = pci_device_owners(addr, Some(smac))
+            .into_iter()
+            .find(|(_, status)| status == "running")
+        {
+            return Err(format!("PCI device {} is in use by running VM '{}'", addr, other));
+        }
+        if require_bound {
+            let driver = sysfs_link_name(&format!("/sys/bus/pci/devices/{}/driver", addr));
+            if driver != "vfio-pci" {
+                return Err(format!(
+                    "PCI device {} is bound to '{}', not vfio-pci — bind it first (POST /api/devices/pci/bind)",
+                    addr,
+                    if driver.is_empty() { "no driver" } else { &driver }
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Write a sysfs attribute, retrying through `sudo tee` when not root
+fn sysfs_write(path: &str, value: &str) -> Result<(), String> {
+    if std::fs::write(path, value).is_ok() {
+        return Ok(());
+    }
+    if get_conf_or("bridge_sudo", "true") != "true" {
+        return Err(format!("Permission denied writing {}", path));
+    }
+    use std::io::Write;
+    let sudo_path = get_conf_or("bridge_sudo_path", "/usr/bin/sudo");
+    let mut child = std::process::Command::new(&sudo_path)
+        .args(["-n", "tee", path])
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("sudo tee {}: {}", path, e))?;
+    if let Some(stdin) = child.stdin.as_mut() {
+        let _ = stdin.write_all(value.as_bytes());
+    }
+    let out = child.wait_with_output().map_err(|e| format!("sudo tee {}: {}", path, e))?;
+    if out.status.success() {
+        Ok(())
+    } else {
+        Err(format!("Writing {} failed: {}", path, String::from_utf8_lossy(&out.stderr).trim()))
+    }
+}
+
+/// All host PCI devices with driver, IOMMU group and VM assignment (Linux)
+pub fn list_pci_devices() -> Vec<serde_json::Value> {
+    let mut devices = Vec::new();
+    let Ok(entries) = std::fs::read_dir("/sys/bus/pci/devices") else {
+        return devices;
+    };
+    // One lspci call for all descriptions: `-D -mm` lines start with the address
+    let lspci = std::process::Command::new("lspci")
+        .args(["-D", "-mm"])
+        .output()
+        .ok()
+        .and_then(|o| String::from_utf8(o.stdout).ok())
+        .unwrap_or_default();
+    let mut addrs: Vec<String> = entries
+        .flatten()
+        .map(|e| e.file_name().to_string_lossy().to_string())
+        .collect();
+    addrs.sort();
+    for addr in addrs {
+        let base = format!("/sys/bus/pci/devices/{}", addr);
+        let read = |f: &str| {
+            std::fs::read_to_string(format!("{}/{}", base, f))
+                .unwrap_or_default()
+                .trim()
+                .to_string()
+        };
+        let (group, members) = iommu_group_members(&addr).unwrap_or_default();
+        let description = lspci
+            .lines()
+            .find(|l| l.starts_with(&addr))
+            .map(|l| l[addr.len()..].trim().to_string())
+            .unwrap_or_default();
+        let assigned: Vec<serde_json::Value> = pci_device_owners(&addr, None)
+            .into_iter()
+            .map(|(vm, status)| serde_json::json!({ "vm": vm, "status": status }))
+            .collect();
+        devices.push(serde_json::json!({
+            "address": addr,
+            "vendor": read("vendor"),
+            "device": read("device"),
+            "class": read("class"),
+            "description": description,
+            "driver": sysfs_link_name(&format!("{}/driver", base)),
+            "iommu_group": group,
+            "iommu_group_devices": members,
+            "is_bridge": is_pci_bridge(&addr),
+            "assigned_to": assigned,
+        }));
+    }
+    devices
+}
+
+/// Bind a PCI device to vfio-pci via driver_override
+pub fn bind_vfio(addr: &str) -> Result<String, String> {
+    let addr = &normalize_pci_address(addr)?;
+    let base = format!("/sys/bus/pci/devices/{}", addr);
+    if !std::path::Path::new(&base).exists() {
+        return Err(format!("PCI device {} not found", addr));
+    }
+    if is_pci_bridge(addr) {
+        return Err(format!("{} is a PCI bridge and cannot be passed through", addr));
+    }
+    if let Some((vm, _)) = pci_device_owners(addr, None).into_iter().find(|(_, s)| s == "running") {
+        return Err(format!("PCI device {} is in use by running VM '{}'", addr, vm));
+    }
+    let driver = sysfs_link_name(&format!("{}/driver", base));
+    if driver == "vfio-pci" {
+        return Ok(format!("{} is already bound to vfio-pci\n", addr));
+    }
+    let mut output = String::new();
+    // Load the module if needed (no-op when built in / already loaded)
+    let _ = std::process::Command::new("modprobe").arg("vfio-pci").output();
+
+    sysfs_write(&format!("{}/driver_override", base), "vfio-pci")?;
+    if !driver.is_empty() {
+        sysfs_write(&format!("{}/driver/unbind", base), addr)?;
+        output.push_str(&format!("Unbound {} from {}\n", addr, driver));
+    }
+    sysfs_write("/sys/bus/pci/drivers_probe", addr)?;
+    let now = sysfs_link_name(&format!("{}/driver", base));
+    if now != "vfio-pci" {
+        return Err(format!("{} did not bind to vfio-pci (driver now: '{}')", addr, now));
+    }
+    output.push_str(&format!("Bound {} to vfio-pci\n", addr));
+    if let Ok((group, members)) = iommu_group_members(addr) {
+        let others: Vec<&String> = members.iter().filter(|m| *m != addr && !is_pci_bridge(m)).collect();
+        if !others.is_empty() {
+            output.push_str(&format!(
+                "Note: IOMMU group {} also contains {} — bind and assign those too\n",
+                group,
+                others.iter().map(|m| m.as_str()).collect::<Vec<_>>().join(", ")
+            ));
+        }
+    }
+    Ok(output)
+}
+
+/// Release a PCI device from vfio-pci back to its host driver
+pub fn unbind_vfio(addr: &str) -> Result<String, String> {
+    let addr = &normalize_pci_address(addr)?;
+    let base = format!("/sys/bus/pci/devices/{}", addr);
+    if !std::path::Path::new(&base).exists() {
+        return Err(format!("PCI device {} not found", addr));
+    }
+    if let Some((vm, _)) = pci_device_owners(addr, None).into_iter().find(|(_, s)| s == "running") {
+        return Err(format!("PCI device {} is in use by running VM '{}'", addr, vm));
+    }
+    let driver = sysfs_link_name(&format!("{}/driver", base));
+    if driver != "vfio-pci" {
+        return Err(format!("{} is not bound to vfio-pci (driver: '{}')", addr, driver));
+    }
+    // Clearing the override lets the normal driver match again on probe
+    sysfs_write(&format!("{}/driver_override", base), "\n")?;
+    sysfs_write(&format!("{}/driver/unbind", base), addr)?;
+    sysfs_write("/sys/bus/pci/drivers_probe", addr)?;
+    let now = sysfs_link_name(&format!("{}/driver", base));
+    Ok(format!(
+        "Released {} from vfio-pci (driver now: {})\n",
+        addr,
+        if now.is_empty() { "none" } else { &now }
+    ))
+}
+
+// --- Screenshot operations ---
+
+/// Last captured screen of a VM, kept after it stops
+fn screenshot_path(smac: &str) -> String {
+    format!("{}/{}_screen.png", get_conf("pctl_path"), smac)
+}
+
+/// Grab the current display via QMP `screendump` and cache it on disk
+pub fn capture_screenshot(smac: &str) -> Result<Vec<u8>, String> {
+    screendump(smac, true)
+}
+
+/// Grab the current display without touching the cached screenshot
+/// (session recorder frames)
+pub fn capture_frame(smac: &str) -> Result<Vec<u8>, String> {
+    screendump(smac, false)
+}
+
+fn screendump(smac: &str, update_cache: bool) -> Result<Vec<u8>, String> {
+    sanitize_name(smac)?;
+    let vm = db::get_vm(smac).map_err(|_| format!("VM '{}' not found", smac))?;
+    if vm.status != "running" {
+        return Err(format!("VM '{}' is not running", smac));
+    }
+    // QEMU writes the file itself; capture to a unique temp name per call so
+    // readers of the cached copy never see a half-written image and
+    // concurrent captures (thumbnail poller, screenshot endpoint, session
+    // recorder) never write or rename each other's file
+    let tmp = format!("{}/{}_screen.{}.tmp.png", get_conf("pctl_path"), smac, generate_random_password(8));
+    let data = crate::qmp::qmp_command(
+        smac,
+        "screendump",
+        Some(serde_json::json!({ "filename": tmp, "format": "png" })),
+    )
+    .map_err(|e| format!("screendump failed (QEMU 7.1+ required for PNG): {}", e))
+    .and_then(|_| std::fs::read(&tmp).map_err(|e| format!("Read screenshot {}: {}", tmp, e)));
+    match data {
+        Ok(data) => {
+            if !update_cache || std::fs::rename(&tmp, screenshot_path(smac)).is_err() {
+                let _ = std::fs::remove_file(&tmp);
+            }
+            Ok(data)
+        }
+        Err(e) => {
+            let _ = std::fs::remove_file(&tmp);
+            Err(e)
+        }
+    }
+}
+
+/// Screenshot for the VM list: the cached image if younger than `max_age`,
+/// otherwise a fresh capture. Stopped VMs return their last screen.
+pub fn vm_thumbnail(smac: &str, max_age: std::time::Duration) -> Result<Vec<u8>, String> {
+    sanitize_name(smac)?;
+    let path = screenshot_path(smac);
+    let age = std::fs::metadata(&path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.elapsed().ok());
+    let running = db::get_vm(smac).map(|vm| vm.status == "running").unwrap_or(false);
+    if running && age.is_none_or(|a| a > max_age) {
+        match capture_screenshot(smac) {
+            Ok(data) => return Ok(data),
+            Err(e) if age.is_none() => return Err(e),
+            Err(_) => {}
+        }
+    }
+    std::fs::read(&path).map_err(|_| format!("No screenshot for VM '{}'", smac))
+}
+
+/// Refresh the cached screenshot of every running VM (background poller)
+pub fn refresh_thumbnails() {
+    let vms = db::list_vms().unwrap_or_default();
+    for vm in vms.iter().filter(|vm| vm.status == "running") {
+        if let Err(e) = capture_screenshot(&vm.smac) {
+            eprintln!("thumbnail {}: {}", vm.smac, e);
+        }
+    }
+}
+
+// --- Guest info poller ---
+
+/// Query a running VM's guest agent for addresses, OS and agent version and
+/// cache the result for the VM list. Loopback and link-local addresses are left out.
+pub fn refresh_guest_info_for(smac: &str) -> Result<(), String> {
+    use crate::guest_agent;
+    if !guest_agent::guest_ping(smac) {
+        return Err("guest agent not responding".into());
+    }
+    let ips: Vec<String> = guest_agent::guest_network_interfaces(smac)?
+        .into_iter()
+        .flat_map(|iface| iface.ip_addresses)
+        .filter_map(|a| a.ip_address.parse::<std::net::IpAddr>().ok())
+        .filter(|ip| match ip {
+            std::net::IpAddr::V4(v4) => !v4.is_loopback() && !v4.is_link_local(),
+            std::net::IpAddr::V6(v6) => {
+                !v6.is_loopback() && (v6.segments()[0] & 0xffc0) != 0xfe80
+            }
+        })
+        .map(|ip| ip.to_string())
+        .collect();
+    // Older agents lack guest-get-osinfo; keep the addresses anyway
+    let os = guest_agent::guest_osinfo(smac).unwrap_or_default();
+    let os_name = os.pretty_name.or(os.name).unwrap_or_default();
+    let kernel = os.kernel_release.unwrap_or_default();
+    let version = guest_agent::guest_agent_version(smac).unwrap_or_default();
+    db::upsert_guest_info(smac, &ips, &os_name, &kernel, &version)?;
+    Ok(())
+}
+
+/// Poll every running VM in parallel, so VMs without an agent (which
+/// take several seconds to time out) do not hold up the others
+pub fn refresh_guest_info() {
+    let vms = db::list_vms().unwrap_or_default();
+    std::thread::scope(|scope| {
+        for vm in vms.iter().filter(|vm| vm.status == "running") {
+            scope.spawn(move || {
+                if let Err(e) = refresh_guest_info_for(&vm.smac) {
+                    log::debug!("guest info {}: {}", vm.smac, e);
+                }
+            });
+        }
+    });
+}
+
+// --- Windows provisioning ---
+
+/// VMs being provisioned right now, so a start watcher and the one resumed
+/// at vm_ctl startup never run it twice at once
+static PROVISIONING: std::sync::OnceLock<std::sync::Mutex<std::collections::HashSet<String>>> =
+    std::sync::OnceLock::new();
+
+/// Per-VM MDS config, or the global one
+fn vm_mds_config(vm_config: &serde_json::Value) -> mds::MdsConfig {
+    vm_config
+        .get("mds")
+        .and_then(|m| serde_json::from_value::<mds::MdsConfig>(m.clone()).ok())
+        .unwrap_or_else(mds::load_mds_config)
+}
+
+/// Single-quoted PowerShell string literal. PowerShell treats all five
+/// single-quote characters as delimiters; each is escaped by doubling it.
+fn ps_quote(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('\'');
+    for c in s.chars() {
+        if matches!(c, '\'' | '\u{2018}' | '\u{2019}' | '\u{201A}' | '\u{201B}') {
+            out.push(c);
+        }
+        out.push(c);
+    }
+    out.push('\'');
+    out
+}
+
+/// Computer name from the MDS config, or the VM hostname: letters, digits
+/// and hyphens, at most 15 characters (the NetBIOS limit)
+fn windows_computer_name(config: &mds::MdsConfig, smac: &str) -> String {
+    let wanted = if config.windows_computer_name.is_empty() {
+        vm_hostname(config, smac)
+    } else {
+        config.windows_computer_name.clone()
+    };
+    let name: String = wanted
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '-' })
+        .take(15)
+        .collect();
+    name.trim_matches('-').to_string()
+}
+
+/// Run a one-line PowerShell script in the guest. The script goes in on
+/// stdin so passwords stay off the guest's process list.
+fn run_guest_powershell(smac: &str, script: &str, timeout: u64) -> Result<String, String> {
+    let args: Vec<String> = ["-NoProfile", "-NonInteractive", "-ExecutionPolicy", "Bypass", "-Command", "-"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+    let wrapped = format!(
+        "$ErrorActionPreference = 'Stop'; try {{ {} }} catch {{ [Console]::Error.WriteLine($_.ToString()); exit 1 }}\n\n",
+        script
+    );
+    let result = crate::guest_agent::guest_exec(
+        smac,
+        "powershell.exe",
+        &args,
+        &[],
+        Some(wrapped.as_bytes()),
+        std::time::Duration::from_secs(timeout),
+    )?;
+    if result.timed_out {
+        return Err(format!("PowerShell still running after {}s (pid {})", timeout, result.pid));
+    }
+    if result.exitcode != Some(0) {
+        let detail = if result.stderr.trim().is_empty() { &result.stdout } else { &result.stderr };
+        return Err(format!(
+            "PowerShell exited with {}: {}",
+            result.exitcode.map(|c| c.to_string()).unwrap_or_else(|| "no code".into()),
+            detail.trim()
+        ));
+    }
+    Ok(result.stdout.trim().to_string())
+}
+
+/// Provision a Windows guest through its agent, in place of cloud-init: set
+/// the administrator password, rename the computer and optionally join a
+/// domain, then reboot if the name or domain changed
+pub fn provision_windows(smac: &str) -> Result<String, String> {
+    sanitize_name(smac)?;
+    let vm = db::get_vm(smac)?;
+    let vm_config: serde_json::Value = serde_json::from_str(&vm.config).unwrap_or_default();
+    let config = vm_mds_config(&vm_config);
+    if !crate::guest_agent::guest_ping(smac) {
+        return Err(format!("Guest agent of VM '{}' is not responding", smac));
+    }
+    let mut output = String::new();
+
+    let user = if config.windows_admin_user.is_empty() { "Administrator" } else { config.windows_admin_user.as_str() };
+    let password = if config.windows_admin_password.is_empty() {
+        &config.root_password
+    } else {
+        &config.windows_admin_password
+    };
+    if !password.is_empty() {
+        crate::guest_agent::guest_set_user_password(smac, user, password, false)
+            .map_err(|e| format!("Set password for {}: {}", user, e))?;
+        output.push_str(&format!("Password set for {}\n", user));
+    }
+
+    let name = windows_computer_name(&config, smac);
+    let current = crate::guest_agent::guest_host_name(smac).unwrap_or_default();
+    let rename = !name.is_empty() && !current.eq_ignore_ascii_case(&name);
+    let timeout: u64 = get_conf_or("windows_provision_exec_timeout", "300").parse().unwrap_or(300);
+    let mut reboot = false;
+
+    if !config.domain_name.is_empty() {
+        if config.domain_user.is_empty() || config.domain_password.is_empty() {
+            return Err("domain_user and domain_password are required to join a domain".into());
+        }
+        let mut join = format!(
+            "$cred = New-Object System.Management.Automation.PSCredential({}, (ConvertTo-SecureString {} -AsPlainText -Force)); \
+             Add-Computer -DomainName {} -Credential $cred -Force",
+            ps_quote(&config.domain_user),
+            ps_quote(&config.domain_password),
+            ps_quote(&config.domain_name)
+        );
+        if !config.domain_ou.is_empty() {
+            join.push_str(&format!(" -OUPath {}", ps_quote(&config.domain_ou)));
+        }
+        if rename {
+            join.push_str(&format!(" -NewName {}", ps_quote(&name)));
+        }
+        let script = format!(
+            "$cs = Get-CimInstance Win32_ComputerSystem; \
+             if ($cs.PartOfDomain -and $cs.Domain -eq {}) {{ 'already joined' }} else {{ {}; 'joined' }}",
+            ps_quote(&config.domain_name),
+            join
+        );
+        let result = run_guest_powershell(smac, &script, timeout).map_err(|e| format!("Domain join: {}", e))?;
+        if result.ends_with("already joined") {
+            output.push_str(&format!("Already a member of {}\n", config.domain_name));
+            if rename {
+                // Renaming a domain member needs domain rights too
+                let script = format!(
+                    "$cred = New-Object System.Management.Automation.PSCredential({}, (ConvertTo-SecureString {} -AsPlainText -Force)); \
+                     Rename-Computer -NewName {} -DomainCredential $cred -Force",
+                    ps_quote(&config.domain_user),
+                    ps_quote(&config.domain_password),
+                    ps_quote(&name)
+                );
+                run_guest_powershell(smac, &script, timeout).map_err(|e| format!("Rename computer: {}", e))?;
+                output.push_str(&format!("Computer renamed {} -> {}\n", current, name));
+                reboot = true;
+            }
+        } else {
+            output.push_str(&format!("Joined domain {}\n", config.domain_name));
+            if rename {
+                output.push_str(&format!("Computer renamed {} -> {}\n", current, name));
+            }
+            reboot = true;
+        }
+    } else if rename {
+        let script = format!("Rename-Computer -NewName {} -Force", ps_quote(&name));
+        run_guest_powershell(smac, &script, timeout).map_err(|e| format!("Rename computer: {}", e))?;
+        output.push_str(&format!("Computer renamed {} -> {}\n", current, name));
+        reboot = true;
+    }
+
+    if reboot {
+        // guest-shutdown sends no reply on success
+        crate::guest_agent::qga_command_no_reply(
+            smac,
+            "guest-shutdown",
+            Some(serde_json::json!({ "mode": "reboot" })),
+        )
+        .map_err(|e| format!("Reboot: {}", e))?;
+        output.push_str("Rebooting to apply the changes\n");
+    }
+    if output.is_empty() {
+        output.push_str("Nothing to change\n");
+    }
+    Ok(output)
+}
+
+/// Provision a Windows VM the first time its agent answers. Only for VMs
+/// with `is_windows` and the MDS `windows_provision` flag; the outcome is
+/// kept in the guest_provision table so it runs once per VM. With
+/// `retry_failed`, a "failed" outcome counts as pending too. Returns
+/// whether it succeeded, or None when nothing ran.
+fn provision_windows_if_pending(smac: &str, retry_failed: bool) -> Option<bool> {
+    if !windows_provision_pending(smac, retry_failed) {
+        return None;
+    }
+    let running = PROVISIONING.get_or_init(Default::default);
+    if !running.lock().unwrap_or_else(|e| e.into_inner()).insert(smac.to_string()) {
+        return None;
+    }
+    let (state, message) = match provision_windows(smac) {
+        Ok(out) => ("done", out),
+        Err(e) => ("failed", e),
+    };
+    log::info!("windows provisioning {}: {} {}", smac, state, message.trim());
+    if let Err(e) = db::set_guest_provision(smac, state, message.trim()) {
+        log::warn!("windows provisioning {}: {}", smac, e);
+    }
+    running.lock().unwrap_or_else(|e| e.into_inner()).remove(smac);
+    Some(state == "done")
+}
+
+/// Windows VM with `windows_provision` on that has not been provisioned yet
+fn windows_provision_pending(smac: &str, retry_failed: bool) -> bool {
+    let Ok(vm) = db::get_vm(smac) else { return false };
+    let vm_config: serde_json::Value = serde_json::from_str(&vm.config).unwrap_or_default();
+    vm_config.pointer("/features/is_windows").and_then(|v| v.as_str()) == Some("1")
+        && vm_mds_config(&vm_config).windows_provision
+        && match db::get_guest_provision(smac) {
+            Ok(None) => true,
+            Ok(Some(p)) => retry_failed && p.state == "failed",
+            Err(_) => false,
+        }
+}
+
+/// Wait for the agent (up to `windows_provision_wait` seconds, default 3600)
+/// and provision if pending. A failed run is retried every
+/// `windows_provision_retry_delay` seconds (default 60), up to
+/// `windows_provision_attempts` runs in all (default 3).
+fn watch_windows_provision(smac: &str) {
+    if !windows_provision_pending(smac, false) {
+        return;
+    }
+    let smac = smac.to_string();
+    let wait: u64 = get_conf_or("windows_provision_wait", "3600").parse().unwrap_or(3600);
+    let attempts: u32 = get_conf_or("windows_provision_attempts", "3").parse().unwrap_or(3).max(1);
+    let retry_delay: u64 = get_conf_or("windows_provision_retry_delay", "60").parse().unwrap_or(60);
+    std::thread::spawn(move || {
+        let start = std::time::Instant::now();
+        let mut tries = 0;
+        while start.elapsed().as_secs() < wait {
+            std::thread::sleep(std::time::Duration::from_secs(10));
+            if !crate::qmp::qemu_alive(&smac) {
+                return;
+            }
+            if !crate::guest_agent::guest_ping(&smac) {
+                continue;
+            }
+            tries += 1;
+            match provision_windows_if_pending(&smac, tries > 1) {
+                Some(false) if tries < attempts => {
+                    log::info!("windows provisioning {}: retry {} of {} in {}s", smac, tries, attempts - 1, retry_delay);
+                    std::thread::sleep(std::time::Duration::from_secs(retry_delay));
+                }
+                _ => return,
+            }
+        }
+    });
+}
+
+/// Start-time work for VMs that kept running across a vm_ctl restart: an
+/// answer file in use counts as used, provisioning is watched again
+pub fn resume_guest_watchers() {
+    for vm in db::list_vms().unwrap_or_default() {
+        if vm.status == "running" && crate::qmp::qemu_alive(&vm.smac) {
+            finish_unattended_install(&vm.smac);
+            watch_windows_provision(&vm.smac);
+        }
+    }
+}
+
+// --- Port forwarding operations ---
+
+/// Collect all host ports used by port_forwards across all VMs
+fn used_host_ports(exclude_smac: Option<&str>) -> Vec<(u16, String, String)> {
+    // Returns Vec<(host_port, protocol, vm_name)>
+    let mut ports = Vec::new();
+    if let Ok(vms) = db::list_vms() {
+        for vm in &vms {
+            if let Some(exc) = exclude_smac {
+                if vm.smac == exc { continue; }
+            }
+            if let Ok(cfg) = serde_json::from_str::<serde_json::Value>(&vm.config) {
+                if let Some(forwards) = cfg.get("port_forwards").and_then(|v| v.as_array()) {
+                    for rule in forwards {
+                        let proto = rule.get("protocol").and_then(|v| v.as_str()).unwrap_or("tcp");
+                        let hp = rule.get("host_port").and_then(|v| v.as_u64()).unwrap_or(0) as u16;
+                        if hp > 0 {
+                            ports.push((hp, proto.to_string(), vm.smac.clone()));
+                        }
+                    }
+                }
+            }
+        }
+    }
+    ports
+}
+
+/// Check that a host port is not already used by another VM's port forward
+pub fn validate_host_port_unique(host_port: u16, protocol: &str, exclude_smac: Option<&str>) -> Result<(), String> {
+    let used = used_host_ports(exclude_smac);
+    for (port, proto, vm_name) in &used {
+        if *port == host_port && proto == protocol {
+            return Err(format!(
+                "Host port {} ({}) is already used by VM '{}'",
+                host_port, protocol, vm_name
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Add a port forward rule to a VM config. If VM is running, also apply via QEMU monitor.
+pub fn add_port_forward(smac: &str, protocol: &str, host_port: u16, guest_port: u16) -> Result<String, String> {
+    // Validate protocol
+    if protocol != "tcp" && protocol != "udp" {
+        return Err("Protocol must be 'tcp' or 'udp'".into());
+    }
+    // Validate port ranges
+    if host_port < 1024 {
+        return Err(format!("Host port {} is below 1024 (reserved range)", host_port));
+    }
+    if guest_port == 0 {
+        return Err("Guest port must be 1-65535".into());
+    }
+
+    // Check host port uniqueness
+    validate_host_port_unique(host_port, protocol, Some(smac))?;
+
+    // Load current config
+    let vm = db::get_vm(smac)?;
+    let mut config: serde_json::Value = serde_json::from_str(&vm.config)
+        .map_err(|e| format!("Config parse error: {}", e))?;
+
+    // Get or create port_forwards array
+    let forwards = config.get("port_forwards")
+        .and_then(|v| v.as_array())
         .cloned()
         .unwrap_or_default();
 
@@ -3174,6 +6168,706 @@ pub fn vnc_start(json_str: &str) -> Result<String, String> {
     Ok(format!("VNC WebSocket ready on port {} (built-in QEMU)\n", actual_port))
 }
 
+/// VNC WebSockets go through vm_ctl's own proxy unless `vnc_proxy: false`
+pub fn vnc_proxy_enabled() -> bool {
+    get_conf_or("vnc_proxy", "true") != "false"
+}
+
+/// Loopback address of a running VM's raw VNC server (proxy target)
+pub fn vnc_proxy_target(smac: &str) -> Result<String, String> {
+    sanitize_name(smac)?;
+    let vm = db::get_vm(smac)?;
+    if vm.status != "running" {
+        return Err(format!("VM '{}' is not running", smac));
+    }
+    let cfg: serde_json::Value = serde_json::from_str(&vm.config).unwrap_or_default();
+    let port = cfg.get("vnc_port").and_then(|v| v.as_u64()).unwrap_or(0) as u16;
+    if !(VNC_PORT_MIN..=VNC_PORT_MAX).contains(&port) {
+        return Err(format!("VM '{}' has no VNC port", smac));
+    }
+    Ok(format!("127.0.0.1:{}", 5900 + (port - VNC_PORT_BASE)))
+}
+
+/// VNC password auth is on unless `vnc_password: false` is set in config.yaml
+pub fn vnc_password_enabled() -> bool {
+    get_conf_or("vnc_password", "true") != "false"
+}
+
+/// Lifetime of a per-session VNC password in seconds (`vnc_password_ttl`)
+pub fn vnc_password_ttl() -> u64 {
+    get_conf_or("vnc_password_ttl", "300").parse().unwrap_or(300).max(30)
+}
+
+/// Random password from an unambiguous alphanumeric set
+fn random_password(len: usize) -> String {
+    const CHARSET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz23456789";
+    // Bytes at or above the last whole multiple of the charset size are
+    // dropped, so every character is equally likely
+    let limit = 256 - 256 % CHARSET.len();
+    let mut password = String::with_capacity(len);
+    while password.len() < len {
+        for b in read_urandom_bytes(len) {
+            if (b as usize) < limit && password.len() < len {
+                password.push(CHARSET[b as usize % CHARSET.len()] as char);
+            }
+        }
+    }
+    password
+}
+
+/// Set a fresh random VNC password on a running VM and let it expire after
+/// `vnc_password_ttl`. Clients already connected stay connected. Returns None
+/// when passwords are off, the VM is stopped, or it was started without
+/// password auth (older start, restart to enable).
+pub fn issue_vnc_password(smac: &str) -> Result<Option<String>, String> {
+    sanitize_name(smac)?;
+    if !vnc_password_enabled() {
+        return Ok(None);
+    }
+    let vm = db::get_vm(smac)?;
+    if vm.status != "running" {
+        return Ok(None);
+    }
+    // VNC DES auth uses at most 8 characters
+    let password = random_password(8);
+    match crate::qmp::qmp_command(
+        smac,
+        "set_password",
+        Some(serde_json::json!({ "protocol": "vnc", "password": password })),
+    ) {
+        Ok(_) => {}
+        Err(e) if e.contains("password auth") => return Ok(None),
+        Err(e) => return Err(format!("Set VNC password: {}", e)),
+    }
+    crate::qmp::qmp_command(
+        smac,
+        "expire_password",
+        Some(serde_json::json!({ "protocol": "vnc", "time": format!("+{}", vnc_password_ttl()) })),
+    )
+    .map_err(|e| format!("Set VNC password expiry: {}", e))?;
+    Ok(Some(password))
+}
+
+// --- Boot settings ---
+
+/// Device classes accepted in boot.order
+const BOOT_DEVICES: &[&str] = &["cdrom", "disk", "net"];
+
+/// Directory holding kernels and initrds for direct kernel boot (`boot_path`)
+pub fn boot_dir() -> String {
+    get_conf_or("boot_path", &format!("{}/boot", get_conf("pctl_path")))
+}
+
+/// x86_64 edk2 build and the NVRAM template copied for new VMs
+struct UefiFirmware {
+    code: String,
+    vars: String,
+    /// Secure Boot build: needs q35 with SMM
+    secure: bool,
+}
+
+/// Whether a VM boots with Secure Boot firmware: boot.secure_boot "auto"
+/// means Windows guests only
+fn secure_boot_wanted(boot: &BootSettings, is_windows: bool) -> Result<bool, String> {
+    match boot.secure_boot.as_str() {
+        "auto" | "" => Ok(is_windows),
+        "1" => Ok(true),
+        "0" => Ok(false),
+        other => Err(format!("Invalid secure_boot '{}' (auto, 1 or 0)", other)),
+    }
+}
+
+/// NVRAM template with the Microsoft keys enrolled, used for new Secure Boot
+/// VMs and by secure_boot_reset. Falls back to the plain vars template (no
+/// keys: setup mode) when it is not installed.
+fn x86_secure_vars() -> String {
+    let ms_vars = get_conf_or("edk2_x86_secure_vars", "/opt/homebrew/share/qemu/OVMF_VARS.ms.fd");
+    if std::path::Path::new(&ms_vars).exists() {
+        return ms_vars;
+    }
+    get_conf_or("edk2_x86_vars", "/opt/homebrew/share/qemu/edk2-i386-vars.fd")
+}
+
+/// Firmware of an x86_64 VM (None = SeaBIOS). "auto" uses the Secure Boot
+/// edk2 build when boot.secure_boot asks for it and it is installed, plain
+/// edk2 for other Windows guests and SeaBIOS for the rest. Plain edk2 must
+/// exist whenever it is picked, and secure_boot "1" fails without the
+/// Secure Boot build.
+fn x86_uefi_firmware(boot: &BootSettings, is_windows: bool) -> Result<Option<UefiFirmware>, String> {
+    let want_secure = secure_boot_wanted(boot, is_windows)?;
+    match boot.firmware.as_str() {
+        "bios" if boot.secure_boot == "1" => {
+            return Err("Secure Boot needs UEFI firmware (firmware is 'bios')".into())
+        }
+        "bios" => return Ok(None),
+        "auto" | "" | "uefi" => {}
+        other => return Err(format!("Unknown firmware '{}' (auto, bios or uefi)", other)),
+    }
+    if want_secure {
+        let secure_code = get_conf_or("edk2_x86_secure_code",
+            "/opt/homebrew/share/qemu/edk2-x86_64-secure-code.fd");
+        if std::path::Path::new(&secure_code).exists() {
+            return Ok(Some(UefiFirmware { code: secure_code, vars: x86_secure_vars(), secure: true }));
+        }
+        if boot.secure_boot == "1" {
+            return Err(format!(
+                "Secure Boot firmware '{}' not found. Install edk2/OVMF or set edk2_x86_secure_code in config.",
+                secure_code
+            ));
+        }
+    }
+    if boot.firmware != "uefi" && !is_windows {
+        return Ok(None);
+    }
+    let code = get_conf_or("edk2_x86_code", "/opt/homebrew/share/qemu/edk2-x86_64-code.fd");
+    if !std::path::Path::new(&code).exists() {
+        return Err(format!(
+            "UEFI firmware '{}' not found. Install edk2/OVMF or set edk2_x86_code in config.",
+            code
+        ));
+    }
+    let vars = get_conf_or("edk2_x86_vars", "/opt/homebrew/share/qemu/edk2-i386-vars.fd");
+    Ok(Some(UefiFirmware { code, vars, secure: false }))
+}
+
+/// Whether an aarch64 VM boots the AAVMF Secure Boot build
+fn aarch64_secure_boot(boot: &BootSettings, is_windows: bool) -> Result<bool, String> {
+    if !secure_boot_wanted(boot, is_windows)? {
+        return Ok(false);
+    }
+    let secure_code = get_conf_or("edk2_aarch64_secure_code",
+        "/opt/homebrew/share/qemu/AAVMF_CODE.secboot.fd");
+    if std::path::Path::new(&secure_code).exists() {
+        return Ok(true);
+    }
+    if boot.secure_boot == "1" {
+        return Err(format!(
+            "Secure Boot firmware '{}' not found. Install AAVMF or set edk2_aarch64_secure_code in config.",
+            secure_code
+        ));
+    }
+    Ok(false)
+}
+
+/// boot.order as a list of device classes
+fn boot_order(boot: &BootSettings) -> Result<Vec<&str>, String> {
+    let mut order: Vec<&str> = Vec::new();
+    for class in boot.order.split(',').map(|c| c.trim()).filter(|c| !c.is_empty()) {
+        if !BOOT_DEVICES.contains(&class) {
+            return Err(format!("Unknown boot device '{}' (cdrom, disk or net)", class));
+        }
+        if order.contains(&class) {
+            return Err(format!("Boot device '{}' listed twice", class));
+        }
+        order.push(class);
+    }
+    Ok(order)
+}
+
+/// bootindex for device `n` of a class, from the class's place in the boot
+/// order (None = not bootable). A direct kernel boot takes bootindex 0
+/// itself, so devices then start at 1.
+fn boot_index(order: &[&str], class: &str, n: u32, kernel_boot: bool) -> Option<u32> {
+    order
+        .iter()
+        .position(|c| *c == class)
+        .map(|pos| pos as u32 * 100 + n + u32::from(kernel_boot))
+}
+
+/// boot.menu_timeout in ms (QEMU's splash-time is at most 65535)
+fn boot_menu_timeout(boot: &BootSettings) -> Result<u32, String> {
+    match boot.menu_timeout.trim().parse::<u32>() {
+        Ok(ms) if ms <= 65535 => Ok(ms),
+        _ => Err(format!("Invalid boot menu timeout '{}' (0-65535 ms)", boot.menu_timeout)),
+    }
+}
+
+/// Host paths of boot.kernel / boot.initrd, which must exist in boot_path
+fn boot_kernel_files(boot: &BootSettings) -> Result<Option<(String, Option<String>)>, String> {
+    if boot.kernel.is_empty() {
+        if !boot.initrd.is_empty() || !boot.cmdline.is_empty() {
+            return Err("Boot initrd / cmdline need a kernel".into());
+        }
+        return Ok(None);
+    }
+    let dir = boot_dir();
+    let file = |name: &str| -> Result<String, String> {
+        sanitize_name(name)?;
+        let path = format!("{}/{}", dir, name);
+        if !std::path::Path::new(&path).is_file() {
+            return Err(format!("Boot file '{}' not found in {}", name, dir));
+        }
+        Ok(path)
+    };
+    let kernel = file(&boot.kernel)?;
+    let initrd = if boot.initrd.is_empty() { None } else { Some(file(&boot.initrd)?) };
+    if boot.cmdline.contains(['\n', '\r']) {
+        return Err("Kernel command line must be a single line".into());
+    }
+    Ok(Some((kernel, initrd)))
+}
+
+/// Check boot settings against the VM's architecture and the firmware paths in config
+pub fn validate_boot(boot: &BootSettings, is_aarch64: bool, is_windows: bool) -> Result<(), String> {
+    boot_order(boot)?;
+    boot_menu_timeout(boot)?;
+    boot_kernel_files(boot)?;
+    if !is_aarch64 {
+        return x86_uefi_firmware(boot, is_windows).map(|_| ());
+    }
+    match boot.firmware.as_str() {
+        "auto" | "" | "uefi" => {}
+        "bios" => return Err("aarch64 VMs boot UEFI only".into()),
+        other => return Err(format!("Unknown firmware '{}' (auto, bios or uefi)", other)),
+    }
+    let code = get_conf("edk2_aarch64_bios");
+    if !aarch64_secure_boot(boot, is_windows)? && !std::path::Path::new(&code).exists() {
+        return Err(format!(
+            "aarch64 requires UEFI firmware but '{}' not found. Install EDK2 or set edk2_aarch64_bios in config.",
+            code
+        ));
+    }
+    Ok(())
+}
+
+/// validate_boot for a VM config as stored in the DB
+fn validate_boot_config(config: &serde_json::Value) -> Result<(), String> {
+    let boot: BootSettings = match config.get("boot") {
+        Some(b) => serde_json::from_value(b.clone()).map_err(|e| format!("Invalid boot settings: {}", e))?,
+        None => BootSettings::default(),
+    };
+    let feature = |k: &str| config.pointer(&format!("/features/{}", k)).and_then(|v| v.as_str()).unwrap_or("");
+    validate_boot(&boot, feature("arch") == "aarch64", feature("is_windows") == "1")
+}
+
+/// Kernels and initrds in boot_path: `[{"name", "size"}]`
+pub fn list_boot_files() -> Result<Vec<serde_json::Value>, String> {
+    let dir = boot_dir();
+    let _ = std::fs::create_dir_all(&dir);
+    let mut files: Vec<serde_json::Value> = std::fs::read_dir(&dir)
+        .map_err(|e| format!("Read {}: {}", dir, e))?
+        .flatten()
+        // Hidden files are uploads still in progress
+        .filter(|e| e.path().is_file() && !e.file_name().to_string_lossy().starts_with('.'))
+        .map(|e| serde_json::json!({
+            "name": e.file_name().to_string_lossy(),
+            "size": e.metadata().map(|m| m.len()).unwrap_or(0),
+        }))
+        .collect();
+    files.sort_by(|a, b| a["name"].as_str().cmp(&b["name"].as_str()));
+    Ok(files)
+}
+
+/// Remove a boot file unless a VM's boot settings still use it
+pub fn delete_boot_file(name: &str) -> Result<String, String> {
+    sanitize_name(name)?;
+    for vm in db::list_vms()? {
+        let cfg: serde_json::Value = serde_json::from_str(&vm.config).unwrap_or_default();
+        let used = ["kernel", "initrd"]
+            .iter()
+            .any(|k| cfg.pointer(&format!("/boot/{}", k)).and_then(|v| v.as_str()) == Some(name));
+        if used {
+            return Err(format!("Boot file '{}' is used by VM '{}'", name, vm.smac));
+        }
+    }
+    std::fs::remove_file(format!("{}/{}", boot_dir(), name))
+        .map_err(|e| format!("Failed to delete boot file: {}", e))?;
+    Ok(format!("Deleted {}", name))
+}
+
+// --- Secure Boot keys ---
+
+/// Per-VM UEFI NVRAM (pflash1), qcow2 once the VM has been started
+pub fn nvram_file(smac: &str) -> String {
+    format!("{}/{}_efivars.fd", get_conf("pctl_path"), smac)
+}
+
+/// Microsoft-keys NVRAM template for the VM's architecture
+fn secure_boot_template(is_aarch64: bool) -> Result<String, String> {
+    let template = if is_aarch64 {
+        get_conf_or("edk2_aarch64_secure_vars", "/opt/homebrew/share/qemu/AAVMF_VARS.ms.fd")
+    } else {
+        get_conf_or("edk2_x86_secure_vars", "/opt/homebrew/share/qemu/OVMF_VARS.ms.fd")
+    };
+    if !std::path::Path::new(&template).exists() {
+        return Err(format!(
+            "Secure Boot NVRAM template '{}' not found. Set {} in config.",
+            template,
+            if is_aarch64 { "edk2_aarch64_secure_vars" } else { "edk2_x86_secure_vars" }
+        ));
+    }
+    Ok(template)
+}
+
+fn is_qcow2(file: &str) -> bool {
+    use std::io::Read;
+    let mut magic = [0u8; 4];
+    std::fs::File::open(file).and_then(|mut f| f.read_exact(&mut magic)).is_ok() && &magic == b"QFI\xfb"
+}
+
+/// Raw contents of an NVRAM file. qcow2 is converted with -U so this also
+/// works while QEMU holds the image.
+fn read_nvram_raw(file: &str) -> Result<Vec<u8>, String> {
+    if !is_qcow2(file) {
+        return std::fs::read(file).map_err(|e| format!("Read NVRAM: {}", e));
+    }
+    // Unique per call: status polls and enrolls may read the same NVRAM at once
+    let tmp = format!("{}.raw.{}", file, generate_random_password(8));
+    let result = run_cmd(&get_conf("qemu_img_path"), &["convert", "-U", "-f", "qcow2", "-O", "raw", file, &tmp])
+        .map_err(|e| format!("NVRAM raw convert failed: {}", e))
+        .and_then(|_| std::fs::read(&tmp).map_err(|e| format!("Read NVRAM: {}", e)));
+    let _ = std::fs::remove_file(&tmp);
+    result
+}
+
+/// Virtual size and snapshot count from a qcow2 header (big-endian u64 at
+/// offset 24, u32 at offset 60)
+fn qcow2_header_info(file: &str) -> Option<(u64, u32)> {
+    use std::io::Read;
+    let mut header = [0u8; 64];
+    std::fs::File::open(file).and_then(|mut f| f.read_exact(&mut header)).ok()?;
+    if &header[..4] != b"QFI\xfb" {
+        return None;
+    }
+    let size = u64::from_be_bytes(header[24..32].try_into().ok()?);
+    let snapshots = u32::from_be_bytes(header[60..64].try_into().ok()?);
+    Some((size, snapshots))
+}
+
+/// Replace an NVRAM's contents with a raw image. A qcow2 NVRAM of the same
+/// size is written in place so its internal snapshots survive. A different
+/// size means a new file, which is refused while the NVRAM has snapshots.
+fn write_nvram_raw(file: &str, raw: &str) -> Result<(), String> {
+    let raw_len = std::fs::metadata(raw).map(|m| m.len()).map_err(|e| format!("Read {}: {}", raw, e))?;
+    match qcow2_header_info(file) {
+        Some((size, _)) if size == raw_len => {
+            run_cmd(&get_conf("qemu_img_path"), &["convert", "-n", "-f", "raw", "-O", "qcow2", raw, file])
+                .map_err(|e| format!("NVRAM write failed: {}", e))?;
+            return Ok(());
+        }
+        Some((size, snapshots)) if snapshots > 0 => {
+            return Err(format!(
+                "The new NVRAM is {} bytes and the VM's is {}; replacing it would lose its {} snapshot(s). Delete the VM's snapshots first",
+                raw_len, size, snapshots
+            ));
+        }
+        _ => {}
+    }
+    std::fs::copy(raw, file).map_err(|e| format!("NVRAM write failed: {}", e))?;
+    ensure_nvram_qcow2(file)
+}
+
+/// Stopped VM and its architecture, for NVRAM changes
+fn secure_boot_vm(smac: &str) -> Result<VmStartConfig, String> {
+    sanitize_name(smac)?;
+    let vm = db::get_vm(smac)?;
+    if vm.status == "running" || crate::qmp::qemu_alive(smac) {
+        return Err("VM must be stopped to change its Secure Boot keys".into());
+    }
+    serde_json::from_str(&vm.config).map_err(|e| format!("Invalid VM config: {}", e))
+}
+
+/// Secure Boot setting and enrolled keys (PK, KEK, db, dbx) of a VM
+pub fn secure_boot_info(smac: &str) -> Result<serde_json::Value, String> {
+    sanitize_name(smac)?;
+    let vm = db::get_vm(smac)?;
+    let cfg: VmStartConfig = serde_json::from_str(&vm.config)
+        .map_err(|e| format!("Invalid VM config: {}", e))?;
+    let is_aarch64 = cfg.features.arch == "aarch64";
+    let is_windows = cfg.features.is_windows == "1";
+    let firmware_secure = if is_aarch64 {
+        aarch64_secure_boot(&cfg.boot, is_windows).unwrap_or(false)
+    } else {
+        matches!(x86_uefi_firmware(&cfg.boot, is_windows), Ok(Some(fw)) if fw.secure)
+    };
+    let mut info = serde_json::json!({
+        "smac": smac,
+        "secure_boot": cfg.boot.secure_boot,
+        "firmware_secure": firmware_secure,
+    });
+    let nvram = nvram_file(smac);
+    if std::path::Path::new(&nvram).exists() {
+        let state = crate::efivars::secure_boot_state(&read_nvram_raw(&nvram)?)?;
+        if let (Some(obj), serde_json::Value::Object(state)) = (info.as_object_mut(), state) {
+            obj.insert("nvram".into(), serde_json::json!(true));
+            obj.extend(state);
+        }
+    } else {
+        // Created from a template on first UEFI boot
+        info["nvram"] = serde_json::json!(false);
+    }
+    Ok(info)
+}
+
+/// "8-4-4-4-12" hex GUID
+fn valid_guid(guid: &str) -> bool {
+    let parts: Vec<&str> = guid.split('-').collect();
+    parts.iter().map(|p| p.len()).eq([8, 4, 4, 4, 12])
+        && parts.iter().all(|p| p.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// Enroll PEM certificates in a stopped VM's NVRAM with virt-fw-vars
+/// (`virt_fw_vars_path`). A VM without NVRAM starts from the Microsoft template.
+pub fn secure_boot_enroll(smac: &str, body: &serde_json::Value) -> Result<String, String> {
+    let cfg = secure_boot_vm(smac)?;
+    let cmd: SecureBootEnrollCmd = serde_json::from_value(body.clone())
+        .map_err(|e| format!("JSON parse error: {}", e))?;
+    if cmd.pk.trim().is_empty() && cmd.kek.is_empty() && cmd.db.is_empty() {
+        return Err("Nothing to enroll: give pk, kek or db certificates".into());
+    }
+    let owner = if cmd.owner.is_empty() {
+        get_conf_or("secure_boot_owner_guid", "4e3a5c1f-8b27-4d69-a0f2-6c1b9d7e3f58")
+    } else {
+        cmd.owner.to_lowercase()
+    };
+    if !valid_guid(&owner) {
+        return Err(format!("Invalid owner GUID '{}'", owner));
+    }
+
+    let work = format!("{}/secureboot_{}_{}", get_conf("pctl_path"), smac, generate_random_password(8));
+    std::fs::create_dir(&work).map_err(|e| format!("Create {}: {}", work, e))?;
+    let result = (|| -> Result<usize, String> {
+        let input = format!("{}/in.fd", work);
+        let output = format!("{}/out.fd", work);
+        let nvram = nvram_file(smac);
+        if std::path::Path::new(&nvram).exists() {
+            std::fs::write(&input, read_nvram_raw(&nvram)?).map_err(|e| format!("Write {}: {}", input, e))?;
+        } else {
+            std::fs::copy(secure_boot_template(cfg.features.arch == "aarch64")?, &input)
+                .map_err(|e| format!("Copy NVRAM template: {}", e))?;
+        }
+
+        let mut args: Vec<String> = vec!["--input".into(), input, "--output".into(), output.clone()];
+        let mut certs = 0;
+        let mut add = |flag: &str, pem: &str| -> Result<(), String> {
+            if !pem.contains("-----BEGIN CERTIFICATE-----") {
+                return Err(format!("{}: not a PEM certificate", flag.trim_start_matches('-')));
+            }
+            let file = format!("{}/cert{}.pem", work, certs);
+            std::fs::write(&file, pem).map_err(|e| format!("Write {}: {}", file, e))?;
+            args.extend([flag.to_string(), owner.clone(), file]);
+            certs += 1;
+            Ok(())
+        };
+        if !cmd.pk.trim().is_empty() {
+            add("--set-pk", &cmd.pk)?;
+        }
+        for pem in &cmd.kek {
+            add("--add-kek", pem)?;
+        }
+        for pem in &cmd.db {
+            add("--add-db", pem)?;
+        }
+        let arg_refs: Vec<&str> = args.iter().map(|a| a.as_str()).collect();
+        run_cmd(&get_conf_or("virt_fw_vars_path", "virt-fw-vars"), &arg_refs)
+            .map_err(|e| format!("virt-fw-vars failed: {}", e))?;
+        write_nvram_raw(&nvram, &output)?;
+        Ok(certs)
+    })();
+    let _ = std::fs::remove_dir_all(&work);
+    let certs = result?;
+    log::info!("Secure Boot: enrolled {} certificate(s) for {}", certs, smac);
+    Ok(format!("Enrolled {} certificate(s) in the NVRAM of VM '{}'\n", certs, smac))
+}
+
+/// Reset a stopped VM's NVRAM to the Microsoft-keys template. Boot entries
+/// and other UEFI variables are reset too.
+pub fn secure_boot_reset(smac: &str) -> Result<String, String> {
+    let cfg = secure_boot_vm(smac)?;
+    let template = secure_boot_template(cfg.features.arch == "aarch64")?;
+    write_nvram_raw(&nvram_file(smac), &template)?;
+    log::info!("Secure Boot: NVRAM of {} reset from {}", smac, template);
+    Ok(format!("NVRAM of VM '{}' reset to the Microsoft Secure Boot keys\n", smac))
+}
+
+// --- TPM state ---
+
+/// swtpm state directory of a VM, created at the first start of a Windows VM.
+/// BitLocker seals its keys here, so it travels with the VM's disks.
+pub fn tpm_dir(smac: &str) -> String {
+    format!("{}/{}_tpm", get_conf("pctl_path"), smac)
+}
+
+/// Replace `dst` with a copy of the TPM state in `src` (swtpm keeps a flat
+/// directory). The copy is made next to `dst` and renamed over it, so a
+/// failed copy leaves the old state untouched. Returns the number of files copied.
+pub fn copy_tpm_state(src: &str, dst: &str) -> Result<usize, String> {
+    let tmp = format!("{}.tmp.{}", dst.trim_end_matches('/'), generate_random_password(8));
+    std::fs::create_dir_all(&tmp).map_err(|e| format!("Create {}: {}", tmp, e))?;
+    let copy = || -> Result<usize, String> {
+        let mut copied = 0;
+        for entry in std::fs::read_dir(src).map_err(|e| format!("Read {}: {}", src, e))?.flatten() {
+            if entry.path().is_file() {
+                std::fs::copy(entry.path(), std::path::Path::new(&tmp).join(entry.file_name()))
+                    .map_err(|e| format!("Copy TPM state: {}", e))?;
+                copied += 1;
+            }
+        }
+        Ok(copied)
+    };
+    let copied = match copy() {
+        Ok(n) => n,
+        Err(e) => {
+            let _ = std::fs::remove_dir_all(&tmp);
+            return Err(e);
+        }
+    };
+    // rename() cannot replace a non-empty directory: move the old state
+    // aside first and put it back if the swap fails
+    let old = format!("{}.old", tmp);
+    let had_old = std::fs::rename(dst, &old).is_ok();
+    if let Err(e) = std::fs::rename(&tmp, dst) {
+        if had_old {
+            let _ = std::fs::rename(&old, dst);
+        }
+        let _ = std::fs::remove_dir_all(&tmp);
+        return Err(format!("Replace {}: {}", dst, e));
+    }
+    if had_old {
+        let _ = std::fs::remove_dir_all(&old);
+    }
+    Ok(copied)
+}
+
+/// Remove a stopped VM's TPM state; swtpm starts with a blank TPM next boot.
+/// Anything sealed to the old TPM (BitLocker keys) needs its recovery key.
+pub fn tpm_reset(smac: &str) -> Result<String, String> {
+    sanitize_name(smac)?;
+    let vm = db::get_vm(smac)?;
+    if vm.status == "running" || crate::qmp::qemu_alive(smac) {
+        return Err("VM must be stopped to reset its TPM".into());
+    }
+    let dir = tpm_dir(smac);
+    if !std::path::Path::new(&dir).exists() {
+        return Ok(format!("VM '{}' has no TPM state\n", smac));
+    }
+    std::fs::remove_dir_all(&dir).map_err(|e| format!("Failed to remove TPM state: {}", e))?;
+    let _ = std::fs::remove_file(format!("{}.sock", dir));
+    log::info!("TPM state of {} reset", smac);
+    Ok(format!("TPM state of VM '{}' removed; a new TPM is created at next start\n", smac))
+}
+
+// --- SPICE display operations ---
+
+/// Video device for the VM's display settings. aarch64 `virt` only has
+/// virtio-gpu; on x86_64 SPICE defaults to QXL.
+fn display_video(cfg: &VmStartConfig, is_aarch64: bool) -> Result<&'static str, String> {
+    let spice = match cfg.display.protocol.as_str() {
+        "vnc" => false,
+        "spice" => true,
+        other => return Err(format!("Unknown display protocol '{}' (vnc or spice)", other)),
+    };
+    let video = match cfg.display.video.as_str() {
+        "auto" | "" if is_aarch64 => "virtio",
+        "auto" | "" => if spice { "qxl" } else { "std" },
+        "std" => "std",
+        "qxl" => "qxl",
+        "virtio" => "virtio",
+        other => return Err(format!("Unknown video device '{}' (auto, std, qxl or virtio)", other)),
+    };
+    if is_aarch64 && video != "virtio" {
+        return Err(format!("aarch64 VMs only support the virtio video device, not '{}'", video));
+    }
+    Ok(video)
+}
+
+/// SPICE port paired with the VNC port (12001-13000 -> 13001-14000)
+pub fn spice_port(vnc_port: u16) -> u16 {
+    vnc_port + 1000
+}
+
+/// SPICE listen address: `spice_bind_host`, else the VNC bind address
+fn spice_bind_host() -> String {
+    let host = get_conf_or("spice_bind_host", "");
+    if host.is_empty() {
+        get_conf_or("vnc_bind_host", "127.0.0.1")
+    } else {
+        host
+    }
+}
+
+/// The SPICE port is derived from the VNC port, so nothing reserves it:
+/// refuse the start when a port forward claims it or something else on the
+/// host already listens there
+fn check_spice_port(port: u16, bind_host: &str) -> Result<(), String> {
+    for (host_port, proto, vm_name) in used_host_ports(None) {
+        if host_port == port && proto == "tcp" {
+            return Err(format!(
+                "SPICE port {} is used by a port forward of VM '{}'",
+                port, vm_name
+            ));
+        }
+    }
+    let addr = if bind_host.is_empty() { "0.0.0.0" } else { bind_host };
+    std::net::TcpListener::bind((addr, port))
+        .map(drop)
+        .map_err(|e| format!("SPICE port {} on {} is not available: {}", port, addr, e))
+}
+
+/// Startup password file QEMU reads through `-object secret`
+fn spice_password_file(smac: &str) -> String {
+    format!("{}/{}_spice.pw", get_conf("pctl_path"), smac)
+}
+
+/// SPICE ticketing (`spice_password`, default on): clients need a password
+/// issued through the VNC token flow
+fn spice_password_enabled() -> bool {
+    get_conf_or("spice_password", "true") != "false"
+}
+
+/// Set a fresh SPICE password on a running SPICE VM and build a remote-viewer
+/// connection file (`.vv`) for it. The client connects to `spice_public_host`
+/// if set, else to the SPICE bind address — or, when SPICE listens on all
+/// addresses, to `host`, the host the request came in on.
+pub fn spice_connection_file(smac: &str, host: &str) -> Result<String, String> {
+    sanitize_name(smac)?;
+    let vm = db::get_vm(smac)?;
+    if vm.status != "running" {
+        return Err(format!("VM '{}' is not running", smac));
+    }
+    let cfg: VmStartConfig = serde_json::from_str(&vm.config)
+        .map_err(|e| format!("Invalid VM config: {}", e))?;
+    if cfg.display.protocol != "spice" {
+        return Err(format!("VM '{}' does not use the SPICE display", smac));
+    }
+    let public_host = get_conf_or("spice_public_host", "");
+    let bind_host = spice_bind_host();
+    let host = if !public_host.is_empty() {
+        public_host.as_str()
+    } else if bind_host.is_empty() || bind_host == "0.0.0.0" || bind_host == "::" {
+        host
+    } else {
+        bind_host.as_str()
+    };
+
+    let mut vv = String::from("[virt-viewer]\ntype=spice\n");
+    vv.push_str(&format!("host={}\nport={}\n", host, spice_port(cfg.vnc_port)));
+    if spice_password_enabled() {
+        let password = random_password(16);
+        crate::qmp::qmp_command(
+            smac,
+            "set_password",
+            Some(serde_json::json!({ "protocol": "spice", "password": password })),
+        )
+        .map_err(|e| format!("Set SPICE password: {}", e))?;
+        crate::qmp::qmp_command(
+            smac,
+            "expire_password",
+            Some(serde_json::json!({ "protocol": "spice", "time": format!("+{}", vnc_password_ttl()) })),
+        )
+        .map_err(|e| format!("Set SPICE password expiry: {}", e))?;
+        vv.push_str(&format!("password={}\n", password));
+    }
+    vv.push_str(&format!("title={} - %d - Press SHIFT+F12 to release cursor\n", smac));
+    vv.push_str("delete-this-file=1\n");
+    vv.push_str("toggle-fullscreen=shift+f11\n");
+    vv.push_str("release-cursor=shift+f12\n");
+    vv.push_str("secure-attention=ctrl+alt+end\n");
+    let usbredir = cfg.display.usb_redirect.parse::<u32>().unwrap_or(0) > 0;
+    vv.push_str(&format!("enable-usbredir={}\n", if usbredir { 1 } else { 0 }));
+    Ok(vv)
+}
+
 pub fn vnc_stop(json_str: &str) -> Result<String, String> {
     // QEMU built-in WebSocket VNC stops when VM stops.
     let cmd: VncCmd =
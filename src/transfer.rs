@@ -0,0 +1,449 @@
+use crate::config::{get_conf, get_conf_or};
+use crate::guest_agent::QgaSession;
+use crate::ssh::sanitize_name;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use tokio::sync::mpsc::{channel, Receiver, Sender};
+
+/// Bytes per guest-file-read/guest-file-write call (1 MB raw = ~1.33 MB base64)
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Cancel flags of transfers in progress, by job id
+static ACTIVE: OnceLock<Mutex<HashMap<String, Arc<AtomicBool>>>> = OnceLock::new();
+
+fn active() -> &'static Mutex<HashMap<String, Arc<AtomicBool>>> {
+    ACTIVE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// A file copy between vm_ctl and a guest through the guest agent.
+/// The index entry is kept current while the worker runs, so progress can be
+/// polled and a failed transfer resumed from `transferred`. Uploads are
+/// spooled on the host first (`received`), then written to the guest.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct TransferJob {
+    pub id: String,
+    pub smac: String,
+    /// "upload" (host → guest) or "download" (guest → host)
+    pub direction: String,
+    pub guest_path: String,
+    /// Total size when known (upload `size`, or the guest file's size)
+    pub total: Option<u64>,
+    pub transferred: u64,
+    /// Upload bytes of the current request spooled on the host
+    #[serde(default)]
+    pub received: u64,
+    /// "pending" (upload created, body not posted yet), "running",
+    /// "completed", "failed" or "cancelled"
+    pub state: String,
+    pub error: Option<String>,
+    pub started: String,
+    pub finished: Option<String>,
+}
+
+/// A piece of an upload body; the channel closing without `End` means the
+/// client went away and the job is left resumable
+pub enum UploadChunk {
+    Data(Vec<u8>),
+    End,
+}
+
+/// Transfers live under `live_path/transfers`: `{id}.json` is the job,
+/// `{id}.bin` the downloaded data or the spooled upload body
+pub fn transfers_dir() -> PathBuf {
+    PathBuf::from(get_conf("live_path")).join("transfers")
+}
+
+fn meta_path(id: &str) -> PathBuf {
+    transfers_dir().join(format!("{}.json", id))
+}
+
+fn data_path(id: &str) -> PathBuf {
+    transfers_dir().join(format!("{}.bin", id))
+}
+
+fn now() -> String {
+    chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+fn save(job: &TransferJob) {
+    let _ = std::fs::create_dir_all(transfers_dir());
+    if let Ok(s) = serde_json::to_string_pretty(job) {
+        let _ = std::fs::write(meta_path(&job.id), s);
+    }
+}
+
+fn finish(job: &mut TransferJob, result: Result<(), String>, cancel: &AtomicBool) {
+    match result {
+        Ok(()) => job.state = "completed".into(),
+        Err(_) if cancel.load(Ordering::Relaxed) => job.state = "cancelled".into(),
+        Err(e) => {
+            job.state = "failed".into();
+            job.error = Some(e);
+        }
+    }
+    job.finished = Some(now());
+    save(job);
+    active().lock().unwrap_or_else(|e| e.into_inner()).remove(&job.id);
+}
+
+pub fn get(id: &str) -> Result<TransferJob, String> {
+    sanitize_name(id)?;
+    let s = std::fs::read_to_string(meta_path(id)).map_err(|_| format!("Transfer '{}' not found", id))?;
+    serde_json::from_str(&s).map_err(|e| format!("Transfer '{}' index is corrupt: {}", id, e))
+}
+
+/// Transfer jobs, newest first
+pub fn list(smac: Option<&str>) -> Vec<TransferJob> {
+    let mut out: Vec<TransferJob> = std::fs::read_dir(transfers_dir())
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter(|e| e.path().extension().and_then(|x| x.to_str()) == Some("json"))
+                .filter_map(|e| std::fs::read_to_string(e.path()).ok())
+                .filter_map(|s| serde_json::from_str::<TransferJob>(&s).ok())
+                .filter(|j| smac.is_none() || Some(j.smac.as_str()) == smac)
+                .collect()
+        })
+        .unwrap_or_default();
+    out.sort_by(|a, b| b.started.cmp(&a.started));
+    out
+}
+
+/// New job id, unique per VM and millisecond
+fn new_job(smac: &str, direction: &str, guest_path: &str, total: Option<u64>) -> TransferJob {
+    TransferJob {
+        id: format!("{}-{}", smac, chrono::Local::now().format("%Y%m%d-%H%M%S%3f")),
+        smac: smac.to_string(),
+        direction: direction.into(),
+        guest_path: guest_path.to_string(),
+        total,
+        transferred: 0,
+        received: 0,
+        state: "running".into(),
+        error: None,
+        started: now(),
+        finished: None,
+    }
+}
+
+/// Move job `id` of `smac` from one of the `from` states to `to`. The state
+/// check and the update happen under the jobs lock, so two requests can
+/// never both resume or start the same job. Moving to "running" registers
+/// its cancel flag.
+fn claim(
+    id: &str,
+    smac: &str,
+    direction: &str,
+    from: &[&str],
+    to: &str,
+) -> Result<(TransferJob, Arc<AtomicBool>), String> {
+    let mut active = active().lock().unwrap_or_else(|e| e.into_inner());
+    let mut job = get(id)?;
+    if job.smac != smac || job.direction != direction {
+        return Err(format!("Transfer '{}' is not a {} for VM '{}'", id, direction, smac));
+    }
+    if active.contains_key(id) || !from.contains(&job.state.as_str()) {
+        return Err(format!("Transfer '{}' is {}", id, job.state));
+    }
+    job.state = to.into();
+    job.error = None;
+    job.finished = None;
+    let flag = Arc::new(AtomicBool::new(false));
+    if to == "running" {
+        active.insert(job.id.clone(), flag.clone());
+    }
+    save(&job);
+    Ok((job, flag))
+}
+
+fn register(job: &TransferJob) -> Arc<AtomicBool> {
+    let flag = Arc::new(AtomicBool::new(false));
+    active()
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(job.id.clone(), flag.clone());
+    save(job);
+    flag
+}
+
+/// Create an upload job, to be fed with `start_upload`. Its id is known
+/// before the body is sent, so the client can poll it. With `resume`, the
+/// earlier failed job is reused and the body must start at its
+/// `transferred` offset; otherwise the file is created at `guest_path`.
+pub fn create_upload(
+    smac: &str,
+    guest_path: &str,
+    total: Option<u64>,
+    resume: Option<&str>,
+) -> Result<TransferJob, String> {
+    sanitize_name(smac)?;
+    purge_expired();
+    match resume {
+        Some(id) => {
+            let (mut job, _) = claim(id, smac, "upload", &["failed", "cancelled"], "pending")?;
+            if total.is_some() {
+                job.total = total;
+                save(&job);
+            }
+            Ok(job)
+        }
+        None => {
+            if guest_path.is_empty() {
+                return Err("Missing guest path".into());
+            }
+            let mut job = new_job(smac, "upload", guest_path, total);
+            job.state = "pending".into();
+            save(&job);
+            Ok(job)
+        }
+    }
+}
+
+/// Start the pending upload `id` from a stream of chunks. The body is
+/// spooled to disk, then written to the guest.
+pub fn start_upload(smac: &str, id: &str) -> Result<(TransferJob, Sender<UploadChunk>), String> {
+    sanitize_name(smac)?;
+    let (mut job, cancel) = claim(id, smac, "upload", &["pending"], "running")?;
+    job.received = 0;
+    let (tx, rx) = channel::<UploadChunk>(8);
+    let snapshot = job.clone();
+    std::thread::spawn(move || {
+        let result = upload_worker(&mut job, rx, &cancel);
+        let _ = std::fs::remove_file(data_path(&job.id));
+        finish(&mut job, result, &cancel);
+    });
+    Ok((snapshot, tx))
+}
+
+fn upload_worker(job: &mut TransferJob, rx: Receiver<UploadChunk>, cancel: &AtomicBool) -> Result<(), String> {
+    // The agent session (and with it the VM's agent lock) is only taken
+    // once the client is done, so a slow client blocks no other agent calls
+    let spool = data_path(&job.id);
+    let complete = spool_body(job, rx, &spool, cancel)?;
+    let result = write_spool(job, &spool, cancel);
+    match (result, complete) {
+        (Err(e), _) => Err(e),
+        (Ok(()), false) => Err(format!("Upload interrupted at {} bytes", job.transferred)),
+        (Ok(()), true) => Ok(()),
+    }
+}
+
+/// Copy the request body into `spool`. Returns false when the client went
+/// away before the end of the body.
+fn spool_body(
+    job: &mut TransferJob,
+    mut rx: Receiver<UploadChunk>,
+    spool: &std::path::Path,
+    cancel: &AtomicBool,
+) -> Result<bool, String> {
+    let mut out = std::fs::File::create(spool).map_err(|e| format!("Create upload spool: {}", e))?;
+    let mut last_saved = 0;
+    loop {
+        if cancel.load(Ordering::Relaxed) {
+            return Err("Cancelled".to_string());
+        }
+        match rx.blocking_recv() {
+            Some(UploadChunk::Data(d)) => {
+                out.write_all(&d).map_err(|e| format!("Write upload spool: {}", e))?;
+                job.received += d.len() as u64;
+                if job.received - last_saved >= CHUNK_SIZE as u64 {
+                    last_saved = job.received;
+                    save(job);
+                }
+            }
+            Some(UploadChunk::End) => break,
+            None => return Ok(false),
+        }
+    }
+    save(job);
+    Ok(true)
+}
+
+/// Write the spooled body to the guest file at the job's `transferred` offset
+fn write_spool(job: &mut TransferJob, spool: &std::path::Path, cancel: &AtomicBool) -> Result<(), String> {
+    use std::io::Read;
+    if job.received == 0 && job.transferred > 0 {
+        return Ok(());
+    }
+    let mut input = std::fs::File::open(spool).map_err(|e| format!("Open upload spool: {}", e))?;
+    let mut session = QgaSession::connect(&job.smac)?;
+    let handle = if job.transferred > 0 {
+        let h = session.file_open(&job.guest_path, "r+b")?;
+        if let Err(e) = session.file_seek(h, job.transferred as i64, "set") {
+            let _ = session.file_close(h);
+            return Err(e);
+        }
+        h
+    } else {
+        session.file_open(&job.guest_path, "wb")?
+    };
+
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    let mut result = Ok(());
+    loop {
+        if cancel.load(Ordering::Relaxed) {
+            result = Err("Cancelled".to_string());
+            break;
+        }
+        let n = match input.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) => {
+                result = Err(format!("Read upload spool: {}", e));
+                break;
+            }
+        };
+        if let Err(e) = session.file_write(handle, &buf[..n]) {
+            result = Err(format!("guest-file-write failed at offset {}: {}", job.transferred, e));
+            break;
+        }
+        job.transferred += n as u64;
+        save(job);
+    }
+    let _ = session.file_close(handle);
+    result
+}
+
+/// Start copying a guest file to the host in the background. With `resume`,
+/// the earlier job's partial data is kept and the read continues after it.
+pub fn start_download(smac: &str, guest_path: &str, resume: Option<&str>) -> Result<TransferJob, String> {
+    sanitize_name(smac)?;
+    purge_expired();
+    let (mut job, cancel) = match resume {
+        Some(id) => {
+            let (mut job, cancel) = claim(id, smac, "download", &["failed", "cancelled"], "running")?;
+            // The host copy is the source of truth for how far we got
+            job.transferred = std::fs::metadata(data_path(id)).map(|m| m.len()).unwrap_or(0);
+            save(&job);
+            (job, cancel)
+        }
+        None => {
+            if guest_path.is_empty() {
+                return Err("Missing guest path".into());
+            }
+            let job = new_job(smac, "download", guest_path, None);
+            let cancel = register(&job);
+            (job, cancel)
+        }
+    };
+    let snapshot = job.clone();
+    std::thread::spawn(move || {
+        let result = download_worker(&mut job, &cancel);
+        finish(&mut job, result, &cancel);
+    });
+    Ok(snapshot)
+}
+
+fn download_worker(job: &mut TransferJob, cancel: &AtomicBool) -> Result<(), String> {
+    let mut session = QgaSession::connect(&job.smac)?;
+    job.total = session.file_size(&job.guest_path).ok();
+    save(job);
+
+    let mut out = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(data_path(&job.id))
+        .map_err(|e| format!("Create download file: {}", e))?;
+    let handle = session.file_open(&job.guest_path, "rb")?;
+    let mut result = Ok(());
+    if job.transferred > 0 {
+        if let Err(e) = session.file_seek(handle, job.transferred as i64, "set") {
+            result = Err(e);
+        }
+    }
+    while result.is_ok() {
+        if cancel.load(Ordering::Relaxed) {
+            result = Err("Cancelled".to_string());
+            break;
+        }
+        match session.file_read(handle, CHUNK_SIZE as u64) {
+            Ok((data, eof)) => {
+                if let Err(e) = out.write_all(&data) {
+                    result = Err(format!("Write download file: {}", e));
+                    break;
+                }
+                job.transferred += data.len() as u64;
+                save(job);
+                if eof {
+                    break;
+                }
+            }
+            Err(e) => result = Err(format!("guest-file-read failed at offset {}: {}", job.transferred, e)),
+        }
+    }
+    let _ = session.file_close(handle);
+    result
+}
+
+/// Stop a running or pending transfer; it ends as "cancelled" and can be
+/// resumed
+pub fn cancel(id: &str) -> Result<String, String> {
+    sanitize_name(id)?;
+    let active = active().lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(flag) = active.get(id) {
+        flag.store(true, Ordering::Relaxed);
+        return Ok(format!("Transfer '{}' cancelling\n", id));
+    }
+    let mut job = get(id)?;
+    if job.state != "pending" {
+        return Err(format!("Transfer '{}' is not running", id));
+    }
+    job.state = "cancelled".into();
+    job.finished = Some(now());
+    save(&job);
+    Ok(format!("Transfer '{}' cancelled\n", id))
+}
+
+pub fn delete(id: &str) -> Result<String, String> {
+    let job = get(id)?;
+    if job.state == "running" || active().lock().unwrap_or_else(|e| e.into_inner()).contains_key(id) {
+        return Err(format!("Transfer '{}' is still running", id));
+    }
+    let _ = std::fs::remove_file(data_path(id));
+    let _ = std::fs::remove_file(meta_path(id));
+    Ok(format!("Transfer '{}' deleted\n", id))
+}
+
+/// Host copy of a completed download
+pub fn download_path(id: &str) -> Result<PathBuf, String> {
+    let job = get(id)?;
+    if job.direction != "download" || job.state != "completed" {
+        return Err(format!("Transfer '{}' is not a completed download", id));
+    }
+    Ok(data_path(id))
+}
+
+/// Drop finished jobs (and their data) older than `transfer_retention_hours`
+/// (default 24), and uploads whose body never came within that time
+fn purge_expired() {
+    let hours: i64 = get_conf_or("transfer_retention_hours", "24").parse().unwrap_or(24);
+    let cutoff = (chrono::Local::now() - chrono::Duration::hours(hours))
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string();
+    for job in list(None) {
+        let expired = match job.state.as_str() {
+            "running" => false,
+            "pending" => job.started < cutoff,
+            _ => job.finished.as_deref().is_some_and(|f| f < cutoff.as_str()),
+        };
+        if expired {
+            let _ = delete(&job.id);
+        }
+    }
+}
+
+/// Transfers left "running" or "pending" by a crash or restart lost their
+/// worker; mark them failed so they can be resumed
+pub fn mark_interrupted() {
+    for mut job in list(None) {
+        if job.state == "running" || job.state == "pending" {
+            job.state = "failed".into();
+            job.error = Some("Interrupted by vm_ctl restart".into());
+            job.finished = Some(now());
+            save(&job);
+        }
+    }
+}
@@ -201,7 +201,6 @@ if exist "C:\msys64\ucrt64\bin" (
 )
 set "SWTPM_PATH=!MSYS_BIN!\swtpm.exe"
 set "MKISOFS_PATH=!MSYS_BIN!\mkisofs.exe"
-set "WEBSOCKIFY_PATH=!MSYS_BIN!\websockify.exe"
 if not exist "%SWTPM_PATH%" (
     if not exist "C:\msys64\usr\bin\bash.exe" (
         echo [INFO] MSYS2 not installed. Downloading latest installer...
@@ -223,19 +222,18 @@ if not exist "%SWTPM_PATH%" (
         set "MSYS_PKG=mingw-w64-ucrt-x86_64"
         set "SWTPM_PATH=!MSYS_BIN!\swtpm.exe"
         set "MKISOFS_PATH=!MSYS_BIN!\mkisofs.exe"
-        set "WEBSOCKIFY_PATH=!MSYS_BIN!\websockify.exe"
     )
     echo [INFO] Refreshing MSYS2 keyring + package databases ^(force resync^)...
     :: cd / first to avoid "drive specified" warnings when bash inherits a cwd
     :: it can't translate to a POSIX path.
     C:\msys64\usr\bin\bash.exe -lc "cd /; pacman-key --init >/dev/null 2>&1; pacman-key --populate msys2 >/dev/null 2>&1; pacman -Syy --noconfirm" >nul 2>&1
-    echo [INFO] Installing swtpm + cdrtools + python + websockify via pacman ^(env: !MSYS_PKG!^)...
-    C:\msys64\usr\bin\bash.exe -lc "cd /; pacman -S --noconfirm --needed !MSYS_PKG!-swtpm !MSYS_PKG!-cdrtools !MSYS_PKG!-python !MSYS_PKG!-python-pip !MSYS_PKG!-python-websockify"
+    echo [INFO] Installing swtpm + cdrtools + python via pacman ^(env: !MSYS_PKG!^)...
+    C:\msys64\usr\bin\bash.exe -lc "cd /; pacman -S --noconfirm --needed !MSYS_PKG!-swtpm !MSYS_PKG!-cdrtools !MSYS_PKG!-python !MSYS_PKG!-python-pip"
     if !errorlevel! neq 0 (
         :: One package may be unavailable (renamed / removed) -- retry per-package
         :: so the others still install. swtpm is the only critical one for Win11.
         echo [INFO] Batch failed -- retrying packages one at a time...
-        for %%P in (swtpm cdrtools python python-pip python-websockify) do (
+        for %%P in (swtpm cdrtools python python-pip) do (
             C:\msys64\usr\bin\bash.exe -lc "cd /; pacman -S --noconfirm --needed !MSYS_PKG!-%%P" >nul 2>&1
             if !errorlevel! equ 0 (
                 echo [OK]   pacman: !MSYS_PKG!-%%P installed
@@ -317,32 +315,6 @@ if not exist "%NSSM%" (
     echo [OK]   NSSM already at %NSSM%
 )
 
-:: --- Step 2a.9: Verify websockify (fallback to pip if pacman package missing) ---
-:: We installed it via pacman above, but if that package wasn't available the
-:: fallback is pip with --break-system-packages (PEP 668 requires this on
-:: MSYS2 since 2024 — system Python rejects unmanaged installs by default).
-if not exist "%WEBSOCKIFY_PATH%" (
-    if exist "!MSYS_BIN!\python.exe" (
-        echo [INFO] websockify pacman package unavailable -- trying pip ^(--break-system-packages^)...
-        "!MSYS_BIN!\python.exe" -m pip install --quiet --break-system-packages --no-warn-script-location websockify
-        if exist "%WEBSOCKIFY_PATH%" (
-            echo [OK]   websockify installed via pip at %WEBSOCKIFY_PATH%
-        ) else (
-            :: pip may drop a .exe shim or a bare script; try the no-extension fallback
-            if exist "!MSYS_BIN!\websockify" (
-                set "WEBSOCKIFY_PATH=!MSYS_BIN!\websockify"
-                echo [OK]   websockify installed at !WEBSOCKIFY_PATH!
-            ) else (
-                echo [WARN] websockify not found after pip install
-            )
-        )
-    ) else (
-        echo [WARN] MSYS2 python not installed; skipping websockify
-    )
-) else (
-    echo [OK]   websockify already present at %WEBSOCKIFY_PATH%
-)
-
 echo.
 
 :: On ARM64, check for qemu-system-aarch64 as primary binary
@@ -922,7 +894,6 @@ if not exist "%CONFIG_YAML%" (
         echo edk2_x86_vars: %EDK2_X86_VARS%
         echo swtpm_path: %SWTPM_PATH%
         echo mkisofs_path: %MKISOFS_PATH%
-        echo websockify_path: %WEBSOCKIFY_PATH%
         :: TCG tuning: qemu64 avoids the Haswell-v4 CPUID features TCG doesn't
         :: emulate (pcid/invpcid/tsc-deadline/spec-ctrl). thread=multi gives each
         :: vCPU its own host core. TCG is safe on Parallels (no nested virt);
@@ -968,11 +939,6 @@ if not exist "%CONFIG_YAML%" (
         echo mkisofs_path: %MKISOFS_PATH%>> "%CONFIG_YAML%"
         echo [INFO] Added mkisofs_path to existing config.yaml
     )
-    findstr /c:"websockify_path" "%CONFIG_YAML%" >nul 2>&1
-    if !errorlevel! neq 0 (
-        echo websockify_path: %WEBSOCKIFY_PATH%>> "%CONFIG_YAML%"
-        echo [INFO] Added websockify_path to existing config.yaml
-    )
     findstr /c:"qemu_cpu_x86" "%CONFIG_YAML%" >nul 2>&1
     if !errorlevel! neq 0 (
         echo qemu_cpu_x86: qemu64>> "%CONFIG_YAML%"
@@ -1058,8 +1024,8 @@ echo [INFO] Adding firewall rule for port 8080...
 netsh advfirewall firewall delete rule name="vmcontrol" >nul 2>&1
 netsh advfirewall firewall delete rule name="vmcontrol-vnc" >nul 2>&1
 netsh advfirewall firewall add rule name="vmcontrol" dir=in action=allow protocol=tcp localport=8080 enable=yes >nul
-:: VNC WebSocket range (12001-13000) — QEMU binds one port per VM for the noVNC console.
-netsh advfirewall firewall add rule name="vmcontrol-vnc" dir=in action=allow protocol=tcp localport=12001-13000 enable=yes >nul
+:: noVNC consoles are proxied through port 8080 (vnc_proxy: true); open 12001-13000
+:: only if you switch vnc_proxy off and expose QEMU's WebSocket ports directly.
 echo [OK]   Firewall rule added
 
 echo.
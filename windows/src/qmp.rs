@@ -0,0 +1,201 @@
+use crate::config::get_conf;
+use std::io::{BufRead, BufReader, Write};
+use std::time::Duration;
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+#[cfg(windows)]
+use uds_windows::UnixStream;
+
+/// Path of the per-VM QMP socket (created by `-qmp unix:...` at start)
+pub fn qmp_socket_path(smac: &str) -> String {
+    format!("{}/{}_qmp", get_conf("pctl_path"), smac)
+}
+
+/// Whether the VM's QEMU process is still there: its QMP socket exists and
+/// accepts connections (QEMU removes the socket on a clean exit; a killed
+/// QEMU leaves it behind but nothing listens). VMs started by an older
+/// vm_ctl have no QMP socket, so the HMP monitor socket `{pctl_path}/{smac}`
+/// is checked instead.
+pub fn qemu_alive(smac: &str) -> bool {
+    let qmp = qmp_socket_path(smac);
+    let path = if std::path::Path::new(&qmp).exists() {
+        qmp
+    } else {
+        format!("{}/{}", get_conf("pctl_path"), smac)
+    };
+    std::path::Path::new(&path).exists() && UnixStream::connect(&path).is_ok()
+}
+
+/// Read one QMP message, skipping asynchronous events
+fn read_reply(reader: &mut BufReader<UnixStream>, command: &str) -> Result<serde_json::Value, String> {
+    let start = std::time::Instant::now();
+    let max_wait = Duration::from_secs(15);
+    let mut line = String::new();
+    loop {
+        if start.elapsed() > max_wait {
+            return Err(format!("QMP response timeout after {:?} for command '{}'", max_wait, command));
+        }
+        line.clear();
+        match reader.read_line(&mut line) {
+            Ok(0) => return Err(format!("QMP connection closed during '{}'", command)),
+            Ok(_) => {
+                let trimmed = line.trim();
+                if trimmed.is_empty() {
+                    continue;
+                }
+                let parsed: serde_json::Value = serde_json::from_str(trimmed)
+                    .map_err(|e| format!("QMP JSON parse error: {} (raw: {})", e, trimmed))?;
+                // Events (DEVICE_DELETED, RESUME, ...) can arrive at any time
+                if parsed.get("event").is_some() {
+                    continue;
+                }
+                if let Some(err) = parsed.get("error") {
+                    let class = err.get("class").and_then(|v| v.as_str()).unwrap_or("Unknown");
+                    let desc = err.get("desc").and_then(|v| v.as_str()).unwrap_or("no description");
+                    return Err(format!("QMP error ({}): {}", class, desc));
+                }
+                return Ok(parsed);
+            }
+            Err(ref e)
+                if e.kind() == std::io::ErrorKind::WouldBlock
+                    || e.kind() == std::io::ErrorKind::TimedOut =>
+            {
+                std::thread::sleep(Duration::from_millis(100));
+                continue;
+            }
+            Err(e) => return Err(format!("Read error: {}", e)),
+        }
+    }
+}
+
+/// Send a QMP command and return the `return` value.
+/// Each call opens a fresh connection and negotiates capabilities.
+pub fn qmp_command(
+    smac: &str,
+    command: &str,
+    args: Option<serde_json::Value>,
+) -> Result<serde_json::Value, String> {
+    let sock_path = qmp_socket_path(smac);
+    let stream = UnixStream::connect(&sock_path).map_err(|e| {
+        format!(
+            "QMP socket connect failed ({}): {} — restart the VM to enable QMP",
+            sock_path, e
+        )
+    })?;
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .map_err(|e| format!("Set timeout error: {}", e))?;
+    stream
+        .set_write_timeout(Some(Duration::from_secs(5)))
+        .map_err(|e| format!("Set write timeout error: {}", e))?;
+    let mut writer = stream
+        .try_clone()
+        .map_err(|e| format!("Socket clone error: {}", e))?;
+    let mut reader = BufReader::new(stream);
+
+    // Greeting: {"QMP": {"version": ..., "capabilities": [...]}}
+    let mut greeting = String::new();
+    reader
+        .read_line(&mut greeting)
+        .map_err(|e| format!("QMP greeting read error: {}", e))?;
+    if !greeting.contains("\"QMP\"") {
+        return Err(format!("Unexpected QMP greeting: {}", greeting.trim()));
+    }
+
+    writer
+        .write_all(b"{\"execute\": \"qmp_capabilities\"}\n")
+        .map_err(|e| format!("Write error: {}", e))?;
+    read_reply(&mut reader, "qmp_capabilities")?;
+
+    let cmd = if let Some(a) = args {
+        serde_json::json!({ "execute": command, "arguments": a })
+    } else {
+        serde_json::json!({ "execute": command })
+    };
+    writer
+        .write_all(format!("{}\n", cmd).as_bytes())
+        .map_err(|e| format!("Write error: {}", e))?;
+    let reply = read_reply(&mut reader, command)?;
+    Ok(reply.get("return").cloned().unwrap_or(serde_json::Value::Null))
+}
+
+/// Check whether a device with the given qdev id is present in the guest
+pub fn device_exists(smac: &str, id: &str) -> Result<bool, String> {
+    let list = qmp_command(
+        smac,
+        "qom-list",
+        Some(serde_json::json!({ "path": "/machine/peripheral" })),
+    )?;
+    Ok(list
+        .as_array()
+        .map(|props| {
+            props
+                .iter()
+                .any(|p| p.get("name").and_then(|n| n.as_str()) == Some(id))
+        })
+        .unwrap_or(false))
+}
+
+/// Unplug a device and wait until the guest has released it.
+/// device_del only *requests* removal — the guest OS must acknowledge it.
+pub fn device_del_wait(smac: &str, id: &str, timeout: Duration) -> Result<(), String> {
+    qmp_command(smac, "device_del", Some(serde_json::json!({ "id": id })))?;
+    let start = std::time::Instant::now();
+    while start.elapsed() < timeout {
+        if !device_exists(smac, id)? {
+            return Ok(());
+        }
+        std::thread::sleep(Duration::from_millis(500));
+    }
+    Err(format!(
+        "Guest did not release device '{}' within {:?} — is the guest OS hot-plug aware?",
+        id, timeout
+    ))
+}
+
+/// Whether device `id` sits directly on the root bus of a PCIe machine
+/// (q35 / virt, recognized by its `hp*` root ports). pcie.0 does not support
+/// hot-unplug, so devices there can only be removed with the VM stopped.
+pub fn on_pcie_root_bus(smac: &str, id: &str) -> Result<bool, String> {
+    let buses = qmp_command(smac, "query-pci", None)?;
+    let mut has_ports = false;
+    let mut on_root = false;
+    for bus in buses.as_array().into_iter().flatten() {
+        for dev in bus.get("devices").and_then(|d| d.as_array()).into_iter().flatten() {
+            let qdev_id = dev.get("qdev_id").and_then(|v| v.as_str()).unwrap_or("");
+            has_ports |= qdev_id.starts_with("hp");
+            on_root |= qdev_id == id;
+        }
+    }
+    Ok(has_ports && on_root)
+}
+
+/// Find a free hot-plug root port (`hp0`..`hpN`) for PCIe machines (q35 / virt).
+/// Returns Ok(None) on machines without root ports (i440fx `pc`), where
+/// devices can be hot-plugged directly on the root bus.
+pub fn free_hotplug_port(smac: &str) -> Result<Option<String>, String> {
+    let buses = qmp_command(smac, "query-pci", None)?;
+    let mut found_ports = false;
+    for bus in buses.as_array().into_iter().flatten() {
+        for dev in bus.get("devices").and_then(|d| d.as_array()).into_iter().flatten() {
+            let qdev_id = dev.get("qdev_id").and_then(|v| v.as_str()).unwrap_or("");
+            if !qdev_id.starts_with("hp") {
+                continue;
+            }
+            found_ports = true;
+            let used = dev
+                .get("pci_bridge")
+                .and_then(|b| b.get("devices"))
+                .and_then(|d| d.as_array())
+                .map(|d| !d.is_empty())
+                .unwrap_or(false);
+            if !used {
+                return Ok(Some(qdev_id.to_string()));
+            }
+        }
+    }
+    if found_ports {
+        return Err("No free hot-plug slots left — stop the VM to add more devices".into());
+    }
+    Ok(None)
+}
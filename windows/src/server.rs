@@ -3,7 +3,7 @@ use actix_web::{middleware, web, App, HttpResponse, HttpServer};
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 
-use crate::config::get_conf;
+use crate::config::{get_conf, get_conf_or};
 use crate::mds;
 use crate::models::ApiResponse;
 use crate::operations;
@@ -13,11 +13,29 @@ use crate::operations;
 pub struct VncToken {
     smac: String,
     created: std::time::Instant,
+    /// Per-session VNC password set on the VM when the token was issued
+    vnc_password: Option<String>,
 }
 
 /// Shared VNC token store
 pub type VncTokenStore = Arc<Mutex<HashMap<String, VncToken>>>;
 
+/// Proxy sessions created when a token is resolved: `/vnc/ws/{session}` may be
+/// (re)connected until the session expires (`vnc_password_ttl`)
+#[derive(Clone, Default)]
+pub struct VncProxyStore(Arc<Mutex<HashMap<String, VncToken>>>);
+
+impl VncProxyStore {
+    /// Drop sessions older than `vnc_password_ttl`
+    fn purge_expired(&self) {
+        let ttl = operations::vnc_password_ttl();
+        self.0
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .retain(|_, v| v.created.elapsed().as_secs() < ttl);
+    }
+}
+
 // ──────────────────────────────────────────
 // API Handlers
 // ──────────────────────────────────────────
@@ -67,6 +85,10 @@ async fn powerdown_vm(body: web::Json<serde_json::Value>) -> HttpResponse {
     handle_operation(body, "powerdown", operations::powerdown).await
 }
 
+async fn shutdown_vm(body: web::Json<serde_json::Value>) -> HttpResponse {
+    handle_operation(body, "shutdown", operations::shutdown).await
+}
+
 async fn create_config_vm(body: web::Json<serde_json::Value>) -> HttpResponse {
     handle_operation(body, "create-config", operations::create_config).await
 }
@@ -92,6 +114,24 @@ async fn rename_vm_handler(body: web::Json<serde_json::Value>) -> HttpResponse {
     }
 }
 
+/// VM config JSON with the MDS and install credentials blanked
+fn redacted_vm_config(config: &str) -> String {
+    match serde_json::from_str::<serde_json::Value>(config) {
+        Ok(mut cfg) => {
+            if let Some(mds) = cfg.get_mut("mds") {
+                mds::redact_mds_secrets(mds);
+            }
+            for (section, key) in operations::VM_CONFIG_SECRETS {
+                if let Some(v) = cfg.get_mut(*section).and_then(|s| s.get_mut(*key)) {
+                    *v = serde_json::json!("");
+                }
+            }
+            serde_json::to_string(&cfg).unwrap_or_default()
+        }
+        Err(_) => config.to_string(),
+    }
+}
+
 async fn get_vm_handler(path: web::Path<String>) -> HttpResponse {
     let smac = path.into_inner();
     if let Err(e) = crate::ssh::sanitize_name(&smac) {
@@ -102,7 +142,10 @@ async fn get_vm_handler(path: web::Path<String>) -> HttpResponse {
         });
     }
     match crate::db::get_vm(&smac) {
-        Ok(vm) => HttpResponse::Ok().json(vm),
+        Ok(mut vm) => {
+            vm.config = redacted_vm_config(&vm.config);
+            HttpResponse::Ok().json(vm)
+        }
         Err(e) => HttpResponse::NotFound().json(ApiResponse {
             success: false,
             message: e,
@@ -124,11 +167,12 @@ async fn get_vm_mds_handler(path: web::Path<String>) -> HttpResponse {
     match crate::db::get_vm(&smac) {
         Ok(vm) => {
             let config: serde_json::Value = serde_json::from_str(&vm.config).unwrap_or_default();
-            let mds = config.get("mds").cloned().unwrap_or_else(|| {
+            let mut mds = config.get("mds").cloned().unwrap_or_else(|| {
                 // Return global defaults if VM has no MDS config
                 let global = mds::load_mds_config();
                 serde_json::to_value(&global).unwrap_or_default()
             });
+            mds::redact_mds_secrets(&mut mds);
             HttpResponse::Ok().json(ApiResponse {
                 success: true,
                 message: "MDS config loaded".into(),
@@ -211,14 +255,16 @@ async fn save_vm_mds_handler(path: web::Path<String>, body: web::Json<serde_json
                 });
             }
 
-            // If root_password is empty, preserve existing password from DB
-            if new_pw.is_empty() {
-                if let Some(existing_pw) = config.get("mds")
-                    .and_then(|m| m.get("root_password"))
-                    .and_then(|v| v.as_str())
-                {
-                    if !existing_pw.is_empty() {
-                        new_mds["root_password"] = serde_json::json!(existing_pw);
+            // Empty password fields keep the existing passwords from DB
+            for key in std::iter::once("root_password").chain(mds::MDS_SECRET_KEYS) {
+                if new_mds.get(key).and_then(|v| v.as_str()).unwrap_or("").is_empty() {
+                    if let Some(existing_pw) = config.get("mds")
+                        .and_then(|m| m.get(key))
+                        .and_then(|v| v.as_str())
+                    {
+                        if !existing_pw.is_empty() {
+                            new_mds[key] = serde_json::json!(existing_pw);
+                        }
                     }
                 }
             }
@@ -310,10 +356,31 @@ async fn backup_vm(body: web::Json<serde_json::Value>) -> HttpResponse {
     handle_operation(body, "backup", operations::backup).await
 }
 
-async fn vnc_start_handler(body: web::Json<serde_json::Value>) -> HttpResponse {
+async fn vnc_start_handler(req: actix_web::HttpRequest, body: web::Json<serde_json::Value>) -> HttpResponse {
+    if let Some(smac) = body.get("smac").and_then(|v| v.as_str()) {
+        crate::recording::note_session_opener(smac, &request_user(&req));
+    }
     handle_operation(body, "vnc_start", operations::vnc_start).await
 }
 
+/// Who made a request: the user set by an authenticating reverse proxy
+/// listed in `trusted_proxies`, otherwise the client address
+fn request_user(req: &actix_web::HttpRequest) -> String {
+    let peer = req.peer_addr().map(|a| a.ip().to_string()).unwrap_or_else(|| "unknown".into());
+    let trusted = get_conf_or("trusted_proxies", "");
+    if !trusted.split(',').any(|p| p.trim() == peer) {
+        return peer;
+    }
+    for header in ["X-Remote-User", "X-Forwarded-User", "Remote-User"] {
+        if let Some(user) = req.headers().get(header).and_then(|v| v.to_str().ok()) {
+            if !user.is_empty() {
+                return user.to_string();
+            }
+        }
+    }
+    req.connection_info().realip_remote_addr().unwrap_or(&peer).to_string()
+}
+
 async fn vnc_stop_handler(body: web::Json<serde_json::Value>) -> HttpResponse {
     handle_operation(body, "vnc_stop", operations::vnc_stop).await
 }
@@ -730,6 +797,7 @@ async fn guest_file_write_handler(
 
 /// Generate a one-time VNC access token for a VM
 async fn vnc_token_handler(
+    req: actix_web::HttpRequest,
     body: web::Json<serde_json::Value>,
     store: web::Data<VncTokenStore>,
 ) -> HttpResponse {
@@ -753,6 +821,37 @@ async fn vnc_token_handler(
         });
     }
 
+    // Each session gets its own VNC password; the previous one stops working
+    // for new connections. SPICE tokens get their password from the .vv
+    // download and leave the VNC password alone.
+    let for_spice = body.get("display").and_then(|v| v.as_str()) == Some("spice");
+    let smac_for_pw = smac.clone();
+    let vnc_password = match web::block(move || {
+        if for_spice {
+            Ok(None)
+        } else {
+            operations::issue_vnc_password(&smac_for_pw)
+        }
+    })
+    .await
+    {
+        Ok(Ok(pw)) => pw,
+        Ok(Err(e)) => {
+            return HttpResponse::BadRequest().json(ApiResponse {
+                success: false,
+                message: e,
+                output: None,
+            });
+        }
+        Err(e) => {
+            return HttpResponse::InternalServerError().json(ApiResponse {
+                success: false,
+                message: format!("Internal error: {}", e),
+                output: None,
+            });
+        }
+    };
+
     // Generate random token using the shared urandom helper
     let token = {
         let bytes = operations::read_urandom_bytes(24);
@@ -770,8 +869,10 @@ async fn vnc_token_handler(
         map.insert(token.clone(), VncToken {
             smac: smac.clone(),
             created: now,
+            vnc_password,
         });
     }
+    crate::recording::note_session_opener(&smac, &request_user(&req));
 
     HttpResponse::Ok().json(serde_json::json!({
         "success": true,
@@ -779,41 +880,86 @@ async fn vnc_token_handler(
     }))
 }
 
-/// Resolve and consume a one-time VNC token — returns VM info for connecting
-async fn vnc_resolve_handler(
-    path: web::Path<String>,
-    store: web::Data<VncTokenStore>,
-) -> HttpResponse {
-    let token = path.into_inner();
-
-    // Remove token (one-time use)
+/// Remove a one-time VNC token from the store, rejecting unknown or expired ones
+fn take_vnc_token(store: &VncTokenStore, token: &str) -> Result<VncToken, HttpResponse> {
     let vnc_token = {
         let mut map = match store.lock() {
             Ok(guard) => guard,
             Err(poisoned) => poisoned.into_inner(),
         };
-        map.remove(&token)
+        map.remove(token)
     };
 
-    let vnc_token = match vnc_token {
+    match vnc_token {
         Some(t) => {
             // Check expiry (5 minutes)
             if t.created.elapsed().as_secs() > 300 {
-                return HttpResponse::Unauthorized().json(ApiResponse {
+                return Err(HttpResponse::Unauthorized().json(ApiResponse {
                     success: false,
                     message: "Token expired".into(),
                     output: None,
-                });
+                }));
             }
-            t
-        }
-        None => {
-            return HttpResponse::Unauthorized().json(ApiResponse {
-                success: false,
-                message: "Invalid or already used token".into(),
-                output: None,
-            });
+            Ok(t)
         }
+        None => Err(HttpResponse::Unauthorized().json(ApiResponse {
+            success: false,
+            message: "Invalid or already used token".into(),
+            output: None,
+        })),
+    }
+}
+
+/// Consume a one-time VNC token and download a remote-viewer `.vv` file for
+/// the VM's SPICE display, with a fresh SPICE password
+async fn spice_vv_handler(
+    req: actix_web::HttpRequest,
+    path: web::Path<String>,
+    store: web::Data<VncTokenStore>,
+) -> HttpResponse {
+    let vnc_token = match take_vnc_token(&store, &path.into_inner()) {
+        Ok(t) => t,
+        Err(resp) => return resp,
+    };
+    let host = req.connection_info().host().to_string();
+    // Strip the HTTP port; keep bracketed IPv6 literals intact
+    let host = match host.rfind(':') {
+        Some(i) if !host[i..].contains(']') => host[..i].to_string(),
+        _ => host,
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']').to_string();
+    let smac = vnc_token.smac.clone();
+    match web::block(move || operations::spice_connection_file(&smac, &host)).await {
+        Ok(Ok(vv)) => HttpResponse::Ok()
+            .content_type("application/x-virt-viewer")
+            .insert_header((
+                "Content-Disposition",
+                format!("attachment; filename=\"{}.vv\"", vnc_token.smac),
+            ))
+            .insert_header(("Cache-Control", "no-store"))
+            .body(vv),
+        Ok(Err(e)) => HttpResponse::BadRequest().json(ApiResponse {
+            success: false,
+            message: e,
+            output: None,
+        }),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse {
+            success: false,
+            message: format!("Internal error: {}", e),
+            output: None,
+        }),
+    }
+}
+
+/// Resolve and consume a one-time VNC token — returns VM info for connecting
+async fn vnc_resolve_handler(
+    path: web::Path<String>,
+    store: web::Data<VncTokenStore>,
+    proxy_store: web::Data<VncProxyStore>,
+) -> HttpResponse {
+    let vnc_token = match take_vnc_token(&store, &path.into_inner()) {
+        Ok(t) => t,
+        Err(resp) => return resp,
     };
 
     // Get VM info
@@ -828,7 +974,23 @@ async fn vnc_resolve_handler(
         }
     };
 
-    let (vnc_port, is_windows, arch, vmctl_password) = if let Ok(cfg) = serde_json::from_str::<serde_json::Value>(&vm.config) {
+    // Proxy session for /vnc/ws/{id} (reusable for reconnects until it expires)
+    let ws_path = if operations::vnc_proxy_enabled() {
+        let id = operations::read_urandom_bytes(24)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>();
+        proxy_store
+            .0
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(id.clone(), vnc_token.clone());
+        Some(format!("/vnc/ws/{}", id))
+    } else {
+        None
+    };
+
+    let (vnc_port, is_windows, arch, vmctl_password, display) = if let Ok(cfg) = serde_json::from_str::<serde_json::Value>(&vm.config) {
         let port = cfg.get("vnc_port").and_then(|v| v.as_u64()).unwrap_or(0);
         let win = cfg.pointer("/features/is_windows")
             .and_then(|v| v.as_str())
@@ -842,9 +1004,13 @@ async fn vnc_resolve_handler(
             .and_then(|v| v.as_str())
             .unwrap_or("")
             .to_string();
-        (port, win, a, pw)
+        let d = cfg.pointer("/display/protocol")
+            .and_then(|v| v.as_str())
+            .unwrap_or("vnc")
+            .to_string();
+        (port, win, a, pw, d)
     } else {
-        (0, false, "x86_64".to_string(), String::new())
+        (0, false, "x86_64".to_string(), String::new(), "vnc".to_string())
     };
 
     HttpResponse::Ok().json(serde_json::json!({
@@ -855,9 +1021,116 @@ async fn vnc_resolve_handler(
         "is_windows": is_windows,
         "arch": arch,
         "vmctl_password": vmctl_password,
+        "vnc_password": vnc_token.vnc_password,
+        "vnc_password_ttl": operations::vnc_password_ttl(),
+        "tls": !crate::config::get_conf_or("vnc_tls_dir", "").is_empty(),
+        "ws_path": ws_path,
+        "display": display,
     }))
 }
 
+/// WebSocket-to-VNC proxy: bridges the browser to the VM's loopback VNC port
+/// so only the main HTTP port has to be reachable
+async fn vnc_ws_proxy_handler(
+    req: actix_web::HttpRequest,
+    path: web::Path<String>,
+    payload: web::Payload,
+    proxy_store: web::Data<VncProxyStore>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let session_id = path.into_inner();
+    proxy_store.purge_expired();
+    let session = proxy_store.0.lock().unwrap_or_else(|e| e.into_inner()).get(&session_id).cloned();
+    let smac = match session {
+        Some(s) => s.smac,
+        None => {
+            return Ok(HttpResponse::Unauthorized().json(ApiResponse {
+                success: false,
+                message: "Invalid or expired VNC session".into(),
+                output: None,
+            }));
+        }
+    };
+    let target = match web::block(move || operations::vnc_proxy_target(&smac)).await {
+        Ok(Ok(t)) => t,
+        Ok(Err(e)) => {
+            return Ok(HttpResponse::BadRequest().json(ApiResponse {
+                success: false, message: e, output: None,
+            }));
+        }
+        Err(e) => {
+            return Ok(HttpResponse::InternalServerError().json(ApiResponse {
+                success: false, message: format!("Internal error: {}", e), output: None,
+            }));
+        }
+    };
+    let tcp = match tokio::net::TcpStream::connect(&target).await {
+        Ok(t) => t,
+        Err(e) => {
+            return Ok(HttpResponse::BadGateway().json(ApiResponse {
+                success: false,
+                message: format!("VNC server {} unreachable: {}", target, e),
+                output: None,
+            }));
+        }
+    };
+    let _ = tcp.set_nodelay(true);
+
+    // Older noVNC clients ask for the "binary" subprotocol and drop the
+    // connection unless it is echoed back
+    let wants_binary = req
+        .headers()
+        .get(actix_web::http::header::SEC_WEBSOCKET_PROTOCOL)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.split(',').any(|p| p.trim() == "binary"))
+        .unwrap_or(false);
+    let (mut response, mut ws, mut messages) = actix_ws::handle(&req, payload)?;
+    if wants_binary {
+        response.headers_mut().insert(
+            actix_web::http::header::SEC_WEBSOCKET_PROTOCOL,
+            actix_web::http::header::HeaderValue::from_static("binary"),
+        );
+    }
+
+    actix_web::rt::spawn(async move {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        let (mut vnc_rx, mut vnc_tx) = tcp.into_split();
+        let mut buf = vec![0u8; 64 * 1024];
+        loop {
+            tokio::select! {
+                n = vnc_rx.read(&mut buf) => match n {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => {
+                        if ws.binary(buf[..n].to_vec()).await.is_err() {
+                            return;
+                        }
+                    }
+                },
+                msg = messages.recv() => match msg {
+                    Some(Ok(actix_ws::Message::Binary(bytes))) => {
+                        if vnc_tx.write_all(&bytes).await.is_err() {
+                            break;
+                        }
+                    }
+                    Some(Ok(actix_ws::Message::Text(text))) => {
+                        if vnc_tx.write_all(text.as_bytes()).await.is_err() {
+                            break;
+                        }
+                    }
+                    Some(Ok(actix_ws::Message::Ping(p))) => {
+                        if ws.pong(&p).await.is_err() {
+                            return;
+                        }
+                    }
+                    Some(Ok(actix_ws::Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => {}
+                },
+            }
+        }
+        let _ = ws.close(None).await;
+    });
+    Ok(response)
+}
+
 /// List VMs — auto-backfill VNC ports + health-check stale "running" status
 async fn list_vms_handler() -> HttpResponse {
     match crate::db::list_vms() {
@@ -908,7 +1181,28 @@ async fn list_vms_handler() -> HttpResponse {
                 }
             }
 
-            HttpResponse::Ok().json(vms)
+            // Merge in what the guest agents last reported
+            let guest_info: HashMap<String, crate::db::GuestInfoRecord> = crate::db::list_guest_info()
+                .unwrap_or_default()
+                .into_iter()
+                .map(|g| (g.smac.clone(), g))
+                .collect();
+            let list: Vec<serde_json::Value> = vms
+                .into_iter()
+                .map(|mut vm| {
+                    vm.config = redacted_vm_config(&vm.config);
+                    let mut v = serde_json::to_value(&vm).unwrap_or_default();
+                    // Only running VMs: a VM that exited on its own may still have a row
+                    let g = guest_info.get(&vm.smac).filter(|_| vm.status == "running");
+                    v["guest_ips"] = serde_json::json!(g.map(|g| g.guest_ips.clone()).unwrap_or_default());
+                    v["os_name"] = serde_json::json!(g.map(|g| g.os_name.as_str()).unwrap_or(""));
+                    v["kernel"] = serde_json::json!(g.map(|g| g.kernel.as_str()).unwrap_or(""));
+                    v["agent_version"] = serde_json::json!(g.map(|g| g.agent_version.as_str()).unwrap_or(""));
+                    v["guest_info_updated"] = serde_json::json!(g.map(|g| g.updated_at.as_str()));
+                    v
+                })
+                .collect();
+            HttpResponse::Ok().json(list)
         }
         Err(e) => HttpResponse::InternalServerError().json(ApiResponse {
             success: false,
@@ -1032,7 +1326,8 @@ async fn upload_iso_handler(
 
 // List PCI devices currently bound to vfio-pci driver (Linux only)
 async fn list_vfio_devices() -> HttpResponse {
-    let devices: Vec<serde_json::Value> = Vec::new();
+    #[allow(unused_mut)]
+    let mut devices: Vec<serde_json::Value> = Vec::new();
 
     #[cfg(target_os = "linux")]
     {
@@ -1077,6 +1372,57 @@ async fn list_vfio_devices() -> HttpResponse {
     HttpResponse::Ok().json(devices)
 }
 
+async fn list_pci_devices() -> HttpResponse {
+    match web::block(operations::list_pci_devices).await {
+        Ok(devices) => HttpResponse::Ok().json(devices),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse {
+            success: false, message: format!("Internal error: {}", e), output: None,
+        }),
+    }
+}
+
+async fn pci_bind_handler(body: web::Json<serde_json::Value>) -> HttpResponse {
+    pci_driver_change(body, true).await
+}
+
+async fn pci_unbind_handler(body: web::Json<serde_json::Value>) -> HttpResponse {
+    pci_driver_change(body, false).await
+}
+
+async fn pci_driver_change(body: web::Json<serde_json::Value>, bind: bool) -> HttpResponse {
+    let addr = match body.get("address").and_then(|v| v.as_str()) {
+        Some(a) if !a.is_empty() => a.trim().to_lowercase(),
+        _ => return HttpResponse::BadRequest().json(ApiResponse {
+            success: false, message: "Missing 'address'".into(), output: None,
+        }),
+    };
+    let result = web::block(move || {
+        if bind { operations::bind_vfio(&addr) } else { operations::unbind_vfio(&addr) }
+    }).await;
+    match result {
+        Ok(Ok(output)) => HttpResponse::Ok().json(ApiResponse {
+            success: true,
+            message: if bind { "Bound to vfio-pci".into() } else { "Released from vfio-pci".into() },
+            output: Some(output),
+        }),
+        Ok(Err(e)) => HttpResponse::BadRequest().json(ApiResponse {
+            success: false, message: e, output: None,
+        }),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse {
+            success: false, message: format!("Internal error: {}", e), output: None,
+        }),
+    }
+}
+
+async fn list_usb_devices() -> HttpResponse {
+    match web::block(operations::list_host_usb_devices).await {
+        Ok(devices) => HttpResponse::Ok().json(devices),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse {
+            success: false, message: format!("Internal error: {}", e), output: None,
+        }),
+    }
+}
+
 async fn list_disks_handler() -> HttpResponse {
     let disk_path = get_conf("disk_path");
 
@@ -1888,7 +2234,59 @@ async fn export_disk_handler(
     }
 }
 
-/// Export a complete VM (config + disk files) as a ZIP archive
+/// Add a VM's swtpm state files to an export ZIP under `prefix`
+fn zip_tpm_state(
+    zip: &mut zip::ZipWriter<std::fs::File>,
+    options: zip::write::SimpleFileOptions,
+    smac: &str,
+    prefix: &str,
+) -> Result<(), String> {
+    let dir = operations::tpm_dir(smac);
+    let Ok(entries) = std::fs::read_dir(&dir) else {
+        return Ok(());
+    };
+    for entry in entries.flatten().filter(|e| e.path().is_file()) {
+        zip.start_file(format!("{}{}", prefix, entry.file_name().to_string_lossy()), options)
+            .map_err(|e| format!("ZIP error: {}", e))?;
+        let mut tpm_file = std::fs::File::open(entry.path())
+            .map_err(|e| format!("Failed to read TPM state {}: {}", smac, e))?;
+        std::io::copy(&mut tpm_file, zip)
+            .map_err(|e| format!("ZIP write error for TPM state {}: {}", smac, e))?;
+    }
+    Ok(())
+}
+
+/// Extract the swtpm state files stored under `prefix` into a VM's TPM directory
+fn unzip_tpm_state(
+    archive: &mut zip::ZipArchive<std::fs::File>,
+    prefix: &str,
+    smac: &str,
+) -> Result<(), String> {
+    let names: Vec<String> = archive
+        .file_names()
+        .filter_map(|n| n.strip_prefix(prefix))
+        .filter(|n| !n.is_empty() && !n.contains('/') && !n.contains('\\') && !n.contains(".."))
+        .map(String::from)
+        .collect();
+    if names.is_empty() {
+        return Ok(());
+    }
+    let dir = operations::tpm_dir(smac);
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).map_err(|e| format!("Failed to create TPM state dir: {}", e))?;
+    for name in names {
+        let mut entry = archive
+            .by_name(&format!("{}{}", prefix, name))
+            .map_err(|e| format!("ZIP entry error: {}", e))?;
+        let mut out_file = std::fs::File::create(format!("{}/{}", dir, name))
+            .map_err(|e| format!("Failed to create TPM state: {}", e))?;
+        std::io::copy(&mut entry, &mut out_file)
+            .map_err(|e| format!("Failed to extract TPM state: {}", e))?;
+    }
+    Ok(())
+}
+
+/// Export a complete VM (config, disk files, NVRAM and TPM state) as a ZIP archive
 async fn export_vm_handler(
     req: actix_web::HttpRequest,
     path: web::Path<String>,
@@ -1955,6 +2353,8 @@ async fn export_vm_handler(
     let zip_path = tmp_zip.clone();
     let meta_json = serde_json::to_string_pretty(&export_meta).unwrap_or_default();
     let dp = disk_path.clone();
+    let nvram_path = operations::nvram_file(&smac);
+    let tpm_smac = smac.clone();
 
     // Build ZIP in blocking thread
     let build_result = web::block(move || {
@@ -2018,6 +2418,19 @@ async fn export_vm_handler(
                 .map_err(|e| format!("ZIP write error for {}: {}", disk_name, e))?;
         }
 
+        // UEFI NVRAM (boot entries, Secure Boot keys)
+        if std::path::Path::new(&nvram_path).exists() {
+            zip.start_file("nvram/efivars.fd", options)
+                .map_err(|e| format!("ZIP error: {}", e))?;
+            let mut nvram_file = std::fs::File::open(&nvram_path)
+                .map_err(|e| format!("Failed to read NVRAM: {}", e))?;
+            std::io::copy(&mut nvram_file, &mut zip)
+                .map_err(|e| format!("ZIP write error for NVRAM: {}", e))?;
+        }
+
+        // swtpm state (BitLocker keys)
+        zip_tpm_state(&mut zip, options, &tpm_smac, "tpm/")?;
+
         // Clean up temp flattened files
         for p in &tmp_flattened {
             let _ = std::fs::remove_file(p);
@@ -2270,6 +2683,15 @@ async fn import_vm_handler(
             disk_names.push(base.to_string());
         }
 
+        // Extract NVRAM if exists
+        if let Ok(mut entry) = archive.by_name("nvram/efivars.fd") {
+            let mut out_file = std::fs::File::create(operations::nvram_file(&smac))
+                .map_err(|e| format!("Failed to create NVRAM: {}", e))?;
+            std::io::copy(&mut entry, &mut out_file)
+                .map_err(|e| format!("Failed to extract NVRAM: {}", e))?;
+        }
+        unzip_tpm_state(&mut archive, "tpm/", &smac)?;
+
         // Create DB entries
         let config_str = serde_json::to_string(&config).unwrap_or_default();
         let mac_str = config
@@ -2510,7 +2932,7 @@ async fn export_group_handler(
             }
 
             // Write NVRAM if exists
-            let nvram_path = format!("{}/{}_efivars.fd", dp, smac);
+            let nvram_path = operations::nvram_file(smac);
             if std::path::Path::new(&nvram_path).exists() {
                 zip.start_file(format!("vms/{}/{}_efivars.fd", smac, smac), options)
                     .map_err(|e| format!("ZIP error: {}", e))?;
@@ -2519,6 +2941,9 @@ async fn export_group_handler(
                 std::io::copy(&mut nvram_file, &mut zip)
                     .map_err(|e| format!("ZIP write error for NVRAM {}: {}", smac, e))?;
             }
+
+            // Write TPM state if exists
+            zip_tpm_state(&mut zip, options, smac, &format!("vms/{}/tpm/", smac))?;
         }
 
         // Clean up temp flattened files
@@ -2786,13 +3211,16 @@ async fn import_group_handler(
             // Extract NVRAM if exists
             let nvram_entry_name = format!("vms/{}/{}_efivars.fd", orig_smac, orig_smac);
             if let Ok(mut entry) = archive.by_name(&nvram_entry_name) {
-                let nvram_dest = format!("{}/{}_efivars.fd", dp, smac);
+                let nvram_dest = operations::nvram_file(&smac);
                 let mut out_file = std::fs::File::create(&nvram_dest)
                     .map_err(|e| format!("Failed to create NVRAM: {}", e))?;
                 std::io::copy(&mut entry, &mut out_file)
                     .map_err(|e| format!("Failed to extract NVRAM: {}", e))?;
             }
 
+            // Extract TPM state if exists
+            unzip_tpm_state(&mut archive, &format!("vms/{}/tpm/", orig_smac), &smac)?;
+
             // Create DB entries
             let config_str = serde_json::to_string(&config).unwrap_or_default();
             let mac_str = config
@@ -3341,6 +3769,24 @@ async fn list_os_templates_handler() -> HttpResponse {
     }
 }
 
+/// Template fields from a create/update request, with the form's defaults
+fn os_template_from_body(body: &serde_json::Value, id: i64, key: String) -> crate::db::OsTemplate {
+    let field = |name: &str, default: &str| -> String {
+        body.get(name).and_then(|v| v.as_str()).unwrap_or(default).to_string()
+    };
+    crate::db::OsTemplate {
+        id,
+        name: field("name", &key),
+        vcpus: field("vcpus", "2"),
+        memory: field("memory", "2048"),
+        is_windows: field("is_windows", "0"),
+        arch: field("arch", "x86_64"),
+        image: field("image", ""),
+        unattend: field("unattend", "0"),
+        key,
+    }
+}
+
 async fn create_os_template_handler(body: web::Json<serde_json::Value>) -> HttpResponse {
     let key = match body.get("key").and_then(|v| v.as_str()) {
         Some(k) if !k.is_empty() => k.to_string(),
@@ -3348,13 +3794,9 @@ async fn create_os_template_handler(body: web::Json<serde_json::Value>) -> HttpR
             success: false, message: "Missing 'key'".into(), output: None,
         }),
     };
-    let name = body.get("name").and_then(|v| v.as_str()).unwrap_or(&key).to_string();
-    let vcpus = body.get("vcpus").and_then(|v| v.as_str()).unwrap_or("2").to_string();
-    let memory = body.get("memory").and_then(|v| v.as_str()).unwrap_or("2048").to_string();
-    let is_windows = body.get("is_windows").and_then(|v| v.as_str()).unwrap_or("0").to_string();
-    let arch = body.get("arch").and_then(|v| v.as_str()).unwrap_or("x86_64").to_string();
-    let image = body.get("image").and_then(|v| v.as_str()).unwrap_or("").to_string();
-    match crate::db::create_os_template(&key, &name, &vcpus, &memory, &is_windows, &arch, &image) {
+    let tpl = os_template_from_body(&body, 0, key);
+    let name = tpl.name.clone();
+    match crate::db::create_os_template(&tpl) {
         Ok(id) => HttpResponse::Ok().json(serde_json::json!({
             "success": true, "message": format!("Template '{}' created", name), "id": id
         })),
@@ -3377,13 +3819,9 @@ async fn update_os_template_handler(body: web::Json<serde_json::Value>) -> HttpR
             success: false, message: "Missing 'key'".into(), output: None,
         }),
     };
-    let name = body.get("name").and_then(|v| v.as_str()).unwrap_or(&key).to_string();
-    let vcpus = body.get("vcpus").and_then(|v| v.as_str()).unwrap_or("2").to_string();
-    let memory = body.get("memory").and_then(|v| v.as_str()).unwrap_or("2048").to_string();
-    let is_windows = body.get("is_windows").and_then(|v| v.as_str()).unwrap_or("0").to_string();
-    let arch = body.get("arch").and_then(|v| v.as_str()).unwrap_or("x86_64").to_string();
-    let image = body.get("image").and_then(|v| v.as_str()).unwrap_or("").to_string();
-    match crate::db::update_os_template(id, &key, &name, &vcpus, &memory, &is_windows, &arch, &image) {
+    let tpl = os_template_from_body(&body, id, key);
+    let name = tpl.name.clone();
+    match crate::db::update_os_template(&tpl) {
         Ok(_) => HttpResponse::Ok().json(ApiResponse {
             success: true, message: format!("Template '{}' updated", name), output: None,
         }),
@@ -3882,55 +4320,1042 @@ async fn set_internal_ip_handler(body: web::Json<serde_json::Value>) -> HttpResp
     }
 }
 
-// ── Port Forwarding ──
+// ── Disk Hot-plug ──
 
-async fn get_port_forwards_handler(path: web::Path<String>) -> HttpResponse {
+async fn attach_disk_handler(
+    path: web::Path<String>,
+    body: web::Json<serde_json::Value>,
+) -> HttpResponse {
     let smac = path.into_inner();
-    if let Err(e) = crate::ssh::sanitize_name(&smac) {
-        return HttpResponse::BadRequest().json(ApiResponse {
-            success: false,
-            message: format!("Invalid VM name: {}", e),
-            output: None,
-        });
-    }
-    match crate::db::get_vm(&smac) {
-        Ok(vm) => {
-            let config: serde_json::Value = serde_json::from_str(&vm.config).unwrap_or_default();
-            let forwards = config.get("port_forwards").cloned()
-                .unwrap_or_else(|| serde_json::json!([]));
-            HttpResponse::Ok().json(serde_json::json!({
-                "success": true,
-                "vm_name": smac,
-                "port_forwards": forwards,
-            }))
-        }
-        Err(e) => HttpResponse::NotFound().json(ApiResponse {
-            success: false,
-            message: e,
-            output: None,
+    let diskname = match body.get("diskname").and_then(|v| v.as_str()) {
+        Some(n) if !n.is_empty() => n.to_string(),
+        _ => return HttpResponse::BadRequest().json(ApiResponse {
+            success: false, message: "Missing 'diskname'".into(), output: None,
+        }),
+    };
+    let result = web::block(move || operations::attach_disk(&smac, &diskname, &body)).await;
+    match result {
+        Ok(Ok(output)) => HttpResponse::Ok().json(ApiResponse {
+            success: true, message: "Disk attached".into(), output: Some(output),
+        }),
+        Ok(Err(e)) => HttpResponse::BadRequest().json(ApiResponse {
+            success: false, message: e, output: None,
+        }),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse {
+            success: false, message: format!("Internal error: {}", e), output: None,
         }),
     }
 }
 
-async fn add_port_forward_handler(
+async fn detach_disk_handler(
     path: web::Path<String>,
     body: web::Json<serde_json::Value>,
 ) -> HttpResponse {
     let smac = path.into_inner();
-    if let Err(e) = crate::ssh::sanitize_name(&smac) {
-        return HttpResponse::BadRequest().json(ApiResponse {
-            success: false,
-            message: format!("Invalid VM name: {}", e),
-            output: None,
-        });
+    let diskname = match body.get("diskname").and_then(|v| v.as_str()) {
+        Some(n) if !n.is_empty() => n.to_string(),
+        _ => return HttpResponse::BadRequest().json(ApiResponse {
+            success: false, message: "Missing 'diskname'".into(), output: None,
+        }),
+    };
+    let result = web::block(move || operations::detach_disk(&smac, &diskname)).await;
+    match result {
+        Ok(Ok(output)) => HttpResponse::Ok().json(ApiResponse {
+            success: true, message: "Disk detached".into(), output: Some(output),
+        }),
+        Ok(Err(e)) => HttpResponse::BadRequest().json(ApiResponse {
+            success: false, message: e, output: None,
+        }),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse {
+            success: false, message: format!("Internal error: {}", e), output: None,
+        }),
     }
+}
 
-    let protocol = body.get("protocol").and_then(|v| v.as_str()).unwrap_or("tcp").to_string();
-    let host_port = body.get("host_port").and_then(|v| v.as_u64()).unwrap_or(0) as u16;
-    let guest_port = body.get("guest_port").and_then(|v| v.as_u64()).unwrap_or(0) as u16;
+// ── NIC Hot-plug ──
 
-    if host_port == 0 || guest_port == 0 {
-        return HttpResponse::BadRequest().json(ApiResponse {
+/// Adapter id from the request body — accepted as string or number
+fn nic_netid_param(body: &serde_json::Value) -> Option<String> {
+    match body.get("netid") {
+        Some(serde_json::Value::String(s)) if !s.is_empty() => Some(s.clone()),
+        Some(serde_json::Value::Number(n)) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+async fn attach_nic_handler(
+    path: web::Path<String>,
+    body: web::Json<serde_json::Value>,
+) -> HttpResponse {
+    let smac = path.into_inner();
+    let result = web::block(move || operations::attach_nic(&smac, &body)).await;
+    match result {
+        Ok(Ok(output)) => HttpResponse::Ok().json(ApiResponse {
+            success: true, message: "Adapter added".into(), output: Some(output),
+        }),
+        Ok(Err(e)) => HttpResponse::BadRequest().json(ApiResponse {
+            success: false, message: e, output: None,
+        }),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse {
+            success: false, message: format!("Internal error: {}", e), output: None,
+        }),
+    }
+}
+
+async fn detach_nic_handler(
+    path: web::Path<String>,
+    body: web::Json<serde_json::Value>,
+) -> HttpResponse {
+    let smac = path.into_inner();
+    let netid = match nic_netid_param(&body) {
+        Some(n) => n,
+        None => return HttpResponse::BadRequest().json(ApiResponse {
+            success: false, message: "Missing 'netid'".into(), output: None,
+        }),
+    };
+    let result = web::block(move || operations::detach_nic(&smac, &netid)).await;
+    match result {
+        Ok(Ok(output)) => HttpResponse::Ok().json(ApiResponse {
+            success: true, message: "Adapter removed".into(), output: Some(output),
+        }),
+        Ok(Err(e)) => HttpResponse::BadRequest().json(ApiResponse {
+            success: false, message: e, output: None,
+        }),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse {
+            success: false, message: format!("Internal error: {}", e), output: None,
+        }),
+    }
+}
+
+async fn nic_link_handler(
+    path: web::Path<String>,
+    body: web::Json<serde_json::Value>,
+) -> HttpResponse {
+    let smac = path.into_inner();
+    let netid = match nic_netid_param(&body) {
+        Some(n) => n,
+        None => return HttpResponse::BadRequest().json(ApiResponse {
+            success: false, message: "Missing 'netid'".into(), output: None,
+        }),
+    };
+    let up = match body.get("up").and_then(|v| v.as_bool()) {
+        Some(u) => u,
+        None => return HttpResponse::BadRequest().json(ApiResponse {
+            success: false, message: "Missing boolean 'up'".into(), output: None,
+        }),
+    };
+    let result = web::block(move || operations::set_nic_link(&smac, &netid, up)).await;
+    match result {
+        Ok(Ok(output)) => HttpResponse::Ok().json(ApiResponse {
+            success: true, message: "Link updated".into(), output: Some(output),
+        }),
+        Ok(Err(e)) => HttpResponse::BadRequest().json(ApiResponse {
+            success: false, message: e, output: None,
+        }),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse {
+            success: false, message: format!("Internal error: {}", e), output: None,
+        }),
+    }
+}
+
+async fn update_nic_handler(
+    path: web::Path<String>,
+    body: web::Json<serde_json::Value>,
+) -> HttpResponse {
+    let smac = path.into_inner();
+    let netid = match nic_netid_param(&body) {
+        Some(n) => n,
+        None => return HttpResponse::BadRequest().json(ApiResponse {
+            success: false, message: "Missing 'netid'".into(), output: None,
+        }),
+    };
+    let result = web::block(move || operations::update_nic(&smac, &netid, &body)).await;
+    match result {
+        Ok(Ok(output)) => HttpResponse::Ok().json(ApiResponse {
+            success: true, message: "Adapter updated".into(), output: Some(output),
+        }),
+        Ok(Err(e)) => HttpResponse::BadRequest().json(ApiResponse {
+            success: false, message: e, output: None,
+        }),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse {
+            success: false, message: format!("Internal error: {}", e), output: None,
+        }),
+    }
+}
+
+// ── USB Passthrough ──
+
+async fn attach_usb_handler(
+    path: web::Path<String>,
+    body: web::Json<serde_json::Value>,
+) -> HttpResponse {
+    let smac = path.into_inner();
+    let result = web::block(move || operations::attach_usb(&smac, &body)).await;
+    match result {
+        Ok(Ok(output)) => HttpResponse::Ok().json(ApiResponse {
+            success: true, message: "USB device attached".into(), output: Some(output),
+        }),
+        Ok(Err(e)) => HttpResponse::BadRequest().json(ApiResponse {
+            success: false, message: e, output: None,
+        }),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse {
+            success: false, message: format!("Internal error: {}", e), output: None,
+        }),
+    }
+}
+
+async fn detach_usb_handler(
+    path: web::Path<String>,
+    body: web::Json<serde_json::Value>,
+) -> HttpResponse {
+    let smac = path.into_inner();
+    let usbid = match body.get("usbid") {
+        Some(serde_json::Value::String(s)) if !s.is_empty() => s.clone(),
+        Some(serde_json::Value::Number(n)) => n.to_string(),
+        _ => return HttpResponse::BadRequest().json(ApiResponse {
+            success: false, message: "Missing 'usbid'".into(), output: None,
+        }),
+    };
+    let result = web::block(move || operations::detach_usb(&smac, &usbid)).await;
+    match result {
+        Ok(Ok(output)) => HttpResponse::Ok().json(ApiResponse {
+            success: true, message: "USB device detached".into(), output: Some(output),
+        }),
+        Ok(Err(e)) => HttpResponse::BadRequest().json(ApiResponse {
+            success: false, message: e, output: None,
+        }),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse {
+            success: false, message: format!("Internal error: {}", e), output: None,
+        }),
+    }
+}
+
+// ── CPU / Memory Hot-add ──
+
+/// Numeric body field — accepted as number or numeric string
+fn body_number(body: &serde_json::Value, key: &str) -> Option<u64> {
+    match body.get(key) {
+        Some(serde_json::Value::Number(n)) => n.as_u64(),
+        Some(serde_json::Value::String(s)) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+async fn set_vcpus_handler(
+    path: web::Path<String>,
+    body: web::Json<serde_json::Value>,
+) -> HttpResponse {
+    let smac = path.into_inner();
+    let value = match body_number(&body, "vcpus").map(u32::try_from) {
+        Some(Ok(v)) => v,
+        Some(Err(_)) => return HttpResponse::BadRequest().json(ApiResponse {
+            success: false, message: "'vcpus' is out of range".into(), output: None,
+        }),
+        None => return HttpResponse::BadRequest().json(ApiResponse {
+            success: false, message: "Missing numeric 'vcpus' (a non-negative integer)".into(), output: None,
+        }),
+    };
+    let result = web::block(move || operations::set_vcpus(&smac, value)).await;
+    match result {
+        Ok(Ok(output)) => HttpResponse::Ok().json(ApiResponse {
+            success: true, message: "vCPUs updated".into(), output: Some(output),
+        }),
+        Ok(Err(e)) => HttpResponse::BadRequest().json(ApiResponse {
+            success: false, message: e, output: None,
+        }),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse {
+            success: false, message: format!("Internal error: {}", e), output: None,
+        }),
+    }
+}
+
+async fn set_memory_handler(
+    path: web::Path<String>,
+    body: web::Json<serde_json::Value>,
+) -> HttpResponse {
+    let smac = path.into_inner();
+    let value = match body_number(&body, "size") {
+        Some(v) => v,
+        None => return HttpResponse::BadRequest().json(ApiResponse {
+            success: false, message: "Missing numeric 'size'".into(), output: None,
+        }),
+    };
+    let result = web::block(move || operations::set_memory(&smac, value)).await;
+    match result {
+        Ok(Ok(output)) => HttpResponse::Ok().json(ApiResponse {
+            success: true, message: "Memory updated".into(), output: Some(output),
+        }),
+        Ok(Err(e)) => HttpResponse::BadRequest().json(ApiResponse {
+            success: false, message: e, output: None,
+        }),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse {
+            success: false, message: format!("Internal error: {}", e), output: None,
+        }),
+    }
+}
+
+async fn set_balloon_handler(
+    path: web::Path<String>,
+    body: web::Json<serde_json::Value>,
+) -> HttpResponse {
+    let smac = path.into_inner();
+    let value = match body_number(&body, "target") {
+        Some(v) => v,
+        None => return HttpResponse::BadRequest().json(ApiResponse {
+            success: false, message: "Missing numeric 'target'".into(), output: None,
+        }),
+    };
+    let result = web::block(move || operations::set_balloon(&smac, value)).await;
+    match result {
+        Ok(Ok(output)) => HttpResponse::Ok().json(ApiResponse {
+            success: true, message: "Balloon target set".into(), output: Some(output),
+        }),
+        Ok(Err(e)) => HttpResponse::BadRequest().json(ApiResponse {
+            success: false, message: e, output: None,
+        }),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse {
+            success: false, message: format!("Internal error: {}", e), output: None,
+        }),
+    }
+}
+
+async fn balloon_info_handler(path: web::Path<String>) -> HttpResponse {
+    let smac = path.into_inner();
+    let result = web::block(move || operations::balloon_info(&smac)).await;
+    match result {
+        Ok(Ok(info)) => HttpResponse::Ok().json(info),
+        Ok(Err(e)) => HttpResponse::BadRequest().json(ApiResponse {
+            success: false, message: e, output: None,
+        }),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse {
+            success: false, message: format!("Internal error: {}", e), output: None,
+        }),
+    }
+}
+
+// ── cgroup Metrics ──
+
+async fn vm_metrics_handler(path: web::Path<String>) -> HttpResponse {
+    let smac = path.into_inner();
+    if let Err(e) = crate::ssh::sanitize_name(&smac) {
+        return HttpResponse::BadRequest().json(ApiResponse {
+            success: false, message: e, output: None,
+        });
+    }
+    let result = web::block(move || crate::cgroups::stats(&smac)).await;
+    match result {
+        Ok(Ok(stats)) => HttpResponse::Ok().json(stats),
+        Ok(Err(e)) => HttpResponse::BadRequest().json(ApiResponse {
+            success: false, message: e, output: None,
+        }),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse {
+            success: false, message: format!("Internal error: {}", e), output: None,
+        }),
+    }
+}
+
+// ── Serial Console ──
+
+/// WebSocket bridged to the VM's serial port. The scrollback is sent first as
+/// one binary frame, then live output; text or binary frames from the client
+/// are typed into the guest.
+async fn console_ws_handler(
+    req: actix_web::HttpRequest,
+    path: web::Path<String>,
+    payload: web::Payload,
+) -> Result<HttpResponse, actix_web::Error> {
+    let smac = path.into_inner();
+    if let Err(e) = crate::ssh::sanitize_name(&smac) {
+        return Ok(HttpResponse::BadRequest().json(ApiResponse {
+            success: false, message: e, output: None,
+        }));
+    }
+    let hub = match web::block(move || crate::console::attach(&smac)).await {
+        Ok(Ok(hub)) => hub,
+        Ok(Err(e)) => return Ok(HttpResponse::BadRequest().json(ApiResponse {
+            success: false, message: e, output: None,
+        })),
+        Err(e) => return Ok(HttpResponse::InternalServerError().json(ApiResponse {
+            success: false, message: format!("Internal error: {}", e), output: None,
+        })),
+    };
+    let (response, mut session, mut messages) = actix_ws::handle(&req, payload)?;
+    let (scrollback, mut output) = hub.subscribe();
+
+    actix_web::rt::spawn(async move {
+        if !scrollback.is_empty() && session.binary(scrollback).await.is_err() {
+            return;
+        }
+        loop {
+            tokio::select! {
+                data = output.recv() => match data {
+                    Some(data) => {
+                        if session.binary(data).await.is_err() {
+                            return;
+                        }
+                    }
+                    // Hub closed: QEMU exited or the VM was stopped
+                    None => break,
+                },
+                msg = messages.recv() => match msg {
+                    Some(Ok(actix_ws::Message::Text(text))) => {
+                        if console_write(&hub, text.as_bytes().to_vec()).await.is_err() {
+                            break;
+                        }
+                    }
+                    Some(Ok(actix_ws::Message::Binary(bytes))) => {
+                        if console_write(&hub, bytes.to_vec()).await.is_err() {
+                            break;
+                        }
+                    }
+                    Some(Ok(actix_ws::Message::Ping(p))) => {
+                        if session.pong(&p).await.is_err() {
+                            return;
+                        }
+                    }
+                    Some(Ok(actix_ws::Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => {}
+                },
+            }
+        }
+        let _ = session.close(None).await;
+    });
+    Ok(response)
+}
+
+/// Serial input on a blocking thread, so a guest that stops reading does not
+/// stall the runtime
+async fn console_write(hub: &std::sync::Arc<crate::console::ConsoleHub>, data: Vec<u8>) -> Result<(), String> {
+    let hub = hub.clone();
+    web::block(move || hub.write(&data))
+        .await
+        .map_err(|e| format!("Internal error: {}", e))?
+}
+
+async fn console_log_handler(path: web::Path<String>) -> HttpResponse {
+    let smac = path.into_inner();
+    if let Err(e) = crate::ssh::sanitize_name(&smac) {
+        return HttpResponse::BadRequest().json(ApiResponse {
+            success: false, message: e, output: None,
+        });
+    }
+    let result = web::block(move || crate::console::read_log(&smac)).await;
+    match result {
+        Ok(Ok(log)) => HttpResponse::Ok().content_type("text/plain; charset=utf-8").body(log),
+        Ok(Err(e)) => HttpResponse::NotFound().json(ApiResponse {
+            success: false, message: e, output: None,
+        }),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse {
+            success: false, message: format!("Internal error: {}", e), output: None,
+        }),
+    }
+}
+
+// ── Screenshots ──
+
+async fn screenshot_handler(path: web::Path<String>) -> HttpResponse {
+    let smac = path.into_inner();
+    png_response(web::block(move || operations::capture_screenshot(&smac)).await)
+}
+
+async fn thumbnail_handler(path: web::Path<String>) -> HttpResponse {
+    let smac = path.into_inner();
+    let max_age = std::time::Duration::from_secs(
+        get_conf_or("thumbnail_max_age", "30").parse().unwrap_or(30),
+    );
+    png_response(web::block(move || operations::vm_thumbnail(&smac, max_age)).await)
+}
+
+fn png_response(result: Result<Result<Vec<u8>, String>, actix_web::error::BlockingError>) -> HttpResponse {
+    match result {
+        Ok(Ok(png)) => HttpResponse::Ok()
+            .content_type("image/png")
+            .insert_header(("Cache-Control", "no-store"))
+            .body(png),
+        Ok(Err(e)) => HttpResponse::BadRequest().json(ApiResponse {
+            success: false, message: e, output: None,
+        }),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse {
+            success: false, message: format!("Internal error: {}", e), output: None,
+        }),
+    }
+}
+
+// ── Session Recording ──
+
+async fn recording_start_handler(req: actix_web::HttpRequest, path: web::Path<String>) -> HttpResponse {
+    let smac = path.into_inner();
+    let user = request_user(&req);
+    op_response(web::block(move || crate::recording::start(&smac, &user, true)).await, "Recording started")
+}
+
+async fn recording_stop_handler(path: web::Path<String>) -> HttpResponse {
+    let smac = path.into_inner();
+    op_response(web::block(move || crate::recording::stop(&smac)).await, "Recording stopping")
+}
+
+async fn list_recordings_handler(query: web::Query<HashMap<String, String>>) -> HttpResponse {
+    let smac = query.get("smac").cloned();
+    match web::block(move || crate::recording::list(smac.as_deref())).await {
+        Ok(list) => HttpResponse::Ok().json(list),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse {
+            success: false, message: format!("Internal error: {}", e), output: None,
+        }),
+    }
+}
+
+async fn get_recording_handler(path: web::Path<String>) -> HttpResponse {
+    let id = path.into_inner();
+    match web::block(move || crate::recording::get(&id)).await {
+        Ok(Ok(meta)) => HttpResponse::Ok().json(meta),
+        Ok(Err(e)) => HttpResponse::NotFound().json(ApiResponse {
+            success: false, message: e, output: None,
+        }),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse {
+            success: false, message: format!("Internal error: {}", e), output: None,
+        }),
+    }
+}
+
+async fn recording_frame_handler(path: web::Path<(String, usize)>) -> HttpResponse {
+    let (id, index) = path.into_inner();
+    png_response(web::block(move || crate::recording::frame(&id, index)).await)
+}
+
+async fn download_recording_handler(req: actix_web::HttpRequest, path: web::Path<String>) -> HttpResponse {
+    let id = path.into_inner();
+    let file_path = match crate::recording::archive_path(&id) {
+        Ok(p) => p,
+        Err(e) => return HttpResponse::NotFound().json(ApiResponse {
+            success: false, message: e, output: None,
+        }),
+    };
+    match fs::NamedFile::open(&file_path) {
+        Ok(f) => f
+            .set_content_disposition(actix_web::http::header::ContentDisposition {
+                disposition: actix_web::http::header::DispositionType::Attachment,
+                parameters: vec![actix_web::http::header::DispositionParam::Filename(format!("{}.zip", id))],
+            })
+            .into_response(&req),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse {
+            success: false, message: format!("Open recording: {}", e), output: None,
+        }),
+    }
+}
+
+async fn delete_recording_handler(body: web::Json<serde_json::Value>) -> HttpResponse {
+    let id = match body.get("id").and_then(|v| v.as_str()) {
+        Some(id) => id.to_string(),
+        None => return HttpResponse::BadRequest().json(ApiResponse {
+            success: false, message: "Missing 'id'".into(), output: None,
+        }),
+    };
+    op_response(web::block(move || crate::recording::delete(&id)).await, "Recording deleted")
+}
+
+fn op_response(
+    result: Result<Result<String, String>, actix_web::error::BlockingError>,
+    message: &str,
+) -> HttpResponse {
+    match result {
+        Ok(Ok(output)) => HttpResponse::Ok().json(ApiResponse {
+            success: true, message: message.into(), output: Some(output),
+        }),
+        Ok(Err(e)) => HttpResponse::BadRequest().json(ApiResponse {
+            success: false, message: e, output: None,
+        }),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse {
+            success: false, message: format!("Internal error: {}", e), output: None,
+        }),
+    }
+}
+
+// ── Guest Agent Operations ──
+
+/// Serialize a guest-agent result as-is, or an ApiResponse error
+fn guest_json<T: serde::Serialize>(result: Result<Result<T, String>, actix_web::error::BlockingError>) -> HttpResponse {
+    match result {
+        Ok(Ok(v)) => HttpResponse::Ok().json(v),
+        Ok(Err(e)) => HttpResponse::BadRequest().json(ApiResponse {
+            success: false, message: e, output: None,
+        }),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse {
+            success: false, message: format!("Internal error: {}", e), output: None,
+        }),
+    }
+}
+
+/// Run a program in the guest and wait for it (`timeout` seconds, default 30)
+async fn guest_exec_handler(path: web::Path<String>, body: web::Json<serde_json::Value>) -> HttpResponse {
+    let smac = path.into_inner();
+    let strings = |key: &str| -> Vec<String> {
+        body.get(key)
+            .and_then(|v| v.as_array())
+            .map(|a| a.iter().filter_map(|x| x.as_str().map(String::from)).collect())
+            .unwrap_or_default()
+    };
+    let program = body.get("path").and_then(|v| v.as_str()).unwrap_or("").to_string();
+    let args = strings("args");
+    let env = strings("env");
+    let input = body.get("input").and_then(|v| v.as_str()).map(|s| s.as_bytes().to_vec());
+    let timeout = body.get("timeout").and_then(|v| v.as_u64()).unwrap_or(30).clamp(1, 3600);
+    guest_json(
+        web::block(move || {
+            crate::guest_agent::guest_exec(
+                &smac,
+                &program,
+                &args,
+                &env,
+                input.as_deref(),
+                std::time::Duration::from_secs(timeout),
+            )
+        })
+        .await,
+    )
+}
+
+/// Status and output of an earlier exec that timed out
+async fn guest_exec_status_handler(path: web::Path<(String, i64)>) -> HttpResponse {
+    let (smac, pid) = path.into_inner();
+    guest_json(web::block(move || crate::guest_agent::guest_exec_status(&smac, pid)).await)
+}
+
+/// Download a file from the guest (`?path=`)
+async fn guest_file_read_handler(
+    path: web::Path<String>,
+    query: web::Query<HashMap<String, String>>,
+) -> HttpResponse {
+    let smac = path.into_inner();
+    let guest_path = match query.get("path") {
+        Some(p) if !p.is_empty() => p.clone(),
+        _ => {
+            return HttpResponse::BadRequest().json(ApiResponse {
+                success: false, message: "Missing 'path' query parameter".into(), output: None,
+            });
+        }
+    };
+    let max_mb: u64 = get_conf_or("guest_file_read_max_mb", "256").parse().unwrap_or(256);
+    let gp = guest_path.clone();
+    match web::block(move || crate::guest_agent::guest_file_read(&smac, &gp, max_mb * 1024 * 1024)).await {
+        Ok(Ok(data)) => {
+            let filename = guest_path
+                .rsplit(['/', '\\'])
+                .next()
+                .filter(|n| !n.is_empty())
+                .unwrap_or("download")
+                .replace('"', "");
+            HttpResponse::Ok()
+                .content_type("application/octet-stream")
+                .insert_header((
+                    "Content-Disposition",
+                    format!("attachment; filename=\"{}\"", filename),
+                ))
+                .body(data)
+        }
+        Ok(Err(e)) => HttpResponse::BadRequest().json(ApiResponse {
+            success: false, message: e, output: None,
+        }),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse {
+            success: false, message: format!("Internal error: {}", e), output: None,
+        }),
+    }
+}
+
+async fn guest_network_handler(path: web::Path<String>) -> HttpResponse {
+    let smac = path.into_inner();
+    guest_json(web::block(move || crate::guest_agent::guest_network_interfaces(&smac)).await)
+}
+
+async fn guest_osinfo_handler(path: web::Path<String>) -> HttpResponse {
+    let smac = path.into_inner();
+    guest_json(web::block(move || crate::guest_agent::guest_osinfo(&smac)).await)
+}
+
+async fn guest_users_handler(path: web::Path<String>) -> HttpResponse {
+    let smac = path.into_inner();
+    guest_json(web::block(move || crate::guest_agent::guest_users(&smac)).await)
+}
+
+async fn guest_fsinfo_handler(path: web::Path<String>) -> HttpResponse {
+    let smac = path.into_inner();
+    guest_json(web::block(move || crate::guest_agent::guest_fsinfo(&smac)).await)
+}
+
+/// Set a guest account's password (`username`, `password`, optional `crypted`)
+async fn guest_password_handler(path: web::Path<String>, body: web::Json<serde_json::Value>) -> HttpResponse {
+    let smac = path.into_inner();
+    let username = body.get("username").and_then(|v| v.as_str()).unwrap_or("").to_string();
+    let password = body.get("password").and_then(|v| v.as_str()).unwrap_or("").to_string();
+    let crypted = body.get("crypted").and_then(|v| v.as_bool()).unwrap_or(false);
+    let message = format!("Password set for '{}'", username);
+    op_response(
+        web::block(move || {
+            crate::guest_agent::guest_set_user_password(&smac, &username, &password, crypted)
+                .map(|_| String::new())
+        })
+        .await,
+        &message,
+    )
+}
+
+/// Outcome of Windows provisioning (null if it has not run)
+async fn guest_provision_status_handler(path: web::Path<String>) -> HttpResponse {
+    let smac = path.into_inner();
+    guest_json(web::block(move || crate::db::get_guest_provision(&smac)).await)
+}
+
+/// Run Windows provisioning now, also when it already ran or failed
+async fn guest_provision_handler(path: web::Path<String>) -> HttpResponse {
+    let smac = path.into_inner();
+    op_response(
+        web::block(move || {
+            let result = operations::provision_windows(&smac);
+            let (state, message) = match &result {
+                Ok(out) => ("done", out.clone()),
+                Err(e) => ("failed", e.clone()),
+            };
+            crate::db::set_guest_provision(&smac, state, message.trim())?;
+            result
+        })
+        .await,
+        "Windows provisioning finished",
+    )
+}
+
+// ── Guest File Transfers ──
+
+fn valid_upload_path(guest_path: &str) -> Result<(), HttpResponse> {
+    if guest_path.contains("..") || (!guest_path.starts_with('/') && !guest_path.contains(':')) {
+        return Err(HttpResponse::BadRequest().json(ApiResponse {
+            success: false,
+            message: "Guest path must be absolute and not contain '..'".into(),
+            output: None,
+        }));
+    }
+    Ok(())
+}
+
+/// Create an upload job before sending its body (`path`, `size`, or
+/// `resume` with the id of a failed upload), so its progress can be polled
+/// while the body is posted with `?job={id}`
+async fn guest_upload_create_handler(path: web::Path<String>, body: web::Json<serde_json::Value>) -> HttpResponse {
+    let smac = path.into_inner();
+    let guest_path = body.get("path").and_then(|v| v.as_str()).unwrap_or("").to_string();
+    let total = body.get("size").and_then(|v| v.as_u64());
+    let resume = body.get("resume").and_then(|v| v.as_str()).filter(|r| !r.is_empty()).map(String::from);
+    if resume.is_none() {
+        if let Err(resp) = valid_upload_path(&guest_path) {
+            return resp;
+        }
+        if guest_path.ends_with('/') || guest_path.ends_with('\\') {
+            return HttpResponse::BadRequest().json(ApiResponse {
+                success: false, message: "Give the full guest file path".into(), output: None,
+            });
+        }
+    }
+    guest_json(
+        web::block(move || crate::transfer::create_upload(&smac, &guest_path, total, resume.as_deref())).await,
+    )
+}
+
+/// Upload a file into the guest through the agent, streamed from the first
+/// file field of a multipart body. `job` is a job from `/guest/upload/create`.
+/// Without it the job is created here: `path` is the guest file (or a
+/// directory ending in a separator, the upload's filename is appended);
+/// `size` is the expected total for progress; `resume` continues a failed
+/// upload, whose body must then start at the job's `transferred` offset.
+async fn guest_upload_handler(
+    path: web::Path<String>,
+    query: web::Query<HashMap<String, String>>,
+    mut payload: actix_multipart::Multipart,
+) -> HttpResponse {
+    use futures_util::StreamExt;
+    use crate::transfer::UploadChunk;
+
+    let smac = path.into_inner();
+    let job_id = query.get("job").filter(|j| !j.is_empty()).cloned();
+    let resume = query.get("resume").filter(|r| !r.is_empty()).cloned();
+    let total = query.get("size").and_then(|s| s.parse::<u64>().ok());
+    let mut guest_path = query.get("path").cloned().unwrap_or_default();
+    if job_id.is_none() && resume.is_none() {
+        if let Err(resp) = valid_upload_path(&guest_path) {
+            return resp;
+        }
+    }
+
+    let mut field = loop {
+        match payload.next().await {
+            Some(Ok(f)) if f.content_disposition().and_then(|cd| cd.get_filename()).is_some() => break f,
+            Some(Ok(_)) => continue,
+            Some(Err(e)) => {
+                return HttpResponse::BadRequest().json(ApiResponse {
+                    success: false, message: format!("Multipart error: {}", e), output: None,
+                });
+            }
+            None => {
+                return HttpResponse::BadRequest().json(ApiResponse {
+                    success: false, message: "No file in upload".into(), output: None,
+                });
+            }
+        }
+    };
+    if job_id.is_none() && resume.is_none() && (guest_path.ends_with('/') || guest_path.ends_with('\\')) {
+        let filename = field
+            .content_disposition()
+            .and_then(|cd| cd.get_filename())
+            .unwrap_or("")
+            .to_string();
+        if filename.is_empty() || filename.contains(['/', '\\']) || filename.contains("..") {
+            return HttpResponse::BadRequest().json(ApiResponse {
+                success: false, message: format!("Invalid filename: {}", filename), output: None,
+            });
+        }
+        guest_path.push_str(&filename);
+    }
+
+    let (job, tx) = match web::block(move || {
+        let id = match job_id {
+            Some(id) => id,
+            None => crate::transfer::create_upload(&smac, &guest_path, total, resume.as_deref())?.id,
+        };
+        crate::transfer::start_upload(&smac, &id)
+    })
+    .await
+    {
+        Ok(Ok(started)) => started,
+        Ok(Err(e)) => {
+            return HttpResponse::BadRequest().json(ApiResponse {
+                success: false, message: e, output: None,
+            });
+        }
+        Err(e) => {
+            return HttpResponse::InternalServerError().json(ApiResponse {
+                success: false, message: format!("Internal error: {}", e), output: None,
+            });
+        }
+    };
+
+    // Forward the body as it arrives; a stream error drops the sender without
+    // `End`, which leaves the job failed and resumable
+    let mut complete = true;
+    while let Some(chunk) = field.next().await {
+        match chunk {
+            Ok(bytes) => {
+                if tx.send(UploadChunk::Data(bytes.to_vec())).await.is_err() {
+                    break; // worker stopped (guest error or cancel)
+                }
+            }
+            Err(_) => {
+                complete = false;
+                break;
+            }
+        }
+    }
+    if complete {
+        let _ = tx.send(UploadChunk::End).await;
+    }
+    drop(tx);
+
+    let id = job.id;
+    loop {
+        let lookup = id.clone();
+        match web::block(move || crate::transfer::get(&lookup)).await {
+            Ok(Ok(job)) if job.state != "running" => {
+                return if job.state == "completed" {
+                    HttpResponse::Ok().json(job)
+                } else {
+                    HttpResponse::BadRequest().json(job)
+                };
+            }
+            Ok(Ok(_)) => tokio::time::sleep(std::time::Duration::from_millis(200)).await,
+            Ok(Err(e)) => {
+                return HttpResponse::InternalServerError().json(ApiResponse {
+                    success: false, message: e, output: None,
+                });
+            }
+            Err(e) => {
+                return HttpResponse::InternalServerError().json(ApiResponse {
+                    success: false, message: format!("Internal error: {}", e), output: None,
+                });
+            }
+        }
+    }
+}
+
+/// Start copying a guest file to the host (`path`, or `resume` with the id of
+/// a failed download); poll the returned job, then fetch `/file`
+async fn guest_download_handler(path: web::Path<String>, body: web::Json<serde_json::Value>) -> HttpResponse {
+    let smac = path.into_inner();
+    let guest_path = body.get("path").and_then(|v| v.as_str()).unwrap_or("").to_string();
+    let resume = body.get("resume").and_then(|v| v.as_str()).map(String::from);
+    guest_json(
+        web::block(move || crate::transfer::start_download(&smac, &guest_path, resume.as_deref())).await,
+    )
+}
+
+async fn list_transfers_handler(query: web::Query<HashMap<String, String>>) -> HttpResponse {
+    let smac = query.get("smac").cloned();
+    match web::block(move || crate::transfer::list(smac.as_deref())).await {
+        Ok(list) => HttpResponse::Ok().json(list),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse {
+            success: false, message: format!("Internal error: {}", e), output: None,
+        }),
+    }
+}
+
+async fn get_transfer_handler(path: web::Path<String>) -> HttpResponse {
+    let id = path.into_inner();
+    match web::block(move || crate::transfer::get(&id)).await {
+        Ok(Ok(job)) => HttpResponse::Ok().json(job),
+        Ok(Err(e)) => HttpResponse::NotFound().json(ApiResponse {
+            success: false, message: e, output: None,
+        }),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse {
+            success: false, message: format!("Internal error: {}", e), output: None,
+        }),
+    }
+}
+
+/// Data of a completed download, named after the guest file
+async fn transfer_file_handler(req: actix_web::HttpRequest, path: web::Path<String>) -> HttpResponse {
+    let id = path.into_inner();
+    let (file_path, guest_path) = match crate::transfer::download_path(&id)
+        .and_then(|p| crate::transfer::get(&id).map(|job| (p, job.guest_path)))
+    {
+        Ok(found) => found,
+        Err(e) => return HttpResponse::NotFound().json(ApiResponse {
+            success: false, message: e, output: None,
+        }),
+    };
+    let filename = guest_path
+        .rsplit(['/', '\\'])
+        .next()
+        .filter(|n| !n.is_empty())
+        .unwrap_or("download")
+        .to_string();
+    match fs::NamedFile::open(&file_path) {
+        Ok(f) => f
+            .set_content_disposition(actix_web::http::header::ContentDisposition {
+                disposition: actix_web::http::header::DispositionType::Attachment,
+                parameters: vec![actix_web::http::header::DispositionParam::Filename(filename)],
+            })
+            .into_response(&req),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse {
+            success: false, message: format!("Open transfer: {}", e), output: None,
+        }),
+    }
+}
+
+async fn cancel_transfer_handler(body: web::Json<serde_json::Value>) -> HttpResponse {
+    let id = match body.get("id").and_then(|v| v.as_str()) {
+        Some(id) => id.to_string(),
+        None => return HttpResponse::BadRequest().json(ApiResponse {
+            success: false, message: "Missing 'id'".into(), output: None,
+        }),
+    };
+    op_response(web::block(move || crate::transfer::cancel(&id)).await, "Transfer cancelling")
+}
+
+async fn delete_transfer_handler(body: web::Json<serde_json::Value>) -> HttpResponse {
+    let id = match body.get("id").and_then(|v| v.as_str()) {
+        Some(id) => id.to_string(),
+        None => return HttpResponse::BadRequest().json(ApiResponse {
+            success: false, message: "Missing 'id'".into(), output: None,
+        }),
+    };
+    op_response(web::block(move || crate::transfer::delete(&id)).await, "Transfer deleted")
+}
+
+// ── Keyboard Input ──
+
+/// Send key combos (`keys`: "ctrl-alt-del", several separated by spaces) or
+/// type `text` with the guest's keyboard `layout`
+async fn sendkeys_handler(path: web::Path<String>, body: web::Json<serde_json::Value>) -> HttpResponse {
+    let smac = path.into_inner();
+    let keys = body.get("keys").and_then(|v| v.as_str()).map(String::from);
+    let text = body.get("text").and_then(|v| v.as_str()).map(String::from);
+    let layout = body
+        .get("layout")
+        .and_then(|v| v.as_str())
+        .map(String::from)
+        .unwrap_or_else(crate::keys::default_layout);
+    let hold_ms = body.get("hold_ms").and_then(|v| v.as_u64()).unwrap_or(100);
+    let delay_ms = body
+        .get("delay_ms")
+        .and_then(|v| v.as_u64())
+        .unwrap_or_else(|| get_conf_or("sendkeys_delay_ms", "20").parse().unwrap_or(20))
+        .min(1000);
+    let result = web::block(move || match (keys, text) {
+        (Some(_), Some(_)) => Err("Give either 'keys' or 'text', not both".to_string()),
+        (Some(keys), None) => crate::keys::send_combos(&smac, &keys, hold_ms)
+            .map(|n| format!("Sent {} key combination(s)", n)),
+        (None, Some(text)) => crate::keys::type_text(&smac, &text, &layout, delay_ms)
+            .map(|n| format!("Typed {} character(s) ({} layout)", n, layout)),
+        (None, None) => Err("Missing 'keys' or 'text' field".to_string()),
+    })
+    .await;
+    match result {
+        Ok(Ok(msg)) => HttpResponse::Ok().json(ApiResponse {
+            success: true, message: msg, output: None,
+        }),
+        Ok(Err(e)) => HttpResponse::BadRequest().json(ApiResponse {
+            success: false, message: e, output: None,
+        }),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse {
+            success: false, message: format!("Internal error: {}", e), output: None,
+        }),
+    }
+}
+
+// ── Port Forwarding ──
+
+async fn get_port_forwards_handler(path: web::Path<String>) -> HttpResponse {
+    let smac = path.into_inner();
+    if let Err(e) = crate::ssh::sanitize_name(&smac) {
+        return HttpResponse::BadRequest().json(ApiResponse {
+            success: false,
+            message: format!("Invalid VM name: {}", e),
+            output: None,
+        });
+    }
+    match crate::db::get_vm(&smac) {
+        Ok(vm) => {
+            let config: serde_json::Value = serde_json::from_str(&vm.config).unwrap_or_default();
+            let forwards = config.get("port_forwards").cloned()
+                .unwrap_or_else(|| serde_json::json!([]));
+            HttpResponse::Ok().json(serde_json::json!({
+                "success": true,
+                "vm_name": smac,
+                "port_forwards": forwards,
+            }))
+        }
+        Err(e) => HttpResponse::NotFound().json(ApiResponse {
+            success: false,
+            message: e,
+            output: None,
+        }),
+    }
+}
+
+async fn add_port_forward_handler(
+    path: web::Path<String>,
+    body: web::Json<serde_json::Value>,
+) -> HttpResponse {
+    let smac = path.into_inner();
+    if let Err(e) = crate::ssh::sanitize_name(&smac) {
+        return HttpResponse::BadRequest().json(ApiResponse {
+            success: false,
+            message: format!("Invalid VM name: {}", e),
+            output: None,
+        });
+    }
+
+    let protocol = body.get("protocol").and_then(|v| v.as_str()).unwrap_or("tcp").to_string();
+    let host_port = body.get("host_port").and_then(|v| v.as_u64()).unwrap_or(0) as u16;
+    let guest_port = body.get("guest_port").and_then(|v| v.as_u64()).unwrap_or(0) as u16;
+
+    if host_port == 0 || guest_port == 0 {
+        return HttpResponse::BadRequest().json(ApiResponse {
             success: false,
             message: "host_port and guest_port are required (non-zero)".into(),
             output: None,
@@ -4007,6 +5432,175 @@ async fn delete_port_forward_handler(
     }
 }
 
+// ── Boot Files ──
+async fn list_boot_files_handler() -> HttpResponse {
+    match web::block(operations::list_boot_files).await {
+        Ok(Ok(files)) => HttpResponse::Ok().json(files),
+        Ok(Err(e)) => HttpResponse::InternalServerError().json(ApiResponse {
+            success: false, message: e, output: None,
+        }),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse {
+            success: false, message: format!("Internal error: {}", e), output: None,
+        }),
+    }
+}
+
+/// Raw body upload of a kernel or initrd into boot_path (name in X-Filename)
+async fn upload_boot_file_handler(
+    req: actix_web::HttpRequest,
+    mut payload: web::Payload,
+) -> HttpResponse {
+    use futures_util::StreamExt;
+
+    let name = req
+        .headers()
+        .get("X-Filename")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("")
+        .to_string();
+    if let Err(e) = crate::ssh::sanitize_name(&name) {
+        return HttpResponse::BadRequest().json(ApiResponse {
+            success: false,
+            message: format!("Invalid X-Filename: {}", e),
+            output: None,
+        });
+    }
+    if name.starts_with('.') {
+        return HttpResponse::BadRequest().json(ApiResponse {
+            success: false,
+            message: "Invalid X-Filename: hidden names are not allowed".into(),
+            output: None,
+        });
+    }
+    // Written to a hidden temp file and renamed over the target only once
+    // complete, so a kernel a VM boots from is never truncated or lost
+    let dir = operations::boot_dir();
+    let dest = format!("{}/{}", dir, name);
+    let tmp = format!("{}/.{}.upload.{}", dir, name, operations::generate_random_password(8));
+    let tmp_for_create = tmp.clone();
+    let created = web::block(move || {
+        let _ = std::fs::create_dir_all(&dir);
+        std::fs::File::create(&tmp_for_create)
+    })
+    .await;
+    let mut file = match created {
+        Ok(Ok(f)) => Some(f),
+        Ok(Err(e)) => {
+            return HttpResponse::InternalServerError().json(ApiResponse {
+                success: false,
+                message: format!("Failed to create file: {}", e),
+                output: None,
+            });
+        }
+        Err(e) => {
+            return HttpResponse::InternalServerError().json(ApiResponse {
+                success: false, message: format!("Internal error: {}", e), output: None,
+            });
+        }
+    };
+    let mut total_size: u64 = 0;
+    let mut failed = None;
+    while let Some(chunk) = payload.next().await {
+        let data = match chunk {
+            Ok(data) => data,
+            Err(e) => {
+                failed = Some(format!("Upload stream error: {}", e));
+                break;
+            }
+        };
+        total_size += data.len() as u64;
+        let Some(mut f) = file.take() else { break };
+        match web::block(move || std::io::Write::write_all(&mut f, &data).map(|_| f)).await {
+            Ok(Ok(f)) => file = Some(f),
+            Ok(Err(e)) => {
+                failed = Some(format!("Failed to write data: {}", e));
+                break;
+            }
+            Err(e) => {
+                failed = Some(format!("Internal error: {}", e));
+                break;
+            }
+        }
+    }
+    drop(file);
+    let tmp_for_finish = tmp.clone();
+    let dest_for_finish = dest.clone();
+    let finished = web::block(move || match failed {
+        Some(e) => Err(e),
+        None => std::fs::rename(&tmp_for_finish, &dest_for_finish)
+            .map_err(|e| format!("Failed to move upload into place: {}", e)),
+    })
+    .await
+    .unwrap_or_else(|e| Err(format!("Internal error: {}", e)));
+    if let Err(e) = finished {
+        let _ = web::block(move || std::fs::remove_file(&tmp)).await;
+        return HttpResponse::InternalServerError().json(ApiResponse {
+            success: false, message: e, output: None,
+        });
+    }
+    HttpResponse::Ok().json(ApiResponse {
+        success: true,
+        message: format!("Uploaded {} ({} bytes)", name, total_size),
+        output: Some(dest),
+    })
+}
+
+async fn delete_boot_file_handler(body: web::Json<serde_json::Value>) -> HttpResponse {
+    let name = body.get("name").and_then(|v| v.as_str()).unwrap_or("").to_string();
+    let result = web::block(move || operations::delete_boot_file(&name)).await;
+    match result {
+        Ok(Ok(msg)) => HttpResponse::Ok().json(ApiResponse {
+            success: true, message: msg, output: None,
+        }),
+        Ok(Err(e)) => HttpResponse::BadRequest().json(ApiResponse {
+            success: false, message: e, output: None,
+        }),
+        Err(e) => HttpResponse::InternalServerError().json(ApiResponse {
+            success: false, message: format!("Internal error: {}", e), output: None,
+        }),
+    }
+}
+
+// ── Secure Boot ──
+
+/// Secure Boot setting and the keys enrolled in a VM's NVRAM
+async fn secure_boot_info_handler(path: web::Path<String>) -> HttpResponse {
+    let smac = path.into_inner();
+    guest_json(web::block(move || operations::secure_boot_info(&smac)).await)
+}
+
+/// Enroll PEM certificates: `{"pk", "kek": [], "db": [], "owner"}`
+async fn secure_boot_enroll_handler(
+    path: web::Path<String>,
+    body: web::Json<serde_json::Value>,
+) -> HttpResponse {
+    let smac = path.into_inner();
+    op_response(
+        web::block(move || operations::secure_boot_enroll(&smac, &body)).await,
+        "Secure Boot keys enrolled",
+    )
+}
+
+/// Reset the NVRAM to the Microsoft Secure Boot keys
+async fn secure_boot_reset_handler(path: web::Path<String>) -> HttpResponse {
+    let smac = path.into_inner();
+    op_response(
+        web::block(move || operations::secure_boot_reset(&smac)).await,
+        "NVRAM reset",
+    )
+}
+
+// ── TPM ──
+
+/// Remove a stopped VM's swtpm state (fresh TPM at next start)
+async fn tpm_reset_handler(path: web::Path<String>) -> HttpResponse {
+    let smac = path.into_inner();
+    op_response(
+        web::block(move || operations::tpm_reset(&smac)).await,
+        "TPM reset",
+    )
+}
+
 // ── IP Pool ──
 async fn list_ip_pool_handler() -> HttpResponse {
     let vms = crate::db::list_vms().unwrap_or_default();
@@ -4107,7 +5701,13 @@ async fn list_internal_network_handler() -> HttpResponse {
 async fn host_ram_handler() -> HttpResponse {
     let host_ram = operations::host_total_ram_mb();
     let host_cpus = operations::host_total_cpus();
-    let used_ram = operations::running_vms_ram_mb(None);
+    // Queries every running VM's balloon over QMP
+    let used_ram = match web::block(|| operations::running_vms_ram_mb(None)).await {
+        Ok(mb) => mb,
+        Err(e) => return HttpResponse::InternalServerError().json(ApiResponse {
+            success: false, message: format!("Internal error: {}", e), output: None,
+        }),
+    };
     let reserved: u64 = 1024;
     let usable = host_ram.saturating_sub(reserved);
     let available = usable.saturating_sub(used_ram);
@@ -4328,6 +5928,14 @@ pub async fn start_server(bind_addr: &str) -> std::io::Result<()> {
 
     // Shared VNC token store (one-time tokens)
     let vnc_tokens: VncTokenStore = Arc::new(Mutex::new(HashMap::new()));
+    let vnc_proxy_sessions = VncProxyStore::default();
+    {
+        let sessions = vnc_proxy_sessions.clone();
+        std::thread::spawn(move || loop {
+            std::thread::sleep(std::time::Duration::from_secs(30));
+            sessions.purge_expired();
+        });
+    }
 
     // Shared mounted-disk store for disk file editor
     let mounted_disks: crate::disk_edit::MountedDiskStore =
@@ -4355,6 +5963,46 @@ pub async fn start_server(bind_addr: &str) -> std::io::Result<()> {
         }
     }
 
+    operations::resume_guest_watchers();
+
+    // Remove VM cgroups once their QEMU process has exited (Linux cgroup v2)
+    if crate::cgroups::available() {
+        crate::cgroups::reap_empty();
+        std::thread::spawn(|| loop {
+            std::thread::sleep(std::time::Duration::from_secs(30));
+            crate::cgroups::reap_empty();
+        });
+    }
+
+    // Session recording: close out archives orphaned by a restart, then start
+    // recordings for opted-in VMs whenever a VNC client connects
+    crate::recording::mark_interrupted();
+    std::thread::spawn(|| loop {
+        std::thread::sleep(std::time::Duration::from_secs(2));
+        crate::recording::supervise();
+    });
+
+    // Guest file transfers whose worker died with the previous process
+    crate::transfer::mark_interrupted();
+
+    // Keep VM list thumbnails fresh so the page never waits on screendump
+    let thumb_interval: u64 = get_conf_or("thumbnail_interval", "60").parse().unwrap_or(60);
+    if thumb_interval > 0 {
+        std::thread::spawn(move || loop {
+            std::thread::sleep(std::time::Duration::from_secs(thumb_interval));
+            operations::refresh_thumbnails();
+        });
+    }
+
+    // Guest-reported IPs and OS for the VM list (needs qemu-guest-agent)
+    let guest_info_interval: u64 = get_conf_or("guest_info_interval", "60").parse().unwrap_or(60);
+    if guest_info_interval > 0 {
+        std::thread::spawn(move || loop {
+            std::thread::sleep(std::time::Duration::from_secs(guest_info_interval));
+            operations::refresh_guest_info();
+        });
+    }
+
     let static_path = get_conf("static_path");
     let mds_bind = "169.254.169.254:80";
 
@@ -4389,6 +6037,7 @@ pub async fn start_server(bind_addr: &str) -> std::io::Result<()> {
         App::new()
             .wrap(middleware::Logger::default())
             .app_data(web::Data::new(vnc_tokens_for_server.clone()))
+            .app_data(web::Data::new(vnc_proxy_sessions.clone()))
             .app_data(web::Data::new(mounted_disks_for_server.clone()))
             // Allow up to 16GB uploads for large disk images and ISOs
             .app_data(web::PayloadConfig::new(17_179_869_184))
@@ -4397,6 +6046,7 @@ pub async fn start_server(bind_addr: &str) -> std::io::Result<()> {
             .route("/api/vm/stop", web::post().to(stop_vm))
             .route("/api/vm/reset", web::post().to(reset_vm))
             .route("/api/vm/powerdown", web::post().to(powerdown_vm))
+            .route("/api/vm/shutdown", web::post().to(shutdown_vm))
             .route("/api/vm/create-config", web::post().to(create_config_vm))
             .route("/api/vm/update-config", web::post().to(update_config_vm))
             .route("/api/vm/rename", web::post().to(rename_vm_handler))
@@ -4413,6 +6063,10 @@ pub async fn start_server(bind_addr: &str) -> std::io::Result<()> {
             .route("/api/vm/list", web::get().to(list_vms_handler))
             // Device routes
             .route("/api/devices/vfio", web::get().to(list_vfio_devices))
+            .route("/api/devices/pci", web::get().to(list_pci_devices))
+            .route("/api/devices/pci/bind", web::post().to(pci_bind_handler))
+            .route("/api/devices/pci/unbind", web::post().to(pci_unbind_handler))
+            .route("/api/devices/usb", web::get().to(list_usb_devices))
             // Disk routes
             .route("/api/disk/list", web::get().to(list_disks_handler))
             .route("/api/disk/create", web::post().to(create_disk_handler))
@@ -4421,6 +6075,53 @@ pub async fn start_server(bind_addr: &str) -> std::io::Result<()> {
             .route("/api/disk/resize", web::post().to(resize_disk_handler))
             .route("/api/disk/flatten", web::post().to(flatten_disk_handler))
             .route("/api/disk/set-template", web::post().to(set_template_handler))
+            // Disk hot-plug routes
+            .route("/api/vm/{smac}/disks/attach", web::post().to(attach_disk_handler))
+            .route("/api/vm/{smac}/disks/detach", web::post().to(detach_disk_handler))
+            // NIC hot-plug routes
+            .route("/api/vm/{smac}/nics/attach", web::post().to(attach_nic_handler))
+            .route("/api/vm/{smac}/nics/detach", web::post().to(detach_nic_handler))
+            .route("/api/vm/{smac}/nics/link", web::post().to(nic_link_handler))
+            .route("/api/vm/{smac}/nics/update", web::post().to(update_nic_handler))
+            // CPU / memory hot-add routes
+            .route("/api/vm/{smac}/cpus", web::post().to(set_vcpus_handler))
+            .route("/api/vm/{smac}/memory", web::post().to(set_memory_handler))
+            .route("/api/vm/{smac}/balloon", web::get().to(balloon_info_handler))
+            .route("/api/vm/{smac}/balloon", web::post().to(set_balloon_handler))
+            .route("/api/vm/{smac}/metrics", web::get().to(vm_metrics_handler))
+            .route("/api/vm/{smac}/console", web::get().to(console_ws_handler))
+            .route("/api/vm/{smac}/console/log", web::get().to(console_log_handler))
+            .route("/api/vm/{smac}/screenshot", web::get().to(screenshot_handler))
+            .route("/api/vm/{smac}/thumbnail", web::get().to(thumbnail_handler))
+            .route("/api/vm/{smac}/sendkeys", web::post().to(sendkeys_handler))
+            .route("/api/vm/{smac}/guest/exec", web::post().to(guest_exec_handler))
+            .route("/api/vm/{smac}/guest/exec/{pid}", web::get().to(guest_exec_status_handler))
+            .route("/api/vm/{smac}/guest/file", web::get().to(guest_file_read_handler))
+            .route("/api/vm/{smac}/guest/network", web::get().to(guest_network_handler))
+            .route("/api/vm/{smac}/guest/osinfo", web::get().to(guest_osinfo_handler))
+            .route("/api/vm/{smac}/guest/users", web::get().to(guest_users_handler))
+            .route("/api/vm/{smac}/guest/fsinfo", web::get().to(guest_fsinfo_handler))
+            .route("/api/vm/{smac}/guest/password", web::post().to(guest_password_handler))
+            .route("/api/vm/{smac}/guest/provision", web::get().to(guest_provision_status_handler))
+            .route("/api/vm/{smac}/guest/provision", web::post().to(guest_provision_handler))
+            .route("/api/vm/{smac}/guest/upload", web::post().to(guest_upload_handler))
+            .route("/api/vm/{smac}/guest/upload/create", web::post().to(guest_upload_create_handler))
+            .route("/api/vm/{smac}/guest/download", web::post().to(guest_download_handler))
+            .route("/api/transfers", web::get().to(list_transfers_handler))
+            .route("/api/transfers/cancel", web::post().to(cancel_transfer_handler))
+            .route("/api/transfers/delete", web::post().to(delete_transfer_handler))
+            .route("/api/transfers/{id}", web::get().to(get_transfer_handler))
+            .route("/api/transfers/{id}/file", web::get().to(transfer_file_handler))
+            .route("/api/vm/{smac}/recording/start", web::post().to(recording_start_handler))
+            .route("/api/vm/{smac}/recording/stop", web::post().to(recording_stop_handler))
+            .route("/api/recordings", web::get().to(list_recordings_handler))
+            .route("/api/recordings/delete", web::post().to(delete_recording_handler))
+            .route("/api/recordings/{id}", web::get().to(get_recording_handler))
+            .route("/api/recordings/{id}/frame/{index}", web::get().to(recording_frame_handler))
+            .route("/api/recordings/{id}/download", web::get().to(download_recording_handler))
+            // USB passthrough routes
+            .route("/api/vm/{smac}/usb/attach", web::post().to(attach_usb_handler))
+            .route("/api/vm/{smac}/usb/detach", web::post().to(detach_usb_handler))
             // Disk file editor routes
             .route("/api/disk/edit-supported", web::get().to(disk_edit_supported_handler))
             .route("/api/disk/mount", web::post().to(mount_disk_handler))
@@ -4445,6 +6146,15 @@ pub async fn start_server(bind_addr: &str) -> std::io::Result<()> {
             .route("/api/iso/list", web::get().to(list_isos_handler))
             .route("/api/iso/upload", web::post().to(upload_iso_handler))
             .route("/api/iso/delete", web::post().to(delete_iso_handler))
+            // Kernels / initrds for direct kernel boot
+            .route("/api/boot/files", web::get().to(list_boot_files_handler))
+            .route("/api/boot/upload", web::post().to(upload_boot_file_handler))
+            .route("/api/boot/delete", web::post().to(delete_boot_file_handler))
+            // Secure Boot keys + TPM state
+            .route("/api/vm/{smac}/secureboot", web::get().to(secure_boot_info_handler))
+            .route("/api/vm/{smac}/secureboot/enroll", web::post().to(secure_boot_enroll_handler))
+            .route("/api/vm/{smac}/secureboot/reset", web::post().to(secure_boot_reset_handler))
+            .route("/api/vm/{smac}/tpm/reset", web::post().to(tpm_reset_handler))
             // Backup routes
             .route("/api/backup/list", web::get().to(list_backups_handler))
             .route("/api/backup/delete", web::post().to(delete_backup_handler))
@@ -4513,7 +6223,10 @@ pub async fn start_server(bind_addr: &str) -> std::io::Result<()> {
             .route("/api/vnc/stop", web::post().to(vnc_stop_handler))
             .route("/api/vnc/token", web::post().to(vnc_token_handler))
             .route("/api/vnc/resolve/{token}", web::get().to(vnc_resolve_handler))
+            .route("/api/vnc/spice/{token}", web::get().to(spice_vv_handler))
+            .route("/vnc/ws/{session}", web::get().to(vnc_ws_proxy_handler))
             // MDS routes
+            .configure(mds::configure_mds_admin_routes)
             .configure(mds::configure_mds_routes)
             // Static files (must be last - catch-all)
             .service(
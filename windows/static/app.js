@@ -67,7 +67,7 @@ async function loadOsTemplates() {
         // Build lookup map
         OS_TEMPLATES = { 'custom': null };
         list.forEach(function(t) {
-            OS_TEMPLATES[t.key] = { vcpus: t.vcpus, memory: t.memory, is_windows: t.is_windows, arch: t.arch || 'x86_64', image: t.image, name: t.name, id: t.id };
+            OS_TEMPLATES[t.key] = { vcpus: t.vcpus, memory: t.memory, is_windows: t.is_windows, arch: t.arch || 'x86_64', image: t.image, unattend: t.unattend || '0', name: t.name, id: t.id };
         });
         // Populate the Create VM template dropdown
         populateOsTemplateDropdown();
@@ -138,6 +138,9 @@ function applyOsTemplate() {
     document.getElementById('start-memory-range').value = tpl.memory;
     document.getElementById('start-memory-label').textContent = formatMemory(tpl.memory);
     document.getElementById('start-is-windows').value = tpl.is_windows;
+    document.getElementById('start-unattend').value = tpl.unattend === '1' ? '1' : '0';
+    document.getElementById('start-linstall').value = LINUX_INSTALL_FLAVORS[tpl.unattend] ? tpl.unattend : '';
+    updateUnattendVisibility();
     document.getElementById('start-arch').value = tpl.arch || 'x86_64';
 
     // Resolve base image: saved mapping → auto-match → none
@@ -318,7 +321,7 @@ function renderOsTemplateList() {
         return;
     }
     listDiv.innerHTML = '<table style="width:100%;border-collapse:collapse;">' +
-        '<tr style="border-bottom:1px solid #30363d;"><th style="text-align:left;padding:4px;">Key</th><th>Name</th><th>vCPUs</th><th>Memory</th><th>Windows</th><th>Arch</th><th>Image</th><th></th></tr>' +
+        '<tr style="border-bottom:1px solid #30363d;"><th style="text-align:left;padding:4px;">Key</th><th>Name</th><th>vCPUs</th><th>Memory</th><th>Windows</th><th>Unattended</th><th>Arch</th><th>Image</th><th></th></tr>' +
         list.map(function(t) {
             return '<tr style="border-bottom:1px solid #21262d;">' +
                 '<td style="padding:4px;">' + escapeHtml(t.key) + '</td>' +
@@ -326,6 +329,7 @@ function renderOsTemplateList() {
                 '<td style="padding:4px;text-align:center;">' + escapeHtml(t.vcpus) + '</td>' +
                 '<td style="padding:4px;text-align:center;">' + escapeHtml(t.memory) + '</td>' +
                 '<td style="padding:4px;text-align:center;">' + (t.is_windows === '1' ? 'Yes' : 'No') + '</td>' +
+                '<td style="padding:4px;text-align:center;">' + escapeHtml(t.unattend === '1' ? 'Windows' : (LINUX_INSTALL_FLAVORS[t.unattend] || 'No')) + '</td>' +
                 '<td style="padding:4px;text-align:center;">' + escapeHtml(t.arch || 'x86_64') + '</td>' +
                 '<td style="padding:4px;">' + escapeHtml(t.image) + '</td>' +
                 '<td style="padding:4px;white-space:nowrap;">' +
@@ -346,6 +350,7 @@ window.saveOsTemplate = async function() {
         is_windows: val('tpl-is-windows'),
         arch: val('tpl-arch'),
         image: val('tpl-image'),
+        unattend: val('tpl-unattend'),
     };
     if (!data.key) { alert('Key is required'); return; }
     if (!data.name) data.name = data.key;
@@ -383,6 +388,7 @@ window.editOsTemplate = function(id) {
     document.getElementById('tpl-is-windows').value = t.is_windows;
     document.getElementById('tpl-arch').value = t.arch || 'x86_64';
     document.getElementById('tpl-image').value = t.image;
+    document.getElementById('tpl-unattend').value = t.unattend || '0';
     document.getElementById('tpl-form-legend').textContent = 'Edit Template: ' + t.name;
 };
 
@@ -414,6 +420,7 @@ window.resetTplForm = function() {
     document.getElementById('tpl-is-windows').value = '0';
     document.getElementById('tpl-arch').value = 'x86_64';
     document.getElementById('tpl-image').value = '';
+    document.getElementById('tpl-unattend').value = '0';
     document.getElementById('tpl-form-legend').textContent = 'Add Template';
 };
 
@@ -433,9 +440,19 @@ function resetCreateForm() {
     document.getElementById('start-memory-size').value = '2048';
     document.getElementById('start-memory-range').value = '2048';
     document.getElementById('start-memory-label').textContent = '2 GB';
+    document.getElementById('start-max-vcpus').value = '0';
+    document.getElementById('start-max-memory').value = '0';
+    document.getElementById('start-balloon').value = '0';
     document.getElementById('start-arch').value = 'x86_64';
     document.getElementById('start-is-windows').value = '0';
     document.getElementById('start-cloudinit').value = '1';
+    document.getElementById('start-recording').value = '0';
+    document.getElementById('start-display').value = 'vnc';
+    document.getElementById('start-video').value = 'auto';
+    document.getElementById('start-usb-redirect').value = '2';
+    fillUnattendForm({});
+    fillLinuxInstallForm({});
+    fillBootForm({});
     // Reset network adapters
     document.getElementById('start-network-adapters').innerHTML = '';
     addNetworkAdapter();
@@ -458,7 +475,7 @@ document.querySelectorAll('.tab').forEach(function(tab) {
         // Auto-load MDS config + SSH key list when switching to metadata tab
         if (tab.dataset.tab === 'metadata') { loadSshKeyList(); loadMdsConfig(); }
         // Auto-load ISO list when switching to mountiso tab
-        if (tab.dataset.tab === 'mountiso') { loadIsoList(); }
+        if (tab.dataset.tab === 'mountiso') { loadIsoList(); loadBootFiles(); }
         // Auto-load VM list when switching to vmlist tab
         if (tab.dataset.tab === 'vmlist') { loadVmListTable(); }
         // Auto-load disk list when switching to disks tab
@@ -787,15 +804,135 @@ function collectVmConfig() {
     return {
         cpu: {
             vcpus: val('start-vcpus'),
+            max_vcpus: val('start-max-vcpus') || '0',
         },
-        memory: { size: val('start-memory-size') },
+        memory: { size: val('start-memory-size'), max_size: val('start-max-memory') || '0', balloon: val('start-balloon') },
         features: { is_windows: val('start-is-windows'), arch: val('start-arch'), cloudinit: val('start-cloudinit') },
+        recording: { enabled: val('start-recording') },
+        display: { protocol: val('start-display'), video: val('start-video'), usb_redirect: val('start-usb-redirect') },
+        unattend: collectUnattend(),
+        linux_install: collectLinuxInstall(),
+        boot: collectBoot(),
         network_adapters: network_adapters,
         disks: disks,
         pci_devices: pci_devices,
     };
 }
 
+// Unattended install settings: Windows fieldset for Windows VMs, Linux otherwise
+function updateUnattendVisibility() {
+    var windows = val('start-is-windows') === '1';
+    document.getElementById('start-unattend-fieldset').style.display = windows ? '' : 'none';
+    document.getElementById('start-linstall-fieldset').style.display = windows ? 'none' : '';
+}
+
+function fillUnattendForm(u) {
+    document.getElementById('start-unattend').value = u.enabled === '1' ? '1' : '0';
+    document.getElementById('start-unattend-locale').value = u.locale || 'en-US';
+    document.getElementById('start-unattend-keyboard').value = u.keyboard || '';
+    document.getElementById('start-unattend-timezone').value = u.timezone || 'UTC';
+    document.getElementById('start-unattend-product-key').value = u.product_key || '';
+    document.getElementById('start-unattend-image-name').value = u.image_name || '';
+    // Passwords are never shown; leave empty to keep the stored one
+    document.getElementById('start-unattend-admin-password').value = '';
+    document.getElementById('start-unattend-driver-os').value = u.driver_os || 'w11';
+    document.getElementById('start-unattend-bypass').value = u.bypass_checks === '0' ? '0' : '1';
+    updateUnattendVisibility();
+}
+
+function collectUnattend() {
+    var u = {
+        enabled: val('start-is-windows') === '1' ? val('start-unattend') : '0',
+        locale: val('start-unattend-locale') || 'en-US',
+        keyboard: val('start-unattend-keyboard'),
+        timezone: val('start-unattend-timezone') || 'UTC',
+        product_key: val('start-unattend-product-key'),
+        image_name: val('start-unattend-image-name'),
+        driver_os: val('start-unattend-driver-os'),
+        bypass_checks: val('start-unattend-bypass'),
+    };
+    // Omitted → the stored password survives the config merge
+    var pw = val('start-unattend-admin-password');
+    if (pw) u.admin_password = pw;
+    return u;
+}
+
+// Unattended Linux install (answer file from the MDS, installer kernel booted directly)
+var LINUX_INSTALL_FLAVORS = { ubuntu: 'Ubuntu', debian: 'Debian', rhel: 'RHEL' };
+
+function fillLinuxInstallForm(l) {
+    document.getElementById('start-linstall').value = l.enabled === '1' ? (l.flavor || 'ubuntu') : '';
+    var isoSel = document.getElementById('start-linstall-iso');
+    if (l.iso && !Array.prototype.some.call(isoSel.options, function(o) { return o.value === l.iso; })) {
+        var opt = document.createElement('option');
+        opt.value = l.iso;
+        opt.textContent = l.iso;
+        isoSel.appendChild(opt);
+    }
+    isoSel.value = l.iso || '';
+    document.getElementById('start-linstall-locale').value = l.locale || 'en_US.UTF-8';
+    document.getElementById('start-linstall-keyboard').value = l.keyboard || 'us';
+    document.getElementById('start-linstall-timezone').value = l.timezone || 'UTC';
+    document.getElementById('start-linstall-username').value = l.username || 'admin';
+    // Passwords are never shown; leave empty to keep the stored one
+    document.getElementById('start-linstall-password').value = '';
+    document.getElementById('start-linstall-kernel-args').value = l.kernel_args || '';
+}
+
+function collectLinuxInstall() {
+    var flavor = val('start-is-windows') === '1' ? '' : val('start-linstall');
+    var l = {
+        enabled: flavor ? '1' : '0',
+        flavor: flavor || 'ubuntu',
+        iso: val('start-linstall-iso'),
+        locale: val('start-linstall-locale') || 'en_US.UTF-8',
+        keyboard: val('start-linstall-keyboard') || 'us',
+        timezone: val('start-linstall-timezone') || 'UTC',
+        username: val('start-linstall-username') || 'admin',
+        kernel_args: val('start-linstall-kernel-args'),
+    };
+    // Omitted → the stored password survives the config merge
+    var pw = val('start-linstall-password');
+    if (pw) l.password = pw;
+    return l;
+}
+
+// Boot settings (firmware, device order, direct kernel boot)
+function selectValueOrAdd(sel, value) {
+    if (value && !Array.prototype.some.call(sel.options, function(o) { return o.value === value; })) {
+        var opt = document.createElement('option');
+        opt.value = value;
+        opt.textContent = value;
+        sel.appendChild(opt);
+    }
+    sel.value = value;
+}
+
+function fillBootForm(b) {
+    document.getElementById('start-boot-firmware').value = b.firmware || 'auto';
+    selectValueOrAdd(document.getElementById('start-boot-order'), b.order || 'cdrom,disk');
+    document.getElementById('start-boot-menu-timeout').value = b.menu_timeout || '0';
+    selectValueOrAdd(document.getElementById('start-boot-kernel'), b.kernel || '');
+    selectValueOrAdd(document.getElementById('start-boot-initrd'), b.initrd || '');
+    document.getElementById('start-boot-cmdline').value = b.cmdline || '';
+    document.getElementById('start-boot-secure-boot').value = b.secure_boot || 'auto';
+    // Key management needs an existing VM
+    document.getElementById('secureboot-panel').style.display = window._editingVm ? '' : 'none';
+    document.getElementById('secureboot-keys').innerHTML = '';
+}
+
+function collectBoot() {
+    return {
+        firmware: val('start-boot-firmware'),
+        order: val('start-boot-order'),
+        menu_timeout: val('start-boot-menu-timeout') || '0',
+        kernel: val('start-boot-kernel'),
+        initrd: val('start-boot-initrd'),
+        cmdline: val('start-boot-cmdline'),
+        secure_boot: val('start-boot-secure-boot'),
+    };
+}
+
 // Create VM — save config to DB + create disk
 async function executeCreateVm() {
     try {
@@ -2102,6 +2239,17 @@ async function loadIsoList() {
             sel.appendChild(opt);
         });
         if (current) sel.value = current;
+        // Installer ISO choice for unattended Linux installs
+        var instSel = document.getElementById('start-linstall-iso');
+        var instCurrent = instSel.value;
+        instSel.innerHTML = '<option value="">-- select ISO --</option>';
+        isos.forEach(function(iso) {
+            var opt = document.createElement('option');
+            opt.value = iso.name;
+            opt.textContent = iso.name;
+            instSel.appendChild(opt);
+        });
+        if (instCurrent) instSel.value = instCurrent;
         // Populate file list
         var listDiv = document.getElementById('iso-file-list');
         if (isos.length === 0) {
@@ -2223,6 +2371,208 @@ async function uploadIso() {
     xhr.send(file);
 }
 
+// Kernels / initrds for direct kernel boot: file list + Boot fieldset dropdowns
+async function loadBootFiles() {
+    try {
+        var response = await apiFetch('/api/boot/files');
+        var files = await safeJson(response) || [];
+        ['start-boot-kernel', 'start-boot-initrd'].forEach(function(id) {
+            var sel = document.getElementById(id);
+            var current = sel.value;
+            sel.innerHTML = id === 'start-boot-kernel'
+                ? '<option value="">-- none (boot from devices) --</option>'
+                : '<option value="">-- none --</option>';
+            files.forEach(function(f) {
+                var opt = document.createElement('option');
+                opt.value = f.name;
+                opt.textContent = f.name;
+                sel.appendChild(opt);
+            });
+            selectValueOrAdd(sel, current);
+        });
+        var listDiv = document.getElementById('boot-file-list');
+        if (files.length === 0) {
+            listDiv.innerHTML = '<em>No boot files</em>';
+        } else {
+            listDiv.innerHTML = files.map(function(f) {
+                return '<div style="display:flex;justify-content:space-between;align-items:center;padding:4px 0;border-bottom:1px solid #333;">' +
+                    '<span>' + escapeHtml(f.name) + ' <small>(' + escapeHtml(formatSize(f.size)) + ')</small></span>' +
+                    '<button class="btn-remove" onclick="deleteBootFile(\'' + f.name.replace(/'/g, "\\'") + '\')">X</button>' +
+                    '</div>';
+            }).join('');
+        }
+    } catch (err) {
+        console.error('Failed to load boot files:', err);
+    }
+}
+
+async function uploadBootFile() {
+    var fileInput = document.getElementById('boot-file-input');
+    if (!fileInput.files.length) {
+        alert('Please select a kernel or initrd file');
+        return;
+    }
+    var file = fileInput.files[0];
+    var statusEl = document.getElementById('status-indicator');
+    statusEl.className = 'loading';
+    statusEl.textContent = 'Uploading ' + file.name + '...';
+    try {
+        var response = await apiFetch('/api/boot/upload', {
+            method: 'POST',
+            headers: { 'X-Filename': file.name, 'Content-Type': 'application/octet-stream' },
+            body: file,
+        });
+        var data = await safeJson(response);
+        if (data.success) {
+            statusEl.className = 'success';
+            statusEl.textContent = data.message;
+            fileInput.value = '';
+            loadBootFiles();
+        } else {
+            statusEl.className = 'error';
+            statusEl.textContent = 'Error: ' + data.message;
+        }
+    } catch (err) {
+        statusEl.className = 'error';
+        statusEl.textContent = 'Network error: ' + err.message;
+    }
+}
+
+async function deleteBootFile(name) {
+    if (!confirm('Delete boot file: ' + name + '?')) return;
+    var statusEl = document.getElementById('status-indicator');
+    try {
+        var response = await apiFetch('/api/boot/delete', {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify({ name: name }),
+        });
+        var data = await safeJson(response);
+        if (data.success) {
+            statusEl.className = 'success';
+            statusEl.textContent = data.message;
+            loadBootFiles();
+        } else {
+            statusEl.className = 'error';
+            statusEl.textContent = 'Error: ' + data.message;
+        }
+    } catch (err) {
+        statusEl.className = 'error';
+        statusEl.textContent = 'Network error: ' + err.message;
+    }
+}
+
+// ── Secure Boot keys of the VM being edited ──
+
+function renderSecureBootKeys(info) {
+    var div = document.getElementById('secureboot-keys');
+    if (!info.nvram) {
+        div.innerHTML = '<em>No NVRAM yet (created on first UEFI boot)</em>';
+        return;
+    }
+    var mode = info.setup_mode ? 'Setup mode (no PK: signatures not checked)'
+        : (info.enforcing ? 'User mode (enforcing)' : 'User mode (disabled)');
+    var html = '<div>Firmware: ' + (info.firmware_secure ? 'Secure Boot build' : 'no Secure Boot') +
+        ' &middot; ' + escapeHtml(mode) + '</div>';
+    ['pk', 'kek', 'db', 'dbx'].forEach(function(db) {
+        var entries = info[db] || [];
+        var certs = entries.filter(function(e) { return e.type === 'x509'; });
+        var hashes = entries.length - certs.length;
+        html += '<div style="padding:4px 0;border-bottom:1px solid #333;"><strong>' + db.toUpperCase() + '</strong> ';
+        if (entries.length === 0) html += '<em>empty</em>';
+        if (hashes > 0) html += '<small>(' + hashes + ' hash' + (hashes === 1 ? '' : 'es') + ')</small>';
+        certs.forEach(function(c) {
+            html += '<div><small>' + escapeHtml(c.subject || '(unnamed certificate)') +
+                (c.issuer && c.issuer !== c.subject ? ' &larr; ' + escapeHtml(c.issuer) : '') + '</small></div>';
+        });
+        html += '</div>';
+    });
+    div.innerHTML = html;
+}
+
+async function loadSecureBootKeys() {
+    var smac = window._editingVm;
+    if (!smac) return;
+    var div = document.getElementById('secureboot-keys');
+    div.innerHTML = '<em>Reading NVRAM...</em>';
+    try {
+        var response = await apiFetch('/api/vm/' + encodeURIComponent(smac) + '/secureboot');
+        var data = await safeJson(response);
+        if (!response.ok) {
+            div.innerHTML = '<span style="color:#f85149;">' + escapeHtml((data && data.message) || 'Failed to read NVRAM') + '</span>';
+            return;
+        }
+        renderSecureBootKeys(data);
+    } catch (err) {
+        div.innerHTML = '<span style="color:#f85149;">Network error: ' + escapeHtml(err.message) + '</span>';
+    }
+}
+
+async function secureBootAction(action, body) {
+    var smac = window._editingVm;
+    var statusEl = document.getElementById('status-indicator');
+    statusEl.className = 'loading';
+    statusEl.textContent = 'Updating Secure Boot keys of ' + smac + '...';
+    try {
+        var response = await apiFetch('/api/vm/' + encodeURIComponent(smac) + '/secureboot/' + action, {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify(body || {}),
+        });
+        var data = await safeJson(response);
+        if (data.success) {
+            statusEl.className = 'success';
+            statusEl.textContent = (data.output || data.message).trim();
+            loadSecureBootKeys();
+        } else {
+            statusEl.className = 'error';
+            statusEl.textContent = 'Error: ' + data.message;
+        }
+    } catch (err) {
+        statusEl.className = 'error';
+        statusEl.textContent = 'Network error: ' + err.message;
+    }
+}
+
+async function enrollSecureBootCert() {
+    var fileInput = document.getElementById('secureboot-cert-input');
+    if (!fileInput.files.length) {
+        alert('Please select a PEM certificate');
+        return;
+    }
+    var pem = await fileInput.files[0].text();
+    var target = val('secureboot-enroll-target');
+    var body = target === 'pk' ? { pk: pem } : {};
+    if (target !== 'pk') body[target] = [pem];
+    await secureBootAction('enroll', body);
+    fileInput.value = '';
+}
+
+async function resetSecureBootKeys() {
+    if (!confirm('Reset the UEFI NVRAM of ' + window._editingVm + ' to the Microsoft Secure Boot keys? Boot entries are reset too.')) return;
+    await secureBootAction('reset');
+}
+
+async function resetTpm() {
+    var smac = window._editingVm;
+    if (!confirm('Reset the TPM of ' + smac + '? BitLocker-protected disks will ask for their recovery key.')) return;
+    var statusEl = document.getElementById('status-indicator');
+    try {
+        var response = await apiFetch('/api/vm/' + encodeURIComponent(smac) + '/tpm/reset', { method: 'POST' });
+        var data = await safeJson(response);
+        if (data.success) {
+            statusEl.className = 'success';
+            statusEl.textContent = (data.output || data.message).trim();
+        } else {
+            statusEl.className = 'error';
+            statusEl.textContent = 'Error: ' + data.message;
+        }
+    } catch (err) {
+        statusEl.className = 'error';
+        statusEl.textContent = 'Network error: ' + err.message;
+    }
+}
+
 // Delete ISO file
 async function deleteIso(name) {
     if (!confirm('Delete ISO: ' + name + '?')) return;
@@ -2304,6 +2654,11 @@ async function vmVncStart(smac) {
     window.location.href = '/vnc.html?smac=' + encodeURIComponent(smac);
 }
 
+// Serial console page (WebSocket bridge to the VM's -serial chardev)
+function vmConsoleOpen(smac) {
+    window.location.href = '/console.html?smac=' + encodeURIComponent(smac);
+}
+
 async function vmVncStop(smac) {
     var port = getVmVncPort(smac);
     if (!port) { alert('No VNC port assigned for ' + smac); return; }
@@ -2514,6 +2869,14 @@ async function loadMdsConfig() {
             document.getElementById('mds-growpart').checked = config.growpart !== false;
             document.getElementById('mds-extra-runcmd').value = config.extra_runcmd || '';
             document.getElementById('mds-write-files').value = config.write_files || '';
+            document.getElementById('mds-windows-provision').checked = !!config.windows_provision;
+            document.getElementById('mds-windows-admin-user').value = config.windows_admin_user || '';
+            document.getElementById('mds-windows-admin-password').value = '';
+            document.getElementById('mds-windows-computer-name').value = config.windows_computer_name || '';
+            document.getElementById('mds-domain-name').value = config.domain_name || '';
+            document.getElementById('mds-domain-user').value = config.domain_user || '';
+            document.getElementById('mds-domain-password').value = '';
+            document.getElementById('mds-domain-ou').value = config.domain_ou || '';
             statusEl.className = 'success';
             statusEl.textContent = 'MDS config loaded for ' + smac;
             outputEl.textContent = data.output;
@@ -2583,6 +2946,14 @@ async function saveMdsConfig() {
         growpart: document.getElementById('mds-growpart').checked,
         extra_runcmd: document.getElementById('mds-extra-runcmd').value,
         write_files: wfVal,
+        windows_provision: document.getElementById('mds-windows-provision').checked,
+        windows_admin_user: val('mds-windows-admin-user'),
+        windows_admin_password: val('mds-windows-admin-password'),
+        windows_computer_name: val('mds-windows-computer-name'),
+        domain_name: val('mds-domain-name'),
+        domain_user: val('mds-domain-user'),
+        domain_password: val('mds-domain-password'),
+        domain_ou: val('mds-domain-ou'),
     };
     try {
         var response = await apiFetch('/api/vm/' + encodeURIComponent(smac) + '/mds', {
@@ -2596,6 +2967,8 @@ async function saveMdsConfig() {
             statusEl.textContent = data.message;
             // Clear root password after save (use-once)
             document.getElementById('mds-root-password').value = '';
+            document.getElementById('mds-windows-admin-password').value = '';
+            document.getElementById('mds-domain-password').value = '';
             outputEl.textContent = JSON.stringify(payload, null, 2);
         } else {
             statusEl.className = 'error';
@@ -2711,11 +3084,13 @@ async function loadVmListTable() {
                     actions += '<button class="btn-vm-action btn-vm-stop" onclick="vmAction(\'stop\',\'' + vm.smac + '\')">Stop</button> ';
                     actions += '<button class="btn-vm-action btn-vm-reset" onclick="vmAction(\'reset\',\'' + vm.smac + '\')">Reset</button> ';
                     actions += '<button class="btn-vm-action btn-vm-powerdown" onclick="vmAction(\'powerdown\',\'' + vm.smac + '\')">Powerdown</button> ';
+                    actions += '<button class="btn-vm-action btn-vm-shutdown" onclick="vmAction(\'shutdown\',\'' + vm.smac + '\')" title="Guest agent, then ACPI, then force quit after the timeout">Shutdown</button> ';
                     if (window._vncActive[vm.smac]) {
                         actions += '<button class="btn-vm-action btn-vm-vncstop" onclick="vmVncStop(\'' + vm.smac + '\')">VNC Stop</button> ';
                     } else {
                         actions += '<button class="btn-vm-action btn-vm-vnc" onclick="vmVncStart(\'' + vm.smac + '\')">VNC</button> ';
                     }
+                    actions += '<button class="btn-vm-action btn-vm-console" onclick="vmConsoleOpen(\'' + vm.smac + '\')" title="Serial console">Console</button> ';
                 } else {
                     // VM stopped → clear VNC active state
                     delete window._vncActive[vm.smac];
@@ -2728,6 +3103,20 @@ async function loadVmListTable() {
                 var nameCell = vm.status === 'running'
                     ? '<a href="javascript:void(0)" class="vm-name-link" onclick="vmVncStart(\'' + vm.smac.replace(/'/g, "\\'") + '\')">' + escapeHtml(vm.smac) + '</a>'
                     : escapeHtml(vm.smac);
+                // Last screen (cached server-side); hidden when none exists yet
+                nameCell = '<img class="vm-thumb" data-smac="' + escapeHtml(vm.smac) + '" src="' + vmThumbUrl(vm.smac) + '"' +
+                    ' onerror="this.style.display=\'none\'" onclick="window.open(\'/api/vm/' + encodeURIComponent(vm.smac).replace(/'/g, "\\'") + '/screenshot\', \'_blank\')"' +
+                    ' title="Click for a full-size screenshot" alt=""> ' + nameCell;
+                // Addresses and OS as reported by the guest agent
+                if (vm.status === 'running' && ((vm.guest_ips && vm.guest_ips.length) || vm.os_name)) {
+                    var guestParts = [];
+                    if (vm.guest_ips && vm.guest_ips.length) guestParts.push(vm.guest_ips.join(', '));
+                    if (vm.os_name) guestParts.push(vm.os_name + (vm.kernel ? ' (' + vm.kernel + ')' : ''));
+                    nameCell += '<div class="vm-guest-info" title="Reported by qemu-guest-agent' +
+                        (vm.agent_version ? ' ' + escapeHtml(vm.agent_version) : '') +
+                        (vm.guest_info_updated ? ' at ' + escapeHtml(vm.guest_info_updated) + ' UTC' : '') + '">' +
+                        escapeHtml(guestParts.join(' · ')) + '</div>';
+                }
 
                 html += '<tr>' +
                     '<td>' + nameCell + '</td>' +
@@ -2747,6 +3136,21 @@ async function loadVmListTable() {
     }
 }
 
+function vmThumbUrl(smac) {
+    return '/api/vm/' + encodeURIComponent(smac) + '/thumbnail?t=' + Date.now();
+}
+
+// Reload VM list thumbnails in place (server caches captures for thumbnail_max_age)
+function refreshVmThumbnails() {
+    document.querySelectorAll('img.vm-thumb').forEach(function(img) {
+        var smac = img.getAttribute('data-smac');
+        var next = new Image();
+        next.onload = function() { img.src = next.src; img.style.display = ''; };
+        next.src = vmThumbUrl(smac);
+    });
+}
+setInterval(refreshVmThumbnails, 30000);
+
 // Start/Stop VM from list
 async function vmAction(action, smac) {
     var ok = await apiCall(action, { smac: smac });
@@ -2803,18 +3207,29 @@ async function editVm(smac) {
                 }
                 document.getElementById('start-vcpus').value = vcpus;
                 document.getElementById('start-vcpus-range').value = vcpus;
+                document.getElementById('start-max-vcpus').value = config.cpu.max_vcpus || '0';
             }
             if (config.memory) {
                 var memVal = config.memory.size || '2048';
                 document.getElementById('start-memory-size').value = memVal;
                 document.getElementById('start-memory-range').value = memVal;
                 document.getElementById('start-memory-label').textContent = formatMemory(memVal);
+                document.getElementById('start-max-memory').value = config.memory.max_size || '0';
+                document.getElementById('start-balloon').value = config.memory.balloon || '0';
             }
             if (config.features) {
                 document.getElementById('start-is-windows').value = config.features.is_windows || '0';
                 document.getElementById('start-arch').value = config.features.arch || 'x86_64';
                 document.getElementById('start-cloudinit').value = config.features.cloudinit || '1';
             }
+            document.getElementById('start-recording').value = (config.recording && config.recording.enabled) || '0';
+            var display = config.display || {};
+            document.getElementById('start-display').value = display.protocol || 'vnc';
+            document.getElementById('start-video').value = display.video || 'auto';
+            document.getElementById('start-usb-redirect').value = display.usb_redirect || '2';
+            fillUnattendForm(config.unattend || {});
+            fillLinuxInstallForm(config.linux_install || {});
+            fillBootForm(config.boot || {});
 
             // Fill Network Adapters
             var adapterContainer = document.getElementById('start-network-adapters');
@@ -3148,6 +3563,7 @@ function initApp() {
     loadHostRam();
     loadVmList();
     loadIsoList();
+    loadBootFiles();
     loadVmListTable();
     loadSwitchList();
     loadGroupList();